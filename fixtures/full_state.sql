@@ -0,0 +1,71 @@
+-- Composite fixture containing a "full, realistic" database state
+-- Combines actors, keys, ID-CSRs, ID-Certs, issuers, tokens, invites and API keys
+-- in a single, self-contained fixture. All primary keys are in the 1000+ range,
+-- so that they are easy to tell apart from the data of other fixtures.
+
+-- Algorithm identifiers
+INSERT INTO algorithm_identifiers (id, algorithm_identifier, common_name, parameters_der_encoded) VALUES
+(1000, '1.3.101.112', 'Edwards-curve Digital Signature Algorithm (EdDSA) Ed25519', NULL);
+
+-- Actors: two active ones, one deactivated one
+INSERT INTO actors (uaid, type) VALUES
+('00000000-0000-0000-0000-000000001001', 'local'),
+('00000000-0000-0000-0000-000000001002', 'local'),
+('00000000-0000-0000-0000-000000001003', 'local');
+
+INSERT INTO local_actors (uaid, local_name, deactivated, joined, password_hash, invites_available) VALUES
+('00000000-0000-0000-0000-000000001001', 'full_state_alice', FALSE, '2024-01-01 12:00:00', 'hash', 4),
+('00000000-0000-0000-0000-000000001002', 'full_state_bob', FALSE, '2024-01-02 12:00:00', 'hash', 0),
+('00000000-0000-0000-0000-000000001003', 'full_state_carol', TRUE, '2024-01-03 12:00:00', 'hash', 0);
+
+-- Public keys: one per actor, plus the home server key
+INSERT INTO public_keys (id, uaid, pubkey, algorithm_identifier) VALUES
+(1001, '00000000-0000-0000-0000-000000001001', 'full_state_pubkey_alice', 1000),
+(1002, '00000000-0000-0000-0000-000000001002', 'full_state_pubkey_bob', 1000),
+(1003, '00000000-0000-0000-0000-000000001003', 'full_state_pubkey_carol', 1000),
+(1100, NULL, 'full_state_pubkey_home_server', 1000);
+
+-- ID-CSRs, one per actor
+INSERT INTO idcsr (
+    id, serial_number, uaid, subject_public_key_id, subject_signature,
+    session_id, valid_not_before, valid_not_after, extensions, pem_encoded
+) VALUES
+(1001, 90000000000000001001, '00000000-0000-0000-0000-000000001001', 1001, 'full_state_signature_alice', 'full_state_session_alice', NOW() - INTERVAL '1 day', NOW() + INTERVAL '30 days', 'full_state_extensions_alice', 'full_state_csr_pem_alice'),
+(1002, 90000000000000001002, '00000000-0000-0000-0000-000000001002', 1002, 'full_state_signature_bob', 'full_state_session_bob', NOW() - INTERVAL '1 day', NOW() + INTERVAL '30 days', 'full_state_extensions_bob', 'full_state_csr_pem_bob'),
+(1003, 90000000000000001003, '00000000-0000-0000-0000-000000001003', 1003, 'full_state_signature_carol', 'full_state_session_carol', NOW() - INTERVAL '1 day', NOW() + INTERVAL '30 days', 'full_state_extensions_carol', 'full_state_csr_pem_carol');
+
+-- The issuer entry of this home server
+INSERT INTO issuers (id, domain_components) VALUES
+(1000, ARRAY['full', 'example', 'com']);
+
+-- ID-Certs, one per ID-CSR
+INSERT INTO idcert (
+    idcsr_id, issuer_info_id, valid_not_before, valid_not_after,
+    home_server_public_key_id, home_server_signature, pem_encoded
+) VALUES
+(1001, 1000, NOW() - INTERVAL '1 day', NOW() + INTERVAL '30 days', 1100, 'full_state_home_server_sig_alice', 'full_state_cert_pem_alice'),
+(1002, 1000, NOW() - INTERVAL '1 day', NOW() + INTERVAL '30 days', 1100, 'full_state_home_server_sig_bob', 'full_state_cert_pem_bob'),
+(1003, 1000, NOW() - INTERVAL '1 day', NOW() + INTERVAL '30 days', 1100, 'full_state_home_server_sig_carol', 'full_state_cert_pem_carol');
+
+-- Tokens: alice and bob have a valid token, carol's token has expired
+INSERT INTO user_tokens (token_hash, cert_id, uaid, valid_not_after) VALUES
+('full_state_token_hash_alice', 1001, '00000000-0000-0000-0000-000000001001', NOW() + INTERVAL '1 hour'),
+('full_state_token_hash_bob', 1002, '00000000-0000-0000-0000-000000001002', NULL),
+-- Insert expired token as non-expired first to avoid auto-cleanup trigger
+('full_state_token_hash_carol', 1003, '00000000-0000-0000-0000-000000001003', NOW() + INTERVAL '1 hour');
+
+-- Update token to be expired after insertion (workaround for cleanup trigger)
+UPDATE user_tokens
+SET valid_not_after = NOW() - INTERVAL '1 hour'
+WHERE token_hash = 'full_state_token_hash_carol';
+
+-- Invites: alice owns an invite link, which bob has been invited with
+INSERT INTO invite_links (id, invite_link_owner, usages_current, usages_maximum, invite, invalid) VALUES
+(1000, '00000000-0000-0000-0000-000000001001', 1, 5, 'FULLSTATEINVITE1', FALSE);
+
+INSERT INTO invitations (invite_id, uaid_inviter, uaid_invited) VALUES
+(1000, '00000000-0000-0000-0000-000000001001', '00000000-0000-0000-0000-000000001002');
+
+-- API keys
+INSERT INTO api_keys (id, token) VALUES
+(1000, 'full_state_api_key_0123456789abcdef');
@@ -1,5 +1,5 @@
 -- Fixture for IdCert integration tests
--- Contains data for testing HomeServerCert::get_idcert_by method
+-- Contains data for testing HomeServerCert::get_idcert_by method and the ID-Cert routes
 
 -- Algorithm identifiers including ED25519
 INSERT INTO algorithm_identifiers (id, algorithm_identifier, common_name, parameters_der_encoded) VALUES
@@ -38,7 +38,10 @@ INSERT INTO idcsr (
 (100, 10000000000000000001, '00000000-0000-0000-0000-000000000010', 100, 'test_signature_idcert_1', 'session_idcert_1', NOW() - INTERVAL '1 day', NOW() + INTERVAL '30 days', 'test_extensions_idcert_1', 'test_csr_pem_idcert_1'),
 (101, 10000000000000000002, '00000000-0000-0000-0000-000000000011', 101, 'test_signature_idcert_2', 'session_idcert_2', NOW() - INTERVAL '1 day', NOW() + INTERVAL '30 days', 'test_extensions_idcert_2', 'test_csr_pem_idcert_2'),
 (102, 10000000000000000003, '00000000-0000-0000-0000-000000000012', 102, 'test_signature_idcert_3', 'session_idcert_3', NOW() - INTERVAL '2 days', NOW() - INTERVAL '1 day', 'test_extensions_idcert_3', 'test_csr_pem_idcert_3'),
-(103, 10000000000000000004, '00000000-0000-0000-0000-000000000013', 103, 'test_signature_idcert_4', 'session_idcert_4', NOW() + INTERVAL '1 day', NOW() + INTERVAL '30 days', 'test_extensions_idcert_4', 'test_csr_pem_idcert_4');
+(103, 10000000000000000004, '00000000-0000-0000-0000-000000000013', 103, 'test_signature_idcert_4', 'session_idcert_4', NOW() + INTERVAL '1 day', NOW() + INTERVAL '30 days', 'test_extensions_idcert_4', 'test_csr_pem_idcert_4'),
+-- ID-CSRs of the home servers themselves
+(110, 10000000000000000010, NULL, 200, 'test_signature_homeserver_1', 'session_homeserver_1', NOW() - INTERVAL '1 day', NOW() + INTERVAL '30 days', 'test_extensions_homeserver_1', 'test_csr_pem_homeserver_1'),
+(111, 10000000000000000011, NULL, 201, 'test_signature_homeserver_2', 'session_homeserver_2', NOW() - INTERVAL '1 day', NOW() + INTERVAL '30 days', 'test_extensions_homeserver_2', 'test_csr_pem_homeserver_2');
 
 -- Test issuers (different domains for testing)
 INSERT INTO issuers (id, domain_components) VALUES
@@ -56,4 +59,8 @@ INSERT INTO idcert (
 -- Valid certificate for test.org
 (101, 101, NOW() - INTERVAL '1 day', NOW() + INTERVAL '30 days', 201, 'homeserver_signature_2', 'PLACEHOLDER_CERT_PEM_2'),
 -- Expired certificate for expired.net
-(102, 102, NOW() - INTERVAL '2 days', NOW() - INTERVAL '1 day', 200, 'homeserver_signature_3', 'PLACEHOLDER_CERT_PEM_3');
+(102, 102, NOW() - INTERVAL '2 days', NOW() - INTERVAL '1 day', 200, 'homeserver_signature_3', 'PLACEHOLDER_CERT_PEM_3'),
+-- Valid home server certificate for example.com
+(110, 100, NOW() - INTERVAL '1 day', NOW() + INTERVAL '30 days', 200, 'homeserver_signature_10', 'PLACEHOLDER_HOMESERVER_CERT_PEM_1'),
+-- Valid home server certificate for test.org
+(111, 101, NOW() - INTERVAL '1 day', NOW() + INTERVAL '30 days', 201, 'homeserver_signature_11', 'PLACEHOLDER_HOMESERVER_CERT_PEM_2');
@@ -0,0 +1,11 @@
+-- Fixture for invite link testing scenarios
+-- Builds on local_actor_tests.sql, which has to be loaded first
+
+-- Invite links owned by alice
+INSERT INTO invite_links (invite_link_owner, usages_current, usages_maximum, invite, invalid) VALUES
+-- Unused, can be used twice
+('00000000-0000-0000-0000-000000000001', 0, 2, 'INVITE0000000001', FALSE),
+-- Unused, can be used once
+('00000000-0000-0000-0000-000000000001', 0, 1, 'INVITE0000000002', FALSE),
+-- Used up
+('00000000-0000-0000-0000-000000000001', 1, 1, 'INVITE0000000003', FALSE);
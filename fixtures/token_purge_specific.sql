@@ -0,0 +1,13 @@
+-- Specific fixture for purging expired tokens
+-- This builds on tokens_base_fixture.sql and only adds the token-specific data
+
+INSERT INTO user_tokens (token_hash, cert_id, uaid, valid_not_after) VALUES
+('expired_token_hash_1', 1, '00000000-0000-0000-0000-000000000001', NOW() + INTERVAL '1 hour'),
+('expired_token_hash_2', 2, '00000000-0000-0000-0000-000000000002', NOW() + INTERVAL '1 hour'),
+('valid_token_hash_1', 5, '00000000-0000-0000-0000-000000000001', NOW() + INTERVAL '1 hour'),
+('never_expiring_token_hash_4', 4, '00000000-0000-0000-0000-000000000004', NULL);
+
+-- Inserting into user_tokens deletes expired tokens, so they have to be expired
+-- afterwards
+UPDATE user_tokens SET valid_not_after = NOW() - INTERVAL '1 hour'
+WHERE token_hash IN ('expired_token_hash_1', 'expired_token_hash_2');
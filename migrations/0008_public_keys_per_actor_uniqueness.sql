@@ -0,0 +1,7 @@
+-- Whether the same public key may be registered by more than one actor is a configuration choice
+-- (`security.enforce_globally_unique_keys`) enforced by sonata. The database only guarantees, that
+-- an actor does not register the same key twice. Keys without an actor, such as those of foreign
+-- actors and home servers, are deduplicated as well, as NULLs are not distinct in this constraint.
+ALTER TABLE public_keys DROP CONSTRAINT IF EXISTS public_keys_pubkey_key;
+ALTER TABLE public_keys DROP CONSTRAINT IF EXISTS public_keys_uaid_pubkey_key;
+ALTER TABLE public_keys ADD CONSTRAINT public_keys_uaid_pubkey_key UNIQUE NULLS NOT DISTINCT (uaid, pubkey);
@@ -0,0 +1,4 @@
+ALTER TABLE user_tokens ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT now();
+ALTER TABLE user_tokens ADD COLUMN last_seen TIMESTAMP NULL;
+
+COMMENT ON COLUMN user_tokens.last_seen IS 'When this token was last used to successfully authenticate a request.';
@@ -0,0 +1,5 @@
+-- Keys created before this migration keep a NULL creation timestamp, as it is unknown.
+ALTER TABLE api_keys ADD COLUMN created_at TIMESTAMP NULL;
+ALTER TABLE api_keys ALTER COLUMN created_at SET DEFAULT now();
+
+COMMENT ON COLUMN api_keys.created_at IS 'When this API key was created. NULL for keys created before this column existed.';
@@ -0,0 +1,7 @@
+ALTER TABLE local_actors ADD COLUMN display_name TEXT NULL;
+ALTER TABLE local_actors ADD COLUMN locale TEXT NULL;
+ALTER TABLE local_actors ADD COLUMN recovery_email TEXT NULL;
+
+COMMENT ON COLUMN local_actors.display_name IS 'Name the actor prefers to be displayed with, instead of the local name.';
+COMMENT ON COLUMN local_actors.locale IS 'BCP 47 language tag of the language the actor prefers.';
+COMMENT ON COLUMN local_actors.recovery_email IS 'E-Mail address the actor can be contacted at to recover their account.';
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS failed_login_attempts (
+    uaid UUID PRIMARY KEY REFERENCES local_actors (uaid) ON DELETE CASCADE,
+    consecutive_failures INT NOT NULL DEFAULT 0,
+    locked_until TIMESTAMP NULL
+);
+
+COMMENT ON TABLE failed_login_attempts IS 'Consecutive failed logins per local actor, used to temporarily lock accounts.';
+COMMENT ON COLUMN failed_login_attempts.locked_until IS 'Until when logins to the account are rejected. NULL, if the account has not been locked.';
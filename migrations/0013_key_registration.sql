@@ -0,0 +1,12 @@
+ALTER TABLE local_actors ALTER COLUMN password_hash DROP NOT NULL;
+
+COMMENT ON COLUMN local_actors.password_hash IS 'Argon2 hash of the password of the actor. NULL, if the actor has registered with a public key instead, and can only log in with it.';
+
+CREATE TABLE IF NOT EXISTS key_login_challenges (
+    id BIGSERIAL PRIMARY KEY,
+    uaid UUID NOT NULL REFERENCES local_actors (uaid) ON DELETE CASCADE,
+    challenge VARCHAR(64) UNIQUE NOT NULL,
+    expires TIMESTAMP NOT NULL
+);
+
+COMMENT ON TABLE key_login_challenges IS 'Challenges issued to local actors logging in with a public key. Each challenge can be signed and used to log in once, before it expires.';
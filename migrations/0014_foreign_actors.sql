@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS foreign_actors (
+    uaid UUID PRIMARY KEY REFERENCES actors (uaid) ON DELETE CASCADE,
+    federation_id TEXT UNIQUE NOT NULL,
+    domain TEXT NOT NULL,
+    idcert_pem TEXT NOT NULL,
+    cached_at TIMESTAMP NOT NULL
+);
+
+COMMENT ON TABLE foreign_actors IS 'Actors from other home servers, which have been discovered through federation. Their public keys are stored in public_keys without a uaid.';
+COMMENT ON COLUMN foreign_actors.cached_at IS 'When the cached ID-Cert has last been fetched from the home server of the actor.';
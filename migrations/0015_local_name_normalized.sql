@@ -0,0 +1,6 @@
+-- Not unique, as names differing only in case can exist, unless case-insensitive local names are
+-- enabled in the configuration.
+ALTER TABLE local_actors ADD COLUMN local_name_normalized TEXT GENERATED ALWAYS AS (lower(local_name)) STORED;
+CREATE INDEX local_actors_local_name_normalized_idx ON local_actors (local_name_normalized);
+
+COMMENT ON COLUMN local_actors.local_name_normalized IS 'The lowercased local_name, for matching local names case-insensitively.';
@@ -0,0 +1,5 @@
+ALTER TABLE api_keys ADD COLUMN revoked BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE api_keys ADD COLUMN expires_at TIMESTAMP NULL;
+
+COMMENT ON COLUMN api_keys.revoked IS 'Whether this API key has been revoked. Revoked keys do not authenticate anymore.';
+COMMENT ON COLUMN api_keys.expires_at IS 'When this API key stops authenticating. NULL for keys which do not expire.';
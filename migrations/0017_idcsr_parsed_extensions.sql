@@ -0,0 +1,12 @@
+-- ID-CSRs stored before this migration keep NULL in these columns, as their extensions have not
+-- been parsed. The raw extensions remain in the extensions column.
+ALTER TABLE idcsr ADD COLUMN key_usages TEXT[] NULL;
+ALTER TABLE idcsr ADD COLUMN basic_constraints_ca BOOLEAN NULL;
+ALTER TABLE idcsr ADD COLUMN basic_constraints_path_length BIGINT NULL;
+ALTER TABLE idcsr ADD COLUMN unrecognized_extensions TEXT[] NULL;
+CREATE INDEX idcsr_key_usages_idx ON idcsr USING GIN (key_usages);
+
+COMMENT ON COLUMN idcsr.key_usages IS 'Names of the key usages of the keyUsage extension, such as digital_signature. NULL, if the extensions have not been parsed.';
+COMMENT ON COLUMN idcsr.basic_constraints_ca IS 'The ca flag of the basicConstraints extension. NULL, if the extension is absent or the extensions have not been parsed.';
+COMMENT ON COLUMN idcsr.basic_constraints_path_length IS 'The path length of the basicConstraints extension, if any.';
+COMMENT ON COLUMN idcsr.unrecognized_extensions IS 'Object identifiers of the extensions, which are only stored in the raw extensions column. NULL, if the extensions have not been parsed.';
@@ -0,0 +1,4 @@
+-- A session ID identifies one client of an actor. The ID-Certs of a client change over time, but
+-- only one ID-Cert, which has not been invalidated, may exist per session of an actor.
+CREATE UNIQUE INDEX IF NOT EXISTS idcsr_uaid_session_id_active_idx ON idcsr (uaid, session_id)
+WHERE invalidation_info IS NULL;
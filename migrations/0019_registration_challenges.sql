@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS registration_challenges (
+    id BIGSERIAL PRIMARY KEY,
+    challenge VARCHAR(64) UNIQUE NOT NULL,
+    expires TIMESTAMP NOT NULL
+);
+
+COMMENT ON TABLE registration_challenges IS 'Challenges issued to clients registering with a public key. Each challenge can be signed as part of the registration proof and used to register once, before it expires.';
@@ -0,0 +1,338 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use argon2::password_hash::PasswordHash;
+use poem::{
+    IntoResponse, Response, handler,
+    http::StatusCode,
+    web::{Data, Json, Path},
+};
+use sqlx::types::Uuid;
+
+use crate::{
+    api::{
+        admin::models::{
+            ActorListSchema, ActorSchema, ActorSummarySchema, ImportActorSchema, PublicKeySchema,
+            RenameActorSchema, SetDeactivatedSchema,
+        },
+        extractors::PaginationParams,
+    },
+    config::ReloadableConfigHandle,
+    database::{Actor, Database, DeletionImpact, LocalActor, PublicKeyInfo},
+    errors::{Context, Errcode, Error},
+};
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// List the local actors of this server, including deactivated ones, ordered
+/// by when they joined and paginated using [PaginationParams]. The total
+/// number of local actors is included, so that admins can tell how many
+/// pages there are.
+pub(super) async fn list_actors(
+    Data(db): Data<&Database>,
+    pagination: PaginationParams,
+) -> Result<Json<ActorListSchema>, Error> {
+    Ok(Json(ActorListSchema {
+        total: LocalActor::count(db).await?,
+        actors: LocalActor::list(db, pagination.limit, pagination.offset)
+            .await?
+            .into_iter()
+            .map(ActorSummarySchema::from)
+            .collect(),
+    }))
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Look up the actor with the unique actor identifier `uaid`, which can be a
+/// local or a foreign one.
+pub(super) async fn get_actor(
+    Path(uaid): Path<String>,
+    Data(db): Data<&Database>,
+) -> Result<Json<ActorSchema>, Error> {
+    let uaid = parse_uaid(&uaid)?;
+    match Actor::by_uaid(db, &uaid).await? {
+        Some(actor) => Ok(Json(ActorSchema::from(actor))),
+        None => Err(Error::new_not_found_error(Some("There is no actor with this uaid"))),
+    }
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Count what would be deleted along with the local actor with the unique
+/// actor identifier `uaid`, so that an admin can confirm the deletion.
+pub(super) async fn get_deletion_impact(
+    Path(uaid): Path<String>,
+    Data(db): Data<&Database>,
+) -> Result<Json<DeletionImpact>, Error> {
+    Ok(Json(LocalActor::deletion_impact(db, &parse_uaid(&uaid)?).await?))
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// List the public keys registered for the actor with the unique actor
+/// identifier `uaid`, along with the names of their algorithms. The list is
+/// empty, if no such actor exists.
+pub(super) async fn list_public_keys(
+    Path(uaid): Path<String>,
+    Data(db): Data<&Database>,
+) -> Result<Json<Vec<PublicKeySchema>>, Error> {
+    let keys = PublicKeyInfo::get_by_with_algorithm(db, Some(parse_uaid(&uaid)?), None, None, None)
+        .await?;
+    Ok(Json(keys.into_iter().map(PublicKeySchema::from).collect()))
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Deactivate or reactivate the account of the local actor with the unique
+/// actor identifier `uaid`. Deactivated actors can neither log in nor use their
+/// existing tokens.
+pub(super) async fn set_deactivated(
+    Path(uaid): Path<String>,
+    Json(payload): Json<SetDeactivatedSchema>,
+    Data(db): Data<&Database>,
+) -> Result<impl IntoResponse, Error> {
+    LocalActor::set_deactivated(db, &parse_uaid(&uaid)?, payload.deactivated).await?;
+    Ok(Response::builder().status(StatusCode::NO_CONTENT).finish())
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Change the local name of the local actor with the unique actor identifier
+/// `uaid`, and return the renamed actor. See [LocalActor::rename] for which
+/// names are accepted.
+pub(super) async fn rename(
+    Path(uaid): Path<String>,
+    Json(payload): Json<RenameActorSchema>,
+    Data(db): Data<&Database>,
+    Data(reloadable_config): Data<&ReloadableConfigHandle>,
+) -> Result<Json<ActorSummarySchema>, Error> {
+    let case_insensitive = reloadable_config.current().security.case_insensitive_local_names;
+    let actor =
+        LocalActor::rename(db, &parse_uaid(&uaid)?, &payload.local_name, case_insensitive).await?;
+    Ok(Json(ActorSummarySchema::from(actor)))
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Import a local actor with a given unique actor identifier and password hash,
+/// for example when migrating from another installation. The imported actor
+/// is returned with `201 Created`.
+pub(super) async fn import_actor(
+    Json(payload): Json<ImportActorSchema>,
+    Data(db): Data<&Database>,
+    Data(reloadable_config): Data<&ReloadableConfigHandle>,
+) -> Result<impl IntoResponse, Error> {
+    if PasswordHash::new(&payload.password_hash).is_err() {
+        return Err(Error::new(
+            Errcode::IllegalInput,
+            Some(Context::new(
+                Some("passwordHash"),
+                None,
+                Some("An argon2 password hash in the PHC string format"),
+                None,
+            )),
+        ));
+    }
+    let case_insensitive = reloadable_config.current().security.case_insensitive_local_names;
+    let actor = LocalActor::create_with_uaid(
+        db,
+        payload.uaid,
+        &payload.local_name,
+        &payload.password_hash,
+        case_insensitive,
+    )
+    .await?;
+    Ok(Json(ActorSummarySchema::from(actor)).with_status(StatusCode::CREATED))
+}
+
+/// Parse the unique actor identifier `uaid` from a request path.
+///
+/// ## Errors
+///
+/// [Errcode::IllegalInput], if `uaid` is not a UUID.
+#[allow(clippy::result_large_err)]
+fn parse_uaid(uaid: &str) -> Result<Uuid, Error> {
+    Uuid::parse_str(uaid).map_err(|_| {
+        Error::new(
+            Errcode::IllegalInput,
+            Some(Context::new(Some("uaid"), Some(uaid), Some("A UUID"), None)),
+        )
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, test::TestClient};
+    use sqlx::{Pool, Postgres};
+
+    use super::*;
+
+    #[sqlx::test(fixtures("../../../fixtures/local_actor_tests.sql"))]
+    async fn test_list_actors(pool: Pool<Postgres>) {
+        let client = TestClient::new(super::super::setup_routes().data(Database { pool }));
+
+        let response = client.get("/actors").query("limit", &2).query("offset", &1).send().await;
+        response.assert_status_is_ok();
+        let json = response.json().await;
+        let page = json.value().object();
+        page.get("total").assert_i64(5);
+        let actors = page.get("actors").array();
+        actors.assert_len(2);
+        actors.get(0).object().get("localName").assert_string("bob");
+        actors
+            .get(0)
+            .object()
+            .get("uniqueActorIdentifier")
+            .assert_string("00000000-0000-0000-0000-000000000002");
+        actors.get(1).object().get("localName").assert_string("charlie");
+
+        client
+            .get("/actors")
+            .query("limit", &0)
+            .send()
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/local_actor_tests.sql"))]
+    async fn test_get_actor(pool: Pool<Postgres>) {
+        let client = TestClient::new(super::super::setup_routes().data(Database { pool }));
+
+        let response = client.get("/actors/00000000-0000-0000-0000-000000000001").send().await;
+        response.assert_status_is_ok();
+        response
+            .assert_json(&serde_json::json!({
+                "uniqueActorIdentifier": "00000000-0000-0000-0000-000000000001",
+                "actorType": "local",
+            }))
+            .await;
+
+        client
+            .get("/actors/00000000-0000-0000-0000-00000000dead")
+            .send()
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+        client.get("/actors/alice").send().await.assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_get_deletion_impact(pool: Pool<Postgres>) {
+        let client = TestClient::new(super::super::setup_routes().data(Database { pool }));
+
+        let response =
+            client.get("/actors/00000000-0000-0000-0000-000000001001/deletion-impact").send().await;
+        response.assert_status_is_ok();
+        let json = response.json().await;
+        let impact = json.value().object();
+        impact.get("publicKeys").assert_i64(1);
+        impact.get("certs").assert_i64(1);
+
+        client
+            .get("/actors/00000000-0000-0000-0000-00000000dead/deletion-impact")
+            .send()
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/local_actor_tests.sql"))]
+    async fn test_set_deactivated(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let client = TestClient::new(super::super::setup_routes().data(db.clone()));
+        let uaid = Uuid::from_u128(1);
+        let request = |uaid: Uuid, deactivated: bool| {
+            client
+                .put(format!("/actors/{uaid}/deactivated"))
+                .body_json(&serde_json::json!({ "deactivated": deactivated }))
+                .send()
+        };
+
+        request(uaid, true).await.assert_status(StatusCode::NO_CONTENT);
+        assert!(LocalActor::by_uaid(&db, &uaid).await.unwrap().unwrap().is_deactivated);
+        request(uaid, false).await.assert_status(StatusCode::NO_CONTENT);
+        assert!(!LocalActor::by_uaid(&db, &uaid).await.unwrap().unwrap().is_deactivated);
+
+        request(Uuid::from_u128(0xdead), true).await.assert_status(StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/local_actor_tests.sql"))]
+    async fn test_rename(pool: Pool<Postgres>) {
+        let client = TestClient::new(
+            super::super::setup_routes()
+                .data(Database { pool })
+                .data(ReloadableConfigHandle::default()),
+        );
+        let request = |uaid: Uuid, local_name: &str| {
+            client
+                .put(format!("/actors/{uaid}/local-name"))
+                .body_json(&serde_json::json!({ "localName": local_name }))
+                .send()
+        };
+
+        let response = request(Uuid::from_u128(1), "alicia").await;
+        response.assert_status_is_ok();
+        response.json().await.value().object().get("localName").assert_string("alicia");
+
+        request(Uuid::from_u128(2), "alicia").await.assert_status(StatusCode::CONFLICT);
+        request(Uuid::from_u128(2), " ").await.assert_status(StatusCode::BAD_REQUEST);
+        request(Uuid::from_u128(0xdead), "dead").await.assert_status(StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/local_actor_tests.sql"))]
+    async fn test_import_actor(pool: Pool<Postgres>) {
+        let client = TestClient::new(
+            super::super::setup_routes()
+                .data(Database { pool: pool.clone() })
+                .data(ReloadableConfigHandle::default()),
+        );
+        let password_hash = "$argon2id$v=19$m=19456,t=2,p=1$c29tZXNhbHQ$6S2kq4dPgJqgJbWy0DOhuFVOhbWhA5U3C6jz9gZo0gQ";
+        let request = |uaid: Uuid, local_name: &str, password_hash: &str| {
+            client
+                .post("/actors/import")
+                .body_json(&serde_json::json!({
+                    "uaid": uaid.to_string(),
+                    "localName": local_name,
+                    "passwordHash": password_hash,
+                }))
+                .send()
+        };
+
+        let response = request(Uuid::from_u128(0x10), "imported", password_hash).await;
+        response.assert_status(StatusCode::CREATED);
+        response.json().await.value().object().get("localName").assert_string("imported");
+        let db = Database { pool };
+        assert!(LocalActor::by_local_name(&db, "imported", false).await.unwrap().is_some());
+
+        request(Uuid::from_u128(1), "someone", password_hash)
+            .await
+            .assert_status(StatusCode::CONFLICT);
+        request(Uuid::from_u128(0x11), "alice", password_hash)
+            .await
+            .assert_status(StatusCode::CONFLICT);
+        request(Uuid::from_u128(0x12), "other", "hunter2")
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_list_public_keys(pool: Pool<Postgres>) {
+        let client = TestClient::new(super::super::setup_routes().data(Database { pool }));
+
+        let response = client.get("/actors/00000000-0000-0000-0000-000000001001/keys").send().await;
+        response.assert_status_is_ok();
+        response
+            .assert_json(&serde_json::json!([{
+                "id": 1001,
+                "pubkey": "full_state_pubkey_alice",
+                "algorithmIdentifier": 1000,
+                "algorithmName": "Edwards-curve Digital Signature Algorithm (EdDSA) Ed25519",
+            }]))
+            .await;
+
+        let response = client.get("/actors/00000000-0000-0000-0000-00000000dead/keys").send().await;
+        response.assert_status_is_ok();
+        response.assert_json(&serde_json::json!([])).await;
+    }
+}
@@ -0,0 +1,48 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use poem::{
+    handler,
+    web::{Data, Json},
+};
+
+use crate::{
+    api::admin::models::AlgorithmSchema,
+    database::{AlgorithmIdentifier, Database},
+    errors::Error,
+};
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// List all entries of the `algorithm_identifiers` table, ordered by their ID.
+/// See [AlgorithmIdentifier::list_all].
+pub(super) async fn list_algorithms(
+    Data(db): Data<&Database>,
+) -> Result<Json<Vec<AlgorithmSchema>>, Error> {
+    let algorithms = AlgorithmIdentifier::list_all(db).await?;
+    Ok(Json(algorithms.into_iter().map(AlgorithmSchema::from).collect()))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, test::TestClient};
+    use sqlx::{Pool, Postgres};
+
+    use super::*;
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_list_algorithms(pool: Pool<Postgres>) {
+        let client = TestClient::new(super::super::setup_routes().data(Database { pool }));
+
+        let response = client.get("/algorithms").send().await;
+        response.assert_status_is_ok();
+        let json = response.json().await;
+        let algorithms = json.value().array();
+        algorithms.assert_len(1);
+        let ed25519 = algorithms.get(0).object();
+        ed25519.get("id").assert_i64(1000);
+        ed25519.get("algorithmIdentifier").assert_string("1.3.101.112");
+    }
+}
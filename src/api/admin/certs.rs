@@ -0,0 +1,144 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::str::FromStr;
+
+use poem::{
+    handler,
+    web::{Data, Json, Path, Query},
+};
+use serde::Deserialize;
+
+use crate::{
+    api::admin::models::{IdCsrSchema, IssuerSchema},
+    database::{Database, Issuer, SerialNumber, StoredIdCsr, key_usage_by_name},
+    errors::{Context, Errcode, Error},
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// Query parameters of [list_idcsrs].
+pub(super) struct IdCsrQuery {
+    /// The name of a key usage, such as `key_cert_sign`, which the `keyUsage`
+    /// extension of the listed ID-CSRs contains.
+    key_usage: String,
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Get the issuer with the ID `id` in the `issuers` table.
+pub(super) async fn get_issuer(
+    Path(id): Path<i64>,
+    Data(db): Data<&Database>,
+) -> Result<Json<IssuerSchema>, Error> {
+    match Issuer::get_by_id(db, id).await? {
+        Some(issuer) => Ok(Json(IssuerSchema::from(issuer))),
+        None => Err(Error::new_not_found_error(Some("No issuer with this ID exists"))),
+    }
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Get the ID-CSR, for which the ID-Cert with the decimal `serial_number` has
+/// been issued.
+pub(super) async fn get_idcsr(
+    Path(serial_number): Path<String>,
+    Data(db): Data<&Database>,
+) -> Result<Json<IdCsrSchema>, Error> {
+    match StoredIdCsr::by_serial(db, &SerialNumber::from_str(&serial_number)?).await? {
+        Some(csr) => Ok(Json(IdCsrSchema::from(csr))),
+        None => Err(Error::new_not_found_error(Some("No ID-CSR with this serial number exists"))),
+    }
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// List the ID-CSRs, whose `keyUsage` extension contains the `keyUsage` of the
+/// query, ordered by their ID. See [StoredIdCsr::by_key_usage].
+pub(super) async fn list_idcsrs(
+    Query(query): Query<IdCsrQuery>,
+    Data(db): Data<&Database>,
+) -> Result<Json<Vec<IdCsrSchema>>, Error> {
+    let Some(key_usage) = key_usage_by_name(&query.key_usage) else {
+        return Err(Error::new(
+            Errcode::IllegalInput,
+            Some(Context::new(
+                Some("keyUsage"),
+                Some(&query.key_usage),
+                Some("The name of a key usage, such as key_cert_sign"),
+                None,
+            )),
+        ));
+    };
+    let csrs = StoredIdCsr::by_key_usage(db, key_usage).await?;
+    Ok(Json(csrs.into_iter().map(IdCsrSchema::from).collect()))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, http::StatusCode, test::TestClient};
+    use sqlx::{Pool, Postgres, query};
+
+    use super::*;
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_get_issuer(pool: Pool<Postgres>) {
+        let client = TestClient::new(super::super::setup_routes().data(Database { pool }));
+
+        let response = client.get("/issuers/1000").send().await;
+        response.assert_status_is_ok();
+        response
+            .assert_json(&serde_json::json!({ "id": 1000, "domain": "full.example.com" }))
+            .await;
+
+        client.get("/issuers/1001").send().await.assert_status(StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_get_idcsr(pool: Pool<Postgres>) {
+        let client = TestClient::new(super::super::setup_routes().data(Database { pool }));
+
+        let response = client.get("/idcsrs/90000000000000001002").send().await;
+        response.assert_status_is_ok();
+        let json = response.json().await;
+        let csr = json.value().object();
+        csr.get("id").assert_i64(1002);
+        csr.get("serialNumber").assert_string("90000000000000001002");
+        csr.get("uniqueActorIdentifier").assert_string("00000000-0000-0000-0000-000000001002");
+        csr.get("sessionId").assert_string("full_state_session_bob");
+        csr.get("invalidated").assert_bool(false);
+
+        client.get("/idcsrs/1").send().await.assert_status(StatusCode::NOT_FOUND);
+        client.get("/idcsrs/0x1002").send().await.assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_list_idcsrs(pool: Pool<Postgres>) {
+        query!(
+            "UPDATE idcsr SET key_usages = ARRAY['digital_signature'], unrecognized_extensions = '{}'
+            WHERE id = 1001"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let client = TestClient::new(super::super::setup_routes().data(Database { pool }));
+        let request = |key_usage: &str| client.get("/idcsrs").query("keyUsage", &key_usage).send();
+
+        let response = request("digital_signature").await;
+        response.assert_status_is_ok();
+        let json = response.json().await;
+        let csrs = json.value().array();
+        csrs.assert_len(1);
+        let csr = csrs.get(0).object();
+        csr.get("id").assert_i64(1001);
+        csr.get("keyUsages").array().get(0).assert_string("digital_signature");
+
+        let response = request("key_cert_sign").await;
+        response.assert_status_is_ok();
+        response.assert_json(&serde_json::json!([])).await;
+
+        request("digitalSignature").await.assert_status(StatusCode::BAD_REQUEST);
+    }
+}
@@ -6,23 +6,88 @@ use rand::{Rng, distr::Alphanumeric};
 use sqlx::{query_as, types::Uuid};
 
 use crate::{
+    config::SecurityConfig,
     database::{Database, Invite},
-    errors::Error,
+    errors::{Context, Errcode, Error},
 };
 
-/// Create an invite.
-#[cfg_attr(coverage_nightly, coverage(off))]
+/// Length of auto-generated invite codes.
+const GENERATED_INVITE_CODE_LENGTH: usize = 16;
+/// The maximum length of invite codes the `invite_links` table can store.
+const INVITE_CODE_COLUMN_LENGTH: usize = 16;
+/// The minimum length of custom invite codes.
+const MIN_INVITE_CODE_LENGTH: usize = 8;
+/// Characters, which custom invite codes may contain next to ASCII letters and
+/// digits.
+const INVITE_CODE_SYMBOLS: &[char] = &['-', '_'];
+
+/// Check, that a custom invite `code` is between [MIN_INVITE_CODE_LENGTH] and
+/// the configured maximum length, and only consists of ASCII letters, digits
+/// and [INVITE_CODE_SYMBOLS].
+#[allow(clippy::result_large_err)]
+fn validate_invite_code(code: &str, security_config: &SecurityConfig) -> Result<(), Error> {
+    let max_length = security_config.max_invite_code_length.min(INVITE_CODE_COLUMN_LENGTH);
+    if !(MIN_INVITE_CODE_LENGTH..=max_length).contains(&code.chars().count()) {
+        return Err(Error::new(
+            Errcode::IllegalInput,
+            Some(Context::new(
+                Some("code"),
+                Some(&format!("{} characters", code.chars().count())),
+                Some(&format!("Between {MIN_INVITE_CODE_LENGTH} and {max_length} characters")),
+                None,
+            )),
+        ));
+    }
+    if !code.chars().all(|c| c.is_ascii_alphanumeric() || INVITE_CODE_SYMBOLS.contains(&c)) {
+        return Err(Error::new(
+            Errcode::IllegalInput,
+            Some(Context::new(
+                Some("code"),
+                Some(code),
+                Some("Only ASCII letters, digits, \"-\" and \"_\""),
+                None,
+            )),
+        ));
+    }
+    Ok(())
+}
+
+/// Create an invite, which can be used `uses_max` times. Custom `code`s are
+/// validated using the [SecurityConfig], auto-generated codes consist of 16
+/// alphanumeric characters.
+///
+/// ## Errors
+///
+/// [Errcode::IllegalInput], if `uses_max` is less than 1 or `code` is not a
+/// valid invite code.
 pub(super) async fn create_invite(
     owner: Option<&Uuid>,
     code: Option<&str>,
     uses_max: i32,
     db: &Database,
+    security_config: &SecurityConfig,
 ) -> Result<Invite, Error> {
+    if uses_max < 1 {
+        return Err(Error::new(
+            Errcode::IllegalInput,
+            Some(Context::new(
+                Some("usesMax"),
+                Some(&uses_max.to_string()),
+                Some("At least 1"),
+                None,
+            )),
+        ));
+    }
     let code = {
         if let Some(code) = code {
+            validate_invite_code(code, security_config)?;
             code
         } else {
-            &rand::rng().sample_iter(&Alphanumeric).take(16).map(char::from).collect::<String>()
+            &rand::rng()
+                .sample_iter(&Alphanumeric)
+                .take(GENERATED_INVITE_CODE_LENGTH)
+                .map(char::from)
+                .collect::<String>()
         }
     };
     Ok(query_as!(
@@ -36,6 +101,7 @@ pub(super) async fn create_invite(
         )
         VALUES ($1, 0, $2, $3, $4)
         RETURNING
+            id,
             invite_link_owner,
             usages_current,
             usages_maximum,
@@ -49,3 +115,87 @@ pub(super) async fn create_invite(
     .fetch_one(&db.pool)
     .await?)
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use sqlx::{Pool, Postgres};
+
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_create_invite_valid_custom_code(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        let invite =
+            create_invite(None, Some("Custom-Code_42"), 3, &db, &SecurityConfig::default())
+                .await
+                .unwrap();
+        assert_eq!(invite.invite_code, "Custom-Code_42");
+        assert_eq!(invite.usages_maximum, 3);
+    }
+
+    #[sqlx::test]
+    async fn test_create_invite_generated_code(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        let invite = create_invite(None, None, 1, &db, &SecurityConfig::default()).await.unwrap();
+        assert_eq!(invite.invite_code.len(), GENERATED_INVITE_CODE_LENGTH);
+        assert!(invite.invite_code.chars().all(|c| c.is_ascii_alphanumeric()));
+    }
+
+    #[sqlx::test]
+    async fn test_create_invite_overlong_code(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        let code = "a".repeat(10_000);
+        let error =
+            create_invite(None, Some(&code), 1, &db, &SecurityConfig::default()).await.unwrap_err();
+        assert_eq!(error.code, Errcode::IllegalInput);
+
+        // The configured maximum applies, if it is stricter than the column length
+        let security_config = SecurityConfig { max_invite_code_length: 10, ..Default::default() };
+        let error =
+            create_invite(None, Some("abcdefghijk"), 1, &db, &security_config).await.unwrap_err();
+        assert_eq!(error.code, Errcode::IllegalInput);
+        assert_eq!(error.context.unwrap().expected, "Between 8 and 10 characters");
+    }
+
+    #[sqlx::test]
+    async fn test_create_invite_short_code(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        let error = create_invite(None, Some("abcdefg"), 1, &db, &SecurityConfig::default())
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, Errcode::IllegalInput);
+        assert_eq!(error.context.unwrap().found, "7 characters");
+        create_invite(None, Some("abcdefgh"), 1, &db, &SecurityConfig::default()).await.unwrap();
+    }
+
+    #[sqlx::test]
+    async fn test_create_invite_without_usages(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        for uses_max in [0, -1] {
+            let error = create_invite(None, None, uses_max, &db, &SecurityConfig::default())
+                .await
+                .unwrap_err();
+            assert_eq!(error.code, Errcode::IllegalInput);
+            assert_eq!(error.context.unwrap().field_name, "usesMax");
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_create_invite_disallowed_characters(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        for code in ["with space", "tab\tcode", "emoji😀code", "semi;colon"] {
+            let error = create_invite(None, Some(code), 1, &db, &SecurityConfig::default())
+                .await
+                .unwrap_err();
+            assert_eq!(error.code, Errcode::IllegalInput);
+            assert_eq!(error.context.unwrap().field_name, "code");
+        }
+    }
+}
@@ -0,0 +1,83 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::sync::Arc;
+
+use poem::{
+    IntoResponse, Response, handler,
+    http::StatusCode,
+    web::{Data, Json},
+};
+
+use crate::{
+    api::admin::models::AnnouncementSchema,
+    errors::{Context, Errcode, Error},
+    gateway::presence::{GatewayEvent, Hub},
+};
+
+/// How many characters an announcement may have at most.
+const MAX_ANNOUNCEMENT_LEN: usize = 2000;
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Send an announcement, such as a maintenance warning, to all clients
+/// connected to the gateway.
+#[allow(clippy::result_large_err)]
+pub(super) fn announce(
+    Json(payload): Json<AnnouncementSchema>,
+    Data(hub): Data<&Arc<Hub>>,
+) -> Result<impl IntoResponse, Error> {
+    if payload.message.trim().is_empty() || payload.message.chars().count() > MAX_ANNOUNCEMENT_LEN {
+        return Err(Error::new(
+            Errcode::IllegalInput,
+            Some(Context::new(
+                Some("message"),
+                None,
+                Some("1 to 2000 characters, not only whitespace"),
+                None,
+            )),
+        ));
+    }
+    hub.broadcast(GatewayEvent::Announcement { message: payload.message });
+    Ok(Response::builder().status(StatusCode::NO_CONTENT).finish())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, test::TestClient};
+    use serde_json::json;
+    use tokio::sync::broadcast::error::TryRecvError;
+
+    use super::*;
+    use crate::config::GatewayConfig;
+
+    #[tokio::test]
+    async fn test_announce() {
+        let hub = Arc::new(Hub::new(&GatewayConfig::for_tests()));
+        let mut events = hub.subscribe();
+        let client = TestClient::new(super::super::setup_routes().data(hub));
+
+        client
+            .post("/gateway/announce")
+            .body_json(&json!({"message": "Maintenance at 10:00"}))
+            .send()
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        assert_eq!(
+            events.try_recv().unwrap(),
+            GatewayEvent::Announcement { message: "Maintenance at 10:00".to_owned() }
+        );
+
+        for message in [String::from(" "), "a".repeat(MAX_ANNOUNCEMENT_LEN + 1)] {
+            client
+                .post("/gateway/announce")
+                .body_json(&json!({ "message": message }))
+                .send()
+                .await
+                .assert_status(StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(events.try_recv(), Err(TryRecvError::Empty));
+    }
+}
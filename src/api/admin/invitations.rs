@@ -2,8 +2,76 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use poem::{IntoResponse, handler};
+use poem::{
+    IntoResponse, handler,
+    http::StatusCode,
+    web::{Data, Json},
+};
+
+use crate::{
+    api::admin::{db, models::CreateInviteSchema},
+    config::ReloadableConfigHandle,
+    database::Database,
+    errors::Error,
+};
 
 #[handler]
-#[cfg_attr(coverage_nightly, coverage(off))]
-pub(crate) async fn create_invite() -> impl IntoResponse {}
+/// Create an invite, which is returned with `201 Created`.
+pub(crate) async fn create_invite(
+    Json(payload): Json<CreateInviteSchema>,
+    Data(database): Data<&Database>,
+    Data(reloadable_config): Data<&ReloadableConfigHandle>,
+) -> Result<impl IntoResponse, Error> {
+    let security_config = &reloadable_config.current().security;
+    let invite = db::create_invite(
+        payload.owner.as_ref(),
+        payload.code.as_deref(),
+        payload.uses_max,
+        database,
+        security_config,
+    )
+    .await?;
+    Ok(Json(invite).with_status(StatusCode::CREATED))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, http::StatusCode, test::TestClient};
+    use serde_json::json;
+    use sqlx::{Pool, Postgres};
+
+    use super::*;
+    use crate::config::{ReloadableConfig, SecurityConfig};
+
+    #[sqlx::test]
+    async fn test_create_invite(pool: Pool<Postgres>) {
+        // Custom invite codes are validated using the injected configuration
+        let reloadable_config = ReloadableConfigHandle::new(ReloadableConfig {
+            security: SecurityConfig { max_invite_code_length: 10, ..Default::default() },
+            ..Default::default()
+        });
+        let client = TestClient::new(
+            super::super::setup_routes().data(Database { pool }).data(reloadable_config),
+        );
+
+        let response = client
+            .post("/invites")
+            .body_json(&json!({"code": "INVITE_01", "usesMax": 2}))
+            .send()
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        let json = response.json().await;
+        let invite = json.value().object();
+        invite.get("inviteCode").assert_string("INVITE_01");
+        invite.get("usagesMaximum").assert_i64(2);
+        invite.get("usagesCurrent").assert_i64(0);
+
+        client
+            .post("/invites")
+            .body_json(&json!({"code": "INVITE_0001", "usesMax": 1}))
+            .send()
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,33 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use poem::{
+    IntoResponse, Response, handler,
+    http::StatusCode,
+    web::{Data, Query},
+};
+use serde::Deserialize;
+
+use crate::{database::Database, errors::Error};
+
+#[derive(Debug, Deserialize)]
+/// Query parameters of [run_maintenance].
+pub(super) struct MaintenanceQuery {
+    /// Whether the tables are vacuumed as well as analyzed. Defaults to
+    /// `false`.
+    #[serde(default)]
+    vacuum: bool,
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Refresh the query planner statistics of the database, and optionally
+/// vacuum it. See [Database::run_maintenance].
+pub(super) async fn run_maintenance(
+    Data(db): Data<&Database>,
+    Query(query): Query<MaintenanceQuery>,
+) -> Result<impl IntoResponse, Error> {
+    db.run_maintenance(query.vacuum).await?;
+    Ok(Response::builder().status(StatusCode::NO_CONTENT).finish())
+}
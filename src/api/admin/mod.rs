@@ -2,5 +2,47 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use poem::{Route, get, post, put};
+
+/// The actor listing, lookup and moderation endpoints
+mod actors;
+/// The algorithm identifier listing endpoint
+mod algorithms;
+/// The issuer and ID-CSR lookup endpoints
+mod certs;
 mod db;
+/// The gateway announcement endpoint
+mod gateway;
 mod invitations;
+/// The database maintenance endpoint
+mod maintenance;
+/// Data models/schemas used for these routes
+mod models;
+/// The server statistics and signup endpoints
+mod stats;
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Route handler for the admin module. Access to these routes is restricted
+/// by the
+/// [AdminIpAllowlistMiddleware](crate::api::middlewares::AdminIpAllowlistMiddleware),
+/// and requires an API key checked by the
+/// [ApiKeyMiddleware](crate::api::middlewares::ApiKeyMiddleware).
+pub(super) fn setup_routes() -> Route {
+    Route::new()
+        .at("/actors", get(actors::list_actors))
+        .at("/actors/import", post(actors::import_actor))
+        .at("/actors/:uaid", get(actors::get_actor))
+        .at("/actors/:uaid/deactivated", put(actors::set_deactivated))
+        .at("/actors/:uaid/deletion-impact", get(actors::get_deletion_impact))
+        .at("/actors/:uaid/keys", get(actors::list_public_keys))
+        .at("/actors/:uaid/local-name", put(actors::rename))
+        .at("/algorithms", get(algorithms::list_algorithms))
+        .at("/gateway/announce", post(gateway::announce))
+        .at("/idcsrs", get(certs::list_idcsrs))
+        .at("/idcsrs/:serial_number", get(certs::get_idcsr))
+        .at("/invites", post(invitations::create_invite))
+        .at("/issuers/:id", get(certs::get_issuer))
+        .at("/maintenance", post(maintenance::run_maintenance))
+        .at("/stats", get(stats::get_stats))
+        .at("/stats/signups", get(stats::get_signups))
+}
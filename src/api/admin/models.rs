@@ -0,0 +1,264 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use serde::{Deserialize, Serialize};
+use sqlx::types::Uuid;
+
+use crate::database::{
+    Actor, ActorType, AlgorithmIdentifier, Issuer, LocalActor, PoolStats, PublicKeyInfo,
+    SerialNumber, StoredIdCsr, key_usage_name,
+};
+
+#[serde_with::serde_as]
+#[derive(PartialEq, Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Information sent to the server by an admin, who wants to create an invite.
+pub struct CreateInviteSchema {
+    #[serde(default)]
+    #[serde_as(as = "Option<serde_with::DisplayFromStr>")]
+    /// Optional: The unique actor identifier of the local actor owning the
+    /// invite.
+    pub owner: Option<Uuid>,
+    /// Optional: A custom invite code. A random one is generated, if this is
+    /// omitted.
+    pub code: Option<String>,
+    /// How often the invite can be used.
+    pub uses_max: i32,
+}
+
+#[derive(PartialEq, Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Information sent to the server by an admin, who wants to make an
+/// announcement to all clients connected to the gateway.
+pub struct AnnouncementSchema {
+    /// The text of the announcement.
+    pub message: String,
+}
+
+#[derive(PartialEq, Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Information sent to the server by an admin, who wants to deactivate or
+/// reactivate the account of a local actor.
+pub struct SetDeactivatedSchema {
+    /// Whether the account should be deactivated.
+    pub deactivated: bool,
+}
+
+#[serde_with::serde_as]
+#[derive(PartialEq, Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Information sent to the server by an admin, who wants to import a local
+/// actor from another installation, keeping its unique actor identifier.
+pub struct ImportActorSchema {
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    /// The unique actor identifier of the imported actor.
+    pub uaid: Uuid,
+    /// The local name of the imported actor.
+    pub local_name: String,
+    /// The argon2 hash of the actor's password, as a PHC string.
+    pub password_hash: String,
+}
+
+#[derive(PartialEq, Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Information sent to the server by an admin, who wants to change the local
+/// name of a local actor.
+pub struct RenameActorSchema {
+    /// The new local name of the actor.
+    pub local_name: String,
+}
+
+#[derive(PartialEq, Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Aggregate statistics about this server, as shown on an admin dashboard.
+pub struct ServerStatsSchema {
+    /// How many local actors exist, including deactivated ones.
+    pub total_actors: i64,
+    /// How many local actors are not deactivated.
+    pub active_actors: i64,
+    /// How many local actors have registered within the last 24 hours.
+    pub registrations_last_24h: i64,
+    /// How many auth tokens are valid.
+    pub active_tokens: i64,
+    /// How many ID-Certs have been issued, including expired ones.
+    pub issued_certs: i64,
+    /// The current state of the database connection pool.
+    pub pool: PoolStats,
+}
+
+#[serde_with::serde_as]
+#[derive(PartialEq, Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+/// A local actor, as listed to an admin.
+pub struct ActorSummarySchema {
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    /// The unique actor identifier of the actor.
+    pub unique_actor_identifier: Uuid,
+    /// The local name of the actor.
+    pub local_name: String,
+    /// Whether the account of the actor is deactivated.
+    pub is_deactivated: bool,
+    /// When the account of the actor has been created.
+    pub joined_at_timestamp: chrono::NaiveDateTime,
+}
+
+impl From<LocalActor> for ActorSummarySchema {
+    fn from(actor: LocalActor) -> Self {
+        Self {
+            unique_actor_identifier: actor.unique_actor_identifier,
+            local_name: actor.local_name,
+            is_deactivated: actor.is_deactivated,
+            joined_at_timestamp: actor.joined_at_timestamp,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+/// A page of the local actors of this server.
+pub struct ActorListSchema {
+    /// How many local actors exist in total, including deactivated ones.
+    pub total: i64,
+    /// The local actors on the requested page, ordered by when they joined.
+    pub actors: Vec<ActorSummarySchema>,
+}
+
+#[serde_with::serde_as]
+#[derive(PartialEq, Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Any actor known to this server, be it local or foreign, as shown to an
+/// admin.
+pub struct ActorSchema {
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    /// The unique actor identifier of the actor.
+    pub unique_actor_identifier: Uuid,
+    /// Whether the actor is registered on this server, or on another one.
+    pub actor_type: ActorType,
+}
+
+impl From<Actor> for ActorSchema {
+    fn from(actor: Actor) -> Self {
+        Self {
+            unique_actor_identifier: actor.unique_actor_identifier,
+            actor_type: actor.actor_type(),
+        }
+    }
+}
+
+#[serde_with::serde_as]
+#[derive(PartialEq, Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+/// A signature or public key algorithm known to this server, as listed to an
+/// admin.
+pub struct AlgorithmSchema {
+    /// The ID of the algorithm in the `algorithm_identifiers` table.
+    pub id: i32,
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    /// The dot-delimited OID of the algorithm.
+    pub algorithm_identifier: polyproto::spki::ObjectIdentifier,
+    /// The human-readable name of the algorithm, if there is one.
+    pub common_name: Option<String>,
+}
+
+impl From<AlgorithmIdentifier> for AlgorithmSchema {
+    fn from(algorithm: AlgorithmIdentifier) -> Self {
+        Self {
+            id: algorithm.id(),
+            algorithm_identifier: algorithm.algorithm_identifier,
+            common_name: algorithm.common_name,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+/// A public key registered for an actor, as listed to an admin.
+pub struct PublicKeySchema {
+    /// The ID of the key in the `public_keys` table.
+    pub id: i64,
+    /// The key, as stored in the `public_keys` table.
+    pub pubkey: String,
+    /// The ID of the algorithm of the key in the `algorithm_identifiers`
+    /// table.
+    pub algorithm_identifier: i32,
+    /// The human-readable name of the algorithm of the key, if there is one.
+    pub algorithm_name: Option<String>,
+}
+
+impl From<(PublicKeyInfo, Option<String>)> for PublicKeySchema {
+    fn from((key, algorithm_name): (PublicKeyInfo, Option<String>)) -> Self {
+        Self {
+            id: key.id(),
+            pubkey: key.pubkey,
+            algorithm_identifier: key.algorithm_identifier,
+            algorithm_name,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+/// An issuer of ID-Certs, as shown to an admin.
+pub struct IssuerSchema {
+    /// The ID of the issuer in the `issuers` table.
+    pub id: i64,
+    /// The domain of the issuer.
+    pub domain: String,
+}
+
+impl From<Issuer> for IssuerSchema {
+    fn from(issuer: Issuer) -> Self {
+        Self { id: issuer.id(), domain: issuer.domain_components.to_string() }
+    }
+}
+
+#[serde_with::serde_as]
+#[derive(PartialEq, Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+/// An ID-CSR stored by this server, as shown to an admin.
+pub struct IdCsrSchema {
+    /// The ID of the ID-CSR, which is also the ID of the ID-Cert issued for it.
+    pub id: i64,
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    /// The serial number of the ID-Cert issued for the ID-CSR.
+    pub serial_number: SerialNumber,
+    #[serde_as(as = "Option<serde_with::DisplayFromStr>")]
+    /// The local actor, which has submitted the ID-CSR, if there is one.
+    pub unique_actor_identifier: Option<Uuid>,
+    /// The session ID of the ID-CSR.
+    pub session_id: String,
+    /// The start of the requested validity period.
+    pub valid_not_before: Option<chrono::NaiveDateTime>,
+    /// The end of the requested validity period.
+    pub valid_not_after: Option<chrono::NaiveDateTime>,
+    /// The names of the key usages of the `keyUsage` extension of the ID-CSR.
+    /// `None`, if the extensions of the ID-CSR have not been parsed.
+    pub key_usages: Option<Vec<String>>,
+    /// Whether the ID-Cert issued for the ID-CSR has been invalidated.
+    pub invalidated: bool,
+    /// The PEM encoding of the ID-CSR.
+    pub pem_encoded: String,
+}
+
+impl From<StoredIdCsr> for IdCsrSchema {
+    fn from(csr: StoredIdCsr) -> Self {
+        Self {
+            id: csr.id,
+            serial_number: csr.serial_number,
+            unique_actor_identifier: csr.uaid,
+            session_id: csr.session_id,
+            valid_not_before: csr.valid_not_before,
+            valid_not_after: csr.valid_not_after,
+            key_usages: csr.parsed_extensions.map(|extensions| {
+                extensions
+                    .key_usages
+                    .into_iter()
+                    .map(|key_usage| key_usage_name(key_usage).to_owned())
+                    .collect()
+            }),
+            invalidated: csr.invalidation_info.is_some(),
+            pem_encoded: csr.pem_encoded,
+        }
+    }
+}
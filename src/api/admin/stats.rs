@@ -0,0 +1,141 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use poem::{
+    handler,
+    web::{Data, Json, Query},
+};
+use serde::Deserialize;
+
+use crate::{
+    api::admin::models::{ActorSummarySchema, ServerStatsSchema},
+    database::{Database, LocalActor, count_issued_idcerts, tokens::TokenStore},
+    errors::Error,
+};
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Get aggregate statistics about this server: How many actors exist and are
+/// active, how many have registered within the last 24 hours, how many tokens
+/// are valid, how many ID-Certs have been issued and the state of the database
+/// connection pool.
+pub(super) async fn get_stats(
+    Data(db): Data<&Database>,
+    Data(token_store): Data<&TokenStore>,
+) -> Result<Json<ServerStatsSchema>, Error> {
+    let now = Utc::now().naive_utc();
+    let day_ago = now.checked_sub_signed(TimeDelta::hours(24)).unwrap_or(now);
+    Ok(Json(ServerStatsSchema {
+        total_actors: LocalActor::count(db).await?,
+        active_actors: LocalActor::count_active(db).await?,
+        registrations_last_24h: LocalActor::count_joined_between(db, day_ago, now).await?,
+        active_tokens: token_store.count_active().await?,
+        issued_certs: count_issued_idcerts(db).await?,
+        pool: db.pool_stats(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+/// Query parameters of [get_signups].
+pub(super) struct SignupsQuery {
+    /// The start of the time interval, inclusive.
+    start: NaiveDateTime,
+    /// The end of the time interval, exclusive.
+    end: NaiveDateTime,
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Get the local actors which have joined in the half-open time interval
+/// `[start, end)`, ordered by when they joined.
+pub(super) async fn get_signups(
+    Data(db): Data<&Database>,
+    Query(query): Query<SignupsQuery>,
+) -> Result<Json<Vec<ActorSummarySchema>>, Error> {
+    let actors = LocalActor::joined_between(db, query.start, query.end).await?;
+    Ok(Json(actors.into_iter().map(ActorSummarySchema::from).collect()))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, test::TestClient};
+    use sqlx::{Pool, Postgres, query, types::Uuid};
+
+    use super::*;
+    use crate::database::test_helpers::insert_session;
+
+    #[sqlx::test(fixtures("../../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_get_stats(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let actor = |n: u128| Uuid::from_u128(n);
+        LocalActor::set_deactivated(&db, &actor(2), true).await.unwrap();
+        query!(
+            "UPDATE local_actors SET joined = NOW() - INTERVAL '2 days' WHERE uaid = $1",
+            actor(3)
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        insert_session(&db.pool, "valid", 1, actor(1), Some(TimeDelta::hours(1))).await;
+        insert_session(&db.pool, "never_expiring", 2, actor(2), None).await;
+        insert_session(&db.pool, "expired", 4, actor(4), Some(TimeDelta::hours(-1))).await;
+        let max_connections = db.pool_stats().max_connections;
+        let token_store = TokenStore::new(db.clone());
+        let client = TestClient::new(super::super::setup_routes().data(db).data(token_store));
+
+        let response = client.get("/stats").send().await;
+        response.assert_status_is_ok();
+        let json = response.json().await;
+        let stats = json.value().object();
+        stats.get("totalActors").assert_i64(4);
+        stats.get("activeActors").assert_i64(3);
+        stats.get("registrationsLast24h").assert_i64(3);
+        stats.get("activeTokens").assert_i64(2);
+        stats.get("issuedCerts").assert_i64(5);
+        let pool = stats.get("pool").object();
+        pool.get("maxConnections").assert_i64(i64::from(max_connections));
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/local_actor_tests.sql"))]
+    async fn test_get_signups(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        query!(
+            "UPDATE local_actors SET joined = '2024-01-01 12:00:00' WHERE uaid = $1",
+            Uuid::from_u128(1)
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        query!(
+            "UPDATE local_actors SET joined = '2024-01-02 00:00:00' WHERE uaid = $1",
+            Uuid::from_u128(2)
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        let client = TestClient::new(super::super::setup_routes().data(db));
+
+        let response = client
+            .get("/stats/signups")
+            .query("start", &"2024-01-01T00:00:00")
+            .query("end", &"2024-01-02T00:00:00")
+            .send()
+            .await;
+        response.assert_status_is_ok();
+        let json = response.json().await;
+        let actors = json.value().array();
+        actors.assert_len(1);
+        actors.get(0).object().get("localName").assert_string("alice");
+
+        client
+            .get("/stats/signups")
+            .query("start", &"yesterday")
+            .query("end", &"2024-01-02T00:00:00")
+            .send()
+            .await
+            .assert_status(poem::http::StatusCode::BAD_REQUEST);
+    }
+}
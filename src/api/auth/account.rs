@@ -0,0 +1,438 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use poem::{
+    IntoResponse, handler,
+    http::StatusCode,
+    web::{Data, Json},
+};
+
+use crate::{
+    api::{
+        auth::{
+            key_login::authenticate_with_key,
+            login::{FailedLoginDelay, authenticate},
+            models::{DeleteAccountSchema, KeyLoginSchema, LoginSchema, UpdateAccountSchema},
+        },
+        extractors::AuthenticatedActor,
+    },
+    config::ReloadableConfigHandle,
+    database::{Database, LocalActor, ProfileUpdate},
+    errors::{Context, Errcode, Error},
+};
+
+/// How many characters a display name may have at most.
+const MAX_DISPLAY_NAME_LEN: usize = 64;
+/// How long a locale may be at most. BCP 47 recommends supporting tags of at
+/// least this length.
+const MAX_LOCALE_LEN: usize = 35;
+/// How long an E-Mail address may be at most, as limited by SMTP.
+const MAX_EMAIL_LEN: usize = 254;
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Update the profile fields present in the request body for the
+/// authenticated actor and return the updated account.
+pub(super) async fn update_account(
+    Json(payload): Json<UpdateAccountSchema>,
+    Data(db): Data<&Database>,
+    AuthenticatedActor(actor): AuthenticatedActor,
+) -> Result<impl IntoResponse, Error> {
+    let update = validate_update(payload)?;
+    Ok(Json(LocalActor::update_profile(db, &actor.unique_actor_identifier, &update).await?))
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Permanently delete the authenticated actor and all of its data. The
+/// deletion has to be confirmed with the password of the actor or a signed
+/// challenge, which are checked like the credentials of a login.
+pub(super) async fn delete_account(
+    Json(payload): Json<DeleteAccountSchema>,
+    Data(db): Data<&Database>,
+    Data(failed_login_delay): Data<&FailedLoginDelay>,
+    Data(reloadable_config): Data<&ReloadableConfigHandle>,
+    AuthenticatedActor(actor): AuthenticatedActor,
+) -> Result<impl IntoResponse, Error> {
+    let security_config = &reloadable_config.current().security;
+    let local_name = actor.local_name.clone();
+    let confirmation = match payload {
+        DeleteAccountSchema::Password { password } => {
+            authenticate(&LoginSchema { local_name, password }, db, security_config).await
+        }
+        DeleteAccountSchema::Key { challenge, signature } => {
+            authenticate_with_key(
+                &KeyLoginSchema { local_name, challenge, signature },
+                db,
+                security_config.case_insensitive_local_names,
+            )
+            .await
+        }
+    };
+    failed_login_delay.apply(confirmation).await?;
+    LocalActor::delete(db, &actor.unique_actor_identifier).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Validate all fields present in the `payload`, turning it into a
+/// [ProfileUpdate]. Clearing a field is always allowed.
+///
+/// ## Errors
+///
+/// [Errcode::IllegalInput], naming the first invalid field in its context.
+#[allow(clippy::result_large_err)]
+fn validate_update(payload: UpdateAccountSchema) -> Result<ProfileUpdate, Error> {
+    let fields = [
+        (
+            "display_name",
+            &payload.display_name,
+            is_valid_display_name as fn(&str) -> bool,
+            "1 to 64 characters, without control characters",
+        ),
+        ("locale", &payload.locale, is_valid_locale, "A BCP 47 language tag, such as en-US"),
+        ("recovery_email", &payload.recovery_email, is_valid_email, "An E-Mail address"),
+    ];
+    for (field_name, value, is_valid, expected) in fields {
+        if let Some(Some(value)) = value
+            && !is_valid(value)
+        {
+            return Err(Error::new(
+                Errcode::IllegalInput,
+                Some(Context::new(Some(field_name), Some(value), Some(expected), None)),
+            ));
+        }
+    }
+    Ok(ProfileUpdate {
+        display_name: payload.display_name,
+        locale: payload.locale,
+        recovery_email: payload.recovery_email,
+    })
+}
+
+/// Whether `display_name` is not empty, not too long and free of control
+/// characters.
+fn is_valid_display_name(display_name: &str) -> bool {
+    !display_name.trim().is_empty()
+        && display_name.chars().count() <= MAX_DISPLAY_NAME_LEN
+        && !display_name.chars().any(char::is_control)
+}
+
+/// Whether `locale` is shaped like a BCP 47 language tag: A language subtag of
+/// two or three letters, followed by any number of alphanumeric subtags of one
+/// to eight characters, all separated by `-`.
+fn is_valid_locale(locale: &str) -> bool {
+    let mut subtags = locale.split('-');
+    let language_is_valid = subtags.next().is_some_and(|language| {
+        (2..=3).contains(&language.len()) && language.chars().all(|c| c.is_ascii_alphabetic())
+    });
+    language_is_valid
+        && locale.len() <= MAX_LOCALE_LEN
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// Whether `email` looks like an E-Mail address: A non-empty local part and a
+/// domain containing a dot, separated by a single `@`, without whitespace.
+fn is_valid_email(email: &str) -> bool {
+    let Some((local_part, domain)) = email.split_once('@') else {
+        return false;
+    };
+    email.len() <= MAX_EMAIL_LEN
+        && !local_part.is_empty()
+        && !domain.contains('@')
+        && domain.split('.').count() >= 2
+        && domain.split('.').all(|label| !label.is_empty())
+        && !email.chars().any(char::is_whitespace)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use argon2::{
+        Argon2,
+        password_hash::{PasswordHasher, SaltString, rand_core::OsRng},
+    };
+    use poem::{EndpointExt, http::StatusCode, test::TestClient};
+    use polyproto::{
+        der::pem::LineEnding,
+        key::{PrivateKey, PublicKey},
+        signature::Signature,
+    };
+    use serde_json::json;
+    use sqlx::{Pool, Postgres, query, types::Uuid};
+
+    use super::*;
+    use crate::{
+        api::{auth::register::registration_proof_message, extractors::ServedDomains},
+        crypto::ed25519::generate_keypair,
+        database::{test_helpers::insert_session, tokens::TokenStore},
+    };
+
+    const PASSWORD: &str = "correct horse battery staple";
+
+    /// A [TestClient] for the auth routes, with the default configuration.
+    fn routes(db: Database) -> TestClient<impl poem::Endpoint> {
+        TestClient::new(
+            super::super::setup_routes()
+                .data(db.clone())
+                .data(TokenStore::new(db))
+                .data(FailedLoginDelay::default())
+                .data(ServedDomains::new(["full.example.com"]))
+                .data(ReloadableConfigHandle::default()),
+        )
+    }
+
+    /// [routes], after giving `test_user_1` the token `session_token`.
+    async fn client(pool: Pool<Postgres>) -> TestClient<impl poem::Endpoint> {
+        insert_session(&pool, "session_token", 1, Uuid::from_u128(1), None).await;
+        routes(Database { pool })
+    }
+
+    /// Give `test_user_1` the password [PASSWORD].
+    async fn set_password(pool: &Pool<Postgres>) {
+        let password_hash = Argon2::default()
+            .hash_password(PASSWORD.as_bytes(), &SaltString::generate(&mut OsRng))
+            .unwrap()
+            .to_string();
+        query!(
+            "UPDATE local_actors SET password_hash = $1 WHERE local_name = 'test_user_1'",
+            password_hash
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn test_field_validation() {
+        assert!(is_valid_display_name("Test User 🦀"));
+        assert!(!is_valid_display_name("  "));
+        assert!(!is_valid_display_name("line\nbreak"));
+        assert!(!is_valid_display_name(&"a".repeat(65)));
+
+        for locale in ["en", "en-US", "de-CH-1996", "zh-Hant-TW"] {
+            assert!(is_valid_locale(locale), "{locale}");
+        }
+        for locale in ["", "e", "english", "en_US", "en-", "en-toolongsubtag"] {
+            assert!(!is_valid_locale(locale), "{locale}");
+        }
+
+        assert!(is_valid_email("alice@example.com"));
+        for email in ["alice", "@example.com", "alice@example", "a@b@example.com", "a b@c.de"] {
+            assert!(!is_valid_email(email), "{email}");
+        }
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_update_one_field(pool: Pool<Postgres>) {
+        let client = client(pool).await;
+
+        let response = client
+            .patch("/account")
+            .header("Authorization", "session_token")
+            .body_json(&json!({"displayName": "Test User"}))
+            .send()
+            .await;
+        response.assert_status_is_ok();
+        let json = response.json().await;
+        let account = json.value().object();
+        account.get("uaid").assert_string("00000000-0000-0000-0000-000000000001");
+        account.get("localName").assert_string("test_user_1");
+        account.get("displayName").assert_string("Test User");
+        account.get("locale").assert_null();
+        account.get("recoveryEmail").assert_null();
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_update_multiple_fields(pool: Pool<Postgres>) {
+        let client = client(pool).await;
+        client
+            .patch("/account")
+            .header("Authorization", "session_token")
+            .body_json(&json!({"displayName": "Test User", "locale": "en-US"}))
+            .send()
+            .await
+            .assert_status_is_ok();
+
+        // Absent fields stay unchanged, null clears a field
+        let response = client
+            .patch("/account")
+            .header("Authorization", "session_token")
+            .body_json(&json!({"locale": null, "recoveryEmail": "test@example.com"}))
+            .send()
+            .await;
+        response.assert_status_is_ok();
+        let json = response.json().await;
+        let account = json.value().object();
+        account.get("displayName").assert_string("Test User");
+        account.get("locale").assert_null();
+        account.get("recoveryEmail").assert_string("test@example.com");
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_update_invalid_field(pool: Pool<Postgres>) {
+        let client = client(pool).await;
+
+        let response = client
+            .patch("/account")
+            .header("Authorization", "session_token")
+            .body_json(&json!({"displayName": "Test User", "recoveryEmail": "not an address"}))
+            .send()
+            .await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        assert!(response.0.into_body().into_string().await.unwrap().contains("recovery_email"));
+
+        // Nothing has been changed
+        let response = client
+            .patch("/account")
+            .header("Authorization", "session_token")
+            .body_json(&json!({}))
+            .send()
+            .await;
+        response.assert_status_is_ok();
+        response.json().await.value().object().get("displayName").assert_null();
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_delete_account(pool: Pool<Postgres>) {
+        set_password(&pool).await;
+        let db = Database { pool: pool.clone() };
+        let client = client(pool).await;
+
+        client
+            .delete("/account")
+            .header("Authorization", "session_token")
+            .body_json(&json!({"password": "wrong horse battery staple"}))
+            .send()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+        assert!(LocalActor::by_local_name(&db, "test_user_1", false).await.unwrap().is_some());
+
+        client
+            .delete("/account")
+            .header("Authorization", "session_token")
+            .body_json(&json!({"password": PASSWORD}))
+            .send()
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        assert!(LocalActor::by_local_name(&db, "test_user_1", false).await.unwrap().is_none());
+
+        // The token of the deleted actor is gone as well
+        client
+            .patch("/account")
+            .header("Authorization", "session_token")
+            .body_json(&json!({}))
+            .send()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_delete_account_is_locked_after_failed_attempts(pool: Pool<Postgres>) {
+        set_password(&pool).await;
+        let db = Database { pool: pool.clone() };
+        let client = client(pool).await;
+
+        // The default configuration locks logins after 5 consecutive failures
+        for password in ["wrong horse battery staple"; 5].into_iter().chain([PASSWORD]) {
+            client
+                .delete("/account")
+                .header("Authorization", "session_token")
+                .body_json(&json!({"password": password}))
+                .send()
+                .await
+                .assert_status(StatusCode::UNAUTHORIZED);
+        }
+        assert!(LocalActor::by_local_name(&db, "test_user_1", false).await.unwrap().is_some());
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_delete_account_without_password(pool: Pool<Postgres>) {
+        let db = Database { pool: pool.clone() };
+        let client = routes(db.clone());
+        let (private_key, _) = generate_keypair();
+        let response = client.post("/register/key/challenge").send().await;
+        response.assert_status_is_ok();
+        let registration_challenge =
+            response.json().await.value().object().get("challenge").string().to_owned();
+        let proof_message =
+            registration_proof_message("full.example.com", "keyed", &registration_challenge);
+        let response = client
+            .post("/register/key")
+            .header("Host", "full.example.com")
+            .body_json(&json!({
+                "tosConsent": true,
+                "localName": "keyed",
+                "publicKey": private_key.pubkey().public_key_info().to_pem(LineEnding::LF).unwrap(),
+                "challenge": registration_challenge,
+                "proof": hex::encode(private_key.sign(proof_message.as_bytes()).as_bytes()),
+            }))
+            .send()
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        let uaid = response
+            .json()
+            .await
+            .value()
+            .object()
+            .get("uniqueActorIdentifier")
+            .string()
+            .parse()
+            .unwrap();
+        // Sessions need an ID-Cert, so the new actor takes over the one of alice
+        let alice = Uuid::from_u128(0x1001);
+        query!("DELETE FROM user_tokens WHERE uaid = $1", alice).execute(&pool).await.unwrap();
+        insert_session(&pool, "keyed_token", 1001, uaid, None).await;
+        let token = "keyed_token";
+        let challenge = || async {
+            let response = client
+                .post("/login/key/challenge")
+                .body_json(&json!({"localName": "keyed"}))
+                .send()
+                .await;
+            response.assert_status_is_ok();
+            response.json().await.value().object().get("challenge").string().to_owned()
+        };
+
+        // There is no password to confirm the deletion with...
+        client
+            .delete("/account")
+            .header("Authorization", token)
+            .body_json(&json!({"password": PASSWORD}))
+            .send()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+        // ...and a challenge signed with another key is rejected...
+        let (other_private_key, _) = generate_keypair();
+        let challenge_for_other_key = challenge().await;
+        client
+            .delete("/account")
+            .header("Authorization", token)
+            .body_json(&json!({
+                "challenge": challenge_for_other_key,
+                "signature": hex::encode(
+                    other_private_key.sign(challenge_for_other_key.as_bytes()).as_bytes(),
+                ),
+            }))
+            .send()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+        assert!(LocalActor::by_local_name(&db, "keyed", false).await.unwrap().is_some());
+
+        // ...but one signed with the registered key is accepted
+        let challenge = challenge().await;
+        client
+            .delete("/account")
+            .header("Authorization", token)
+            .body_json(&json!({
+                "challenge": challenge,
+                "signature": hex::encode(private_key.sign(challenge.as_bytes()).as_bytes()),
+            }))
+            .send()
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        assert!(LocalActor::by_local_name(&db, "keyed", false).await.unwrap().is_none());
+    }
+}
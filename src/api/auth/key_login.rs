@@ -0,0 +1,188 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use poem::{
+    IntoResponse, Response, handler,
+    http::StatusCode,
+    web::{Data, Json},
+};
+use polyproto::{key::PublicKey, signature::Signature};
+use serde_json::json;
+use sqlx::types::Uuid;
+
+use crate::{
+    api::auth::{
+        login::FailedLoginDelay,
+        models::{KeyLoginChallengeSchema, KeyLoginSchema},
+    },
+    config::ReloadableConfigHandle,
+    crypto::{ActorPublicKey, ecdsa, ed25519},
+    database::{
+        AlgorithmIdentifier, Database, KeyLoginChallenge, LocalActor, PublicKeyInfo,
+        tokens::TokenStore,
+    },
+    errors::{Context, Errcode, Error},
+};
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Issue a challenge, which the actor has to sign with one of its public keys
+/// to log in via [key_login]. Actors without a registered public key are
+/// treated like unknown ones, as they could never use the challenge.
+pub(super) async fn key_login_challenge(
+    Json(payload): Json<KeyLoginChallengeSchema>,
+    Data(db): Data<&Database>,
+    Data(reloadable_config): Data<&ReloadableConfigHandle>,
+) -> Result<impl IntoResponse, Error> {
+    let security_config = &reloadable_config.current().security;
+    let local_actor = match LocalActor::by_local_name(
+        db,
+        &payload.local_name,
+        security_config.case_insensitive_local_names,
+    )
+    .await?
+    {
+        Some(actor) if !actor.is_deactivated => actor,
+        _ => return Err(Error::new_invalid_login()),
+    };
+    if PublicKeyInfo::get_by(db, Some(local_actor.unique_actor_identifier), None, None, None)
+        .await?
+        .is_empty()
+    {
+        return Err(Error::new_invalid_login());
+    }
+    let challenge = KeyLoginChallenge::issue(
+        db,
+        &local_actor.unique_actor_identifier,
+        security_config.key_login_challenge_ttl_secs,
+    )
+    .await?;
+    Ok(Response::builder().status(StatusCode::OK).body(json!(challenge).to_string()))
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Log in with a signed challenge instead of a password.
+pub(super) async fn key_login(
+    Json(payload): Json<KeyLoginSchema>,
+    Data(db): Data<&Database>,
+    Data(token_store): Data<&TokenStore>,
+    Data(failed_login_delay): Data<&FailedLoginDelay>,
+    Data(reloadable_config): Data<&ReloadableConfigHandle>,
+) -> Result<impl IntoResponse, Error> {
+    let security_config = &reloadable_config.current().security;
+    let local_actor = failed_login_delay
+        .apply(
+            authenticate_with_key(&payload, db, security_config.case_insensitive_local_names).await,
+        )
+        .await?;
+    let token = token_store
+        .generate_upsert_token(
+            &local_actor.unique_actor_identifier,
+            None,
+            security_config.token_validity(),
+        )
+        .await?;
+    Ok(Response::builder().status(StatusCode::OK).body(json!({"token": token}).to_string()))
+}
+
+/// Check that the `challenge` in `payload` has been issued to the actor and is
+/// signed with one of its `ed25519` or ECDSA P-256 public keys, and return the
+/// [LocalActor]. The challenge is used up, even if the signature is wrong.
+/// Unknown and deactivated actors, unknown or expired challenges and wrong
+/// signatures all result in the same [Error::new_invalid_login]. See
+/// [LocalActor::by_local_name] for `case_insensitive`.
+pub(super) async fn authenticate_with_key(
+    payload: &KeyLoginSchema,
+    db: &Database,
+    case_insensitive: bool,
+) -> Result<LocalActor, Error> {
+    let signature = decode_signature(&payload.signature, "signature")?;
+    let local_actor =
+        match LocalActor::by_local_name(db, &payload.local_name, case_insensitive).await? {
+            Some(actor) if !actor.is_deactivated => actor,
+            _ => return Err(Error::new_invalid_login()),
+        };
+    if !KeyLoginChallenge::consume(db, &local_actor.unique_actor_identifier, &payload.challenge)
+        .await?
+    {
+        return Err(Error::new_invalid_login());
+    }
+    let uaid = local_actor.unique_actor_identifier;
+    let ed25519_keys = public_keys_of::<ed25519::DigitalSignature, _>(db, uaid).await?;
+    let ecdsa_keys = public_keys_of::<ecdsa::DigitalSignature, _>(db, uaid).await?;
+    if ed25519_keys
+        .into_iter()
+        .map(ActorPublicKey::Ed25519)
+        .chain(ecdsa_keys.into_iter().map(ActorPublicKey::Ecdsa))
+        .any(|public_key| public_key.verify(&signature, payload.challenge.as_bytes()))
+    {
+        return Ok(local_actor);
+    }
+    Err(Error::new_invalid_login())
+}
+
+/// The public keys of the algorithm of `S`, which the actor identified by
+/// `uaid` has registered.
+async fn public_keys_of<S: Signature, P: PublicKey<S>>(
+    db: &Database,
+    uaid: Uuid,
+) -> Result<Vec<P>, Error> {
+    let Some(algorithm_identifier) =
+        AlgorithmIdentifier::get_by_algorithm_identifier(db, &S::algorithm_identifier()).await?
+    else {
+        return Ok(Vec::new());
+    };
+    PublicKeyInfo::get_by(db, Some(uaid), None, Some(algorithm_identifier.id()), None)
+        .await?
+        .iter()
+        .map(PublicKeyInfo::to_public_key::<S, P>)
+        .collect()
+}
+
+/// Decode a hex-encoded signature, to be checked with
+/// [ActorPublicKey::verify]. `field_name` names the offending field in the
+/// error.
+///
+/// ## Errors
+///
+/// [Errcode::IllegalInput], if `signature` is not valid hex.
+#[allow(clippy::result_large_err)]
+pub(super) fn decode_signature(signature: &str, field_name: &str) -> Result<Vec<u8>, Error> {
+    hex::decode(signature).map_err(|_| {
+        Error::new(
+            Errcode::IllegalInput,
+            Some(Context::new(Some(field_name), None, Some("A hex-encoded signature"), None)),
+        )
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, test::TestClient};
+    use sqlx::{Pool, Postgres, query};
+
+    use super::*;
+
+    #[sqlx::test(fixtures("../../../fixtures/local_actor_tests.sql"))]
+    async fn test_challenge_requires_registered_key(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let client = TestClient::new(
+            super::super::setup_routes().data(db.clone()).data(ReloadableConfigHandle::default()),
+        );
+        let request_challenge =
+            || client.post("/login/key/challenge").body_json(&json!({"localName": "alice"})).send();
+
+        request_challenge().await.assert_status(StatusCode::UNAUTHORIZED);
+        query!(
+            "INSERT INTO public_keys (uaid, pubkey, algorithm_identifier) VALUES ($1, 'key', 2)",
+            sqlx::types::Uuid::from_u128(1)
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        request_challenge().await.assert_status_is_ok();
+    }
+}
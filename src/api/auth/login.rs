@@ -1,5 +1,7 @@
+use std::time::Duration;
+
 use argon2::{Argon2, PasswordHash, PasswordVerifier};
-use log::error;
+use log::info;
 use poem::{
     IntoResponse, Response, handler,
     http::StatusCode,
@@ -10,7 +12,8 @@ use serde_json::json;
 use crate::{
     MAX_PERMITTED_PASSWORD_LEN,
     api::auth::models::LoginSchema,
-    database::{Database, LocalActor, tokens::TokenStore},
+    config::{ApiConfig, ReloadableConfigHandle, SecurityConfig},
+    database::{ActorRepository, Database, LocalActor, tokens::TokenStore},
     errors::{Context, Errcode, Error},
 };
 
@@ -20,40 +23,341 @@ pub(super) async fn login(
     Json(payload): Json<LoginSchema>,
     Data(db): Data<&Database>,
     Data(token_store): Data<&TokenStore>,
+    Data(failed_login_delay): Data<&FailedLoginDelay>,
+    Data(reloadable_config): Data<&ReloadableConfigHandle>,
 ) -> Result<impl IntoResponse, Error> {
-    if payload.password.len() > MAX_PERMITTED_PASSWORD_LEN {
+    let security_config = &reloadable_config.current().security;
+    let local_actor =
+        failed_login_delay.apply(authenticate(&payload, db, security_config).await).await?;
+    let token = token_store
+        .generate_upsert_token(
+            &local_actor.unique_actor_identifier,
+            None,
+            security_config.token_validity(),
+        )
+        .await?;
+    Ok(Response::builder().status(StatusCode::OK).body(json!({"token": token}).to_string()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// The delay of responses to failed logins, as configured by
+/// [ApiConfig::failed_login_delay_ms]. The default is no delay at all.
+pub(crate) struct FailedLoginDelay(Duration);
+
+impl FailedLoginDelay {
+    /// The [FailedLoginDelay] configured in the [ApiConfig].
+    pub(crate) fn new(api_config: &ApiConfig) -> Self {
+        Self(Duration::from_millis(api_config.failed_login_delay_ms))
+    }
+
+    /// Pass on the `result` of a login attempt, after sleeping for the delay,
+    /// if it is an [Errcode::Unauthorized] error. Other errors, such as
+    /// malformed input, and successful logins are passed on immediately.
+    pub(super) async fn apply<T>(&self, result: Result<T, Error>) -> Result<T, Error> {
+        if let Err(error) = &result
+            && error.code == Errcode::Unauthorized
+            && !self.0.is_zero()
+        {
+            tokio::time::sleep(self.0).await;
+        }
+        result
+    }
+}
+
+/// Check the credentials in `payload` and return the [LocalActor] they belong
+/// to. Unknown, deactivated and locked actors as well as wrong passwords all
+/// result in the same [Error::new_invalid_login]. After
+/// [SecurityConfig::login_lockout_threshold] consecutive wrong passwords, the
+/// actor is locked for [SecurityConfig::login_lockout_cooldown_secs]; a
+/// successful login resets the count.
+pub(super) async fn authenticate<R: ActorRepository>(
+    payload: &LoginSchema,
+    repository: &R,
+    security_config: &SecurityConfig,
+) -> Result<LocalActor, Error> {
+    check_password_length(&payload.password, "password")?;
+    let case_insensitive = security_config.case_insensitive_local_names;
+    let local_actor =
+        match repository.local_actor_by_name(&payload.local_name, case_insensitive).await? {
+            Some(actor) => actor,
+            None => return Err(Error::new_invalid_login()),
+        };
+    // Deactivated actors get the same response as unknown ones
+    if local_actor.is_deactivated {
+        return Err(Error::new_invalid_login());
+    }
+    let lockout_enabled = security_config.login_lockout_threshold > 0;
+    // Locked actors are rejected without checking the password, so that it cannot
+    // be guessed during the lock
+    if lockout_enabled && repository.is_login_locked(&local_actor.unique_actor_identifier).await? {
+        return Err(Error::new_invalid_login());
+    }
+    let actor_password_hashstring =
+        match repository.password_hash(&payload.local_name, case_insensitive).await? {
+            Some(hash_string) => hash_string,
+            None => {
+                return Err(Error::new_invalid_login());
+            }
+        };
+    if let Err(error) = verify_password(&payload.password, &actor_password_hashstring) {
+        if lockout_enabled
+            && error.code == Errcode::Unauthorized
+            && repository
+                .record_failed_login(
+                    &local_actor.unique_actor_identifier,
+                    security_config.login_lockout_threshold,
+                    security_config.login_lockout_cooldown_secs,
+                )
+                .await?
+        {
+            info!("Locked logins to {} after too many failed attempts", local_actor.local_name);
+        }
+        return Err(error);
+    }
+    repository.clear_failed_logins(&local_actor.unique_actor_identifier).await?;
+    Ok(local_actor)
+}
+
+/// Reject passwords longer than [MAX_PERMITTED_PASSWORD_LEN] before spending
+/// any time on hashing them. `field_name` names the offending field in the
+/// error.
+#[allow(clippy::result_large_err)]
+pub(super) fn check_password_length(password: &str, field_name: &str) -> Result<(), Error> {
+    if password.len() > MAX_PERMITTED_PASSWORD_LEN {
         return Err(Error::new(
             Errcode::IllegalInput,
             Some(Context::new(
-                Some("password"),
-                Some(&format!("{} characters", payload.password.len())),
+                Some(field_name),
+                Some(&format!("{} characters", password.len())),
                 Some(&format!("Not more than {MAX_PERMITTED_PASSWORD_LEN} characters")),
                 None,
             )),
         ));
     }
-    let local_actor = match LocalActor::by_local_name(db, &payload.local_name).await? {
-        Some(actor) => actor,
-        None => return Err(Error::new_invalid_login()),
+    Ok(())
+}
+
+/// Verify `password` against the argon2 `password_hash` PHC string of an
+/// actor.
+///
+/// ## Errors
+///
+/// - [Error::new_invalid_login], if the password does not match
+/// - [Errcode::Internal], if the stored hash is not a valid PHC string
+#[allow(clippy::result_large_err)]
+pub(super) fn verify_password(password: &str, password_hash: &str) -> Result<(), Error> {
+    let password_hash = PasswordHash::new(password_hash)?;
+    Ok(Argon2::default().verify_password(password.as_bytes(), &password_hash)?)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use argon2::{
+        Argon2,
+        password_hash::{PasswordHasher, SaltString, rand_core::OsRng},
     };
-    let actor_password_hashstring =
-        match LocalActor::get_password_hash(db, &payload.local_name).await? {
-            Some(hash_string) => hash_string,
-            None => {
-                return Err(Error::new_invalid_login());
-            }
-        };
-    let actor_password_hash = PasswordHash::new(&actor_password_hashstring).map_err(|e| {
-        error!(
-            "Password hash for user {} is not in PHC string format? Got error: {e}",
-            payload.password
-        );
-        Error::new(Errcode::Internal, None)
-    })?;
-    Argon2::default()
-        .verify_password(payload.password.as_bytes(), &actor_password_hash)
-        .map_err(|_| Error::new_invalid_login())?;
-    let token =
-        token_store.generate_upsert_token(&local_actor.unique_actor_identifier, None).await?;
-    Ok(Response::builder().status(StatusCode::OK).body(json!({"token": token}).to_string()))
+    use std::time::Instant;
+
+    use poem::{EndpointExt, test::TestClient};
+    use sqlx::{Pool, Postgres, query, types::Uuid};
+
+    use super::*;
+    use crate::{config::ReloadableConfig, database::test_helpers::MockActorRepository};
+
+    const PASSWORD: &str = "correct horse battery staple";
+
+    fn credentials(local_name: &str, password: &str) -> LoginSchema {
+        LoginSchema { local_name: local_name.to_owned(), password: password.to_owned() }
+    }
+
+    fn hash(password: &str) -> String {
+        Argon2::default()
+            .hash_password(password.as_bytes(), &SaltString::generate(&mut OsRng))
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_authenticate() {
+        let repository = MockActorRepository::default()
+            .with_actor("alice", &hash(PASSWORD), false)
+            .with_actor("deactivated", &hash(PASSWORD), true);
+
+        let actor =
+            authenticate(&credentials("alice", PASSWORD), &repository, &SecurityConfig::default())
+                .await
+                .unwrap();
+        assert_eq!(actor.local_name, "alice");
+        for (local_name, password) in
+            [("alice", "not the password"), ("unknown", PASSWORD), ("deactivated", PASSWORD)]
+        {
+            let error = authenticate(
+                &credentials(local_name, password),
+                &repository,
+                &SecurityConfig::default(),
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(error.code, Errcode::Unauthorized, "{local_name}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_login_delay() {
+        let repository = MockActorRepository::default().with_actor("alice", &hash(PASSWORD), false);
+        let mut api_config = ApiConfig::for_tests();
+        api_config.failed_login_delay_ms = 200;
+        let delay = FailedLoginDelay::new(&api_config);
+        let config = SecurityConfig::default();
+
+        let failed =
+            authenticate(&credentials("alice", "wrong password"), &repository, &config).await;
+        let started = Instant::now();
+        assert!(delay.apply(failed).await.is_err());
+        assert!(started.elapsed() >= Duration::from_millis(200));
+
+        let succeeded = authenticate(&credentials("alice", PASSWORD), &repository, &config).await;
+        let started = Instant::now();
+        assert!(delay.apply(succeeded).await.is_ok());
+        assert!(started.elapsed() < Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_rejects_overlong_password() {
+        let repository = MockActorRepository::default().with_actor("alice", &hash(PASSWORD), false);
+        let password = "a".repeat(MAX_PERMITTED_PASSWORD_LEN.saturating_add(1));
+        let error =
+            authenticate(&credentials("alice", &password), &repository, &SecurityConfig::default())
+                .await
+                .unwrap_err();
+        assert_eq!(error.code, Errcode::IllegalInput);
+    }
+
+    fn lockout(threshold: u32) -> SecurityConfig {
+        SecurityConfig { login_lockout_threshold: threshold, ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_locks_after_consecutive_failures() {
+        let repository = MockActorRepository::default().with_actor("alice", &hash(PASSWORD), false);
+        let config = lockout(3);
+
+        // A successful login resets the count...
+        for _ in 0..2 {
+            authenticate(&credentials("alice", "wrong password"), &repository, &config)
+                .await
+                .unwrap_err();
+        }
+        authenticate(&credentials("alice", PASSWORD), &repository, &config).await.unwrap();
+        // ...so that only three further consecutive failures lock the account
+        for _ in 0..3 {
+            authenticate(&credentials("alice", "wrong password"), &repository, &config)
+                .await
+                .unwrap_err();
+        }
+        let error =
+            authenticate(&credentials("alice", PASSWORD), &repository, &config).await.unwrap_err();
+        assert_eq!(error.code, Errcode::Unauthorized);
+        assert_eq!(error.to_json(), Error::new_invalid_login().to_json());
+
+        // Without a lockout, the correct password is always accepted
+        authenticate(&credentials("alice", PASSWORD), &repository, &lockout(0)).await.unwrap();
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/local_actor_tests.sql"))]
+    async fn test_authenticate_succeeds_after_cooldown(pool: Pool<Postgres>) {
+        query!(
+            "UPDATE local_actors SET password_hash = $1 WHERE local_name = 'alice'",
+            hash(PASSWORD)
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let db = Database { pool };
+        let config = lockout(3);
+
+        for _ in 0..3 {
+            authenticate(&credentials("alice", "wrong password"), &db, &config).await.unwrap_err();
+        }
+        let error = authenticate(&credentials("alice", PASSWORD), &db, &config).await.unwrap_err();
+        assert_eq!(error.code, Errcode::Unauthorized);
+
+        // Let the cooldown pass
+        query!("UPDATE failed_login_attempts SET locked_until = now() - interval '1 second'")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        authenticate(&credentials("alice", PASSWORD), &db, &config).await.unwrap();
+        let remaining = query!(r#"SELECT COUNT(*) AS "count!" FROM failed_login_attempts"#)
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(remaining.count, 0);
+    }
+
+    /// A [TestClient] for the auth routes, using `db` and the `security_config`.
+    fn client(db: Database, security_config: SecurityConfig) -> TestClient<impl poem::Endpoint> {
+        TestClient::new(
+            super::super::setup_routes()
+                .data(db.clone())
+                .data(TokenStore::new(db))
+                .data(FailedLoginDelay::default())
+                .data(ReloadableConfigHandle::new(ReloadableConfig {
+                    security: security_config,
+                    ..Default::default()
+                })),
+        )
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/local_actor_tests.sql"))]
+    async fn test_login_locks_account(pool: Pool<Postgres>) {
+        query!(
+            "UPDATE local_actors SET password_hash = $1 WHERE local_name = 'alice'",
+            hash(PASSWORD)
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let client = client(Database { pool }, lockout(2));
+
+        for _ in 0..2 {
+            client
+                .post("/login")
+                .body_json(&json!({"localName": "alice", "password": "wrong password"}))
+                .send()
+                .await
+                .assert_status(StatusCode::UNAUTHORIZED);
+        }
+        client
+            .post("/login")
+            .body_json(&json!({"localName": "alice", "password": PASSWORD}))
+            .send()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/local_actor_tests.sql"))]
+    async fn test_login_rejects_deactivated_actor(pool: Pool<Postgres>) {
+        let password_hash = hash(PASSWORD);
+        query!(
+            "UPDATE local_actors SET password_hash = $1 WHERE local_name = 'deactivated_user'",
+            password_hash
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let db = Database { pool };
+        let client = client(db.clone(), SecurityConfig::default());
+        let credentials = json!({"localName": "deactivated_user", "password": PASSWORD});
+
+        // Correct credentials are not enough for a deactivated actor...
+        let response = client.post("/login").body_json(&credentials).send().await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+
+        // ...but they are, once the actor has been reactivated
+        LocalActor::set_deactivated(&db, &Uuid::from_u128(4), false).await.unwrap();
+        let response = client.post("/login").body_json(&credentials).send().await;
+        response.assert_status_is_ok();
+    }
 }
@@ -0,0 +1,75 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use poem::{IntoResponse, Response, handler, http::StatusCode, web::Data};
+
+use crate::{
+    database::tokens::{TokenActorIdPair, TokenStore},
+    errors::{Errcode, Error},
+};
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Log out by revoking the token used to authenticate this request. Other
+/// sessions of the actor stay valid.
+pub(super) async fn logout(
+    Data(token_store): Data<&TokenStore>,
+    Data(token): Data<&TokenActorIdPair>,
+) -> Result<impl IntoResponse, Error> {
+    // `token` holds the hash of the presented token, as set by the
+    // AuthenticationMiddleware
+    if !token_store.revoke_token(token.token.as_str()).await? {
+        return Err(Error::new(Errcode::Unauthorized, None));
+    }
+    Ok(Response::builder().status(StatusCode::NO_CONTENT).finish())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, http::StatusCode, test::TestClient};
+    use sqlx::{Pool, Postgres, types::Uuid};
+
+    use crate::database::{Database, test_helpers::insert_session, tokens::TokenStore};
+
+    #[sqlx::test(fixtures("../../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_logout_revokes_only_current_token(pool: Pool<Postgres>) {
+        insert_session(&pool, "session_token_a", 1, Uuid::from_u128(1), None).await;
+        insert_session(&pool, "session_token_b", 5, Uuid::from_u128(1), None).await;
+        let db = Database { pool };
+        let client = TestClient::new(
+            super::super::setup_routes().data(db.clone()).data(TokenStore::new(db)),
+        );
+
+        let response =
+            client.post("/logout").header("Authorization", "session_token_a").send().await;
+        response.assert_status(StatusCode::NO_CONTENT);
+
+        // The revoked token can no longer be used...
+        let response =
+            client.post("/logout").header("Authorization", "session_token_a").send().await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+        // ...while the other session of the same actor is still valid
+        let response =
+            client.get("/sessions").header("Authorization", "session_token_b").send().await;
+        response.assert_status_is_ok();
+        response.json().await.value().array().assert_len(1);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_logout_requires_authentication(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let client = TestClient::new(
+            super::super::setup_routes().data(db.clone()).data(TokenStore::new(db)),
+        );
+
+        client.post("/logout").send().await.assert_status(StatusCode::UNAUTHORIZED);
+        client
+            .post("/logout")
+            .header("Authorization", "not_a_valid_token")
+            .send()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+    }
+}
@@ -1,14 +1,49 @@
-use poem::{Route, post};
+use poem::{EndpointExt, Route, get, patch, post};
 
+use crate::api::middlewares::AuthenticationMiddleware;
+
+/// The account profile endpoint
+mod account;
+/// The login endpoints for actors logging in with a public key
+mod key_login;
 /// The login endpoint
 mod login;
+/// The logout endpoint
+mod logout;
 /// Data models/schemas used for these routes
 pub(crate) mod models;
+/// The password change endpoint
+mod password;
+/// The password policy endpoint
+mod password_policy;
+/// The token refresh endpoint
+mod refresh;
 /// The register endpoint
 mod register;
+/// The session listing endpoint
+mod sessions;
+
+pub(crate) use login::FailedLoginDelay;
 
 #[cfg_attr(coverage_nightly, coverage(off))]
 /// Route handler for the auth module
 pub(super) fn setup_routes() -> Route {
-    Route::new().at("/register", post(register::register)).at("/login", post(login::login))
+    Route::new()
+        .at("/register", post(register::register))
+        .at("/login", post(login::login))
+        .at("/login/key/challenge", post(key_login::key_login_challenge))
+        .at("/login/key/verify", post(key_login::key_login))
+        .at("/register/key/challenge", post(register::registration_challenge))
+        .at("/register/key", post(register::register_with_key))
+        .at("/logout", post(logout::logout).with(AuthenticationMiddleware))
+        .at("/token/refresh", post(refresh::refresh).with(AuthenticationMiddleware))
+        .at("/password-policy", get(password_policy::get_password_policy))
+        .at("/password", post(password::change_password).with(AuthenticationMiddleware))
+        .at("/sessions", get(sessions::sessions).with(AuthenticationMiddleware))
+        .at(
+            "/account",
+            patch(account::update_account)
+                .delete(account::delete_account)
+                .with(AuthenticationMiddleware),
+        )
 }
@@ -1,4 +1,7 @@
 use serde::{Deserialize, Serialize};
+use sqlx::types::Uuid;
+
+use crate::database::LocalActor;
 
 // TODO: captcha_key for RegisterSchema and LoginSchema
 
@@ -25,6 +28,37 @@ pub struct RegisterSchema {
     pub invite: Option<String>,
 }
 
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Information sent to the server by a client, when the client wants to create
+/// a new account without a password, which logs in with a public key instead.
+///
+/// ## Important Note
+///
+/// sonata is in an MVP phase. As such, things like this `RegisterWithKeySchema`
+/// are subject to a lot of change. If you build clients around sonata, expect
+/// things to break in future versions.
+pub struct RegisterWithKeySchema {
+    /// Whether the client has agreed to the terms of service offered by the
+    /// instance.
+    pub tos_consent: bool,
+    /// The local name the client would like to choose
+    pub local_name: String,
+    /// The PEM-encoded `ed25519` or ECDSA P-256 public key the client wants to
+    /// log in with
+    pub public_key: String,
+    /// A challenge issued by the server for this registration, which can only
+    /// be used once
+    pub challenge: String,
+    /// The hex-encoded signature of the registration proof message for the
+    /// server's domain, the `local_name` and the `challenge`, made with the
+    /// private key of the `public_key`
+    pub proof: String,
+    /// Optional: An invite code, which the client got referred to this instance
+    /// with.
+    pub invite: Option<String>,
+}
+
 #[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 /// Information sent to the server by a client, when the client wants to log
@@ -42,6 +76,137 @@ pub struct LoginSchema {
     pub password: String,
 }
 
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Information sent to the server by a client, when the client wants to log
+/// into an account with a public key, and needs a challenge to sign.
+pub struct KeyLoginChallengeSchema {
+    /// The name of the account the client wants to login to
+    pub local_name: String,
+}
+
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Information sent to the server by a client, when the client wants to log
+/// into an account with a public key.
+///
+/// ## Important Note
+///
+/// sonata is in an MVP phase. As such, things like this `KeyLoginSchema` are
+/// subject to a lot of change. If you build clients around sonata, expect
+/// things to break in future versions.
+pub struct KeyLoginSchema {
+    /// The name of the account the client wants to login to
+    pub local_name: String,
+    /// A challenge the server has issued for the account
+    pub challenge: String,
+    /// The hex-encoded signature of the `challenge`, made with the private key
+    /// of one of the public keys registered for the account
+    pub signature: String,
+}
+
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Information sent to the server by a client, when the client wants to change
+/// the password of the account it is logged into.
+///
+/// ## Important Note
+///
+/// sonata is in an MVP phase. As such, things like this `ChangePasswordSchema`
+/// are subject to a lot of change. If you build clients around sonata, expect
+/// things to break in future versions.
+pub struct ChangePasswordSchema {
+    /// The current password of the account
+    pub old_password: String,
+    /// The password the account should have from now on
+    pub new_password: String,
+}
+
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+/// Information sent to the server by a client, when the client wants to update
+/// profile fields of the account it is logged into. Fields which are not
+/// present are left unchanged; fields which are `null` are cleared.
+///
+/// ## Important Note
+///
+/// sonata is in an MVP phase. As such, things like this `UpdateAccountSchema`
+/// are subject to a lot of change. If you build clients around sonata, expect
+/// things to break in future versions.
+pub struct UpdateAccountSchema {
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    /// The name the actor prefers to be displayed with
+    pub display_name: Option<Option<String>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    /// BCP 47 language tag of the language the actor prefers, such as `en-US`
+    pub locale: Option<Option<String>>,
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    /// E-Mail address the actor can be contacted at to recover the account
+    pub recovery_email: Option<Option<String>>,
+}
+
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+/// Information sent to the server by a client, when the client wants to
+/// permanently delete the account it is logged into. The deletion is confirmed
+/// either with the password of the account or, for accounts without a
+/// password, with a signed challenge, as for a key login.
+///
+/// ## Important Note
+///
+/// sonata is in an MVP phase. As such, things like this `DeleteAccountSchema`
+/// are subject to a lot of change. If you build clients around sonata, expect
+/// things to break in future versions.
+pub enum DeleteAccountSchema {
+    /// Confirmation with a password
+    Password {
+        /// The current password of the account
+        password: String,
+    },
+    /// Confirmation with a public key
+    Key {
+        /// A challenge the server has issued for the account
+        challenge: String,
+        /// The hex-encoded signature of the `challenge`, made with the private
+        /// key of one of the public keys registered for the account
+        signature: String,
+    },
+}
+
+#[serde_with::serde_as]
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Information sent to a client by the server, after the client has
+/// registered a new account.
+///
+/// ## Important Note
+///
+/// sonata is in an MVP phase. As such, things like this `RegisteredSchema` are
+/// subject to a lot of change. If you build clients around sonata, expect
+/// things to break in future versions.
+pub struct RegisteredSchema {
+    /// An auth token for the new account
+    pub token: String,
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    /// The unique actor identifier of the new account, which is needed to
+    /// enroll an ID-Cert
+    pub unique_actor_identifier: Uuid,
+    /// When the new account has been created
+    pub joined_at_timestamp: chrono::NaiveDateTime,
+}
+
+impl RegisteredSchema {
+    /// The [RegisteredSchema] for the newly registered `actor`, which has been
+    /// issued the auth `token`.
+    pub fn new(token: String, actor: &LocalActor) -> Self {
+        Self {
+            token,
+            unique_actor_identifier: actor.unique_actor_identifier,
+            joined_at_timestamp: actor.joined_at_timestamp,
+        }
+    }
+}
+
 #[cfg(test)]
 #[allow(
     clippy::unwrap_used,
@@ -79,4 +244,24 @@ mod test {
         assert_eq!(schema.password, "testpassword123");
         assert_eq!(schema.invite, Some("invite123".to_string()));
     }
+
+    #[test]
+    fn test_registered_schema_deserialization() {
+        let json_str = r#"{"token":"abc","uniqueActorIdentifier":"00000000-0000-0000-0000-000000000001","joinedAtTimestamp":"2025-01-02T03:04:05.678"}"#;
+        let schema: RegisteredSchema = serde_json::from_str(json_str).unwrap();
+
+        assert_eq!(schema.token, "abc");
+        assert_eq!(schema.unique_actor_identifier, Uuid::from_u128(1));
+        assert_eq!(
+            schema.joined_at_timestamp,
+            chrono::NaiveDate::from_ymd_opt(2025, 1, 2)
+                .unwrap()
+                .and_hms_milli_opt(3, 4, 5, 678)
+                .unwrap()
+        );
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(json_str).unwrap(),
+            serde_json::to_value(&schema).unwrap()
+        );
+    }
 }
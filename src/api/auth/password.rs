@@ -0,0 +1,240 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use argon2::{
+    Argon2,
+    password_hash::{PasswordHasher, SaltString, rand_core::OsRng},
+};
+use poem::{
+    IntoResponse, Response, handler,
+    http::StatusCode,
+    web::{Data, Json},
+};
+use serde_json::json;
+
+use crate::{
+    api::{
+        auth::{
+            login::{FailedLoginDelay, authenticate, check_password_length},
+            models::{ChangePasswordSchema, LoginSchema},
+        },
+        extractors::AuthenticatedActor,
+        models::PasswordChecker,
+    },
+    config::{ReloadableConfigHandle, SecurityConfig},
+    database::{Database, LocalActor, tokens::TokenStore},
+    errors::Error,
+};
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Change the password of the authenticated actor. The old password has to be
+/// provided as well, and is checked like the password of a login. All tokens
+/// of the actor are revoked, logging out every other session; a new token for
+/// the requesting client is returned instead.
+pub(super) async fn change_password(
+    Json(payload): Json<ChangePasswordSchema>,
+    Data(db): Data<&Database>,
+    Data(password_checker): Data<&PasswordChecker>,
+    Data(failed_login_delay): Data<&FailedLoginDelay>,
+    Data(reloadable_config): Data<&ReloadableConfigHandle>,
+    AuthenticatedActor(actor): AuthenticatedActor,
+) -> Result<impl IntoResponse, Error> {
+    let security_config = &reloadable_config.current().security;
+    let token = replace_password(
+        &payload,
+        &actor,
+        db,
+        security_config,
+        password_checker,
+        failed_login_delay,
+    )
+    .await?;
+    Ok(Response::builder().status(StatusCode::OK).body(json!({"token": token}).to_string()))
+}
+
+/// Replace the password of `actor` as described by the `payload`, revoke all
+/// of its tokens and return a new token, which is valid for
+/// [SecurityConfig::token_validity]. The old password is checked by
+/// [authenticate], with the `failed_login_delay` applied to wrong ones, and
+/// the new password by the `password_checker`. Replacing the password and the
+/// tokens happens in a single transaction, so that either all of it or none of
+/// it is applied.
+async fn replace_password(
+    payload: &ChangePasswordSchema,
+    actor: &LocalActor,
+    db: &Database,
+    security_config: &SecurityConfig,
+    password_checker: &PasswordChecker,
+    failed_login_delay: &FailedLoginDelay,
+) -> Result<String, Error> {
+    check_password_length(&payload.old_password, "old_password")?;
+    let login = LoginSchema {
+        local_name: actor.local_name.clone(),
+        password: payload.old_password.clone(),
+    };
+    failed_login_delay.apply(authenticate(&login, db, security_config).await).await?;
+    let old_password_hash = LocalActor::get_password_hash(db, &actor.local_name, false)
+        .await?
+        .ok_or(Error::new_invalid_login())?;
+    let new_password =
+        password_checker.verify(security_config.password_policy, &payload.new_password)?;
+    let salt = SaltString::generate(&mut OsRng);
+    let new_password_hash = Argon2::default().hash_password(new_password.as_bytes(), &salt)?;
+    let mut transaction = db.pool.begin().await?;
+    LocalActor::replace_password_hash_on(
+        &mut transaction,
+        &actor.unique_actor_identifier,
+        &old_password_hash,
+        new_password_hash.serialize().as_str(),
+    )
+    .await?;
+    TokenStore::revoke_all_tokens_on(&mut transaction, &actor.unique_actor_identifier).await?;
+    let token = TokenStore::generate_upsert_token_on(
+        &mut transaction,
+        &actor.unique_actor_identifier,
+        None,
+        security_config.token_validity(),
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(token)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use argon2::{
+        Argon2,
+        password_hash::{PasswordHasher, SaltString, rand_core::OsRng},
+    };
+    use poem::{EndpointExt, http::StatusCode, test::TestClient};
+    use serde_json::json;
+    use sqlx::{Pool, Postgres, query, types::Uuid};
+
+    use crate::{
+        api::{auth::FailedLoginDelay, models::PasswordChecker},
+        config::ReloadableConfigHandle,
+        database::{Database, test_helpers::insert_session, tokens::TokenStore},
+    };
+
+    const OLD_PASSWORD: &str = "correct horse battery staple";
+    const NEW_PASSWORD: &str = "staple battery horse correct";
+
+    /// Give `test_user_1` the password [OLD_PASSWORD] and two sessions, using
+    /// the tokens `session_token_a` and `session_token_b`.
+    async fn setup_test_user_1(pool: &Pool<Postgres>) {
+        let password_hash = Argon2::default()
+            .hash_password(OLD_PASSWORD.as_bytes(), &SaltString::generate(&mut OsRng))
+            .unwrap()
+            .to_string();
+        query!(
+            "UPDATE local_actors SET password_hash = $1 WHERE local_name = 'test_user_1'",
+            password_hash
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        insert_session(pool, "session_token_a", 1, Uuid::from_u128(1), None).await;
+        insert_session(pool, "session_token_b", 5, Uuid::from_u128(1), None).await;
+    }
+
+    fn client(db: Database) -> TestClient<impl poem::Endpoint> {
+        TestClient::new(
+            super::super::setup_routes()
+                .data(db.clone())
+                .data(TokenStore::new(db))
+                .data(PasswordChecker::default())
+                .data(FailedLoginDelay::default())
+                .data(ReloadableConfigHandle::default()),
+        )
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_change_password(pool: Pool<Postgres>) {
+        setup_test_user_1(&pool).await;
+        let client = client(Database { pool });
+
+        let response = client
+            .post("/password")
+            .header("Authorization", "session_token_a")
+            .body_json(&json!({"oldPassword": OLD_PASSWORD, "newPassword": NEW_PASSWORD}))
+            .send()
+            .await;
+        response.assert_status_is_ok();
+        response.json().await.value().object().get("token").assert_not_null();
+
+        // All sessions are logged out
+        for token in ["session_token_a", "session_token_b"] {
+            client
+                .get("/sessions")
+                .header("Authorization", token)
+                .send()
+                .await
+                .assert_status(StatusCode::UNAUTHORIZED);
+        }
+        // Only the new password can be used to log in
+        for (password, status) in
+            [(OLD_PASSWORD, StatusCode::UNAUTHORIZED), (NEW_PASSWORD, StatusCode::OK)]
+        {
+            client
+                .post("/login")
+                .body_json(&json!({"localName": "test_user_1", "password": password}))
+                .send()
+                .await
+                .assert_status(status);
+        }
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_change_password_wrong_old_password(pool: Pool<Postgres>) {
+        setup_test_user_1(&pool).await;
+        let client = client(Database { pool });
+
+        client
+            .post("/password")
+            .header("Authorization", "session_token_a")
+            .body_json(&json!({"oldPassword": "not the password", "newPassword": NEW_PASSWORD}))
+            .send()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+
+        // Neither the password nor the sessions have changed
+        client
+            .get("/sessions")
+            .header("Authorization", "session_token_b")
+            .send()
+            .await
+            .assert_status_is_ok();
+        client
+            .post("/login")
+            .body_json(&json!({"localName": "test_user_1", "password": OLD_PASSWORD}))
+            .send()
+            .await
+            .assert_status_is_ok();
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_change_password_is_locked_after_failed_attempts(pool: Pool<Postgres>) {
+        setup_test_user_1(&pool).await;
+        let client = client(Database { pool });
+
+        // The default configuration locks logins after 5 consecutive failures
+        for old_password in ["not the password"; 5].into_iter().chain([OLD_PASSWORD]) {
+            client
+                .post("/password")
+                .header("Authorization", "session_token_a")
+                .body_json(&json!({"oldPassword": old_password, "newPassword": NEW_PASSWORD}))
+                .send()
+                .await
+                .assert_status(StatusCode::UNAUTHORIZED);
+        }
+        client
+            .get("/sessions")
+            .header("Authorization", "session_token_b")
+            .send()
+            .await
+            .assert_status_is_ok();
+    }
+}
@@ -0,0 +1,65 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use poem::{
+    handler,
+    web::{Data, Json},
+};
+
+use crate::{
+    api::models::{PasswordChecker, PasswordPolicy},
+    config::ReloadableConfigHandle,
+};
+
+#[handler]
+/// Get the [PasswordPolicy] new passwords are checked against when registering
+/// or changing the password, as selected by
+/// [SecurityConfig::password_policy](crate::config::SecurityConfig::password_policy).
+pub(super) fn get_password_policy(
+    Data(password_checker): Data<&PasswordChecker>,
+    Data(reloadable_config): Data<&ReloadableConfigHandle>,
+) -> Json<PasswordPolicy> {
+    Json(password_checker.policy(reloadable_config.current().security.password_policy))
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{EndpointExt, test::TestClient};
+
+    use super::*;
+    use crate::config::{PasswordPolicyKind, ReloadableConfig, SecurityConfig};
+
+    #[tokio::test]
+    async fn test_get_password_policy() {
+        let reloadable_config = ReloadableConfigHandle::default();
+        let client = TestClient::new(
+            super::super::setup_routes()
+                .data(PasswordChecker::default())
+                .data(reloadable_config.clone()),
+        );
+
+        let response = client.get("/password-policy").send().await;
+        response.assert_status_is_ok();
+        let json = response.json().await;
+        let policy = json.value().object();
+        policy.get("minLength").assert_i64(8);
+        policy.get("requiredCharacterClasses").array().assert_len(0);
+        policy.get("rejectsBreachedPasswords").assert_bool(false);
+
+        // The policy follows the reloaded configuration
+        reloadable_config.store(ReloadableConfig {
+            security: SecurityConfig {
+                password_policy: PasswordPolicyKind::Strict,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let response = client.get("/password-policy").send().await;
+        response.assert_status_is_ok();
+        let json = response.json().await;
+        let policy = json.value().object();
+        policy.get("minLength").assert_i64(12);
+        policy.get("requiredCharacterClasses").array().assert_len(4);
+    }
+}
@@ -0,0 +1,72 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use poem::{IntoResponse, Response, handler, http::StatusCode, web::Data};
+use serde_json::json;
+
+use crate::{
+    database::tokens::{TokenActorIdPair, TokenStore},
+    errors::{Errcode, Error},
+};
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Replace the token used to authenticate this request with a new one for the
+/// same session, keeping its expiry. The old token stops working immediately.
+pub(super) async fn refresh(
+    Data(token_store): Data<&TokenStore>,
+    Data(token): Data<&TokenActorIdPair>,
+) -> Result<impl IntoResponse, Error> {
+    // `token` holds the hash of the presented token, as set by the
+    // AuthenticationMiddleware
+    let Some(new_token) = token_store.rotate_token(token.token.as_str()).await? else {
+        return Err(Error::new(Errcode::Unauthorized, None));
+    };
+    Ok(Response::builder().status(StatusCode::OK).body(json!({"token": new_token}).to_string()))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, http::StatusCode, test::TestClient};
+    use sqlx::{Pool, Postgres, types::Uuid};
+
+    use crate::database::{Database, test_helpers::insert_session, tokens::TokenStore};
+
+    #[sqlx::test(fixtures("../../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_refresh_replaces_current_token(pool: Pool<Postgres>) {
+        insert_session(&pool, "session_token", 1, Uuid::from_u128(1), None).await;
+        let db = Database { pool };
+        let client = TestClient::new(
+            super::super::setup_routes().data(db.clone()).data(TokenStore::new(db)),
+        );
+
+        let response =
+            client.post("/token/refresh").header("Authorization", "session_token").send().await;
+        response.assert_status_is_ok();
+        let new_token = response.json().await.value().object().get("token").string().to_owned();
+        assert_ne!(new_token, "session_token");
+
+        // Only the new token can be used from now on
+        client
+            .get("/sessions")
+            .header("Authorization", "session_token")
+            .send()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+        let response = client.get("/sessions").header("Authorization", &new_token).send().await;
+        response.assert_status_is_ok();
+        response.json().await.value().array().assert_len(1);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_refresh_requires_authentication(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let client = TestClient::new(
+            super::super::setup_routes().data(db.clone()).data(TokenStore::new(db)),
+        );
+
+        client.post("/token/refresh").send().await.assert_status(StatusCode::UNAUTHORIZED);
+    }
+}
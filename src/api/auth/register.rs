@@ -7,43 +7,710 @@ use poem::{
     http::StatusCode,
     web::{Data, Json},
 };
+use polyproto::certs::PublicKeyInfo;
 use serde_json::json;
 
-use super::models::RegisterSchema;
+use super::{
+    key_login::decode_signature,
+    models::{RegisterSchema, RegisterWithKeySchema, RegisteredSchema},
+};
 use crate::{
-    api::models::{NISTPasswordRequirements, PasswordRequirements},
-    database::{Database, LocalActor, tokens::TokenStore},
+    api::{extractors::RequestIssuer, models::PasswordChecker},
+    config::{ReloadableConfigHandle, SecurityConfig},
+    crypto::ActorPublicKey,
+    database::{ActorRepository, Database, KeyLoginChallenge, LocalActor, tokens::TokenStore},
     errors::{Context, Errcode, Error},
 };
 
 #[handler]
 #[cfg_attr(coverage_nightly, coverage(off))]
+/// Register a new actor with a password. The response is a [RegisteredSchema],
+/// containing an auth token and the unique actor identifier of the new actor.
 pub(super) async fn register(
     Json(payload): Json<RegisterSchema>,
     Data(db): Data<&Database>,
     Data(token_store): Data<&TokenStore>,
+    Data(password_checker): Data<&PasswordChecker>,
+    Data(reloadable_config): Data<&ReloadableConfigHandle>,
 ) -> Result<impl IntoResponse, Error> {
-    // TODO: Check if registration is currently allowed
-    // TODO: Check for tos_consent
-    // TODO: Check if registration is currently in invite-only mode
-    if LocalActor::by_local_name(db, &payload.local_name).await?.is_some() {
+    let security_config = &reloadable_config.current().security;
+    let new_actor = register_actor(payload, db, security_config, password_checker).await?;
+    let token_hash = token_store
+        .generate_upsert_token(
+            &new_actor.unique_actor_identifier,
+            None,
+            security_config.token_validity(),
+        )
+        .await?;
+    Ok(Response::builder()
+        .status(StatusCode::CREATED)
+        .body(json!(RegisteredSchema::new(token_hash, &new_actor)).to_string()))
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Issue a challenge, which a client registering via [register_with_key] has to
+/// sign as part of its registration proof.
+pub(super) async fn registration_challenge(
+    Data(db): Data<&Database>,
+    Data(reloadable_config): Data<&ReloadableConfigHandle>,
+) -> Result<impl IntoResponse, Error> {
+    let challenge = KeyLoginChallenge::issue_for_registration(
+        db,
+        reloadable_config.current().security.key_login_challenge_ttl_secs,
+    )
+    .await?;
+    Ok(Response::builder().status(StatusCode::OK).body(json!(challenge).to_string()))
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Register a new actor without a password, which logs in with a public key
+/// instead. The registration proof has to be made for the domain the request
+/// is addressed to.
+pub(super) async fn register_with_key(
+    Json(payload): Json<RegisterWithKeySchema>,
+    Data(db): Data<&Database>,
+    Data(token_store): Data<&TokenStore>,
+    Data(reloadable_config): Data<&ReloadableConfigHandle>,
+    RequestIssuer(issuer): RequestIssuer,
+) -> Result<impl IntoResponse, Error> {
+    let security_config = &reloadable_config.current().security;
+    let domain = issuer.domain_components.to_string();
+    let new_actor = register_actor_with_key(payload, &domain, db, security_config).await?;
+    let token_hash = token_store
+        .generate_upsert_token(
+            &new_actor.unique_actor_identifier,
+            None,
+            security_config.token_validity(),
+        )
+        .await?;
+    Ok(Response::builder()
+        .status(StatusCode::CREATED)
+        .body(json!(RegisteredSchema::new(token_hash, &new_actor)).to_string()))
+}
+
+/// Register a new [LocalActor] as described by the `payload` and return it.
+/// The client has to consent to the terms of service. If the `payload`
+/// contains an invite, one usage of it is consumed together with creating the
+/// actor. Registering without an invite is only possible, if
+/// [SecurityConfig::invite_only_registration] is disabled. The password is
+/// checked by the `password_checker`.
+async fn register_actor<R: ActorRepository>(
+    payload: RegisterSchema,
+    repository: &R,
+    security_config: &SecurityConfig,
+    password_checker: &PasswordChecker,
+) -> Result<LocalActor, Error> {
+    let invite = check_registration_allowed(
+        payload.tos_consent,
+        payload.invite.as_deref(),
+        security_config,
+    )?;
+    let case_insensitive = security_config.case_insensitive_local_names;
+    if repository.local_actor_by_name(&payload.local_name, case_insensitive).await?.is_some() {
         return Err(Error::new(
             Errcode::Duplicate,
             Some(Context::new(Some("local_name"), Some(&payload.local_name), None, None)),
         ));
     }
-    let password = NISTPasswordRequirements::verify_requirements(&payload.password)?;
+    let password = password_checker.verify(security_config.password_policy, &payload.password)?;
     let salt = SaltString::generate(&mut OsRng);
     let argon2 = Argon2::default();
-    let password_hash = argon2
-        .hash_password(password.as_bytes(), &salt)
-        .map_err(|_| Error::new(Errcode::Internal, None))?;
+    let password_hash = argon2.hash_password(password.as_bytes(), &salt)?;
     // TODO: Check if registration is currently in whitelist mode
-    let new_user =
-        LocalActor::create(db, &payload.local_name, password_hash.serialize().as_str()).await?;
-    let token_hash =
-        token_store.generate_upsert_token(&new_user.unique_actor_identifier, None).await?;
-    Ok(Response::builder()
-        .status(StatusCode::CREATED)
-        .body(json!({"token": token_hash}).to_string()))
+    match invite {
+        Some(invite) => {
+            repository
+                .create_local_actor_with_invite(
+                    invite,
+                    &payload.local_name,
+                    password_hash.serialize().as_str(),
+                    case_insensitive,
+                )
+                .await
+        }
+        None => {
+            repository
+                .create_local_actor(
+                    &payload.local_name,
+                    password_hash.serialize().as_str(),
+                    case_insensitive,
+                )
+                .await
+        }
+    }
+}
+
+/// Register a new [LocalActor] without a password as described by the
+/// `payload` and return it. The `payload` has to contain the signature of the
+/// [registration_proof_message] for the `domain` of this server, the
+/// `local_name` and a challenge issued via [registration_challenge], proving
+/// that the client holds the private key of the public key it registers. The
+/// challenge is used up, even if the registration fails. Otherwise, the same
+/// rules as for [register_actor] apply.
+async fn register_actor_with_key(
+    payload: RegisterWithKeySchema,
+    domain: &str,
+    db: &Database,
+    security_config: &SecurityConfig,
+) -> Result<LocalActor, Error> {
+    let invite = check_registration_allowed(
+        payload.tos_consent,
+        payload.invite.as_deref(),
+        security_config,
+    )?;
+    let public_key = parse_public_key(&payload.public_key)?;
+    let proof = decode_signature(&payload.proof, "proof")?;
+    if !KeyLoginChallenge::consume_for_registration(db, &payload.challenge).await? {
+        return Err(Error::new(
+            Errcode::IllegalInput,
+            Some(Context::new(
+                Some("challenge"),
+                None,
+                Some("An unused registration challenge issued by this server"),
+                None,
+            )),
+        ));
+    }
+    let message = registration_proof_message(domain, &payload.local_name, &payload.challenge);
+    if !public_key.verify(&proof, message.as_bytes()) {
+        return Err(Error::new(
+            Errcode::IllegalInput,
+            Some(Context::new(
+                Some("proof"),
+                None,
+                Some("A signature of the registration proof message"),
+                None,
+            )),
+        ));
+    }
+    match &public_key {
+        ActorPublicKey::Ed25519(public_key) => {
+            LocalActor::create_with_key(
+                db,
+                &payload.local_name,
+                public_key,
+                invite,
+                security_config,
+            )
+            .await
+        }
+        ActorPublicKey::Ecdsa(public_key) => {
+            LocalActor::create_with_key(
+                db,
+                &payload.local_name,
+                public_key,
+                invite,
+                security_config,
+            )
+            .await
+        }
+    }
+}
+
+/// The message an actor registering with a public key on the server with the
+/// given `domain` has to sign, to prove that it holds the corresponding private
+/// key. Including the `challenge` issued by the server keeps the signature from
+/// being replayed.
+pub(super) fn registration_proof_message(
+    domain: &str,
+    local_name: &str,
+    challenge: &str,
+) -> String {
+    format!("sonata key registration: {domain} {local_name} {challenge}")
+}
+
+/// Parse a PEM-encoded `ed25519` or ECDSA P-256 public key.
+///
+/// ## Errors
+///
+/// [Errcode::IllegalInput], if `pem` is not a PEM-encoded public key, or a key
+/// of another algorithm.
+#[allow(clippy::result_large_err)]
+fn parse_public_key(pem: &str) -> Result<ActorPublicKey, Error> {
+    PublicKeyInfo::from_pem(pem).ok().and_then(ActorPublicKey::from_public_key_info).ok_or_else(
+        || {
+            Error::new(
+                Errcode::IllegalInput,
+                Some(Context::new(
+                    Some("public_key"),
+                    None,
+                    Some("A PEM-encoded ed25519 or ECDSA P-256 public key"),
+                    None,
+                )),
+            )
+        },
+    )
+}
+
+/// Check that the client has consented to the terms of service, and has sent
+/// an `invite`, if [SecurityConfig::invite_only_registration] is enabled.
+/// Invites containing control characters, such as null bytes, are rejected
+/// before they reach the database. Returns the `invite`, unless it is empty.
+#[allow(clippy::result_large_err)]
+fn check_registration_allowed<'a>(
+    tos_consent: bool,
+    invite: Option<&'a str>,
+    security_config: &SecurityConfig,
+) -> Result<Option<&'a str>, Error> {
+    // TODO: Check if registration is currently allowed
+    if !tos_consent {
+        return Err(Error::new(
+            Errcode::IllegalInput,
+            Some(Context::new(
+                Some("tos_consent"),
+                Some("false"),
+                Some("true"),
+                Some("The terms of service have to be accepted to register"),
+            )),
+        ));
+    }
+    let invite = invite.filter(|invite| !invite.is_empty());
+    if let Some(invite) = invite
+        && invite.chars().any(char::is_control)
+    {
+        return Err(Error::new(
+            Errcode::IllegalInput,
+            Some(Context::new(Some("invite"), Some(invite), Some("No control characters"), None)),
+        ));
+    }
+    if security_config.invite_only_registration && invite.is_none() {
+        return Err(Error::new(
+            Errcode::Unauthorized,
+            Some(Context::new(
+                Some("invite"),
+                None,
+                None,
+                Some("This server only allows registration with an invite"),
+            )),
+        ));
+    }
+    Ok(invite)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use chrono::{TimeDelta, Utc};
+    use poem::{EndpointExt, test::TestClient};
+    use polyproto::{
+        der::pem::LineEnding,
+        key::{PrivateKey, PublicKey},
+        signature::Signature,
+    };
+    use sqlx::{Pool, Postgres, query};
+
+    use super::*;
+    use crate::{
+        api::auth::{
+            key_login::authenticate_with_key,
+            login::authenticate,
+            models::{KeyLoginSchema, LoginSchema},
+        },
+        config::ReloadableConfig,
+        crypto::{ecdsa, ed25519::generate_keypair, known_algorithm_identifiers},
+        database::{AlgorithmIdentifier, test_helpers::MockActorRepository},
+    };
+
+    const PASSWORD: &str = "correct horse battery staple";
+
+    fn payload(local_name: &str, invite: Option<&str>) -> RegisterSchema {
+        RegisterSchema {
+            tos_consent: true,
+            local_name: local_name.to_owned(),
+            password: PASSWORD.to_owned(),
+            invite: invite.map(String::from),
+        }
+    }
+
+    fn invite_only() -> SecurityConfig {
+        SecurityConfig { invite_only_registration: true, ..Default::default() }
+    }
+
+    /// A [TestClient] for the auth routes, using `db` and the `security_config`.
+    fn client(db: Database, security_config: SecurityConfig) -> TestClient<impl poem::Endpoint> {
+        TestClient::new(
+            super::super::setup_routes()
+                .data(db.clone())
+                .data(TokenStore::new(db))
+                .data(PasswordChecker::default())
+                .data(ReloadableConfigHandle::new(ReloadableConfig {
+                    security: security_config,
+                    ..Default::default()
+                })),
+        )
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/local_actor_tests.sql"))]
+    async fn test_register_issues_token_with_configured_validity(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let client =
+            client(db.clone(), SecurityConfig { token_validity_secs: 3600, ..Default::default() });
+
+        client
+            .post("/register")
+            .body_json(&payload("new_actor", None))
+            .send()
+            .await
+            .assert_status(StatusCode::CREATED);
+        let token = query!(
+            r#"SELECT valid_not_after AS "valid_not_after!" FROM user_tokens
+            JOIN local_actors ON user_tokens.uaid = local_actors.uaid
+            WHERE local_actors.local_name = 'new_actor'"#
+        )
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        let expires_in = token.valid_not_after.signed_duration_since(Utc::now().naive_utc());
+        assert!(
+            expires_in > TimeDelta::minutes(59) && expires_in <= TimeDelta::hours(1),
+            "{expires_in}"
+        );
+    }
+
+    #[sqlx::test(fixtures(
+        "../../../fixtures/local_actor_tests.sql",
+        "../../../fixtures/invite_tests.sql"
+    ))]
+    async fn test_register_invite_only_with_valid_invite(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        register_actor(
+            payload("invited", Some("INVITE0000000002")),
+            &db,
+            &invite_only(),
+            &PasswordChecker::default(),
+        )
+        .await
+        .unwrap();
+        assert!(LocalActor::by_local_name(&db, "invited", false).await.unwrap().is_some());
+        let invite =
+            query!("SELECT usages_current FROM invite_links WHERE invite = $1", "INVITE0000000002")
+                .fetch_one(&db.pool)
+                .await
+                .unwrap();
+        assert_eq!(invite.usages_current, 1);
+    }
+
+    #[sqlx::test(fixtures(
+        "../../../fixtures/local_actor_tests.sql",
+        "../../../fixtures/invite_tests.sql"
+    ))]
+    async fn test_register_invite_only_with_exhausted_invite(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let error = register_actor(
+            payload("invited", Some("INVITE0000000003")),
+            &db,
+            &invite_only(),
+            &PasswordChecker::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, Errcode::Unauthorized);
+        assert!(LocalActor::by_local_name(&db, "invited", false).await.unwrap().is_none());
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/local_actor_tests.sql"))]
+    async fn test_register_invite_only_without_invite(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        for invite in [None, Some("")] {
+            let error = register_actor(
+                payload("uninvited", invite),
+                &db,
+                &invite_only(),
+                &PasswordChecker::default(),
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(error.code, Errcode::Unauthorized);
+        }
+        assert!(LocalActor::by_local_name(&db, "uninvited", false).await.unwrap().is_none());
+
+        // Without invite-only mode, registering without an invite is possible
+        register_actor(
+            payload("uninvited", None),
+            &db,
+            &SecurityConfig::default(),
+            &PasswordChecker::default(),
+        )
+        .await
+        .unwrap();
+        assert!(LocalActor::by_local_name(&db, "uninvited", false).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_register_creates_actor() {
+        let repository = MockActorRepository::default();
+        let actor = register_actor(
+            payload("new_actor", None),
+            &repository,
+            &SecurityConfig::default(),
+            &PasswordChecker::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(actor.local_name, "new_actor");
+        assert!(repository.contains("new_actor"));
+
+        let response = RegisteredSchema::new("token".to_owned(), &actor);
+        assert_eq!(
+            json!(response),
+            json!({
+                "token": "token",
+                "uniqueActorIdentifier": actor.unique_actor_identifier.to_string(),
+                "joinedAtTimestamp": actor.joined_at_timestamp,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_duplicate_local_name() {
+        let repository = MockActorRepository::default().with_actor("taken", "", false);
+        let error = register_actor(
+            payload("taken", None),
+            &repository,
+            &SecurityConfig::default(),
+            &PasswordChecker::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, Errcode::Duplicate);
+    }
+
+    #[tokio::test]
+    async fn test_register_rejects_weak_password() {
+        let repository = MockActorRepository::default();
+        let weak_password =
+            RegisterSchema { password: "short".to_owned(), ..payload("weak", None) };
+        let error = register_actor(
+            weak_password,
+            &repository,
+            &SecurityConfig::default(),
+            &PasswordChecker::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, Errcode::IllegalInput);
+        assert!(!repository.contains("weak"));
+    }
+
+    #[tokio::test]
+    async fn test_register_requires_tos_consent() {
+        let repository = MockActorRepository::default();
+        let no_consent = RegisterSchema { tos_consent: false, ..payload("no_consent", None) };
+        let error = register_actor(
+            no_consent,
+            &repository,
+            &SecurityConfig::default(),
+            &PasswordChecker::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, Errcode::IllegalInput);
+        assert!(!repository.contains("no_consent"));
+    }
+
+    #[tokio::test]
+    async fn test_register_rejects_control_characters() {
+        let repository = MockActorRepository::default().with_invite("INVITE\0");
+        let error = register_actor(
+            payload("invited", Some("INVITE\0")),
+            &repository,
+            &SecurityConfig::default(),
+            &PasswordChecker::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, Errcode::IllegalInput);
+        assert_eq!(error.context.unwrap().field_name, "invite");
+
+        let null_password =
+            RegisterSchema { password: format!("{PASSWORD}\0"), ..payload("nul", None) };
+        let error = register_actor(
+            null_password,
+            &repository,
+            &SecurityConfig::default(),
+            &PasswordChecker::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, Errcode::IllegalInput);
+        assert_eq!(error.context.unwrap().field_name, "password");
+        assert!(!repository.contains("invited"));
+        assert!(!repository.contains("nul"));
+    }
+
+    #[tokio::test]
+    async fn test_register_consumes_invite() {
+        let repository = MockActorRepository::default().with_invite("INVITE0000000001");
+        register_actor(
+            payload("first", Some("INVITE0000000001")),
+            &repository,
+            &invite_only(),
+            &PasswordChecker::default(),
+        )
+        .await
+        .unwrap();
+        let error = register_actor(
+            payload("second", Some("INVITE0000000001")),
+            &repository,
+            &invite_only(),
+            &PasswordChecker::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, Errcode::Unauthorized);
+        assert!(!repository.contains("second"));
+    }
+
+    /// The domain of the issuer in the `full_state.sql` fixture.
+    const DOMAIN: &str = "full.example.com";
+
+    /// A payload registering `local_name` with the public key of `private_key`
+    /// on the server with the `domain`, using a newly issued challenge.
+    async fn key_payload<S: Signature, P: PrivateKey<S>>(
+        db: &Database,
+        domain: &str,
+        local_name: &str,
+        private_key: &P,
+    ) -> RegisterWithKeySchema {
+        let challenge = KeyLoginChallenge::issue_for_registration(db, 300).await.unwrap().challenge;
+        let message = registration_proof_message(domain, local_name, &challenge);
+        RegisterWithKeySchema {
+            tos_consent: true,
+            local_name: local_name.to_owned(),
+            public_key: private_key.pubkey().public_key_info().to_pem(LineEnding::LF).unwrap(),
+            proof: hex::encode(private_key.sign(message.as_bytes()).as_bytes()),
+            challenge,
+            invite: None,
+        }
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_register_with_key_only_allows_key_login(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let (private_key, _) = generate_keypair();
+        let actor = register_actor_with_key(
+            key_payload(&db, DOMAIN, "keyed", &private_key).await,
+            DOMAIN,
+            &db,
+            &SecurityConfig::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(LocalActor::get_password_hash(&db, "keyed", false).await.unwrap(), None);
+
+        // There is no password to log in with...
+        let error = authenticate(
+            &LoginSchema { local_name: "keyed".to_owned(), password: PASSWORD.to_owned() },
+            &db,
+            &SecurityConfig::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, Errcode::Unauthorized);
+
+        // ...but a challenge signed with the registered key is accepted
+        let challenge =
+            KeyLoginChallenge::issue(&db, &actor.unique_actor_identifier, 300).await.unwrap();
+        let key_login = KeyLoginSchema {
+            local_name: "keyed".to_owned(),
+            signature: hex::encode(private_key.sign(challenge.challenge.as_bytes()).as_bytes()),
+            challenge: challenge.challenge,
+        };
+        let logged_in = authenticate_with_key(&key_login, &db, false).await.unwrap();
+        assert_eq!(logged_in.unique_actor_identifier, actor.unique_actor_identifier);
+        // Challenges can only be used once
+        let error = authenticate_with_key(&key_login, &db, false).await.unwrap_err();
+        assert_eq!(error.code, Errcode::Unauthorized);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_register_with_ecdsa_key(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        AlgorithmIdentifier::ensure_all(&db, &known_algorithm_identifiers()).await.unwrap();
+        let (private_key, _) = ecdsa::generate_keypair();
+        let actor = register_actor_with_key(
+            key_payload(&db, DOMAIN, "keyed", &private_key).await,
+            DOMAIN,
+            &db,
+            &SecurityConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        let challenge =
+            KeyLoginChallenge::issue(&db, &actor.unique_actor_identifier, 300).await.unwrap();
+        let signature = private_key.sign(challenge.challenge.as_bytes());
+        let key_login = KeyLoginSchema {
+            local_name: "keyed".to_owned(),
+            // The fixed-size encoding is accepted next to DER
+            signature: hex::encode(signature.as_signature().unwrap().to_bytes()),
+            challenge: challenge.challenge,
+        };
+        let logged_in = authenticate_with_key(&key_login, &db, false).await.unwrap();
+        assert_eq!(logged_in.unique_actor_identifier, actor.unique_actor_identifier);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_register_with_key_rejects_invalid_proof(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let (private_key, _) = generate_keypair();
+        let (other_private_key, _) = generate_keypair();
+        let foreign_proof = RegisterWithKeySchema {
+            public_key: other_private_key
+                .pubkey()
+                .public_key_info()
+                .to_pem(LineEnding::LF)
+                .unwrap(),
+            ..key_payload(&db, DOMAIN, "keyed", &private_key).await
+        };
+        let proof_for_other_name = RegisterWithKeySchema {
+            local_name: "other".to_owned(),
+            ..key_payload(&db, DOMAIN, "keyed", &private_key).await
+        };
+        let proof_for_other_domain =
+            key_payload(&db, "other.example.com", "keyed", &private_key).await;
+        let proof_for_other_challenge = RegisterWithKeySchema {
+            challenge: KeyLoginChallenge::issue_for_registration(&db, 300).await.unwrap().challenge,
+            ..key_payload(&db, DOMAIN, "keyed", &private_key).await
+        };
+        let unissued_challenge = RegisterWithKeySchema {
+            challenge: "00".repeat(32),
+            ..key_payload(&db, DOMAIN, "keyed", &private_key).await
+        };
+
+        for (payload, field_name) in [
+            (foreign_proof, "proof"),
+            (proof_for_other_name, "proof"),
+            (proof_for_other_domain, "proof"),
+            (proof_for_other_challenge, "proof"),
+            (unissued_challenge, "challenge"),
+        ] {
+            let local_name = payload.local_name.clone();
+            let error = register_actor_with_key(payload, DOMAIN, &db, &SecurityConfig::default())
+                .await
+                .unwrap_err();
+            assert_eq!(error.code, Errcode::IllegalInput);
+            assert_eq!(error.context.unwrap().field_name, field_name);
+            assert!(LocalActor::by_local_name(&db, &local_name, false).await.unwrap().is_none());
+        }
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_register_with_key_challenge_is_used_once(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let (private_key, _) = generate_keypair();
+        let payload = key_payload(&db, DOMAIN, "keyed", &private_key).await;
+        register_actor_with_key(payload.clone(), DOMAIN, &db, &SecurityConfig::default())
+            .await
+            .unwrap();
+        let replayed = RegisterWithKeySchema { local_name: "replayed".to_owned(), ..payload };
+        let error = register_actor_with_key(replayed, DOMAIN, &db, &SecurityConfig::default())
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, Errcode::IllegalInput);
+        assert_eq!(error.context.unwrap().field_name, "challenge");
+    }
 }
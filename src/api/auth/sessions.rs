@@ -0,0 +1,98 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use poem::{
+    IntoResponse, handler,
+    web::{Data, Json},
+};
+
+use crate::{
+    api::extractors::{AuthenticatedActor, PaginationParams},
+    database::tokens::TokenStore,
+    errors::Error,
+};
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// List the sessions of the authenticated actor, paginated using
+/// [PaginationParams]. Expired sessions, which have not been cleaned up yet,
+/// are flagged as such.
+pub(super) async fn sessions(
+    Data(token_store): Data<&TokenStore>,
+    AuthenticatedActor(actor): AuthenticatedActor,
+    pagination: PaginationParams,
+) -> Result<impl IntoResponse, Error> {
+    Ok(Json(
+        token_store
+            .list_sessions(&actor.unique_actor_identifier, pagination.limit, pagination.offset)
+            .await?,
+    ))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use chrono::TimeDelta;
+    use poem::{EndpointExt, http::StatusCode, test::TestClient};
+    use sqlx::{Pool, Postgres, types::Uuid};
+
+    use crate::database::{Database, test_helpers::insert_session, tokens::TokenStore};
+
+    #[sqlx::test(fixtures("../../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_sessions_lists_all_sessions_of_actor(pool: Pool<Postgres>) {
+        insert_session(&pool, "session_token_a", 1, Uuid::from_u128(1), None).await;
+        insert_session(&pool, "session_token_b", 5, Uuid::from_u128(1), Some(TimeDelta::days(1)))
+            .await;
+        insert_session(&pool, "other_actors_token", 2, Uuid::from_u128(2), None).await;
+        let db = Database { pool };
+        let client = TestClient::new(
+            super::super::setup_routes().data(db.clone()).data(TokenStore::new(db)),
+        );
+
+        let response =
+            client.get("/sessions").header("Authorization", "session_token_a").send().await;
+        response.assert_status_is_ok();
+        let json = response.json().await;
+        let session_list = json.value().array();
+        session_list.assert_len(2);
+
+        let first = session_list.get(0).object();
+        first.get("sessionId").assert_string("test_session_1");
+        first.get("validNotAfter").assert_null();
+        // The request itself was authenticated using this session
+        first.get("lastSeen").string();
+        first.get("createdAt").string();
+        assert!(first.get_opt("tokenHash").is_none());
+
+        let second = session_list.get(1).object();
+        second.get("sessionId").assert_string("test_session_1_b");
+        second.get("validNotAfter").string();
+        second.get("lastSeen").assert_null();
+
+        // The sessions are paginated
+        let response = client
+            .get("/sessions")
+            .query("limit", &1)
+            .query("offset", &1)
+            .header("Authorization", "session_token_a")
+            .send()
+            .await;
+        response.assert_status_is_ok();
+        let json = response.json().await;
+        let session_list = json.value().array();
+        session_list.assert_len(1);
+        session_list.get(0).object().get("sessionId").assert_string("test_session_1_b");
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_sessions_requires_authentication(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let client = TestClient::new(
+            super::super::setup_routes().data(db.clone()).data(TokenStore::new(db)),
+        );
+
+        let response = client.get("/sessions").send().await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+    }
+}
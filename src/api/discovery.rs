@@ -0,0 +1,140 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use poem::{
+    IntoResponse, handler,
+    web::{Data, Json},
+};
+use serde_json::json;
+
+use crate::{
+    config::{ApiConfig, ComponentConfig, GatewayConfig},
+    database::{AlgorithmIdentifier, Database},
+    errors::Error,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The parts of the discovery document served at
+/// `/.well-known/polyproto-core`, which are derived from the configuration.
+/// The URLs are built from the `server_domain` and the port of the first
+/// address each component binds to, so they only match what clients have to
+/// connect to, if no reverse proxy remaps the ports.
+pub(crate) struct Discovery {
+    /// The domain of this sonata instance.
+    server_domain: String,
+    /// Base URL of the polyproto API, such as `https://example.com/.p2`.
+    api_url: String,
+    /// URL of the WebSocket gateway. `None`, if the gateway is disabled.
+    gateway_url: Option<String>,
+}
+
+impl Discovery {
+    /// Derive the [Discovery] of the instance at `server_domain` from the
+    /// [ApiConfig] and the [GatewayConfig].
+    pub(crate) fn new(
+        server_domain: &str,
+        api_config: &ApiConfig,
+        gateway_config: &GatewayConfig,
+    ) -> Self {
+        let api_url = format!(
+            "{}{}",
+            base_url(server_domain, api_config, "https", "http"),
+            api_config.effective_base_path()
+        );
+        let gateway_url =
+            gateway_config.enabled.then(|| base_url(server_domain, gateway_config, "wss", "ws"));
+        Self { server_domain: server_domain.to_owned(), api_url, gateway_url }
+    }
+}
+
+/// The URL of the `component` at `server_domain`, using the `tls_scheme` or
+/// the `plain_scheme`, depending on [ComponentConfig::tls]. Default ports are
+/// omitted.
+fn base_url(
+    server_domain: &str,
+    component: &ComponentConfig,
+    tls_scheme: &str,
+    plain_scheme: &str,
+) -> String {
+    let (scheme, default_port) = if component.tls { (tls_scheme, 443) } else { (plain_scheme, 80) };
+    match component.bind_addresses().first() {
+        Some((_, port)) if *port != default_port => format!("{scheme}://{server_domain}:{port}"),
+        _ => format!("{scheme}://{server_domain}"),
+    }
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// The discovery document describing this instance to federating servers:
+/// Its domain, the OIDs of the signature algorithms it supports and the URLs
+/// of its API and gateway. Does not require authentication.
+pub(super) async fn polyproto_core(
+    Data(db): Data<&Database>,
+    Data(discovery): Data<&Discovery>,
+) -> Result<impl IntoResponse, Error> {
+    Ok(Json(json!({
+        "serverDomain": discovery.server_domain,
+        "signatureAlgorithms": AlgorithmIdentifier::supported_oids(db).await?,
+        "api": discovery.api_url,
+        "gateway": discovery.gateway_url,
+    })))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, Route, get, test::TestClient};
+    use polyproto::signature::Signature;
+    use sqlx::{Pool, Postgres};
+
+    use super::*;
+    use crate::{config::BindHost, crypto::ed25519::DigitalSignature};
+
+    #[test]
+    fn test_urls() {
+        let mut api_config = ApiConfig::for_tests();
+        api_config.port = 443;
+        api_config.tls = true;
+        api_config.base_path = "/polyproto/.p2/".to_owned();
+        let mut gateway_config = GatewayConfig::for_tests();
+        gateway_config.tls = true;
+        let derived = Discovery::new("example.com", &api_config, &gateway_config);
+        assert_eq!(derived.api_url, "https://example.com/polyproto/.p2");
+        assert_eq!(derived.gateway_url.as_deref(), Some("wss://example.com:3012"));
+
+        let mut api_config = ApiConfig::for_tests();
+        api_config.host = BindHost::Multiple(vec!["127.0.0.1:8080".to_owned()]);
+        let mut gateway_config = GatewayConfig::for_tests();
+        gateway_config.enabled = false;
+        gateway_config.port = 80;
+        let derived = Discovery::new("example.com", &api_config, &gateway_config);
+        assert_eq!(derived.api_url, "http://example.com:8080/.p2");
+        assert_eq!(derived.gateway_url, None);
+    }
+
+    #[sqlx::test]
+    async fn test_polyproto_core(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let ed25519 = DigitalSignature::algorithm_identifier().oid;
+        AlgorithmIdentifier::try_insert(&db, &ed25519, Some("Ed25519"), Default::default())
+            .await
+            .unwrap();
+        let client = TestClient::new(
+            Route::new().at("/.well-known/polyproto-core", get(polyproto_core)).data(db).data(
+                Discovery::new("example.com", &ApiConfig::for_tests(), &GatewayConfig::for_tests()),
+            ),
+        );
+
+        let response = client.get("/.well-known/polyproto-core").send().await;
+        response.assert_status_is_ok();
+        response
+            .assert_json(json!({
+                "serverDomain": "example.com",
+                "signatureAlgorithms": [ed25519.to_string()],
+                "api": "http://example.com:3011/.p2",
+                "gateway": "ws://example.com:3012",
+            }))
+            .await;
+    }
+}
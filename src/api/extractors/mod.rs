@@ -0,0 +1,462 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use log::error;
+use poem::{
+    FromRequest, Request, RequestBody, Response,
+    http::{StatusCode, header},
+    web::Query,
+};
+use serde::Deserialize;
+
+use crate::{
+    config::PaginationConfig,
+    database::{Database, Issuer, LocalActor, tokens::TokenActorIdPair},
+    errors::{Context, Errcode, Error},
+};
+
+/// Extractor resolving the [LocalActor] authenticated by the
+/// [AuthenticationMiddleware](crate::api::middlewares::AuthenticationMiddleware).
+/// Can only be used in handlers behind that middleware.
+///
+/// Rejects the request with `401 Unauthorized`, if the actor has been deleted
+/// or deactivated since the token was issued.
+#[derive(Debug)]
+pub struct AuthenticatedActor(pub LocalActor);
+
+impl<'a> FromRequest<'a> for AuthenticatedActor {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> poem::Result<Self> {
+        let token_actor_id_pair = req
+            .data::<TokenActorIdPair>()
+            .ok_or(poem::error::Error::from_status(StatusCode::UNAUTHORIZED))?;
+        let db = req.data::<Database>().ok_or_else(|| {
+            error!("AuthenticatedActor extractor used without a Database in the request data");
+            poem::error::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+        let actor = LocalActor::by_uaid(db, &token_actor_id_pair.uaid)
+            .await
+            .map_err(|_| poem::error::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?
+            .ok_or(poem::error::Error::from_status(StatusCode::UNAUTHORIZED))?;
+        if actor.is_deactivated {
+            return Err(poem::error::Error::from_status(StatusCode::UNAUTHORIZED));
+        }
+        Ok(Self(actor))
+    }
+}
+
+/// The normalized domains served by this sonata instance. Needs to be
+/// available as request data for the [RequestIssuer] extractor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServedDomains(Vec<String>);
+
+impl ServedDomains {
+    /// Create [ServedDomains] from a list of domains, such as
+    /// [GeneralConfig::served_domains](crate::config::GeneralConfig::served_domains).
+    pub fn new<'a>(domains: impl IntoIterator<Item = &'a str>) -> Self {
+        Self(domains.into_iter().map(Issuer::normalize_domain).collect())
+    }
+
+    /// Find the served domain addressed by `host`, the value of a `Host`
+    /// header. An optional port is ignored. Returns `None`, if `host` does
+    /// not address any of the served domains.
+    pub fn resolve(&self, host: &str) -> Option<&str> {
+        let host = match host.rsplit_once(':') {
+            Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+            _ => host,
+        };
+        let host = Issuer::normalize_domain(host);
+        self.0.iter().find(|domain| **domain == host).map(String::as_str)
+    }
+}
+
+/// Extractor resolving the [Issuer] of the served domain a request is addressed
+/// to, based on its `Host` header, or the authority of its URI. Requires
+/// [ServedDomains] and a [Database] in the request data.
+///
+/// Rejects the request with `421 Misdirected Request`, if it is not addressed
+/// to a domain served by this sonata instance.
+#[derive(Debug)]
+pub struct RequestIssuer(pub Issuer);
+
+impl<'a> FromRequest<'a> for RequestIssuer {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> poem::Result<Self> {
+        let (Some(served_domains), Some(db)) =
+            (req.data::<ServedDomains>(), req.data::<Database>())
+        else {
+            error!(
+                "RequestIssuer extractor used without ServedDomains or a Database in the request data"
+            );
+            return Err(poem::error::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR));
+        };
+        let domain = req
+            .uri()
+            .authority()
+            .map(|authority| authority.as_str())
+            .or_else(|| req.header(header::HOST))
+            .and_then(|host| served_domains.resolve(host))
+            .ok_or(poem::error::Error::from_status(StatusCode::MISDIRECTED_REQUEST))?;
+        match Issuer::get_by_domain(db, domain).await {
+            Ok(Some(issuer)) => Ok(Self(issuer)),
+            Ok(None) => {
+                error!("No issuer entry exists for the served domain {domain}");
+                Err(poem::error::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))
+            }
+            Err(_) => Err(poem::error::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR)),
+        }
+    }
+}
+
+/// Extractor negotiating the encoding of certificates in a response, based on
+/// the `Accept` header of the request. PEM is used, if the client accepts any
+/// media type or does not send an `Accept` header at all.
+///
+/// Rejects the request with `406 Not Acceptable`, if the client accepts
+/// neither of the [CertEncoding::PEM_MEDIA_TYPE] and
+/// [CertEncoding::DER_MEDIA_TYPE].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertEncoding {
+    /// PEM-encoded certificates, as stored in the database.
+    Pem,
+    /// DER-encoded certificates.
+    Der,
+}
+
+impl CertEncoding {
+    /// Media type of PEM-encoded certificates.
+    pub const PEM_MEDIA_TYPE: &str = "application/x-pem-file";
+    /// Media type of DER-encoded certificates.
+    pub const DER_MEDIA_TYPE: &str = "application/pkix-cert";
+
+    /// Pick the [CertEncoding] for the value of an `Accept` header, preferring
+    /// the media type with the highest quality value. Returns `None`, if none
+    /// of the accepted media types is supported.
+    fn negotiate(accept: Option<&str>) -> Option<Self> {
+        let Some(accept) = accept.filter(|accept| !accept.trim().is_empty()) else {
+            return Some(Self::Pem);
+        };
+        let mut best: Option<(Self, f32)> = None;
+        for media_range in accept.split(',') {
+            let mut parameters = media_range.split(';').map(str::trim);
+            let media_type = parameters.next().unwrap_or_default().to_ascii_lowercase();
+            let quality = parameters
+                .filter_map(|parameter| parameter.strip_prefix("q="))
+                .find_map(|quality| quality.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let encoding = match media_type.as_str() {
+                Self::DER_MEDIA_TYPE => Self::Der,
+                Self::PEM_MEDIA_TYPE | "application/*" | "*/*" => Self::Pem,
+                _ => continue,
+            };
+            if quality > 0.0 && best.is_none_or(|(_, best_quality)| quality > best_quality) {
+                best = Some((encoding, quality));
+            }
+        }
+        best.map(|(encoding, _)| encoding)
+    }
+
+    /// Build a `200 OK` response containing the PEM-encoded certificate `pem`
+    /// in this encoding.
+    ///
+    /// ## Errors
+    ///
+    /// Errors with `500 Internal Server Error`, if `pem` is not valid PEM.
+    pub fn respond(self, pem: &str) -> poem::Result<Response> {
+        let response = Response::builder().status(StatusCode::OK);
+        Ok(match self {
+            Self::Pem => response.content_type(Self::PEM_MEDIA_TYPE).body(pem.to_owned()),
+            Self::Der => {
+                let (_label, der) =
+                    polyproto::der::pem::decode_vec(pem.as_bytes()).map_err(|e| {
+                        error!("Stored certificate is not valid PEM: {e}");
+                        poem::error::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR)
+                    })?;
+                response.content_type(Self::DER_MEDIA_TYPE).body(der)
+            }
+        })
+    }
+}
+
+impl<'a> FromRequest<'a> for CertEncoding {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> poem::Result<Self> {
+        Self::negotiate(req.header(header::ACCEPT))
+            .ok_or(poem::error::Error::from_status(StatusCode::NOT_ACCEPTABLE))
+    }
+}
+
+/// Extractor for the `?limit=` and `?offset=` query parameters of listing
+/// endpoints. The page size is determined by
+/// [PaginationConfig::page_size], using the [PaginationConfig] in the request
+/// data, or its default, if there is none.
+///
+/// Rejects the request with `400 Bad Request`, if `limit` is less than `1`,
+/// `offset` is negative, or either is not a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaginationParams {
+    /// How many items to return at most.
+    pub limit: i64,
+    /// How many items to skip.
+    pub offset: i64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+/// The query parameters [PaginationParams] are parsed from.
+struct PaginationQuery {
+    /// The requested page size.
+    limit: Option<i64>,
+    /// The requested number of items to skip.
+    offset: Option<i64>,
+}
+
+impl PaginationParams {
+    /// Validate the requested `query` and apply the page sizes of `config`.
+    ///
+    /// ## Errors
+    ///
+    /// [Errcode::IllegalInput], if `limit` is less than `1` or `offset` is
+    /// negative.
+    #[allow(clippy::result_large_err)]
+    fn new(query: &PaginationQuery, config: &PaginationConfig) -> Result<Self, Error> {
+        let invalid = |field: &str, value: i64, expected: &str| {
+            Error::new(
+                Errcode::IllegalInput,
+                Some(Context::new(Some(field), Some(&value.to_string()), Some(expected), None)),
+            )
+        };
+        let requested = match query.limit {
+            Some(limit) if limit < 1 => return Err(invalid("limit", limit, "1 or more")),
+            Some(limit) => Some(u32::try_from(limit).unwrap_or(u32::MAX)),
+            None => None,
+        };
+        let offset = query.offset.unwrap_or(0);
+        if offset < 0 {
+            return Err(invalid("offset", offset, "0 or more"));
+        }
+        Ok(Self { limit: i64::from(config.page_size(requested)), offset })
+    }
+}
+
+impl<'a> FromRequest<'a> for PaginationParams {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> poem::Result<Self> {
+        let Query(query) = Query::<PaginationQuery>::from_request_without_body(req).await?;
+        let config = req.data::<PaginationConfig>().copied().unwrap_or_default();
+        Ok(Self::new(&query, &config)?)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, Route, get, handler, test::TestClient};
+    use sqlx::{Pool, Postgres, query, types::Uuid};
+
+    use super::*;
+    use crate::{
+        api::middlewares::AuthenticationMiddleware,
+        database::{test_helpers::insert_session, tokens::TokenStore},
+    };
+
+    #[handler]
+    fn local_name(AuthenticatedActor(actor): AuthenticatedActor) -> String {
+        actor.local_name
+    }
+
+    async fn client(pool: Pool<Postgres>) -> TestClient<impl poem::Endpoint> {
+        insert_session(&pool, "token_user_1", 1, Uuid::from_u128(1), None).await;
+        insert_session(&pool, "token_user_2", 2, Uuid::from_u128(2), None).await;
+        let db = Database { pool };
+        TestClient::new(
+            Route::new()
+                .at("/", get(local_name).with(AuthenticationMiddleware))
+                .data(db.clone())
+                .data(TokenStore::new(db)),
+        )
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_authenticated_actor_resolves_actor(pool: Pool<Postgres>) {
+        let client = client(pool).await;
+
+        let response = client.get("/").header("Authorization", "token_user_1").send().await;
+        response.assert_status_is_ok();
+        response.assert_text("test_user_1").await;
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_authenticated_actor_rejects_deactivated_actor(pool: Pool<Postgres>) {
+        query!(
+            "UPDATE local_actors SET deactivated = TRUE
+            WHERE uaid = '00000000-0000-0000-0000-000000000002'"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let client = client(pool).await;
+
+        let response = client.get("/").header("Authorization", "token_user_2").send().await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+    }
+
+    #[handler]
+    fn issuer_domain(RequestIssuer(issuer): RequestIssuer) -> String {
+        issuer.domain_components.to_string()
+    }
+
+    #[test]
+    fn test_served_domains_resolve() {
+        let served_domains = ServedDomains::new(["example.com", "Other.Example.org."]);
+        assert_eq!(served_domains.resolve("example.com"), Some("example.com"));
+        assert_eq!(served_domains.resolve("EXAMPLE.com:3011"), Some("example.com"));
+        assert_eq!(served_domains.resolve("other.example.org."), Some("other.example.org"));
+        assert_eq!(served_domains.resolve("sub.example.com"), None);
+        assert_eq!(served_domains.resolve("example.com:port"), None);
+        assert_eq!(served_domains.resolve(""), None);
+    }
+
+    #[sqlx::test]
+    async fn test_request_issuer_resolves_issuer_by_host(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        Issuer::create_many(&db, ["a.example.com", "b.example.com", "unserved.example.com"])
+            .await
+            .unwrap();
+        let client = TestClient::new(
+            Route::new()
+                .at("/issuer", get(issuer_domain))
+                .data(ServedDomains::new(["a.example.com", "B.Example.com"]))
+                .data(db),
+        );
+
+        for (host, expected) in [
+            ("a.example.com", "a.example.com"),
+            ("b.example.com:3011", "b.example.com"),
+            ("A.EXAMPLE.COM", "a.example.com"),
+        ] {
+            let response = client.get("/issuer").header("Host", host).send().await;
+            response.assert_status_is_ok();
+            response.assert_text(expected).await;
+        }
+        // Issuers existing in the database are not enough, the domain must be served
+        for host in ["unserved.example.com", "c.example.com"] {
+            let response = client.get("/issuer").header("Host", host).send().await;
+            response.assert_status(StatusCode::MISDIRECTED_REQUEST);
+        }
+        client.get("/issuer").send().await.assert_status(StatusCode::MISDIRECTED_REQUEST);
+    }
+
+    #[sqlx::test]
+    async fn test_request_issuer_missing_issuer_entry(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let client = TestClient::new(
+            Route::new()
+                .at("/issuer", get(issuer_domain))
+                .data(ServedDomains::new(["a.example.com"]))
+                .data(db),
+        );
+
+        let response = client.get("/issuer").header("Host", "a.example.com").send().await;
+        response.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[handler]
+    fn cert(encoding: CertEncoding) -> poem::Result<Response> {
+        // A DER-encoded `SEQUENCE { INTEGER 1 }`. Any PEM document will do, as the
+        // encoding does not depend on the contents
+        let pem = polyproto::der::pem::encode_string(
+            "CERTIFICATE",
+            polyproto::der::pem::LineEnding::LF,
+            &[0x30, 0x03, 0x02, 0x01, 0x01],
+        )
+        .unwrap();
+        encoding.respond(&pem)
+    }
+
+    #[test]
+    fn test_cert_encoding_negotiate() {
+        for (accept, expected) in [
+            (None, Some(CertEncoding::Pem)),
+            (Some(""), Some(CertEncoding::Pem)),
+            (Some("*/*"), Some(CertEncoding::Pem)),
+            (Some("application/x-pem-file"), Some(CertEncoding::Pem)),
+            (Some("Application/PKIX-Cert"), Some(CertEncoding::Der)),
+            (Some("text/html, application/pkix-cert;q=0.9, */*;q=0.1"), Some(CertEncoding::Der)),
+            (Some("application/pkix-cert;q=0.5, application/x-pem-file"), Some(CertEncoding::Pem)),
+            (Some("application/pkix-cert;q=0"), None),
+            (Some("text/html, application/json"), None),
+        ] {
+            assert_eq!(CertEncoding::negotiate(accept), expected, "Accept: {accept:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cert_encoding_responses() {
+        let client = TestClient::new(Route::new().at("/cert", get(cert)));
+
+        let response = client.get("/cert").send().await;
+        response.assert_status_is_ok();
+        response.assert_content_type(CertEncoding::PEM_MEDIA_TYPE);
+        let pem = response.0.into_body().into_string().await.unwrap();
+        assert!(pem.starts_with("-----BEGIN CERTIFICATE-----"));
+
+        let response =
+            client.get("/cert").header("Accept", CertEncoding::DER_MEDIA_TYPE).send().await;
+        response.assert_status_is_ok();
+        response.assert_content_type(CertEncoding::DER_MEDIA_TYPE);
+        let der = response.0.into_body().into_vec().await.unwrap();
+        assert_eq!(der, [0x30, 0x03, 0x02, 0x01, 0x01]);
+
+        let response = client.get("/cert").header("Accept", "application/json").send().await;
+        response.assert_status(StatusCode::NOT_ACCEPTABLE);
+    }
+
+    #[test]
+    fn test_pagination_params_defaults_and_capping() {
+        let config = PaginationConfig { default_page_size: 20, max_page_size: 50 };
+        let params = |limit, offset| {
+            PaginationParams::new(&PaginationQuery { limit, offset }, &config).map_err(|e| e.code)
+        };
+
+        assert_eq!(params(None, None).unwrap(), PaginationParams { limit: 20, offset: 0 });
+        assert_eq!(params(Some(5), Some(10)).unwrap(), PaginationParams { limit: 5, offset: 10 });
+        assert_eq!(params(Some(51), None).unwrap().limit, 50);
+        assert_eq!(params(Some(i64::MAX), None).unwrap().limit, 50);
+    }
+
+    #[test]
+    fn test_pagination_params_rejects_invalid_values() {
+        let config = PaginationConfig::default();
+        let params = |limit, offset| {
+            PaginationParams::new(&PaginationQuery { limit, offset }, &config).map_err(|e| e.code)
+        };
+
+        for (limit, offset) in [(Some(-1), None), (Some(0), None), (None, Some(-1))] {
+            assert_eq!(params(limit, offset).unwrap_err(), Errcode::IllegalInput);
+        }
+    }
+
+    #[handler]
+    fn pagination(pagination: PaginationParams) -> String {
+        format!("{} {}", pagination.limit, pagination.offset)
+    }
+
+    #[tokio::test]
+    async fn test_pagination_params_extractor() {
+        let client = TestClient::new(
+            Route::new()
+                .at("/", get(pagination))
+                .data(PaginationConfig { default_page_size: 10, max_page_size: 30 }),
+        );
+
+        client.get("/").send().await.assert_text("10 0").await;
+        client
+            .get("/")
+            .query("limit", &100)
+            .query("offset", &5)
+            .send()
+            .await
+            .assert_text("30 5")
+            .await;
+        for (name, value) in [("limit", "-5"), ("offset", "-1"), ("limit", "many")] {
+            client.get("/").query(name, &value).send().await.assert_status(StatusCode::BAD_REQUEST);
+        }
+    }
+}
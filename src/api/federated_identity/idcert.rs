@@ -0,0 +1,191 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use poem::{
+    Response, handler,
+    web::{Data, Path, Query},
+};
+use serde::Deserialize;
+
+use crate::{
+    api::extractors::{CertEncoding, RequestIssuer},
+    database::{Database, HomeServerCert},
+    errors::{Context, Errcode, Error},
+};
+
+#[derive(Debug, Deserialize)]
+/// Query parameters of the ID-Cert retrieval endpoints.
+pub(super) struct IdCertQuery {
+    /// UNIX timestamp in seconds, at which the ID-Certs must be valid.
+    /// Defaults to the current time.
+    timestamp: Option<i64>,
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Get the ID-Cert of this home server, valid at the requested timestamp.
+pub(super) async fn get_server_idcert(
+    Data(db): Data<&Database>,
+    RequestIssuer(issuer): RequestIssuer,
+    Query(query): Query<IdCertQuery>,
+    encoding: CertEncoding,
+) -> poem::Result<Response> {
+    let timestamp = query.timestamp()?;
+    let pem = HomeServerCert::get_pem_by(db, &issuer.domain_components, &timestamp)
+        .await?
+        .ok_or_else(|| {
+            Error::new_not_found_error(Some("This home server has no ID-Cert valid at this time"))
+        })?;
+    encoding.respond(&pem)
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Get the ID-Certs of the local actor called `local_name`, valid at the
+/// requested timestamp. PEM-encoded ID-Certs are returned as a bundle of all of
+/// them, DER-encoded ones only as the most recently issued one, as DER cannot
+/// hold more than a single certificate.
+pub(super) async fn get_actor_idcerts(
+    Path(local_name): Path<String>,
+    Data(db): Data<&Database>,
+    RequestIssuer(issuer): RequestIssuer,
+    Query(query): Query<IdCertQuery>,
+    encoding: CertEncoding,
+) -> poem::Result<Response> {
+    let timestamp = query.timestamp()?;
+    let pems =
+        HomeServerCert::get_actor_pems_by(db, &issuer.domain_components, &local_name, &timestamp)
+            .await?;
+    let Some(newest) = pems.first() else {
+        return Err(Error::new_not_found_error(Some(
+            "This actor does not exist or has no ID-Cert valid at this time",
+        ))
+        .into());
+    };
+    match encoding {
+        CertEncoding::Pem => encoding.respond(&pems.concat()),
+        CertEncoding::Der => encoding.respond(newest),
+    }
+}
+
+impl IdCertQuery {
+    /// The requested timestamp, or the current time if there is none.
+    ///
+    /// ## Errors
+    ///
+    /// [Errcode::IllegalInput], if the timestamp is out of range.
+    #[allow(clippy::result_large_err)]
+    fn timestamp(&self) -> Result<NaiveDateTime, Error> {
+        let Some(timestamp) = self.timestamp else {
+            return Ok(Utc::now().naive_utc());
+        };
+        DateTime::from_timestamp(timestamp, 0).map(|time| time.naive_utc()).ok_or_else(|| {
+            Error::new(
+                Errcode::IllegalInput,
+                Some(Context::new(
+                    Some("timestamp"),
+                    Some(&timestamp.to_string()),
+                    Some("A UNIX timestamp in seconds"),
+                    None,
+                )),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, http::StatusCode, test::TestClient};
+    use sqlx::{Pool, Postgres};
+
+    use super::*;
+    use crate::api::extractors::ServedDomains;
+
+    fn client(pool: Pool<Postgres>) -> TestClient<impl poem::Endpoint> {
+        TestClient::new(
+            super::super::setup_routes().data(Database { pool }).data(ServedDomains::new([
+                "example.com",
+                "test.org",
+                "expired.net",
+            ])),
+        )
+    }
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(
+            IdCertQuery { timestamp: Some(1_700_000_000) }.timestamp().unwrap(),
+            DateTime::from_timestamp(1_700_000_000, 0).unwrap().naive_utc()
+        );
+        assert_eq!(
+            IdCertQuery { timestamp: Some(i64::MAX) }.timestamp().unwrap_err().code,
+            Errcode::IllegalInput
+        );
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/idcert_integration_tests.sql"))]
+    async fn test_get_server_idcert(pool: Pool<Postgres>) {
+        let client = client(pool);
+
+        let response = client.get("/idcert/server").header("Host", "example.com").send().await;
+        response.assert_status_is_ok();
+        response.assert_content_type(CertEncoding::PEM_MEDIA_TYPE);
+        response.assert_text("PLACEHOLDER_HOMESERVER_CERT_PEM_1").await;
+
+        // The ID-Cert is not valid yet in 2020, and there is none for expired.net
+        client
+            .get("/idcert/server")
+            .header("Host", "example.com")
+            .query("timestamp", &1_577_836_800)
+            .send()
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+        client
+            .get("/idcert/server")
+            .header("Host", "expired.net")
+            .send()
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/idcert_integration_tests.sql"))]
+    async fn test_get_actor_idcerts(pool: Pool<Postgres>) {
+        let client = client(pool);
+
+        let response = client
+            .get("/idcert/actor/idcert_test_user_1")
+            .header("Host", "example.com")
+            .send()
+            .await;
+        response.assert_status_is_ok();
+        response.assert_text("PLACEHOLDER_CERT_PEM_1").await;
+
+        // The ID-Cert of the actor has been issued by another domain
+        client
+            .get("/idcert/actor/idcert_test_user_1")
+            .header("Host", "test.org")
+            .send()
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+        // The ID-Cert of the actor has expired
+        client
+            .get("/idcert/actor/idcert_test_user_3")
+            .header("Host", "expired.net")
+            .send()
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/idcert_integration_tests.sql"))]
+    async fn test_get_nonexistent_actor_idcerts(pool: Pool<Postgres>) {
+        let client = client(pool);
+
+        let response =
+            client.get("/idcert/actor/nonexistent").header("Host", "example.com").send().await;
+        response.assert_status(StatusCode::NOT_FOUND);
+        assert!(response.0.into_body().into_string().await.unwrap().contains("P2_CORE_NOT_FOUND"));
+    }
+}
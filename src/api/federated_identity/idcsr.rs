@@ -0,0 +1,499 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, Utc};
+use log::{debug, error};
+use poem::{Response, handler, web::Data};
+use polyproto::{
+    Name, OID_RDN_UID, OID_RDN_UNIQUE_IDENTIFIER,
+    certs::{SessionId, Target, idcert::IdCert, idcsr::IdCsr},
+    der::{DecodePem, Encode, pem::LineEnding},
+    key::PublicKey,
+    signature::Signature,
+    spki::AlgorithmIdentifierOwned,
+};
+use x509_cert::{
+    ext::Extensions,
+    request::CertReq,
+    time::{Time, Validity},
+};
+
+use crate::{
+    api::extractors::{AuthenticatedActor, CertEncoding, RequestIssuer},
+    config::{ReloadableConfigHandle, SecurityConfig},
+    crypto::{
+        ed25519::{DigitalPublicKey, DigitalSignature},
+        signing_key::HomeServerSigningKey,
+    },
+    database::{
+        AlgorithmIdentifier, Database, Issuer, LocalActor, NewIdCert, PublicKeyInfo, SerialNumber,
+    },
+    errors::{Context, Errcode, Error},
+};
+
+#[handler]
+/// Issue an ID-Cert for the PEM-encoded ID-CSR in the request body, which the
+/// authenticated actor has signed with one of its registered public keys. The
+/// ID-Cert is returned in the [CertEncoding] negotiated with the client.
+pub(super) async fn submit_idcsr(
+    csr_pem: String,
+    Data(db): Data<&Database>,
+    Data(signing_key): Data<&HomeServerSigningKey>,
+    Data(reloadable_config): Data<&ReloadableConfigHandle>,
+    AuthenticatedActor(actor): AuthenticatedActor,
+    RequestIssuer(issuer): RequestIssuer,
+    encoding: CertEncoding,
+) -> poem::Result<Response> {
+    let security_config = &reloadable_config.current().security;
+    let cert_pem =
+        issue_idcert(db, signing_key, &actor, &issuer, &csr_pem, security_config).await?;
+    encoding.respond(&cert_pem)
+}
+
+/// Verify the ID-CSR `csr_pem` of `actor` and issue an ID-Cert for it, signed
+/// with the `signing_key` on behalf of `issuer`. The ID-CSR and the ID-Cert
+/// are stored, and the PEM encoding of the ID-Cert is returned. The ID-Cert is
+/// valid for [SecurityConfig::idcert_validity_secs] from now on.
+///
+/// ## Errors
+///
+/// [Errcode::IllegalInput], if
+///
+/// - `csr_pem` is not a well-formed, correctly signed ID-CSR for an actor
+/// - the signature algorithm of the ID-CSR is not in the
+///   `algorithm_identifiers` table, or not the one of the `signing_key`
+/// - the subject of the ID-CSR is not `actor` at the domain of `issuer`
+/// - the public key of the ID-CSR is not registered for `actor`
+/// - the session ID of the ID-CSR is not 1 to 32 ASCII characters long
+///
+/// [Errcode::Duplicate], if `actor` already has an ID-Cert for the session of
+/// the ID-CSR, which has not been invalidated.
+pub(super) async fn issue_idcert(
+    db: &Database,
+    signing_key: &HomeServerSigningKey,
+    actor: &LocalActor,
+    issuer: &Issuer,
+    csr_pem: &str,
+    security_config: &SecurityConfig,
+) -> Result<String, Error> {
+    let request = CertReq::from_pem(csr_pem).map_err(|e| malformed_csr(&e.to_string()))?;
+    if AlgorithmIdentifier::get_by_algorithm_identifier(db, &request.algorithm).await?.is_none()
+        || request.algorithm != DigitalSignature::algorithm_identifier()
+    {
+        return Err(unsupported_algorithm(&request.algorithm));
+    }
+    let csr = IdCsr::<DigitalSignature, DigitalPublicKey>::from_pem(csr_pem, Some(Target::Actor))
+        .map_err(|e| malformed_csr(&e.to_string()))?;
+
+    let federation_id = format!("{}@{}", actor.local_name, issuer.domain_components);
+    let uid = subject_attribute(&csr.inner_csr.subject, OID_RDN_UID);
+    if uid.as_deref() != Some(federation_id.as_str()) {
+        return Err(Error::new(
+            Errcode::IllegalInput,
+            Some(Context::new(
+                Some("csr"),
+                uid.as_deref(),
+                Some(&federation_id),
+                Some("The subject of the ID-CSR must be the authenticated actor"),
+            )),
+        ));
+    }
+    let session_id = subject_attribute(&csr.inner_csr.subject, OID_RDN_UNIQUE_IDENTIFIER)
+        .ok_or_else(|| malformed_csr("The subject of the ID-CSR has no session ID"))?;
+    let session_id = parse_session_id(&session_id)?;
+
+    let Some(stored_key) = PublicKeyInfo::get_by(
+        db,
+        Some(actor.unique_actor_identifier),
+        Some(PublicKeyInfo::encode_public_key(&csr.inner_csr.subject_public_key)?),
+        None,
+        None,
+    )
+    .await?
+    .into_iter()
+    .next() else {
+        return Err(Error::new(
+            Errcode::IllegalInput,
+            Some(Context::new(
+                Some("csr"),
+                None,
+                None,
+                Some("The public key of the ID-CSR is not registered for this actor"),
+            )),
+        ));
+    };
+    let subject_public_key = stored_key.to_public_key::<DigitalSignature, DigitalPublicKey>()?;
+    let signature_data = csr.signature_data().map_err(|e| malformed_csr(&e.to_string()))?;
+    subject_public_key
+        .verify_signature(&csr.signature, &signature_data)
+        .map_err(|_| malformed_csr("The signature of the ID-CSR is invalid"))?;
+
+    // The `idcsr` table still rejects a serial number taken concurrently, after
+    // this has checked it
+    let serial_number =
+        SerialNumber::try_generate_unique_random(db, security_config.serial_number_msb_strategy)
+            .await?;
+    // X.509 validity periods have a precision of seconds
+    let now = UNIX_EPOCH
+        .checked_add(Duration::from_secs(
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+        ))
+        .unwrap_or(UNIX_EPOCH);
+    let valid_not_after = now
+        .checked_add(Duration::from_secs(security_config.idcert_validity_secs))
+        .ok_or_else(|| Error::new_internal_error(Some("The ID-Cert validity is too long")))?;
+    let validity = Validity {
+        not_before: Time::try_from(now).map_err(internal_error)?,
+        not_after: Time::try_from(valid_not_after).map_err(internal_error)?,
+    };
+    let extensions = Extensions::try_from(csr.inner_csr.capabilities.clone())
+        .map_err(internal_error)?
+        .to_der()
+        .map_err(internal_error)?;
+    let cert = IdCert::from_actor_csr(
+        csr.clone(),
+        &signing_key.key,
+        serial_number.clone().into(),
+        issuer_name(issuer)?,
+        validity,
+    )
+    .map_err(internal_error)?;
+    let home_server_signature = cert.signature.to_string();
+    let cert_pem = cert.to_pem(LineEnding::LF).map_err(internal_error)?;
+
+    PublicKeyInfo::insert_with_cert(
+        db,
+        &subject_public_key,
+        Some(actor.unique_actor_identifier),
+        security_config,
+        &NewIdCert {
+            serial_number,
+            session_id,
+            subject_signature: csr.signature.to_string(),
+            extensions: hex::encode(extensions),
+            csr_pem: csr_pem.to_owned(),
+            valid_not_before: DateTime::<Utc>::from(now).naive_utc(),
+            valid_not_after: DateTime::<Utc>::from(valid_not_after).naive_utc(),
+            issuer_info_id: issuer.id(),
+            home_server_public_key_id: signing_key.public_key_id,
+            home_server_signature,
+            cert_pem: cert_pem.clone(),
+        },
+    )
+    .await?;
+    debug!("Issued an ID-Cert to {federation_id}");
+    Ok(cert_pem)
+}
+
+/// The value of the first attribute with the object identifier `oid` in
+/// `subject`, if there is one.
+fn subject_attribute(subject: &Name, oid: &str) -> Option<String> {
+    subject
+        .0
+        .iter()
+        .flat_map(|rdn| rdn.0.iter())
+        .find(|attribute| attribute.oid.to_string() == oid)
+        .map(|attribute| String::from_utf8_lossy(attribute.value.value()).into_owned())
+}
+
+/// Parse the session ID of an ID-CSR, which has to consist of 1 to 32 ASCII
+/// characters.
+#[allow(clippy::result_large_err)]
+fn parse_session_id(session_id: &str) -> Result<SessionId, Error> {
+    SessionId::new_validated(session_id).map_err(|_| {
+        malformed_csr("The session ID of the ID-CSR must consist of 1 to 32 ASCII characters")
+    })
+}
+
+/// The distinguished name of `issuer`, made up of its domain components.
+#[allow(clippy::result_large_err)]
+fn issuer_name(issuer: &Issuer) -> Result<Name, Error> {
+    let domain_components = issuer
+        .domain_components
+        .to_string()
+        .split('.')
+        .map(|component| format!("DC={component}"))
+        .collect::<Vec<_>>()
+        .join(",");
+    Name::from_str(&domain_components).map_err(internal_error)
+}
+
+/// [Errcode::IllegalInput] for an ID-CSR, which is not acceptable because of
+/// `reason`.
+fn malformed_csr(reason: &str) -> Error {
+    Error::new(Errcode::IllegalInput, Some(Context::new(Some("csr"), None, None, Some(reason))))
+}
+
+/// [Errcode::IllegalInput] for an ID-CSR signed with the unsupported
+/// `algorithm`.
+fn unsupported_algorithm(algorithm: &AlgorithmIdentifierOwned) -> Error {
+    Error::new(
+        Errcode::IllegalInput,
+        Some(Context::new(
+            Some("csr"),
+            Some(&algorithm.oid.to_string()),
+            Some(&DigitalSignature::algorithm_identifier().oid.to_string()),
+            Some("The ID-CSR is signed with an algorithm not supported by this server"),
+        )),
+    )
+}
+
+/// Log `e`, which cannot be caused by the client, and turn it into an internal
+/// error.
+fn internal_error(e: impl std::fmt::Display) -> Error {
+    error!("Error while issuing an ID-Cert: {e}");
+    Error::new_internal_error(None)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, http::StatusCode, test::TestClient};
+    use polyproto::{certs::capabilities::Capabilities, key::PrivateKey};
+    use sqlx::{Pool, Postgres, query, types::Uuid};
+    use x509_cert::Certificate;
+
+    use super::*;
+    use crate::{
+        api::extractors::ServedDomains,
+        config::ReloadableConfig,
+        crypto::{ecdsa, ed25519::generate_keypair},
+        database::{test_helpers::insert_session, tokens::TokenStore},
+    };
+
+    /// The UAID of `full_state_alice` in the `full_state` fixture.
+    const ALICE: Uuid = Uuid::from_u128(0x1001);
+
+    /// Everything needed to issue an ID-Cert to `full_state_alice`, whose
+    /// returned private key is registered.
+    async fn setup(
+        db: &Database,
+    ) -> (HomeServerSigningKey, LocalActor, Issuer, crate::crypto::ed25519::DigitalPrivateKey) {
+        let signing_key =
+            HomeServerSigningKey::register(db, generate_keypair().0, &SecurityConfig::default())
+                .await
+                .unwrap();
+        let (private_key, public_key) = generate_keypair();
+        PublicKeyInfo::insert::<DigitalSignature, _>(
+            db,
+            &public_key,
+            Some(ALICE),
+            &SecurityConfig::default(),
+        )
+        .await
+        .unwrap();
+        let actor = LocalActor::by_uaid(db, &ALICE).await.unwrap().unwrap();
+        let issuer = Issuer::get_by_domain(db, "full.example.com").await.unwrap().unwrap();
+        (signing_key, actor, issuer, private_key)
+    }
+
+    fn subject(local_name: &str, session_id: &str) -> Name {
+        Name::from_str(&format!(
+            "CN={local_name},DC=full,DC=example,DC=com,UID={local_name}@full.example.com,\
+             uniqueIdentifier={session_id}"
+        ))
+        .unwrap()
+    }
+
+    fn csr_pem<S: Signature, P: PublicKey<S>>(
+        local_name: &str,
+        private_key: &impl PrivateKey<S, PublicKey = P>,
+    ) -> String {
+        session_csr_pem(local_name, "session1", private_key)
+    }
+
+    fn session_csr_pem<S: Signature, P: PublicKey<S>>(
+        local_name: &str,
+        session_id: &str,
+        private_key: &impl PrivateKey<S, PublicKey = P>,
+    ) -> String {
+        IdCsr::new(
+            &subject(local_name, session_id),
+            private_key,
+            &Capabilities::default_actor(),
+            None,
+        )
+        .unwrap()
+        .to_pem(LineEnding::LF)
+        .unwrap()
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_issue_idcert(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let (signing_key, actor, issuer, private_key) = setup(&db).await;
+
+        let cert_pem = issue_idcert(
+            &db,
+            &signing_key,
+            &actor,
+            &issuer,
+            &csr_pem("full_state_alice", &private_key),
+            &SecurityConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        let now = u64::try_from(Utc::now().timestamp()).unwrap();
+        let cert = IdCert::<DigitalSignature, DigitalPublicKey>::from_pem(
+            &cert_pem,
+            Target::Actor,
+            now,
+            &signing_key.key.pubkey,
+        )
+        .unwrap();
+        assert_eq!(cert.id_cert_tbs.subject_public_key, private_key.pubkey);
+        let serial_number = SerialNumber::from(cert.id_cert_tbs.serial_number);
+        let stored = query!(
+            "SELECT idcsr.session_id, idcert.pem_encoded, idcert.home_server_public_key_id
+            FROM idcsr JOIN idcert ON idcert.idcsr_id = idcsr.id
+            WHERE idcsr.uaid = $1 AND idcsr.serial_number = $2",
+            ALICE,
+            serial_number.as_bigdecimal()
+        )
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(stored.session_id, "session1");
+        assert_eq!(stored.pem_encoded, cert_pem);
+        assert_eq!(stored.home_server_public_key_id, signing_key.public_key_id);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_unsupported_algorithm_is_rejected(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let (signing_key, actor, issuer, _) = setup(&db).await;
+        // ID-Certs are signed with the Ed25519 key of this server, so ECDSA ID-CSRs are rejected
+        let (private_key, _) = ecdsa::generate_keypair();
+
+        let error = issue_idcert(
+            &db,
+            &signing_key,
+            &actor,
+            &issuer,
+            &csr_pem("full_state_alice", &private_key),
+            &SecurityConfig::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, Errcode::IllegalInput);
+        assert_eq!(
+            error.context.unwrap().found,
+            ecdsa::DigitalSignature::algorithm_identifier().oid.to_string()
+        );
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_foreign_subject_or_key_is_rejected(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let (signing_key, actor, issuer, private_key) = setup(&db).await;
+
+        for csr in [
+            csr_pem("full_state_bob", &private_key),
+            csr_pem("full_state_alice", &generate_keypair().0),
+        ] {
+            let error =
+                issue_idcert(&db, &signing_key, &actor, &issuer, &csr, &SecurityConfig::default())
+                    .await
+                    .unwrap_err();
+            assert_eq!(error.code, Errcode::IllegalInput);
+        }
+        let issued = query!("SELECT id FROM idcsr WHERE session_id = 'session1'")
+            .fetch_all(&db.pool)
+            .await
+            .unwrap();
+        assert!(issued.is_empty());
+    }
+
+    #[test]
+    fn test_parse_session_id() {
+        for valid in ["s", "session1", &"s".repeat(32)] {
+            assert_eq!(parse_session_id(valid).unwrap().to_string(), valid);
+        }
+        for invalid in ["", &"s".repeat(33), "séance"] {
+            assert_eq!(parse_session_id(invalid).unwrap_err().code, Errcode::IllegalInput);
+        }
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_session_id_is_unique_per_actor(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let (signing_key, actor, issuer, private_key) = setup(&db).await;
+        let (second_private_key, second_public_key) = generate_keypair();
+        PublicKeyInfo::insert::<DigitalSignature, _>(
+            &db,
+            &second_public_key,
+            Some(ALICE),
+            &SecurityConfig::default(),
+        )
+        .await
+        .unwrap();
+        let issue = async |csr: String| {
+            issue_idcert(&db, &signing_key, &actor, &issuer, &csr, &SecurityConfig::default()).await
+        };
+
+        issue(session_csr_pem("full_state_alice", &"s".repeat(32), &private_key)).await.unwrap();
+        let error =
+            issue(session_csr_pem("full_state_alice", &"s".repeat(32), &second_private_key))
+                .await
+                .unwrap_err();
+        assert_eq!(error.code, Errcode::Duplicate);
+        assert_eq!(error.context.unwrap().field_name, "session_id");
+
+        // Other sessions of the same actor are not affected
+        issue(session_csr_pem("full_state_alice", "session2", &second_private_key)).await.unwrap();
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_submit_idcsr(pool: Pool<Postgres>) {
+        query!("DELETE FROM user_tokens WHERE uaid = $1", ALICE).execute(&pool).await.unwrap();
+        insert_session(&pool, "alice_token", 1001, ALICE, None).await;
+        let db = Database { pool };
+        let (signing_key, _, _, private_key) = setup(&db).await;
+        // The ID-Cert validity is read from the injected configuration
+        let reloadable_config = ReloadableConfigHandle::new(ReloadableConfig {
+            security: SecurityConfig { idcert_validity_secs: 3600, ..Default::default() },
+            ..Default::default()
+        });
+        let client = TestClient::new(
+            super::super::setup_routes()
+                .data(db.clone())
+                .data(TokenStore::new(db))
+                .data(signing_key)
+                .data(reloadable_config)
+                .data(ServedDomains::new(["full.example.com"])),
+        );
+
+        let response = client
+            .post("/session/idcsr")
+            .header("Host", "full.example.com")
+            .header("Authorization", "alice_token")
+            .body(csr_pem("full_state_alice", &private_key))
+            .send()
+            .await;
+        response.assert_status_is_ok();
+        response.assert_content_type(CertEncoding::PEM_MEDIA_TYPE);
+        let cert =
+            Certificate::from_pem(response.0.into_body().into_string().await.unwrap()).unwrap();
+        let validity = cert.tbs_certificate.validity;
+        assert_eq!(
+            validity.not_after.to_unix_duration() - validity.not_before.to_unix_duration(),
+            Duration::from_secs(3600)
+        );
+
+        // Only authenticated actors can submit ID-CSRs
+        client
+            .post("/session/idcsr")
+            .header("Host", "full.example.com")
+            .body(csr_pem("full_state_alice", &private_key))
+            .send()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,99 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use poem::{
+    handler,
+    web::{Data, Json},
+};
+use serde::Serialize;
+
+use crate::crypto::{ed25519::DigitalPublicKey, signing_key::HomeServerSigningKey};
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+/// A JSON Web Key Set, as specified in RFC 7517.
+pub(super) struct JwkSet {
+    /// The keys of the set.
+    keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+/// An `ed25519` public key as a JSON Web Key of the Octet Key Pair type, as
+/// specified in RFC 8037.
+pub(super) struct Jwk {
+    /// The key type, always `OKP`.
+    kty: &'static str,
+    /// The curve, always `Ed25519`.
+    crv: &'static str,
+    /// The base64url-encoded bytes of the public key, without padding.
+    x: String,
+    /// The intended use of the key, always `sig`.
+    #[serde(rename = "use")]
+    key_use: &'static str,
+    /// The algorithm the key is used with, always `EdDSA`.
+    alg: &'static str,
+}
+
+impl From<&DigitalPublicKey> for Jwk {
+    fn from(public_key: &DigitalPublicKey) -> Self {
+        Self {
+            kty: "OKP",
+            crv: "Ed25519",
+            x: URL_SAFE_NO_PAD.encode(public_key.to_bytes()),
+            key_use: "sig",
+            alg: "EdDSA",
+        }
+    }
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Get the public key this home server signs ID-Certs with, as a [JwkSet].
+pub(super) fn get_jwks(Data(signing_key): Data<&HomeServerSigningKey>) -> Json<JwkSet> {
+    Json(JwkSet { keys: vec![Jwk::from(&signing_key.key.pubkey)] })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use ed25519_dalek::VerifyingKey;
+    use poem::{EndpointExt, test::TestClient};
+
+    use super::*;
+    use crate::crypto::ed25519::generate_keypair;
+
+    #[test]
+    fn test_jwk_of_known_key() {
+        // The example key of RFC 8037, Appendix A.2
+        let key = VerifyingKey::from_bytes(
+            &hex::decode("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a")
+                .unwrap()
+                .try_into()
+                .unwrap(),
+        )
+        .unwrap();
+
+        let jwk = serde_json::to_value(Jwk::from(&DigitalPublicKey { key })).unwrap();
+        assert_eq!(jwk["kty"], "OKP");
+        assert_eq!(jwk["crv"], "Ed25519");
+        assert_eq!(jwk["x"], "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo");
+        assert_eq!(jwk["use"], "sig");
+    }
+
+    #[tokio::test]
+    async fn test_get_jwks() {
+        let (key, _) = generate_keypair();
+        let expected = URL_SAFE_NO_PAD.encode(key.pubkey.to_bytes());
+        let client = TestClient::new(
+            super::super::setup_routes().data(HomeServerSigningKey { key, public_key_id: 0 }),
+        );
+
+        let response = client.get("/server/jwks").send().await;
+        response.assert_status_is_ok();
+        let json = response.json().await;
+        let keys = json.value().object().get("keys").array();
+        keys.assert_len(1);
+        keys.get(0).object().get("x").assert_string(&expected);
+    }
+}
@@ -1 +1,24 @@
-// TODO
+use poem::{EndpointExt, Route, get, post};
+
+use crate::api::middlewares::AuthenticationMiddleware;
+
+/// The ID-Cert retrieval endpoints
+mod idcert;
+/// The ID-CSR submission endpoint
+mod idcsr;
+/// The endpoint serving the public key of this home server as a JSON Web Key
+/// Set
+mod jwks;
+/// The endpoint verifying ID-Certs against the issuers of this home server
+mod verify_cert;
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Route handler for the federated identity module
+pub(super) fn setup_routes() -> Route {
+    Route::new()
+        .at("/session/idcsr", post(idcsr::submit_idcsr).with(AuthenticationMiddleware))
+        .at("/idcert/server", get(idcert::get_server_idcert))
+        .at("/idcert/actor/:local_name", get(idcert::get_actor_idcerts))
+        .at("/server/jwks", get(jwks::get_jwks))
+        .at("/verify-cert", post(verify_cert::verify_cert))
+}
@@ -0,0 +1,246 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use chrono::{NaiveDateTime, Utc};
+use poem::{
+    handler,
+    web::{Data, Json},
+};
+use polyproto::certs::idcert::IdCert;
+use serde::Serialize;
+
+use crate::{
+    api::extractors::ServedDomains,
+    crypto::{
+        ed25519::{DigitalPublicKey, DigitalSignature},
+        signing_key::HomeServerSigningKey,
+    },
+    database::{Database, HomeServerCert, Issuer},
+    errors::{Context, Errcode, Error},
+};
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+/// The outcome of verifying an ID-Cert against the trust of this home server.
+pub(super) struct CertVerification {
+    /// Whether the ID-Cert has been issued by this home server, is correctly
+    /// signed and is valid at the time of the request.
+    valid: bool,
+    /// Why the ID-Cert is not valid. `None`, if it is.
+    reason: Option<String>,
+}
+
+impl CertVerification {
+    /// A [CertVerification] of a valid ID-Cert.
+    fn valid() -> Self {
+        Self { valid: true, reason: None }
+    }
+
+    /// A [CertVerification] of an ID-Cert, which is not valid because of
+    /// `reason`.
+    fn invalid(reason: impl Into<String>) -> Self {
+        Self { valid: false, reason: Some(reason.into()) }
+    }
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Verify the PEM-encoded actor ID-Cert in the request body against the
+/// issuers of this home server.
+pub(super) async fn verify_cert(
+    cert_pem: String,
+    Data(db): Data<&Database>,
+    Data(signing_key): Data<&HomeServerSigningKey>,
+    Data(served_domains): Data<&ServedDomains>,
+) -> poem::Result<Json<CertVerification>> {
+    Ok(Json(verify(db, signing_key, served_domains, &cert_pem, Utc::now().naive_utc()).await?))
+}
+
+/// Verify the actor ID-Cert `cert_pem` at `now`. The issuer of the ID-Cert
+/// has to be in the `issuers` table. Its signature is checked with the public
+/// key of the ID-Cert of that issuer valid at `now`. If the issuer has no such
+/// ID-Cert, the public key of the `signing_key` is used instead, but only if
+/// the issuer is one of the `served_domains` of this server.
+///
+/// ## Errors
+///
+/// [Errcode::IllegalInput], if `cert_pem` is not a PEM-encoded ID-Cert with an
+/// `ed25519` public key.
+pub(super) async fn verify(
+    db: &Database,
+    signing_key: &HomeServerSigningKey,
+    served_domains: &ServedDomains,
+    cert_pem: &str,
+    now: NaiveDateTime,
+) -> Result<CertVerification, Error> {
+    let cert = IdCert::<DigitalSignature, DigitalPublicKey>::from_pem_unchecked(cert_pem).map_err(
+        |e| {
+            Error::new(
+                Errcode::IllegalInput,
+                Some(Context::new(Some("cert"), None, None, Some(&e.to_string()))),
+            )
+        },
+    )?;
+    let Some(issuer_domain) =
+        cert.issuer_url().ok().and_then(|url| url.host_str().map(str::to_owned))
+    else {
+        return Ok(CertVerification::invalid("The issuer is not a valid domain"));
+    };
+    let Some(issuer) = Issuer::get_by_domain(db, &issuer_domain).await? else {
+        return Ok(CertVerification::invalid(format!(
+            "The issuer {issuer_domain} is not known to this server"
+        )));
+    };
+    let issuer_public_key =
+        match HomeServerCert::get_idcert_by::<DigitalSignature, DigitalPublicKey>(
+            db,
+            &issuer.domain_components,
+            &now,
+        )
+        .await?
+        {
+            Some(issuer_cert) => issuer_cert.id_cert_tbs.subject_public_key,
+            None if served_domains.resolve(&issuer_domain).is_some() => {
+                signing_key.key.pubkey.clone()
+            }
+            None => {
+                return Ok(CertVerification::invalid(format!(
+                    "The issuer {issuer_domain} has no ID-Cert valid at this time"
+                )));
+            }
+        };
+    let timestamp = u64::try_from(now.and_utc().timestamp()).unwrap_or_default();
+    Ok(match cert.full_verify_actor(timestamp, &issuer_public_key) {
+        Ok(()) => CertVerification::valid(),
+        Err(e) => CertVerification::invalid(e.to_string()),
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::{
+        str::FromStr,
+        time::{Duration, SystemTime},
+    };
+
+    use polyproto::{
+        certs::{capabilities::Capabilities, idcsr::IdCsr},
+        der::pem::LineEnding,
+    };
+    use sqlx::{Pool, Postgres};
+    use x509_cert::{
+        name::Name,
+        time::{Time, Validity},
+    };
+
+    use super::*;
+    use crate::{
+        config::SecurityConfig, crypto::ed25519::generate_keypair, database::SerialNumber,
+    };
+
+    /// The PEM encoding of an actor ID-Cert issued by `issuer` and signed by
+    /// `signing_key` `issued_secs_ago`, valid for `validity_secs` seconds.
+    fn cert_pem(
+        signing_key: &HomeServerSigningKey,
+        issuer: &str,
+        issued_secs_ago: u64,
+        validity_secs: u64,
+    ) -> String {
+        let subject = Name::from_str(&format!(
+            "CN=alice,{},UID=alice@{issuer},uniqueIdentifier=session1",
+            domain_components(issuer)
+        ))
+        .unwrap();
+        let csr = IdCsr::new(&subject, &generate_keypair().0, &Capabilities::default_actor(), None)
+            .unwrap();
+        let not_before =
+            SystemTime::now().checked_sub(Duration::from_secs(issued_secs_ago)).unwrap();
+        let not_after = not_before.checked_add(Duration::from_secs(validity_secs)).unwrap();
+        IdCert::from_actor_csr(
+            csr,
+            &signing_key.key,
+            SerialNumber::try_generate_random(&mut rand::rng(), Default::default()).unwrap().into(),
+            Name::from_str(&domain_components(issuer)).unwrap(),
+            Validity {
+                not_before: Time::try_from(not_before).unwrap(),
+                not_after: Time::try_from(not_after).unwrap(),
+            },
+        )
+        .unwrap()
+        .to_pem(LineEnding::LF)
+        .unwrap()
+    }
+
+    fn domain_components(domain: &str) -> String {
+        domain.split('.').map(|component| format!("DC={component}")).collect::<Vec<_>>().join(",")
+    }
+
+    fn served_domains() -> ServedDomains {
+        ServedDomains::new(["full.example.com"])
+    }
+
+    async fn register_signing_key(db: &Database) -> HomeServerSigningKey {
+        HomeServerSigningKey::register(db, generate_keypair().0, &SecurityConfig::default())
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_valid_cert(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let signing_key = register_signing_key(&db).await;
+        let now = Utc::now().naive_utc();
+        let pem = cert_pem(&signing_key, "full.example.com", 60, 3600);
+
+        let verification = verify(&db, &signing_key, &served_domains(), &pem, now).await.unwrap();
+        assert_eq!(verification, CertVerification::valid());
+
+        // A cert signed by another key is not trusted
+        let other_key = register_signing_key(&db).await;
+        let pem = cert_pem(&other_key, "full.example.com", 60, 3600);
+        assert!(!verify(&db, &signing_key, &served_domains(), &pem, now).await.unwrap().valid);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_expired_cert(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let signing_key = register_signing_key(&db).await;
+        let now = Utc::now().naive_utc();
+        let pem = cert_pem(&signing_key, "full.example.com", 7200, 3600);
+
+        let verification = verify(&db, &signing_key, &served_domains(), &pem, now).await.unwrap();
+        assert!(!verification.valid);
+        assert!(verification.reason.unwrap().contains("expired"));
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_unknown_issuer(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let signing_key = register_signing_key(&db).await;
+        let now = Utc::now().naive_utc();
+        let pem = cert_pem(&signing_key, "unknown.example.org", 60, 3600);
+
+        let verification = verify(&db, &signing_key, &served_domains(), &pem, now).await.unwrap();
+        assert!(!verification.valid);
+        assert!(verification.reason.unwrap().contains("unknown.example.org"));
+
+        let error =
+            verify(&db, &signing_key, &served_domains(), "not a cert", now).await.unwrap_err();
+        assert_eq!(error.code, Errcode::IllegalInput);
+    }
+
+    #[sqlx::test(fixtures("../../../fixtures/full_state.sql"))]
+    async fn test_foreign_issuer_without_cert(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let signing_key = register_signing_key(&db).await;
+        Issuer::create(&db, "other.example.org").await.unwrap().unwrap();
+        let now = Utc::now().naive_utc();
+        let pem = cert_pem(&signing_key, "other.example.org", 60, 3600);
+
+        // The own signing key is not trusted for issuers other than this server
+        let verification = verify(&db, &signing_key, &served_domains(), &pem, now).await.unwrap();
+        assert!(!verification.valid);
+        assert!(verification.reason.unwrap().contains("other.example.org"));
+    }
+}
@@ -0,0 +1,221 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    fmt::Write,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use poem::{
+    Endpoint, IntoResponse, Middleware, Request, Response, handler, http::StatusCode, web::Data,
+};
+
+use crate::{database::Database, gateway::ConnectionLimiter};
+
+/// Media type of the Prometheus text exposition format.
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// The lowest valid HTTP status code.
+const MIN_STATUS_CODE: u16 = 100;
+/// The number of valid HTTP status codes, `100` to `999`.
+const STATUS_CODE_COUNT: usize = 900;
+
+#[derive(Debug, Clone)]
+/// Counters of the HTTP requests handled by the API, shared between the
+/// [MetricsMiddleware] counting them and the [metrics] endpoint reporting
+/// them. Clones share the same counters.
+pub(crate) struct RequestMetrics(Arc<RequestCounters>);
+
+#[derive(Debug)]
+/// The counters behind [RequestMetrics].
+struct RequestCounters {
+    /// Total number of handled requests.
+    total: AtomicU64,
+    /// Number of responses per status code, indexed by the status code minus
+    /// [MIN_STATUS_CODE].
+    by_status: [AtomicU64; STATUS_CODE_COUNT],
+}
+
+impl Default for RequestMetrics {
+    fn default() -> Self {
+        Self(Arc::new(RequestCounters {
+            total: AtomicU64::new(0),
+            by_status: std::array::from_fn(|_| AtomicU64::new(0)),
+        }))
+    }
+}
+
+impl RequestMetrics {
+    /// Count a handled request, which has been answered with `status`.
+    fn record(&self, status: StatusCode) {
+        self.0.total.fetch_add(1, Ordering::Relaxed);
+        let index = usize::from(status.as_u16().saturating_sub(MIN_STATUS_CODE));
+        if let Some(counter) = self.0.by_status.get(index) {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Total number of handled requests.
+    fn total(&self) -> u64 {
+        self.0.total.load(Ordering::Relaxed)
+    }
+
+    /// Status codes which have been responded with at least once, together
+    /// with their number of responses, in ascending order.
+    fn by_status(&self) -> impl Iterator<Item = (u16, u64)> {
+        (MIN_STATUS_CODE..).zip(self.0.by_status.iter()).filter_map(|(status, counter)| {
+            let count = counter.load(Ordering::Relaxed);
+            (count > 0).then_some((status, count))
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Middleware counting every handled request and its response status in
+/// [RequestMetrics]. Implements [Endpoint] via [MetricsMiddlewareImpl].
+pub(crate) struct MetricsMiddleware {
+    /// The counters to update
+    request_metrics: RequestMetrics,
+}
+
+impl MetricsMiddleware {
+    /// Create the middleware, counting requests in `request_metrics`.
+    pub(crate) fn new(request_metrics: &RequestMetrics) -> Self {
+        Self { request_metrics: request_metrics.clone() }
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl<E: Endpoint> Middleware<E> for MetricsMiddleware {
+    type Output = MetricsMiddlewareImpl<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        Self::Output { ep, request_metrics: self.request_metrics.clone() }
+    }
+}
+
+/// Struct for middleware functionality implementation
+pub(crate) struct MetricsMiddlewareImpl<E> {
+    /// The wrapped endpoint
+    ep: E,
+    /// The counters to update
+    request_metrics: RequestMetrics,
+}
+
+impl<E: Endpoint> Endpoint for MetricsMiddlewareImpl<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        // `get_response` also turns errors into responses, so that failed requests are
+        // counted with their actual status code
+        let response = self.ep.get_response(req).await.into_response();
+        self.request_metrics.record(response.status());
+        Ok(response)
+    }
+}
+
+#[handler]
+/// Serve the [RequestMetrics], the state of the database connection pool and
+/// the number of gateway connections in the Prometheus text exposition format.
+pub(super) fn metrics(
+    Data(request_metrics): Data<&RequestMetrics>,
+    Data(db): Data<&Database>,
+    Data(connection_limiter): Data<&ConnectionLimiter>,
+) -> impl IntoResponse {
+    let mut body = String::new();
+    // Writing to a String cannot fail
+    _ = writeln!(body, "# HELP sonata_http_requests_total Total number of handled HTTP requests.");
+    _ = writeln!(body, "# TYPE sonata_http_requests_total counter");
+    _ = writeln!(body, "sonata_http_requests_total {}", request_metrics.total());
+    _ = writeln!(
+        body,
+        "# HELP sonata_http_responses_total Number of HTTP responses per status code."
+    );
+    _ = writeln!(body, "# TYPE sonata_http_responses_total counter");
+    for (status, count) in request_metrics.by_status() {
+        _ = writeln!(body, "sonata_http_responses_total{{status=\"{status}\"}} {count}");
+    }
+    _ = writeln!(
+        body,
+        "# HELP sonata_database_pool_connections Number of open database connections."
+    );
+    _ = writeln!(body, "# TYPE sonata_database_pool_connections gauge");
+    _ = writeln!(body, "sonata_database_pool_connections {}", db.pool.size());
+    _ = writeln!(
+        body,
+        "# HELP sonata_database_pool_idle_connections Number of idle database connections."
+    );
+    _ = writeln!(body, "# TYPE sonata_database_pool_idle_connections gauge");
+    _ = writeln!(body, "sonata_database_pool_idle_connections {}", db.pool.num_idle());
+    _ = writeln!(body, "# HELP sonata_gateway_connections Number of open gateway connections.");
+    _ = writeln!(body, "# TYPE sonata_gateway_connections gauge");
+    _ = writeln!(body, "sonata_gateway_connections {}", connection_limiter.active_connections());
+    _ = writeln!(
+        body,
+        "# HELP sonata_gateway_connections_peak Highest number of simultaneously open gateway \
+         connections."
+    );
+    _ = writeln!(body, "# TYPE sonata_gateway_connections_peak gauge");
+    _ = writeln!(body, "sonata_gateway_connections_peak {}", connection_limiter.peak_connections());
+    Response::builder().status(StatusCode::OK).content_type(PROMETHEUS_CONTENT_TYPE).body(body)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, Route, get, test::TestClient};
+    use sqlx::{Pool, Postgres};
+
+    use super::*;
+    use crate::config::GatewayConfig;
+
+    #[handler]
+    fn sample() -> &'static str {
+        "sample"
+    }
+
+    #[sqlx::test]
+    async fn test_metrics(pool: Pool<Postgres>) {
+        let request_metrics = RequestMetrics::default();
+        let connection_limiter = ConnectionLimiter::new(&GatewayConfig::for_tests());
+        let first_connection = connection_limiter.try_acquire().unwrap();
+        let _second_connection = connection_limiter.try_acquire().unwrap();
+        drop(first_connection);
+        let client = TestClient::new(
+            Route::new()
+                .at("/", get(sample))
+                .at("/metrics", get(metrics))
+                .with(MetricsMiddleware::new(&request_metrics))
+                .data(request_metrics)
+                .data(Database { pool })
+                .data(connection_limiter),
+        );
+        client.get("/").send().await.assert_status_is_ok();
+        client.get("/").send().await.assert_status_is_ok();
+        client.get("/does-not-exist").send().await.assert_status(StatusCode::NOT_FOUND);
+
+        let response = client.get("/metrics").send().await;
+        response.assert_status_is_ok();
+        response.assert_content_type(PROMETHEUS_CONTENT_TYPE);
+        let body = response.0.into_body().into_string().await.unwrap();
+        // The request to `/metrics` itself is counted once it has been answered
+        for line in [
+            "sonata_http_requests_total 3",
+            "sonata_http_responses_total{status=\"200\"} 2",
+            "sonata_http_responses_total{status=\"404\"} 1",
+            "sonata_gateway_connections 1",
+            "sonata_gateway_connections_peak 2",
+        ] {
+            assert!(body.lines().any(|l| l == line), "{line:?} missing in:\n{body}");
+        }
+        for metric in
+            ["sonata_database_pool_connections ", "sonata_database_pool_idle_connections "]
+        {
+            assert!(body.lines().any(|l| l.starts_with(metric)), "{metric:?} missing in:\n{body}");
+        }
+    }
+}
@@ -0,0 +1,111 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use log::{debug, error};
+use poem::{Endpoint, Middleware, Request, http::StatusCode};
+
+use crate::database::{Database, api_keys::ApiKey};
+
+/// Name of the header carrying the API key.
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// Middleware restricting access to the wrapped endpoints, such as the admin
+/// routes, to clients presenting an API key from the `api_keys` table in the
+/// [API_KEY_HEADER]. Other requests are rejected with `401 Unauthorized`.
+/// Implements [Endpoint] via [ApiKeyMiddlewareImpl].
+pub struct ApiKeyMiddleware;
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl<E: Endpoint> Middleware<E> for ApiKeyMiddleware {
+    type Output = ApiKeyMiddlewareImpl<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        Self::Output { ep }
+    }
+}
+
+/// Struct for middleware functionality implementation
+pub struct ApiKeyMiddlewareImpl<E> {
+    /// The wrapped endpoint
+    ep: E,
+}
+
+impl<E: Endpoint> Endpoint for ApiKeyMiddlewareImpl<E> {
+    type Output = E::Output;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let Some(api_key) = req.header(API_KEY_HEADER) else {
+            debug!("Rejected request to {} without an API key", req.uri().path());
+            return Err(poem::error::Error::from_status(StatusCode::UNAUTHORIZED));
+        };
+        let Some(db) = req.data::<Database>() else {
+            error!("The API key middleware is used on a route without access to the database");
+            return Err(poem::error::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR));
+        };
+        if !ApiKey::is_active_in_database(db, api_key)
+            .await
+            .map_err(|_| poem::error::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?
+        {
+            debug!(
+                "Rejected request to {} with an unknown, revoked or expired API key",
+                req.uri().path()
+            );
+            return Err(poem::error::Error::from_status(StatusCode::UNAUTHORIZED));
+        }
+        self.ep.call(req).await
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, Route, get, handler, test::TestClient};
+    use sqlx::{Pool, Postgres};
+
+    use super::*;
+    use crate::database::api_keys::add_api_key_to_database;
+
+    #[handler]
+    fn sample() -> &'static str {
+        "sample"
+    }
+
+    #[sqlx::test]
+    async fn test_api_key_is_required(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let api_key = ApiKey::new_random(&mut rand::rng());
+        add_api_key_to_database(api_key.token(), &db).await.unwrap();
+        let client =
+            TestClient::new(Route::new().at("/", get(sample)).with(ApiKeyMiddleware).data(db));
+
+        client.get("/").send().await.assert_status(StatusCode::UNAUTHORIZED);
+        let unknown = ApiKey::new_random(&mut rand::rng());
+        client
+            .get("/")
+            .header(API_KEY_HEADER, unknown.token())
+            .send()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+        client.get("/").header(API_KEY_HEADER, api_key.token()).send().await.assert_status_is_ok();
+    }
+
+    #[sqlx::test]
+    async fn test_revoked_api_key_is_rejected(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let api_key = ApiKey::new_random(&mut rand::rng());
+        add_api_key_to_database(api_key.token(), &db).await.unwrap();
+        let client = TestClient::new(
+            Route::new().at("/", get(sample)).with(ApiKeyMiddleware).data(db.clone()),
+        );
+        client.get("/").header(API_KEY_HEADER, api_key.token()).send().await.assert_status_is_ok();
+
+        ApiKey::revoke(&db, api_key.token()).await.unwrap();
+        client
+            .get("/")
+            .header(API_KEY_HEADER, api_key.token())
+            .send()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+    }
+}
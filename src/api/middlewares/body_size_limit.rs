@@ -0,0 +1,109 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use poem::{Endpoint, Middleware, Request, error::ReadBodyError, web::headers::HeaderMapExt};
+
+use crate::config::ApiConfig;
+
+/// Middleware rejecting requests with a body larger than
+/// [ApiConfig::max_body_bytes] with `413 Payload Too Large`. Implements
+/// [Endpoint] via [BodySizeLimitMiddlewareImpl].
+///
+/// Unlike [poem::middleware::SizeLimit], requests without a `Content-Length`
+/// header are accepted, but their body is read up to the limit before the
+/// request is passed on.
+#[derive(Debug, Clone, Copy)]
+pub struct BodySizeLimitMiddleware {
+    /// How many bytes the body of a request may have at most.
+    max_body_bytes: usize,
+}
+
+impl BodySizeLimitMiddleware {
+    /// Create the middleware for the [ApiConfig::max_body_bytes].
+    pub fn new(api_config: &ApiConfig) -> Self {
+        Self { max_body_bytes: api_config.max_body_bytes }
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl<E: Endpoint> Middleware<E> for BodySizeLimitMiddleware {
+    type Output = BodySizeLimitMiddlewareImpl<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        Self::Output { ep, max_body_bytes: self.max_body_bytes }
+    }
+}
+
+/// Struct for middleware functionality implementation
+pub struct BodySizeLimitMiddlewareImpl<E> {
+    /// The wrapped endpoint
+    ep: E,
+    /// How many bytes the body of a request may have at most.
+    max_body_bytes: usize,
+}
+
+impl<E: Endpoint> Endpoint for BodySizeLimitMiddlewareImpl<E> {
+    type Output = E::Output;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        // A declared length is checked without reading the body at all
+        if req.headers().typed_get::<poem::web::headers::ContentLength>().is_some_and(|length| {
+            u64::try_from(self.max_body_bytes).is_ok_and(|max| length.0 > max)
+        }) {
+            return Err(ReadBodyError::PayloadTooLarge.into());
+        }
+        let body = req.take_body().into_bytes_limit(self.max_body_bytes).await?;
+        req.set_body(body);
+        self.ep.call(req).await
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use futures_util::stream;
+    use poem::{Body, EndpointExt, Route, handler, http::StatusCode, post, test::TestClient};
+
+    use super::*;
+
+    #[handler]
+    fn echo(body: String) -> String {
+        body
+    }
+
+    fn client(max_body_bytes: usize) -> TestClient<impl Endpoint> {
+        let mut api_config = ApiConfig::for_tests();
+        api_config.max_body_bytes = max_body_bytes;
+        TestClient::new(
+            Route::new().at("/", post(echo)).with(BodySizeLimitMiddleware::new(&api_config)),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_body_within_limit() {
+        let response = client(8).post("/").body("12345678").send().await;
+        response.assert_status_is_ok();
+        response.assert_text("12345678").await;
+    }
+
+    #[tokio::test]
+    async fn test_body_over_limit() {
+        let client = client(8);
+        client
+            .post("/")
+            .body("123456789")
+            .send()
+            .await
+            .assert_status(StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Bodies of unknown length are limited as well
+        let chunks = stream::iter(["12345", "6789"].map(Ok::<_, std::io::Error>));
+        client
+            .post("/")
+            .body(Body::from_bytes_stream(chunks))
+            .send()
+            .await
+            .assert_status(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
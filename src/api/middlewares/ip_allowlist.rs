@@ -0,0 +1,142 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::net::IpAddr;
+
+use ipnet::IpNet;
+use log::debug;
+use poem::{Endpoint, Middleware, Request, http::StatusCode};
+
+use super::client_ip;
+use crate::config::ApiConfig;
+
+/// Middleware restricting access to the wrapped endpoints, such as the admin
+/// routes, to clients from the networks in [ApiConfig::admin_ip_allowlist].
+/// Requests from other sources are rejected with `403 Forbidden`. An empty
+/// allowlist does not restrict access. Implements [Endpoint] via
+/// [AdminIpAllowlistMiddlewareImpl].
+#[derive(Debug, Clone)]
+pub struct AdminIpAllowlistMiddleware {
+    /// The allowed networks
+    allowlist: Vec<IpNet>,
+}
+
+impl AdminIpAllowlistMiddleware {
+    /// Create the middleware from the [ApiConfig].
+    pub fn new(api_config: &ApiConfig) -> Self {
+        Self { allowlist: api_config.admin_ip_allowlist.clone() }
+    }
+
+    /// Whether a client with the IP address `ip`, as returned by [client_ip],
+    /// may access the wrapped endpoints. `None` stands for a client with an
+    /// unknown IP address, which is only allowed if the allowlist is empty.
+    pub fn is_allowed(&self, ip: Option<IpAddr>) -> bool {
+        if self.allowlist.is_empty() {
+            return true;
+        }
+        ip.is_some_and(|ip| self.allowlist.iter().any(|network| network.contains(&ip)))
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl<E: Endpoint> Middleware<E> for AdminIpAllowlistMiddleware {
+    type Output = AdminIpAllowlistMiddlewareImpl<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        Self::Output { ep, config: self.clone() }
+    }
+}
+
+/// Struct for middleware functionality implementation
+pub struct AdminIpAllowlistMiddlewareImpl<E> {
+    /// The wrapped endpoint
+    ep: E,
+    /// The allowed networks
+    config: AdminIpAllowlistMiddleware,
+}
+
+impl<E: Endpoint> Endpoint for AdminIpAllowlistMiddlewareImpl<E> {
+    type Output = E::Output;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let ip = client_ip(req.remote_addr());
+        if !self.config.is_allowed(ip) {
+            debug!("Rejected request to {} from disallowed address {ip:?}", req.uri().path());
+            return Err(poem::error::Error::from_status(StatusCode::FORBIDDEN));
+        }
+        self.ep.call(req).await
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use poem::{Addr, EndpointExt, Route, get, handler, test::TestClient, web::RemoteAddr};
+
+    use super::*;
+
+    #[handler]
+    fn sample() -> &'static str {
+        "sample"
+    }
+
+    fn middleware(allowlist: &[&str]) -> AdminIpAllowlistMiddleware {
+        let mut api_config = ApiConfig::for_tests();
+        api_config.admin_ip_allowlist =
+            allowlist.iter().map(|network| network.parse().unwrap()).collect();
+        AdminIpAllowlistMiddleware::new(&api_config)
+    }
+
+    /// The [client_ip] of a client connecting from `ip`.
+    fn client(ip: &str) -> Option<IpAddr> {
+        client_ip(&RemoteAddr(Addr::SocketAddr(SocketAddr::new(ip.parse().unwrap(), 0))))
+    }
+
+    #[test]
+    fn test_allowed_ip() {
+        let middleware = middleware(&["10.0.0.0/8", "192.168.1.10/32", "fd00::/8"]);
+        for ip in ["10.1.2.3", "192.168.1.10", "fd00::1", "::ffff:10.0.0.1"] {
+            assert!(middleware.is_allowed(client(ip)), "{ip} should be allowed");
+        }
+    }
+
+    #[test]
+    fn test_disallowed_ip() {
+        let middleware = middleware(&["10.0.0.0/8", "192.168.1.10/32"]);
+        for ip in ["11.0.0.1", "192.168.1.11", "::1", "::ffff:192.168.1.11"] {
+            assert!(!middleware.is_allowed(client(ip)), "{ip} should be rejected");
+        }
+        assert!(!middleware.is_allowed(None));
+    }
+
+    #[test]
+    fn test_invalid_network_is_rejected_by_config() {
+        let result = toml::from_str::<ApiConfig>(
+            "enabled = true\nport = 3011\nhost = \"0.0.0.0\"\ntls = false\nadmin_ip_allowlist = \
+             [\"not a network\"]",
+        );
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_empty_allowlist_passes_through() {
+        let middleware = middleware(&[]);
+        assert!(middleware.is_allowed(client("203.0.113.7")));
+        assert!(middleware.is_allowed(None));
+
+        let client = TestClient::new(Route::new().at("/", get(sample)).with(middleware));
+        client.get("/").send().await.assert_status_is_ok();
+    }
+
+    #[tokio::test]
+    async fn test_request_from_disallowed_source_is_forbidden() {
+        // Test requests don't have a socket address, so they are treated as coming from
+        // an unknown, disallowed source
+        let client =
+            TestClient::new(Route::new().at("/", get(sample)).with(middleware(&["127.0.0.1/32"])));
+        client.get("/").send().await.assert_status(StatusCode::FORBIDDEN);
+    }
+}
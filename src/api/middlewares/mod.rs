@@ -2,9 +2,41 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use poem::{Endpoint, Middleware, http::StatusCode};
+use std::net::IpAddr;
 
-use crate::database::tokens::{TokenStore, hash_auth_token};
+use log::warn;
+use poem::{Endpoint, Middleware, http::StatusCode, web::RemoteAddr};
+use zeroize::Zeroizing;
+
+use crate::database::tokens::{TokenStore, constant_time_eq, hash_auth_token};
+
+/// API key authentication middleware for admin routes.
+mod api_key;
+/// Request body size limiting middleware.
+mod body_size_limit;
+/// IP allowlist middleware for admin routes.
+mod ip_allowlist;
+/// RFC 9457 problem details error format middleware.
+mod problem_details;
+/// Per-client rate limiting middleware for the authentication routes.
+mod rate_limit;
+/// Security headers and `Server` header middleware.
+mod security_headers;
+
+pub use api_key::*;
+pub use body_size_limit::*;
+pub use ip_allowlist::*;
+pub use problem_details::*;
+pub use rate_limit::*;
+pub use security_headers::*;
+
+/// The IP address of the client at `remote_addr`, if it connected over an
+/// internet socket.
+pub(crate) fn client_ip(remote_addr: &RemoteAddr) -> Option<IpAddr> {
+    // IPv4 clients connecting to a dual-stack socket show up as IPv4-mapped IPv6
+    // addresses
+    remote_addr.as_socket_addr().map(|addr| addr.ip().to_canonical())
+}
 
 /// Authentication middleware, implementing [Endpoint] via
 /// [AuthenticationMiddlewareImpl]
@@ -36,7 +68,7 @@ impl<E: Endpoint> Endpoint for AuthenticationMiddlewareImpl<E> {
             .ok_or(poem::error::Error::from_status(StatusCode::UNAUTHORIZED))?;
 
         let token_store = req.data::<TokenStore>().unwrap();
-        let hashed_user_token = hash_auth_token(auth);
+        let hashed_user_token = Zeroizing::new(hash_auth_token(auth));
         // We first get the serial_number of the cert that this token is associated
         // with...
         let user_serial_number = token_store
@@ -50,7 +82,10 @@ impl<E: Endpoint> Endpoint for AuthenticationMiddlewareImpl<E> {
             .await
             .map_err(|_| poem::error::Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?
             .ok_or(poem::error::Error::from_status(StatusCode::UNAUTHORIZED))?;
-        if valid_token_in_db_for_user.token == hashed_user_token.into() {
+        if constant_time_eq(&valid_token_in_db_for_user.token, &hashed_user_token) {
+            if let Err(e) = token_store.update_last_seen(&hashed_user_token).await {
+                warn!("Could not update last_seen timestamp of token: {e:?}");
+            }
             req.set_data(valid_token_in_db_for_user);
         } else {
             return Err(poem::error::Error::from_status(StatusCode::UNAUTHORIZED));
@@ -0,0 +1,112 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use poem::{Endpoint, IntoResponse, Middleware, Request, Response, http::header};
+
+use crate::errors::{Error, PROBLEM_DETAILS_CONTENT_TYPE};
+
+/// Middleware rendering [Error]s as RFC 9457 `application/problem+json`
+/// responses, if the client asks for that media type in its `Accept` header.
+/// All other clients keep receiving the regular error format. Implements
+/// [Endpoint] via [ProblemDetailsMiddlewareImpl].
+pub struct ProblemDetailsMiddleware;
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl<E: Endpoint> Middleware<E> for ProblemDetailsMiddleware {
+    type Output = ProblemDetailsMiddlewareImpl<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        Self::Output { ep }
+    }
+}
+
+/// Struct for middleware functionality implementation
+pub struct ProblemDetailsMiddlewareImpl<E> {
+    /// The wrapped endpoint
+    ep: E,
+}
+
+/// Whether the `Accept` header of `req` lists the problem details media type.
+fn accepts_problem_details(req: &Request) -> bool {
+    req.headers().get_all(header::ACCEPT).iter().filter_map(|value| value.to_str().ok()).any(
+        |value| {
+            value.split(',').any(|media_range| {
+                media_range
+                    .split(';')
+                    .next()
+                    .is_some_and(|media_type| media_type.trim() == PROBLEM_DETAILS_CONTENT_TYPE)
+            })
+        },
+    )
+}
+
+impl<E: Endpoint> Endpoint for ProblemDetailsMiddlewareImpl<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let problem_details = accepts_problem_details(&req);
+        match self.ep.call(req).await {
+            Ok(output) => Ok(output.into_response()),
+            Err(e) if problem_details => match e.downcast_ref::<Error>() {
+                Some(error) => Ok(error.to_problem_details_response()),
+                None => Err(e),
+            },
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, Route, get, handler, http::StatusCode, test::TestClient};
+
+    use super::*;
+    use crate::errors::{Context, Errcode};
+
+    #[handler]
+    #[allow(clippy::result_large_err)]
+    fn failing() -> Result<&'static str, Error> {
+        Err(Error::new(
+            Errcode::IllegalInput,
+            Some(Context::new(Some("local_name"), Some("a b"), None, None)),
+        ))
+    }
+
+    fn client() -> TestClient<impl Endpoint> {
+        TestClient::new(Route::new().at("/", get(failing)).with(ProblemDetailsMiddleware))
+    }
+
+    #[tokio::test]
+    async fn test_problem_details_on_accept() {
+        let response = client()
+            .get("/")
+            .header("Accept", "application/json;q=0.5, application/problem+json")
+            .send()
+            .await;
+
+        response.assert_status(StatusCode::BAD_REQUEST);
+        response.assert_content_type(PROBLEM_DETAILS_CONTENT_TYPE);
+        let json = response.json().await;
+        let body = json.value().object();
+        body.get("type").assert_string("about:blank");
+        body.get("title").assert_string("Bad Request");
+        body.get("status").assert_i64(400);
+        body.get("detail").assert_string(&Errcode::IllegalInput.message());
+        body.get("code").assert_string("P2_CORE_ILLEGAL_INPUT");
+        body.get("context").object().get("fieldName").assert_string("local_name");
+    }
+
+    #[tokio::test]
+    async fn test_regular_error_format_by_default() {
+        let response = client().get("/").header("Accept", "application/json").send().await;
+
+        response.assert_status(StatusCode::BAD_REQUEST);
+        response.assert_content_type("application/json");
+        let json = response.json().await;
+        let body = json.value().object();
+        body.get("code").assert_string("P2_CORE_ILLEGAL_INPUT");
+        assert!(body.get_opt("type").is_none());
+    }
+}
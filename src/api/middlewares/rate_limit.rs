@@ -0,0 +1,245 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use log::debug;
+use poem::{
+    Endpoint, Middleware, Request, Response,
+    http::{StatusCode, header},
+};
+
+use super::client_ip;
+use crate::config::ReloadableConfigHandle;
+
+/// Number of tracked clients, above which clients with a completely refilled
+/// bucket are forgotten again.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Middleware limiting how many requests a single client, identified by its IP
+/// address, may send to the wrapped endpoints, such as the authentication
+/// routes. Every client has a token bucket holding up to
+/// [RateLimitConfig::requests_per_minute](crate::config::RateLimitConfig::requests_per_minute)
+/// requests, which refills at the same rate. Requests exceeding the limit are
+/// rejected with `429 Too Many Requests` and a `Retry-After` header.
+/// Implements [Endpoint] via [RateLimitMiddlewareImpl].
+///
+/// The [RateLimitConfig](crate::config::RateLimitConfig) is read from the
+/// [ReloadableConfigHandle] for every request, so that a reloaded limit
+/// applies immediately.
+#[derive(Debug, Clone)]
+pub struct RateLimitMiddleware {
+    /// The buckets of all clients, shared by all endpoints this middleware
+    /// wraps
+    limiter: RateLimiter,
+    /// Where the currently active rate limit is read from
+    config: ReloadableConfigHandle,
+}
+
+impl RateLimitMiddleware {
+    /// Create the middleware, reading the rate limit from `config`.
+    pub fn new(config: &ReloadableConfigHandle) -> Self {
+        Self { limiter: RateLimiter::default(), config: config.clone() }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Token buckets per client, implemented as a generic cell rate algorithm:
+/// Instead of a token count, every client has a "theoretical arrival time",
+/// which is pushed into the future by every request and at which its bucket
+/// would be full again. Clones share the same buckets.
+struct RateLimiter {
+    /// Theoretical arrival time per client. `None` stands for clients with an
+    /// unknown IP address, which share a bucket.
+    buckets: Arc<Mutex<HashMap<Option<IpAddr>, Instant>>>,
+}
+
+impl RateLimiter {
+    /// Take one request from the bucket of the client with the IP address
+    /// `ip` at the time `now`, allowing up to `requests_per_minute` requests
+    /// per client and minute. If the bucket is empty, the time until the next
+    /// request will be allowed is returned as the error.
+    fn check(
+        &self,
+        ip: Option<IpAddr>,
+        now: Instant,
+        requests_per_minute: u32,
+    ) -> Result<(), Duration> {
+        let minute = Duration::from_secs(60);
+        // Time it takes to refill the bucket by one request
+        let emission_interval = minute.checked_div(requests_per_minute).unwrap_or(minute);
+        // How far the theoretical arrival time may be ahead of the current time, before
+        // requests are rejected. Determines the size of the bucket.
+        let burst_tolerance =
+            emission_interval.saturating_mul(requests_per_minute.saturating_sub(1));
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        if buckets.len() >= PRUNE_THRESHOLD {
+            buckets.retain(|_, arrival_time| *arrival_time > now);
+        }
+        let arrival_time = buckets.get(&ip).copied().filter(|time| *time > now).unwrap_or(now);
+        let ahead = arrival_time.saturating_duration_since(now);
+        if ahead > burst_tolerance {
+            return Err(ahead.saturating_sub(burst_tolerance));
+        }
+        buckets.insert(ip, arrival_time.checked_add(emission_interval).unwrap_or(arrival_time));
+        Ok(())
+    }
+}
+
+/// The `429 Too Many Requests` response, telling the client to retry after
+/// `retry_after`, rounded up to whole seconds.
+fn too_many_requests(retry_after: Duration) -> Response {
+    let seconds =
+        retry_after.as_secs().saturating_add(u64::from(retry_after.subsec_nanos() > 0)).max(1);
+    Response::builder()
+        .status(StatusCode::TOO_MANY_REQUESTS)
+        .header(header::RETRY_AFTER, seconds)
+        .finish()
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl<E: Endpoint> Middleware<E> for RateLimitMiddleware {
+    type Output = RateLimitMiddlewareImpl<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        Self::Output { ep, limiter: self.limiter.clone(), config: self.config.clone() }
+    }
+}
+
+/// Struct for middleware functionality implementation
+pub struct RateLimitMiddlewareImpl<E> {
+    /// The wrapped endpoint
+    ep: E,
+    /// The buckets of all clients
+    limiter: RateLimiter,
+    /// Where the currently active rate limit is read from
+    config: ReloadableConfigHandle,
+}
+
+impl<E: Endpoint> Endpoint for RateLimitMiddlewareImpl<E> {
+    type Output = E::Output;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let rate_limit = self.config.current().rate_limit.clone();
+        if !rate_limit.enabled {
+            return self.ep.call(req).await;
+        }
+        let ip = client_ip(req.remote_addr());
+        if let Err(retry_after) =
+            self.limiter.check(ip, Instant::now(), rate_limit.requests_per_minute)
+        {
+            debug!("Rate limited request to {} from {ip:?}", req.uri().path());
+            return Err(poem::error::Error::from_response(too_many_requests(retry_after)));
+        }
+        self.ep.call(req).await
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, Route, get, handler, test::TestClient};
+
+    use super::*;
+    use crate::config::{RateLimitConfig, ReloadableConfig};
+
+    #[handler]
+    fn sample() -> &'static str {
+        "sample"
+    }
+
+    fn ip(ip: &str) -> Option<IpAddr> {
+        Some(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn test_bucket_is_exhausted() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        for _ in 0..3 {
+            limiter.check(ip("192.0.2.1"), now, 3).unwrap();
+        }
+        assert_eq!(limiter.check(ip("192.0.2.1"), now, 3), Err(Duration::from_secs(20)));
+        // Other clients have their own bucket
+        limiter.check(ip("192.0.2.2"), now, 3).unwrap();
+        limiter.check(None, now, 3).unwrap();
+    }
+
+    #[test]
+    fn test_bucket_refills_over_time() {
+        let limiter = RateLimiter::default();
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.check(ip("192.0.2.1"), start, 3).unwrap();
+        }
+
+        // One request is refilled every 20 seconds...
+        let later = start.checked_add(Duration::from_secs(19)).unwrap();
+        assert_eq!(limiter.check(ip("192.0.2.1"), later, 3), Err(Duration::from_secs(1)));
+        let later = start.checked_add(Duration::from_secs(20)).unwrap();
+        limiter.check(ip("192.0.2.1"), later, 3).unwrap();
+        assert!(limiter.check(ip("192.0.2.1"), later, 3).is_err());
+
+        // ...up to the full bucket, but not beyond
+        let much_later = start.checked_add(Duration::from_secs(3600)).unwrap();
+        for _ in 0..3 {
+            limiter.check(ip("192.0.2.1"), much_later, 3).unwrap();
+        }
+        assert!(limiter.check(ip("192.0.2.1"), much_later, 3).is_err());
+    }
+
+    fn config(enabled: bool, requests_per_minute: u32) -> ReloadableConfig {
+        ReloadableConfig {
+            rate_limit: RateLimitConfig { enabled, requests_per_minute },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exceeding_limit_is_too_many_requests() {
+        let config = ReloadableConfigHandle::new(config(true, 2));
+        let client = TestClient::new(
+            Route::new().at("/", get(sample)).with(RateLimitMiddleware::new(&config)),
+        );
+
+        client.get("/").send().await.assert_status_is_ok();
+        client.get("/").send().await.assert_status_is_ok();
+        let response = client.get("/").send().await;
+        response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = response
+            .0
+            .headers()
+            .get(header::RETRY_AFTER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=30).contains(&retry_after), "Retry-After is {retry_after}");
+    }
+
+    #[tokio::test]
+    async fn test_reloaded_limit_applies_immediately() {
+        let handle = ReloadableConfigHandle::new(config(true, 60));
+        let client = TestClient::new(
+            Route::new().at("/", get(sample)).with(RateLimitMiddleware::new(&handle)),
+        );
+        client.get("/").send().await.assert_status_is_ok();
+        client.get("/").send().await.assert_status_is_ok();
+
+        // The requests sent so far exceed the stricter limit
+        handle.store(config(true, 1));
+        client.get("/").send().await.assert_status(StatusCode::TOO_MANY_REQUESTS);
+
+        handle.store(config(false, 1));
+        for _ in 0..3 {
+            client.get("/").send().await.assert_status_is_ok();
+        }
+    }
+}
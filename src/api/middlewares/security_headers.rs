@@ -0,0 +1,133 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use log::warn;
+use poem::{
+    Endpoint, IntoResponse, Middleware, Request, Response,
+    http::{HeaderValue, header},
+};
+
+use crate::config::ApiConfig;
+
+/// Middleware setting security-related response headers, as well as the
+/// configurable `Server` header, on every response. Implements [Endpoint] via
+/// [SecurityHeadersMiddlewareImpl].
+#[derive(Debug, Clone)]
+pub struct SecurityHeadersMiddleware {
+    /// Value of the `Server` header. `None` omits the header.
+    server: Option<HeaderValue>,
+    /// Value of the `Strict-Transport-Security` header. `None` omits the
+    /// header.
+    strict_transport_security: Option<HeaderValue>,
+}
+
+impl SecurityHeadersMiddleware {
+    /// Create the middleware from the [ApiConfig]. The
+    /// `Strict-Transport-Security` header is only sent if TLS is enabled.
+    pub fn new(api_config: &ApiConfig) -> Self {
+        let server = match api_config.server_header.as_str() {
+            "" => None,
+            value => HeaderValue::from_str(value)
+                .inspect_err(|e| warn!("Invalid server_header value {value:?}, omitting it: {e}"))
+                .ok(),
+        };
+        let strict_transport_security = api_config.tls.then(|| {
+            HeaderValue::from_str(&format!("max-age={}", api_config.hsts_max_age))
+                .unwrap_or(HeaderValue::from_static("max-age=31536000"))
+        });
+        Self { server, strict_transport_security }
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl<E: Endpoint> Middleware<E> for SecurityHeadersMiddleware {
+    type Output = SecurityHeadersMiddlewareImpl<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        Self::Output { ep, config: self.clone() }
+    }
+}
+
+/// Struct for middleware functionality implementation
+pub struct SecurityHeadersMiddlewareImpl<E> {
+    /// The wrapped endpoint
+    ep: E,
+    /// The header values to set
+    config: SecurityHeadersMiddleware,
+}
+
+impl<E: Endpoint> Endpoint for SecurityHeadersMiddlewareImpl<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        // `get_response` also turns errors into responses, so error responses get the
+        // headers as well
+        let mut response = self.ep.get_response(req).await.into_response();
+        let headers = response.headers_mut();
+        headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+        headers.insert(header::REFERRER_POLICY, HeaderValue::from_static("no-referrer"));
+        if let Some(server) = &self.config.server {
+            headers.insert(header::SERVER, server.clone());
+        }
+        if let Some(strict_transport_security) = &self.config.strict_transport_security {
+            headers.insert(header::STRICT_TRANSPORT_SECURITY, strict_transport_security.clone());
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, Route, get, handler, http::StatusCode, test::TestClient};
+
+    use super::*;
+
+    #[handler]
+    fn sample() -> &'static str {
+        "sample"
+    }
+
+    fn api_config(tls: bool, server_header: &str) -> ApiConfig {
+        let mut api_config = ApiConfig::for_tests();
+        api_config.tls = tls;
+        api_config.server_header = server_header.to_owned();
+        api_config.hsts_max_age = 600;
+        api_config
+    }
+
+    #[tokio::test]
+    async fn test_security_headers_present() {
+        let client = TestClient::new(
+            Route::new()
+                .at("/", get(sample))
+                .with(SecurityHeadersMiddleware::new(&api_config(false, "sonata-test"))),
+        );
+
+        let response = client.get("/").send().await;
+        response.assert_status_is_ok();
+        response.assert_header("X-Content-Type-Options", "nosniff");
+        response.assert_header("Referrer-Policy", "no-referrer");
+        response.assert_header("Server", "sonata-test");
+        response.assert_header_is_not_exist("Strict-Transport-Security");
+
+        // Error responses get the headers as well
+        let response = client.get("/does-not-exist").send().await;
+        response.assert_status(StatusCode::NOT_FOUND);
+        response.assert_header("X-Content-Type-Options", "nosniff");
+    }
+
+    #[tokio::test]
+    async fn test_security_headers_with_tls() {
+        let client = TestClient::new(
+            Route::new()
+                .at("/", get(sample))
+                .with(SecurityHeadersMiddleware::new(&api_config(true, ""))),
+        );
+
+        let response = client.get("/").send().await;
+        response.assert_header("Strict-Transport-Security", "max-age=600");
+        response.assert_header_is_not_exist("Server");
+    }
+}
@@ -2,46 +2,185 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use log::info;
+use std::{sync::Arc, time::Duration};
+
+use log::{error, info};
 use poem::{
-    EndpointExt, IntoResponse, Response, Route, Server, handler,
+    EndpointExt, IntoResponse, Response, Route, Server, get, handler,
     http::{Method, StatusCode},
-    listener::TcpListener,
+    listener::{Acceptor, AcceptorExt, BoxAcceptor, Listener, TcpListener},
     middleware::{Cors, NormalizePath},
+    web::{Data, Json, LocalAddr},
 };
+use serde_json::json;
+use tokio::sync::watch;
 
 use crate::{
-    config::ApiConfig,
+    StdResult,
+    api::{
+        auth::FailedLoginDelay,
+        discovery::Discovery,
+        extractors::ServedDomains,
+        metrics::{MetricsMiddleware, RequestMetrics},
+        middlewares::{
+            AdminIpAllowlistMiddleware, ApiKeyMiddleware, BodySizeLimitMiddleware,
+            ProblemDetailsMiddleware, RateLimitMiddleware, SecurityHeadersMiddleware,
+        },
+        models::PasswordChecker,
+    },
+    config::{ApiConfig, ReloadableConfigHandle},
+    crypto::signing_key::HomeServerSigningKey,
     database::{Database, tokens::TokenStore},
+    gateway::{ConnectionLimiter, presence::Hub},
+    shutdown_requested,
 };
 
 /// Admin-only functionality.
 pub(super) mod admin;
 /// Authentication functionality.
 mod auth;
+/// The discovery document describing this instance.
+pub(crate) mod discovery;
+/// Custom request extractors, such as the authenticated actor.
+pub(crate) mod extractors;
 /// Routes coveringthe "federated identity" section of the polyproto-core
 /// specification.
 mod federated_identity;
+/// Prometheus metrics endpoint and the middleware collecting them.
+mod metrics;
 /// Custom middlewares, such as authentication and active-user.
 pub(crate) mod middlewares;
 /// API models, such as response schemas
 pub(crate) mod models;
 
-#[allow(clippy::expect_used)]
+/// How long in-flight requests are given to complete after a shutdown has been
+/// requested, before their connections are closed.
+pub(crate) const GRACEFUL_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[cfg_attr(coverage_nightly, coverage(off))]
-/// Build the API [Route]s and start a `tokio::task`, which is a poem [Server]
-/// processing incoming HTTP API requests.
-pub(super) fn start_api(
+/// Build the API [Route]s, bind to the configured addresses and start a
+/// `tokio::task`, which is a poem [Server] processing incoming HTTP API
+/// requests. Gateway announcements made through the admin API are broadcast
+/// through the `hub`, and the metrics endpoint reports the gateway connections
+/// counted by the `connection_limiter`. New passwords are checked by the
+/// `password_checker`. The handlers and the rate limit of the authentication
+/// routes read the `reloadable_config` for every request, so that they follow
+/// configuration reloads.
+///
+/// Once `true` is sent through the channel belonging to `shutdown`, or its
+/// sender is dropped, the server stops accepting new connections and the task
+/// completes after all in-flight requests have been answered, or after
+/// [GRACEFUL_SHUTDOWN_TIMEOUT] has passed.
+///
+/// Next to the task, the addresses the server is listening on are returned.
+/// They differ from the configured ones for addresses with port `0`.
+///
+/// ## Errors
+///
+/// If the server cannot bind to one of the configured addresses, for example
+/// because the port is already in use. The error message names the host and
+/// port, as well as the error reported by the operating system.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn start_api(
     api_config: ApiConfig,
+    served_domains: ServedDomains,
+    discovery: Discovery,
     db: Database,
     token_store: TokenStore,
-) -> tokio::task::JoinHandle<()> {
-    let routes = Route::new()
-        .at("/healthz", healthz)
-        .nest("/.p2/core/", setup_p2_core_routes())
-        .nest("/.p2/auth/", auth::setup_routes())
+    signing_key: HomeServerSigningKey,
+    password_checker: PasswordChecker,
+    reloadable_config: ReloadableConfigHandle,
+    connection_limiter: ConnectionLimiter,
+    hub: Arc<Hub>,
+    mut shutdown: watch::Receiver<bool>,
+) -> StdResult<(tokio::task::JoinHandle<()>, Vec<LocalAddr>)> {
+    let request_metrics = RequestMetrics::default();
+    let routes = setup_routes(&api_config, &reloadable_config)
         .with(NormalizePath::new(poem::middleware::TrailingSlash::Trim))
-        .with(Cors::new().allow_methods(&[
+        .with(BodySizeLimitMiddleware::new(&api_config))
+        .with(ProblemDetailsMiddleware)
+        .with(SecurityHeadersMiddleware::new(&api_config))
+        .with(cors(&api_config))
+        .with(MetricsMiddleware::new(&request_metrics))
+        .data(request_metrics)
+        .data(api_config.pagination)
+        .data(FailedLoginDelay::new(&api_config))
+        .data(served_domains)
+        .data(discovery)
+        .data(db)
+        .data(token_store)
+        .data(signing_key)
+        .data(password_checker)
+        .data(reloadable_config)
+        .data(connection_limiter)
+        .data(hub);
+
+    let mut acceptor: Option<BoxAcceptor> = None;
+    for (host, port) in api_config.bind_addresses() {
+        let bound =
+            TcpListener::bind((host.as_str(), port)).into_acceptor().await.map_err(|e| {
+                format!(
+                    "Couldn't start the HTTP API server at {host}, port {port}: {e}. Is another \
+                 process already using this port?"
+                )
+            })?;
+        acceptor = Some(match acceptor {
+            Some(acceptor) => acceptor.combine(bound).boxed(),
+            None => bound.boxed(),
+        });
+    }
+    let acceptor = acceptor.ok_or("The HTTP API server has no address to bind to")?;
+    let local_addresses = acceptor.local_addr();
+    let handle = tokio::task::spawn(async move {
+        if let Err(e) = Server::new_with_acceptor(acceptor)
+            .run_with_graceful_shutdown(
+                routes,
+                async move { shutdown_requested(&mut shutdown).await },
+                Some(GRACEFUL_SHUTDOWN_TIMEOUT),
+            )
+            .await
+        {
+            error!("HTTP Server stopped due to an error: {e}");
+        }
+        info!("HTTP Server stopped");
+    });
+    for address in &local_addresses {
+        info!("Started HTTP API server at {address}");
+    }
+    Ok((handle, local_addresses))
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// All routes of the API, with the polyproto routes nested under the
+/// [ApiConfig::effective_base_path].
+fn setup_routes(api_config: &ApiConfig, reloadable_config: &ReloadableConfigHandle) -> Route {
+    let base_path = api_config.effective_base_path();
+    let mut routes = Route::new()
+        .at("/healthz", healthz)
+        .at("/readyz", readyz)
+        .at("/.well-known/polyproto-core", get(discovery::polyproto_core))
+        .nest(format!("{base_path}/core/"), setup_p2_core_routes())
+        .nest(
+            format!("{base_path}/auth/"),
+            auth::setup_routes().with(RateLimitMiddleware::new(reloadable_config)),
+        )
+        .nest(
+            format!("{base_path}/admin/"),
+            admin::setup_routes()
+                .with(ApiKeyMiddleware)
+                .with(AdminIpAllowlistMiddleware::new(api_config)),
+        );
+    if api_config.metrics_enabled {
+        routes = routes.at("/metrics", metrics::metrics);
+    }
+    routes
+}
+
+/// Build the [Cors] middleware, allowing and exposing the headers configured
+/// in the [ApiConfig].
+fn cors(api_config: &ApiConfig) -> Cors {
+    Cors::new()
+        .allow_methods(&[
             Method::CONNECT,
             Method::GET,
             Method::POST,
@@ -49,30 +188,316 @@ pub(super) fn start_api(
             Method::DELETE,
             Method::PATCH,
             Method::OPTIONS,
-        ]))
-        .data(db)
-        .data(token_store);
-
-    let api_config_clone = api_config.clone();
-    let handle = tokio::task::spawn(async move {
-        Server::new(TcpListener::bind((api_config.host.as_str().trim(), api_config.port)))
-            .run(routes)
-            .await
-            .expect("Failed to start HTTP server");
-        log::info!("HTTP Server stopped");
-    });
-    info!("Started HTTP API server at {}, port {}", api_config_clone.host, api_config_clone.port);
-    handle
+        ])
+        .allow_headers(&api_config.cors_allow_headers)
+        .expose_headers(&api_config.cors_expose_headers)
 }
 
 #[cfg_attr(coverage_nightly, coverage(off))]
 #[handler]
+/// Liveness probe. Always answers with `200 OK` and does not touch the
+/// database; see `/readyz` for that.
 fn healthz() -> impl IntoResponse {
     Response::builder().status(StatusCode::OK).finish()
 }
 
+/// How long `/readyz` waits for the database to answer, before reporting the
+/// server as not ready.
+const READYZ_DATABASE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[handler]
+/// Readiness probe. Unlike `/healthz`, this also checks whether the database
+/// is reachable, answering with `503 Service Unavailable` if it is not. The
+/// JSON body reports the state of the database.
+async fn readyz(Data(db): Data<&Database>) -> impl IntoResponse {
+    let (status, database) = if db.ping_with_timeout(READYZ_DATABASE_TIMEOUT).await {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "unreachable")
+    };
+    Json(json!({ "database": database })).with_status(status)
+}
+
 #[cfg_attr(coverage_nightly, coverage(off))]
 /// All routes under `/.p2/core/`.
 fn setup_p2_core_routes() -> Route {
-    Route::new()
+    federated_identity::setup_routes()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use poem::{EndpointExt, Route, test::TestClient};
+    use sqlx::{Pool, Postgres};
+
+    use super::*;
+    use crate::{
+        config::{BindHost, GatewayConfig, RateLimitConfig, ReloadableConfig},
+        crypto::ed25519::generate_keypair,
+    };
+
+    fn gateway_config() -> GatewayConfig {
+        let mut gateway_config = GatewayConfig::for_tests();
+        gateway_config.enabled = false;
+        gateway_config
+    }
+
+    fn test_discovery(api_config: &ApiConfig) -> Discovery {
+        Discovery::new("localhost", api_config, &gateway_config())
+    }
+
+    fn hub() -> Arc<Hub> {
+        Arc::new(Hub::new(&gateway_config()))
+    }
+
+    #[sqlx::test]
+    async fn test_readyz(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let client = TestClient::new(Route::new().at("/readyz", readyz).data(db.clone()));
+
+        let response = client.get("/readyz").send().await;
+        response.assert_status_is_ok();
+        response.assert_json(json!({"database": "ok"})).await;
+
+        db.pool.close().await;
+        let response = client.get("/readyz").send().await;
+        response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+        response.assert_content_type("application/json; charset=utf-8");
+        response.assert_json(json!({"database": "unreachable"})).await;
+    }
+
+    #[sqlx::test]
+    async fn test_routes_under_configured_base_path(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let mut api_config = ApiConfig::for_tests();
+        api_config.base_path = "/polyproto/.p2/".to_owned();
+        let client = TestClient::new(
+            setup_routes(&api_config, &ReloadableConfigHandle::default())
+                .data(db.clone())
+                .data(TokenStore::new(db)),
+        );
+
+        // The route exists, but requires authentication
+        client
+            .get("/polyproto/.p2/auth/sessions")
+            .send()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+        client.get("/.p2/auth/sessions").send().await.assert_status(StatusCode::NOT_FOUND);
+        // Probes are not moved
+        client.get("/healthz").send().await.assert_status_is_ok();
+    }
+
+    #[sqlx::test]
+    async fn test_oversized_body_is_rejected(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let mut api_config = ApiConfig::for_tests();
+        api_config.max_body_bytes = 64;
+        let client = TestClient::new(
+            setup_routes(&api_config, &ReloadableConfigHandle::default())
+                .with(BodySizeLimitMiddleware::new(&api_config))
+                .data(db.clone())
+                .data(TokenStore::new(db)),
+        );
+
+        let payload = json!({
+            "tos_consent": true,
+            "local_name": "a".repeat(64),
+            "password": "password123",
+        });
+        client
+            .post("/.p2/auth/register")
+            .body_json(&payload)
+            .send()
+            .await
+            .assert_status(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[sqlx::test]
+    async fn test_only_auth_routes_are_rate_limited(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let api_config = ApiConfig::for_tests();
+        let reloadable_config = ReloadableConfigHandle::new(ReloadableConfig {
+            rate_limit: RateLimitConfig { enabled: true, requests_per_minute: 1 },
+            ..Default::default()
+        });
+        let client = TestClient::new(
+            setup_routes(&api_config, &reloadable_config)
+                .data(db.clone())
+                .data(TokenStore::new(db)),
+        );
+
+        client.get("/.p2/auth/sessions").send().await.assert_status(StatusCode::UNAUTHORIZED);
+        client.get("/.p2/auth/sessions").send().await.assert_status(StatusCode::TOO_MANY_REQUESTS);
+        for _ in 0..2 {
+            client.get("/healthz").send().await.assert_status_is_ok();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight_advertises_configured_headers() {
+        let mut api_config = ApiConfig::for_tests();
+        api_config.cors_expose_headers = vec!["Retry-After".to_owned()];
+        let client = TestClient::new(Route::new().at("/healthz", healthz).with(cors(&api_config)));
+
+        let response = client
+            .options("/healthz")
+            .header("Origin", "https://client.example.com")
+            .header("Access-Control-Request-Method", "POST")
+            .header("Access-Control-Request-Headers", "authorization, idempotency-key")
+            .send()
+            .await;
+        response.assert_status_is_ok();
+        let allowed_headers = response
+            .0
+            .headers()
+            .get("Access-Control-Allow-Headers")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_lowercase();
+        for header in ["authorization", "content-type", "x-api-key", "idempotency-key"] {
+            assert!(allowed_headers.contains(header), "{header} missing in {allowed_headers}");
+        }
+
+        let response =
+            client.get("/healthz").header("Origin", "https://client.example.com").send().await;
+        response.assert_status_is_ok();
+        assert!(
+            response
+                .0
+                .headers()
+                .get("Access-Control-Expose-Headers")
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .eq_ignore_ascii_case("retry-after")
+        );
+    }
+
+    /// The port the server has been bound to at `address`.
+    fn port(address: &LocalAddr) -> u16 {
+        address.as_socket_addr().unwrap().port()
+    }
+
+    /// Send a `GET /healthz` request to the server listening on `port`,
+    /// retrying until the server accepts connections, and return the status
+    /// line.
+    async fn get_healthz(port: u16) -> String {
+        tokio::task::spawn_blocking(move || {
+            use std::io::{BufRead, BufReader, Write};
+
+            for _ in 0..50 {
+                let Ok(mut stream) = std::net::TcpStream::connect(("127.0.0.1", port)) else {
+                    std::thread::sleep(Duration::from_millis(100));
+                    continue;
+                };
+                stream
+                    .write_all(
+                        b"GET /healthz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                    )
+                    .unwrap();
+                let mut status_line = String::new();
+                BufReader::new(stream).read_line(&mut status_line).unwrap();
+                return status_line;
+            }
+            panic!("The server did not accept connections on port {port}");
+        })
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn test_start_api_graceful_shutdown(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let mut api_config = ApiConfig::for_tests();
+        api_config.port = 0;
+        api_config.host = BindHost::Single("127.0.0.1".to_owned());
+        let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+        let (handle, addresses) = start_api(
+            api_config.clone(),
+            ServedDomains::new(["localhost"]),
+            test_discovery(&api_config),
+            db.clone(),
+            TokenStore::new(db),
+            HomeServerSigningKey { key: generate_keypair().0, public_key_id: 0 },
+            PasswordChecker::default(),
+            ReloadableConfigHandle::default(),
+            ConnectionLimiter::new(&gateway_config()),
+            hub(),
+            shutdown_receiver,
+        )
+        .await
+        .unwrap();
+
+        assert!(get_healthz(port(addresses.first().unwrap())).await.starts_with("HTTP/1.1 200"));
+
+        shutdown_sender.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap();
+    }
+
+    #[sqlx::test]
+    async fn test_start_api_multiple_addresses(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let mut api_config = ApiConfig::for_tests();
+        api_config.host = BindHost::Multiple(vec!["127.0.0.1:0".to_owned(); 2]);
+        let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+        let (handle, addresses) = start_api(
+            api_config.clone(),
+            ServedDomains::new(["localhost"]),
+            test_discovery(&api_config),
+            db.clone(),
+            TokenStore::new(db),
+            HomeServerSigningKey { key: generate_keypair().0, public_key_id: 0 },
+            PasswordChecker::default(),
+            ReloadableConfigHandle::default(),
+            ConnectionLimiter::new(&gateway_config()),
+            hub(),
+            shutdown_receiver,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(addresses.len(), 2);
+        for address in &addresses {
+            assert!(get_healthz(port(address)).await.starts_with("HTTP/1.1 200"));
+        }
+
+        shutdown_sender.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap();
+    }
+
+    #[sqlx::test]
+    async fn test_start_api_port_in_use(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let occupied = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = occupied.local_addr().unwrap().port();
+        let mut api_config = ApiConfig::for_tests();
+        api_config.port = port;
+        api_config.host = BindHost::Single("127.0.0.1".to_owned());
+        let (_shutdown_sender, shutdown_receiver) = watch::channel(false);
+
+        let error = start_api(
+            api_config.clone(),
+            ServedDomains::new(["localhost"]),
+            test_discovery(&api_config),
+            db.clone(),
+            TokenStore::new(db),
+            HomeServerSigningKey { key: generate_keypair().0, public_key_id: 0 },
+            PasswordChecker::default(),
+            ReloadableConfigHandle::default(),
+            ConnectionLimiter::new(&gateway_config()),
+            hub(),
+            shutdown_receiver,
+        )
+        .await
+        .unwrap_err()
+        .to_string();
+        assert!(
+            error.starts_with(&format!(
+                "Couldn't start the HTTP API server at 127.0.0.1, port {port}: "
+            )),
+            "{error}"
+        );
+    }
 }
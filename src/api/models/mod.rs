@@ -1,8 +1,25 @@
+use std::{collections::HashSet, path::Path, sync::Arc};
+
+use serde::Serialize;
+
 use crate::{
-    MAX_PERMITTED_PASSWORD_LEN,
-    errors::{Context, Error},
+    MAX_PERMITTED_PASSWORD_LEN, StdResult,
+    config::PasswordPolicyKind,
+    errors::{Context, Errcode, Error},
 };
 
+/// The minimum length of passwords accepted by [NISTPasswordRequirements].
+const MIN_PERMITTED_PASSWORD_LEN: usize = 8;
+/// The minimum length of passwords accepted by [StrictPasswordRequirements].
+const MIN_STRICT_PASSWORD_LEN: usize = 12;
+/// The [CharacterClass]es required by [StrictPasswordRequirements].
+const STRICT_CHARACTER_CLASSES: [CharacterClass; 4] = [
+    CharacterClass::Lowercase,
+    CharacterClass::Uppercase,
+    CharacterClass::Digit,
+    CharacterClass::Symbol,
+];
+
 /// A trait to verify that a password string matches a set of requirements, such
 /// as length, composition details, permitted character set, etc.
 pub trait PasswordRequirements {
@@ -12,14 +29,99 @@ pub trait PasswordRequirements {
     /// Returns a [String] containing the input password, if the verification
     /// has been passed.
     fn verify_requirements(password: &str) -> Result<String, Error>;
+
+    /// Describe the requirements checked by [Self::verify_requirements], so
+    /// that clients can show them before a password is submitted.
+    fn policy() -> PasswordPolicy;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+/// A description of the requirements a [PasswordRequirements] implementation
+/// checks passwords against.
+pub struct PasswordPolicy {
+    /// The minimum length of a password in bytes.
+    pub min_length: usize,
+    /// The maximum length of a password in bytes.
+    pub max_length: usize,
+    /// Character classes of which a password must contain at least one
+    /// character each.
+    pub required_character_classes: Vec<CharacterClass>,
+    /// Whether passwords known to have been leaked in a data breach are
+    /// rejected.
+    pub rejects_breached_passwords: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+/// A class of characters, which a [PasswordPolicy] can require.
+pub enum CharacterClass {
+    /// Lowercase letters.
+    Lowercase,
+    /// Uppercase letters.
+    Uppercase,
+    /// Digits.
+    Digit,
+    /// Characters, which are neither letters nor digits.
+    Symbol,
+}
+
+impl CharacterClass {
+    /// Whether `c` belongs to this class.
+    fn contains(self, c: char) -> bool {
+        match self {
+            CharacterClass::Lowercase => c.is_lowercase(),
+            CharacterClass::Uppercase => c.is_uppercase(),
+            CharacterClass::Digit => c.is_numeric(),
+            CharacterClass::Symbol => !c.is_alphanumeric(),
+        }
+    }
+}
+
+/// Verify that `password` is at least `min_length` and at most
+/// [MAX_PERMITTED_PASSWORD_LEN] bytes long.
+#[allow(clippy::result_large_err)]
+fn verify_length(password: &str, min_length: usize) -> Result<(), Error> {
+    let len = password.len();
+    if !(min_length..=MAX_PERMITTED_PASSWORD_LEN).contains(&len) {
+        return Err(Error::new(
+            crate::errors::Errcode::IllegalInput,
+            Some(Context::new(
+                Some("password"),
+                Some(&(len.to_string() + " characters")),
+                Some(&format!(
+                    "More than {} and less than {} characters",
+                    min_length.saturating_sub(1),
+                    MAX_PERMITTED_PASSWORD_LEN.saturating_add(1)
+                )),
+                None,
+            )),
+        ));
+    }
+    Ok(())
+}
+
+/// Verify that `password` contains no null bytes, which many systems treat
+/// as the end of a string.
+#[allow(clippy::result_large_err)]
+fn verify_no_null_bytes(password: &str) -> Result<(), Error> {
+    match password.contains('\0') {
+        true => Err(Error::new(
+            crate::errors::Errcode::IllegalInput,
+            Some(Context::new(Some("password"), None, Some("No null bytes"), None)),
+        )),
+        false => Ok(()),
+    }
 }
 
 /// A very basic manifestation of NIST 2024 password security guidelines,
 /// stating:
 ///
-/// - All Unicode characters are allowed, including the space (` `) character
+/// - All Unicode characters are allowed, including the space (` `) character,
+///   except for the null byte
 /// - Passwords must be at least 8 characters in length and should be at least
-///   64 characters in length (this implementation chooses 64 as a limit)
+///   64 characters in length (this implementation chooses
+///   [MAX_PERMITTED_PASSWORD_LEN] as a limit)
 /// - No password composition rules are enforced (Numbers, uppercase, lowercase
 ///   characters are not enforced)
 ///
@@ -32,26 +134,186 @@ pub struct NISTPasswordRequirements;
 
 impl PasswordRequirements for NISTPasswordRequirements {
     fn verify_requirements(password: &str) -> Result<String, Error> {
-        let len = password.len();
-        if !(8..=MAX_PERMITTED_PASSWORD_LEN).contains(&len) {
+        verify_length(password, MIN_PERMITTED_PASSWORD_LEN)?;
+        verify_no_null_bytes(password)?;
+        Ok(password.to_owned())
+    }
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: MIN_PERMITTED_PASSWORD_LEN,
+            max_length: MAX_PERMITTED_PASSWORD_LEN,
+            required_character_classes: Vec::new(),
+            rejects_breached_passwords: false,
+        }
+    }
+}
+
+/// A list of known-breached passwords, such as the most common passwords found
+/// in breach corpora. The list is held in memory and checked offline.
+#[derive(Debug, Default, Clone)]
+pub struct BreachedPasswords {
+    /// The breached passwords, exactly as listed. Matching is case-sensitive.
+    passwords: HashSet<String>,
+}
+
+impl BreachedPasswords {
+    /// Parse a list of breached passwords, containing one password per line.
+    /// Empty lines are ignored.
+    pub fn parse(input: &str) -> Self {
+        Self {
+            passwords: input
+                .lines()
+                .map(|line| line.trim_end_matches('\r'))
+                .filter(|line| !line.is_empty())
+                .map(String::from)
+                .collect(),
+        }
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    /// Read the list of breached passwords from the file at `path`. See
+    /// [Self::parse] for the format.
+    pub fn read(path: &Path) -> StdResult<Self> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+
+    /// Whether `password` is contained in this list.
+    pub fn contains(&self, password: &str) -> bool {
+        self.passwords.contains(password)
+    }
+
+    /// How many passwords this list contains.
+    pub fn len(&self) -> usize {
+        self.passwords.len()
+    }
+
+    /// Whether this list contains no passwords.
+    pub fn is_empty(&self) -> bool {
+        self.passwords.is_empty()
+    }
+
+    /// Verify that `password` is not contained in this list, returning an
+    /// [Errcode::IllegalInput] error otherwise.
+    #[allow(clippy::result_large_err)]
+    pub fn verify(&self, password: &str) -> Result<(), Error> {
+        if self.contains(password) {
+            return Err(Error::new(
+                Errcode::IllegalInput,
+                Some(Context::new(
+                    Some("password"),
+                    None,
+                    Some("A password which has not been found in a known data breach"),
+                    Some("This password is known to have been leaked and cannot be used"),
+                )),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Password requirements for operators who prefer composition rules over
+/// the [NISTPasswordRequirements]:
+///
+/// - Passwords must be at least [MIN_STRICT_PASSWORD_LEN] and at most
+///   [MAX_PERMITTED_PASSWORD_LEN] characters in length
+/// - Passwords must contain at least one lowercase letter, uppercase letter,
+///   digit and symbol each
+pub struct StrictPasswordRequirements;
+
+impl PasswordRequirements for StrictPasswordRequirements {
+    fn verify_requirements(password: &str) -> Result<String, Error> {
+        verify_length(password, MIN_STRICT_PASSWORD_LEN)?;
+        verify_no_null_bytes(password)?;
+        if !STRICT_CHARACTER_CLASSES.iter().all(|class| password.chars().any(|c| class.contains(c)))
+        {
             return Err(Error::new(
-                crate::errors::Errcode::IllegalInput,
+                Errcode::IllegalInput,
                 Some(Context::new(
                     Some("password"),
-                    Some(&(len.to_string() + " characters")),
-                    Some(&format!(
-                        "More than 7 and less than {} characters",
-                        MAX_PERMITTED_PASSWORD_LEN.saturating_add(1)
-                    )),
+                    None,
+                    Some("At least one lowercase letter, uppercase letter, digit and symbol each"),
                     None,
                 )),
             ));
         }
         Ok(password.to_owned())
     }
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: MIN_STRICT_PASSWORD_LEN,
+            max_length: MAX_PERMITTED_PASSWORD_LEN,
+            required_character_classes: STRICT_CHARACTER_CLASSES.to_vec(),
+            rejects_breached_passwords: false,
+        }
+    }
+}
+
+/// The [PasswordRequirements::verify_requirements] of the implementor selected
+/// by `policy`: [NISTPasswordRequirements] for [PasswordPolicyKind::Nist] and
+/// [StrictPasswordRequirements] for [PasswordPolicyKind::Strict].
+pub fn password_verifier(policy: PasswordPolicyKind) -> fn(&str) -> Result<String, Error> {
+    match policy {
+        PasswordPolicyKind::Nist => NISTPasswordRequirements::verify_requirements,
+        PasswordPolicyKind::Strict => StrictPasswordRequirements::verify_requirements,
+    }
+}
+
+/// The [PasswordRequirements::policy] of the implementor selected by `policy`.
+/// See [password_verifier].
+pub fn password_policy(policy: PasswordPolicyKind) -> PasswordPolicy {
+    match policy {
+        PasswordPolicyKind::Nist => NISTPasswordRequirements::policy(),
+        PasswordPolicyKind::Strict => StrictPasswordRequirements::policy(),
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Checks new passwords against the [PasswordRequirements] selected by a
+/// [PasswordPolicyKind] and, as recommended by NIST, against a list of
+/// [BreachedPasswords], if one has been loaded on startup. Handlers receive it
+/// as request data.
+pub struct PasswordChecker {
+    /// The passwords to reject regardless of the [PasswordPolicyKind].
+    breached_passwords: Option<Arc<BreachedPasswords>>,
+}
+
+impl PasswordChecker {
+    /// Creates [Self], additionally rejecting the `breached_passwords`, if
+    /// given.
+    pub fn new(breached_passwords: Option<BreachedPasswords>) -> Self {
+        Self { breached_passwords: breached_passwords.map(Arc::new) }
+    }
+
+    /// Verify that `password` fulfills the [PasswordRequirements] selected by
+    /// `policy` and is not contained in the [BreachedPasswords], returning the
+    /// `password`.
+    ///
+    /// ## Errors
+    ///
+    /// [Errcode::IllegalInput], if the `password` does not fulfill the
+    /// requirements, or is known to have been breached.
+    #[allow(clippy::result_large_err)]
+    pub fn verify(&self, policy: PasswordPolicyKind, password: &str) -> Result<String, Error> {
+        let password = password_verifier(policy)(password)?;
+        if let Some(breached_passwords) = &self.breached_passwords {
+            breached_passwords.verify(&password)?;
+        }
+        Ok(password)
+    }
+
+    /// The [PasswordPolicy] [Self::verify] checks passwords against.
+    pub fn policy(&self, policy: PasswordPolicyKind) -> PasswordPolicy {
+        PasswordPolicy {
+            rejects_breached_passwords: self.breached_passwords.is_some(),
+            ..password_policy(policy)
+        }
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::unwrap_used)]
 mod tests {
 
     use super::*;
@@ -63,6 +325,17 @@ mod tests {
         assert_eq!(result.unwrap(), "password123");
     }
 
+    #[test]
+    fn test_password_requirements_reject_null_bytes() {
+        for password in ["pass\0word123", "\0\0\0\0\0\0\0\0"] {
+            let error = NISTPasswordRequirements::verify_requirements(password).unwrap_err();
+            assert_eq!(error.code, Errcode::IllegalInput);
+            assert_eq!(error.context.unwrap().expected, "No null bytes");
+        }
+        let error = StrictPasswordRequirements::verify_requirements("Password123!\0").unwrap_err();
+        assert_eq!(error.context.unwrap().expected, "No null bytes");
+    }
+
     #[test]
     fn test_nist_password_requirements_minimum_length() {
         let result = NISTPasswordRequirements::verify_requirements("12345678");
@@ -198,4 +471,117 @@ mod tests {
         let too_long = "a".repeat(MAX_PERMITTED_PASSWORD_LEN.saturating_add(1));
         assert!(NISTPasswordRequirements::verify_requirements(&too_long).is_err());
     }
+
+    const BREACHED_PASSWORDS_LIST: &str = "123456\r\npassword\n\nqwertyuiop\nletmein!\n";
+
+    #[test]
+    fn test_breached_passwords_parse() {
+        let list = BreachedPasswords::parse(BREACHED_PASSWORDS_LIST);
+        assert_eq!(list.len(), 4);
+        assert!(list.contains("123456"));
+        assert!(list.contains("letmein!"));
+        assert!(!list.contains(""));
+        assert!(!list.contains("Password"));
+    }
+
+    #[test]
+    fn test_password_checker_rejects_breached_passwords() {
+        let checker = PasswordChecker::new(Some(BreachedPasswords::parse(BREACHED_PASSWORDS_LIST)));
+
+        for policy in [PasswordPolicyKind::Nist, PasswordPolicyKind::Strict] {
+            let error = checker.verify(policy, "qwertyuiop").unwrap_err();
+            assert_eq!(error.code, Errcode::IllegalInput);
+            assert_eq!(error.context.unwrap().field_name, "password");
+        }
+        let strong = "correct horse battery staple 8492";
+        assert_eq!(checker.verify(PasswordPolicyKind::Nist, strong).unwrap(), strong);
+        // The requirements of the policy still apply
+        assert!(checker.verify(PasswordPolicyKind::Nist, "short").is_err());
+        assert!(checker.verify(PasswordPolicyKind::Strict, strong).is_err());
+
+        // Without a list, only the requirements of the policy are checked
+        let checker = PasswordChecker::default();
+        assert_eq!(checker.verify(PasswordPolicyKind::Nist, "qwertyuiop").unwrap(), "qwertyuiop");
+    }
+
+    #[test]
+    fn test_nist_password_policy() {
+        let policy = NISTPasswordRequirements::policy();
+        assert_eq!(policy.min_length, 8);
+        assert_eq!(policy.max_length, MAX_PERMITTED_PASSWORD_LEN);
+        assert!(policy.required_character_classes.is_empty());
+        assert!(!policy.rejects_breached_passwords);
+        // The bounds are the ones actually enforced
+        assert!(
+            NISTPasswordRequirements::verify_requirements(&"a".repeat(policy.min_length)).is_ok()
+        );
+        assert!(
+            NISTPasswordRequirements::verify_requirements(&"a".repeat(policy.max_length)).is_ok()
+        );
+        assert!(NISTPasswordRequirements::verify_requirements("1234567").is_err());
+    }
+
+    #[test]
+    fn test_password_checker_policy() {
+        let checker = PasswordChecker::new(Some(BreachedPasswords::parse(BREACHED_PASSWORDS_LIST)));
+
+        let policy = checker.policy(PasswordPolicyKind::Nist);
+        assert_eq!(
+            policy,
+            PasswordPolicy {
+                rejects_breached_passwords: true,
+                ..NISTPasswordRequirements::policy()
+            }
+        );
+        assert_eq!(
+            serde_json::to_value(&policy).unwrap(),
+            serde_json::json!({
+                "minLength": 8,
+                "maxLength": MAX_PERMITTED_PASSWORD_LEN,
+                "requiredCharacterClasses": [],
+                "rejectsBreachedPasswords": true,
+            })
+        );
+        assert!(checker.policy(PasswordPolicyKind::Strict).rejects_breached_passwords);
+        assert_eq!(
+            PasswordChecker::default().policy(PasswordPolicyKind::Nist),
+            NISTPasswordRequirements::policy()
+        );
+    }
+
+    #[test]
+    fn test_strict_password_requirements() {
+        for valid in ["Correct-Horse-8", "пАроль-123-ПАРОЛЬ", "Tr0ub4dor&3xyz"] {
+            assert_eq!(StrictPasswordRequirements::verify_requirements(valid).unwrap(), valid);
+        }
+        // Too short, but otherwise valid
+        let error = StrictPasswordRequirements::verify_requirements("Sh0rt-Pass!").unwrap_err();
+        assert_eq!(error.context.unwrap().found, "11 characters");
+        for missing_class in
+            ["correct-horse-8", "CORRECT-HORSE-8", "Correct-Horse-Battery", "CorrectHorse8Battery"]
+        {
+            let error = StrictPasswordRequirements::verify_requirements(missing_class).unwrap_err();
+            assert_eq!(error.code, Errcode::IllegalInput, "{missing_class}");
+            assert_eq!(error.context.unwrap().field_name, "password");
+        }
+        let too_long = format!("Aa1!{}", "a".repeat(MAX_PERMITTED_PASSWORD_LEN));
+        assert!(StrictPasswordRequirements::verify_requirements(&too_long).is_err());
+    }
+
+    #[test]
+    fn test_password_verifier_dispatch() {
+        let nist = password_verifier(PasswordPolicyKind::Nist);
+        let strict = password_verifier(PasswordPolicyKind::Strict);
+        assert!(nist("password123").is_ok());
+        assert!(strict("password123").is_err());
+        assert!(nist("Correct-Horse-8").is_ok());
+        assert!(strict("Correct-Horse-8").is_ok());
+        assert!(nist("short").is_err());
+        assert!(strict("short").is_err());
+
+        assert_eq!(password_policy(PasswordPolicyKind::Nist).min_length, 8);
+        let policy = password_policy(PasswordPolicyKind::Strict);
+        assert_eq!(policy.min_length, 12);
+        assert_eq!(policy.required_character_classes, STRICT_CHARACTER_CLASSES.to_vec());
+    }
 }
@@ -0,0 +1,76 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::path::Path;
+
+use crate::{
+    StdResult,
+    cli::ApiKeyCommand,
+    config::SonataConfig,
+    database::{
+        Database,
+        api_keys::{self, ApiKey},
+    },
+};
+
+/// Run the given `sonata api-key` subcommand against the database configured in
+/// the configuration file at `config_location`, and return the exit code: `0`
+/// on success, `1` otherwise.
+#[cfg_attr(coverage_nightly, coverage(off))]
+pub(crate) async fn run(command: ApiKeyCommand, config_location: &Path) -> i32 {
+    match try_run(command, config_location).await {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    }
+}
+
+/// Load the configuration at `config_location`, connect to its database and
+/// run the `command` there, printing its result. Returns the error of the first
+/// step which has failed.
+#[cfg_attr(coverage_nightly, coverage(off))]
+async fn try_run(command: ApiKeyCommand, config_location: &Path) -> StdResult<()> {
+    let input = std::fs::read_to_string(config_location)
+        .map_err(|e| format!("Couldn't read the configuration file at {config_location:?}: {e}"))?;
+    let config = SonataConfig::parse(&input)
+        .map_err(|e| format!("The configuration file is invalid: {e}"))?;
+    let database = Database::connect_with_config(&config.general.database, config.general.log_sql)
+        .await
+        .map_err(|e| format!("Couldn't connect to the database: {e}"))?;
+    // Keys are stored in a table that may not exist yet, or may have changed
+    database.run_migrations().await.map_err(|e| format!("Couldn't apply migrations: {e}"))?;
+    match command {
+        ApiKeyCommand::Create => {
+            let api_key =
+                api_keys::add_api_key_to_database(&ApiKey::new_random(&mut rand::rng()), &database)
+                    .await?;
+            println!("{api_key}");
+            eprintln!("Save this API key, as it will not be shown again.");
+        }
+        ApiKeyCommand::List => {
+            let keys = api_keys::list_api_keys(&database).await?;
+            println!("{} API keys exist", keys.len());
+            for key in keys {
+                let created = match key.created_at {
+                    Some(created_at) => format!("created at {created_at}"),
+                    None => String::from("created at an unknown time"),
+                };
+                let state = match (key.revoked, key.expires_at) {
+                    (true, _) => String::from(", revoked"),
+                    (false, Some(expires_at)) => format!(", expires at {expires_at}"),
+                    (false, None) => String::new(),
+                };
+                println!("#{}: {created}{state}", key.id);
+            }
+        }
+        ApiKeyCommand::Revoke { token } => {
+            ApiKey::revoke(&database, &token).await?;
+            println!("Revoked the API key.");
+        }
+    }
+    database.close().await;
+    Ok(())
+}
@@ -0,0 +1,80 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::path::Path;
+
+use chrono::{TimeDelta, Utc};
+use polyproto::{certs::idcert::IdCert, types::FederationId};
+
+use crate::{
+    StdResult,
+    cli::ForeignActorCommand,
+    config::SonataConfig,
+    crypto::ed25519::{DigitalPublicKey, DigitalSignature},
+    database::{Database, ForeignActor},
+};
+
+/// Run the given `sonata foreign-actor` subcommand against the database
+/// configured in the configuration file at `config_location`, and return the
+/// exit code: `0` on success, `1` otherwise.
+#[cfg_attr(coverage_nightly, coverage(off))]
+pub(crate) async fn run(command: ForeignActorCommand, config_location: &Path) -> i32 {
+    match try_run(command, config_location).await {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    }
+}
+
+/// [run], but returning the error instead of printing it.
+#[cfg_attr(coverage_nightly, coverage(off))]
+async fn try_run(command: ForeignActorCommand, config_location: &Path) -> StdResult<()> {
+    let input = std::fs::read_to_string(config_location)
+        .map_err(|e| format!("Couldn't read the configuration file at {config_location:?}: {e}"))?;
+    let config = SonataConfig::parse(&input)
+        .map_err(|e| format!("The configuration file is invalid: {e}"))?;
+    let database = Database::connect_with_config(&config.general.database, config.general.log_sql)
+        .await
+        .map_err(|e| format!("Couldn't connect to the database: {e}"))?;
+    database.run_migrations().await.map_err(|e| format!("Couldn't apply migrations: {e}"))?;
+    match command {
+        ForeignActorCommand::Import { federation_id, cert } => {
+            let federation_id = parse_federation_id(&federation_id)?;
+            let idcert_pem = std::fs::read_to_string(&cert)
+                .map_err(|e| format!("Couldn't read the ID-Cert at {cert:?}: {e}"))?;
+            IdCert::<DigitalSignature, DigitalPublicKey>::from_pem_unchecked(&idcert_pem)
+                .map_err(|e| format!("The file at {cert:?} is not a PEM-encoded ID-Cert: {e}"))?;
+            let foreign_actor =
+                ForeignActor::upsert(&database, &federation_id, &idcert_pem).await?;
+            println!("Cached the ID-Cert of {federation_id} as actor {}.", foreign_actor.uaid);
+        }
+        ForeignActorCommand::Show { federation_id, max_age_hours } => {
+            let federation_id = parse_federation_id(&federation_id)?;
+            match ForeignActor::by_federation_id(&database, &federation_id).await? {
+                Some(foreign_actor) => {
+                    let stale = foreign_actor
+                        .is_stale(TimeDelta::hours(max_age_hours), Utc::now().naive_utc());
+                    println!(
+                        "{federation_id} is actor {}, cached at {}{}",
+                        foreign_actor.uaid,
+                        foreign_actor.cached_at,
+                        if stale { ", stale" } else { "" }
+                    );
+                    println!("{}", foreign_actor.idcert_pem);
+                }
+                None => println!("{federation_id} is not cached."),
+            }
+        }
+    }
+    database.close().await;
+    Ok(())
+}
+
+/// Parse `federation_id`, or describe why it is not a valid [FederationId].
+fn parse_federation_id(federation_id: &str) -> StdResult<FederationId> {
+    Ok(FederationId::new(federation_id)
+        .map_err(|e| format!("{federation_id:?} is not a valid federation ID: {e}"))?)
+}
@@ -0,0 +1,78 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{fs::OpenOptions, io::Write, path::Path};
+
+use crate::StdResult;
+
+/// The commented default configuration file written by `sonata init`.
+pub(crate) const DEFAULT_CONFIG: &str = include_str!("default_config.toml");
+
+/// Run `sonata init`, writing the [DEFAULT_CONFIG] to `output`, and return the
+/// exit code: `0` on success, `1` otherwise.
+#[cfg_attr(coverage_nightly, coverage(off))]
+pub(crate) fn run(output: &Path, force: bool) -> i32 {
+    match write_default_config(output, force) {
+        Ok(()) => {
+            println!("Wrote a default configuration to {output:?}");
+            0
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    }
+}
+
+/// Write the [DEFAULT_CONFIG] to `output`. An existing file is only
+/// overwritten, if `force` is set.
+pub(crate) fn write_default_config(output: &Path, force: bool) -> StdResult<()> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        // Fails atomically, if the file already exists
+        options.create_new(true);
+    }
+    let mut file = options.open(output).map_err(|e| match e.kind() {
+        std::io::ErrorKind::AlreadyExists => {
+            format!("{output:?} already exists. Pass --force to overwrite it")
+        }
+        _ => format!("Couldn't create {output:?}: {e}"),
+    })?;
+    file.write_all(DEFAULT_CONFIG.as_bytes())
+        .map_err(|e| format!("Couldn't write to {output:?}: {e}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::config::SonataConfig;
+
+    #[test]
+    fn test_write_default_config() {
+        let dir = std::env::temp_dir().join(format!("sonata_init_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sonata.toml");
+        _ = fs::remove_file(&path);
+
+        write_default_config(&path, false).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        toml::from_str::<SonataConfig>(&written).unwrap();
+        SonataConfig::parse(&written).unwrap();
+
+        // Existing files are only overwritten with --force
+        fs::write(&path, "edited").unwrap();
+        assert!(write_default_config(&path, false).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "edited");
+        write_default_config(&path, true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), DEFAULT_CONFIG);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -8,6 +8,13 @@ use clap::Parser;
 
 use crate::StdResult;
 
+/// The `api-key` subcommand, managing API keys.
+pub(crate) mod api_key;
+/// The `foreign-actor` subcommand, managing the cache of foreign actors.
+pub(crate) mod foreign_actor;
+/// The `init` subcommand, writing a default configuration file.
+pub(crate) mod init;
+
 /// Module-local global for storing CLI arg values after they have been parsed.
 static CLI_ARGUMENTS: OnceLock<Args> = OnceLock::new();
 
@@ -35,6 +42,84 @@ pub struct Args {
     /// except for regular stdout) (-qqq). "Quiet" settings override "verbose"
     /// settings. If set, overrides config value.
     pub(crate) quiet: u8,
+    #[arg(long)]
+    /// Delete orphaned local actors on startup. These are entries in the
+    /// "actors" table without a matching "local_actors" entry, which can be
+    /// left behind if account creation is interrupted. Without this flag,
+    /// orphaned actors are only reported.
+    pub(crate) purge_orphan_actors: bool,
+    #[command(subcommand)]
+    /// Run a subcommand instead of starting the server.
+    pub(crate) command: Option<Command>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, clap::Subcommand)]
+/// `sonata` subcommands
+pub enum Command {
+    /// Diagnose common setup problems, such as an invalid configuration or an
+    /// unreachable database, without starting the server. Exits with a non-zero
+    /// status code, if a critical check fails.
+    Doctor,
+    /// Manage the API keys of this server, without starting the server.
+    ApiKey {
+        #[command(subcommand)]
+        /// What to do with the API keys.
+        command: ApiKeyCommand,
+    },
+    /// Inspect and fill the cache of actors from other home servers, without
+    /// starting the server.
+    ForeignActor {
+        #[command(subcommand)]
+        /// What to do with the cached foreign actors.
+        command: ForeignActorCommand,
+    },
+    /// Write a commented default configuration file, without starting the
+    /// server.
+    Init {
+        #[arg(short, long, value_name = "PATH", default_value = "sonata.toml")]
+        /// Where to write the configuration file to.
+        output: PathBuf,
+        #[arg(long)]
+        /// Overwrite the file at `output`, if it already exists.
+        force: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, clap::Subcommand)]
+/// `sonata api-key` subcommands
+pub enum ApiKeyCommand {
+    /// Create a new, random API key and print it. The key is not shown again.
+    Create,
+    /// Print how many API keys exist, when they have been created and whether
+    /// they are still active. Does not print the keys themselves.
+    List,
+    /// Revoke an API key, so that it does not authenticate anymore.
+    Revoke {
+        /// The API key to revoke.
+        token: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, clap::Subcommand)]
+/// `sonata foreign-actor` subcommands
+pub enum ForeignActorCommand {
+    /// Cache the ID-Cert of an actor from another home server, replacing the
+    /// cached ID-Cert if there is one.
+    Import {
+        /// The federation ID of the actor, such as `alice@example.com`.
+        federation_id: String,
+        /// Path to the PEM-encoded ID-Cert of the actor.
+        cert: PathBuf,
+    },
+    /// Print the cached ID-Cert of an actor from another home server, and when
+    /// it has been cached.
+    Show {
+        /// The federation ID of the actor, such as `alice@example.com`.
+        federation_id: String,
+        #[arg(long, default_value_t = 24)]
+        /// After how many hours a cached ID-Cert is reported as stale.
+        max_age_hours: i64,
+    },
 }
 
 impl Args {
@@ -56,9 +141,82 @@ impl Args {
 }
 
 #[cfg(test)]
+#[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_api_key_subcommands() {
+        let args = Args::try_parse_from(["sonata", "api-key", "create"]).unwrap();
+        assert_eq!(args.command, Some(Command::ApiKey { command: ApiKeyCommand::Create }));
+
+        let args =
+            Args::try_parse_from(["sonata", "-c", "sonata.toml", "api-key", "list"]).unwrap();
+        assert_eq!(args.command, Some(Command::ApiKey { command: ApiKeyCommand::List }));
+        assert_eq!(args.config, Some(PathBuf::from("sonata.toml")));
+
+        assert!(Args::try_parse_from(["sonata", "api-key"]).is_err());
+        let args = Args::try_parse_from(["sonata", "api-key", "revoke", "key"]).unwrap();
+        assert_eq!(
+            args.command,
+            Some(Command::ApiKey { command: ApiKeyCommand::Revoke { token: "key".to_owned() } })
+        );
+        assert!(Args::try_parse_from(["sonata", "api-key", "revoke"]).is_err());
+        assert!(Args::try_parse_from(["sonata", "api-key", "delete"]).is_err());
+        assert_eq!(Args::try_parse_from(["sonata"]).unwrap().command, None);
+    }
+
+    #[test]
+    fn test_parse_foreign_actor_subcommands() {
+        let args = Args::try_parse_from([
+            "sonata",
+            "foreign-actor",
+            "import",
+            "alice@example.com",
+            "alice.pem",
+        ])
+        .unwrap();
+        assert_eq!(
+            args.command,
+            Some(Command::ForeignActor {
+                command: ForeignActorCommand::Import {
+                    federation_id: "alice@example.com".to_owned(),
+                    cert: PathBuf::from("alice.pem")
+                }
+            })
+        );
+
+        let args =
+            Args::try_parse_from(["sonata", "foreign-actor", "show", "alice@example.com"]).unwrap();
+        assert_eq!(
+            args.command,
+            Some(Command::ForeignActor {
+                command: ForeignActorCommand::Show {
+                    federation_id: "alice@example.com".to_owned(),
+                    max_age_hours: 24
+                }
+            })
+        );
+        assert!(Args::try_parse_from(["sonata", "foreign-actor", "import", "a@b.c"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_init_subcommand() {
+        let args = Args::try_parse_from(["sonata", "init"]).unwrap();
+        assert_eq!(
+            args.command,
+            Some(Command::Init { output: PathBuf::from("sonata.toml"), force: false })
+        );
+
+        let args =
+            Args::try_parse_from(["sonata", "init", "--output", "/etc/sonata.toml", "--force"])
+                .unwrap();
+        assert_eq!(
+            args.command,
+            Some(Command::Init { output: PathBuf::from("/etc/sonata.toml"), force: true })
+        );
+    }
+
     #[test]
     #[should_panic(expected = "cli arguments should have been set")]
     fn test_get_or_panic_without_init() {
@@ -2,13 +2,25 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::{ops::Deref, sync::OnceLock};
+use std::{net::SocketAddr, ops::Deref, path::PathBuf, sync::OnceLock, time::Duration};
 
+use ipnet::IpNet;
+use log::LevelFilter;
+use polyproto::types::DomainName;
 use serde::Deserialize;
 use serde_with::{DisplayFromStr, serde_as};
 
 use crate::{StdError, StdResult};
 
+/// Reloading the subset of the configuration which can be changed at runtime.
+mod reload;
+
+/// Secret configuration values, which can be zeroized on shutdown.
+mod secret;
+
+pub use reload::*;
+pub use secret::*;
+
 /// Module-private "global" variable for storing the configuration values once
 /// they are parsed.
 static CONFIG: OnceLock<SonataConfig> = OnceLock::new();
@@ -27,7 +39,7 @@ const TLS_CONFIG_VERIFY_CA: &str = "verify_ca";
 /// verification
 const TLS_CONFIG_VERIFY_FULL: &str = "verify_full";
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 /// The `sonata.toml` configuration file as Rust structs.
 pub struct SonataConfig {
     /// API module configuration
@@ -36,14 +48,162 @@ pub struct SonataConfig {
     pub gateway: GatewayConfig,
     /// General configuration, mostly consisting of [DatabaseConfig]
     pub general: GeneralConfig,
+    #[serde(default)]
+    /// Security-related configuration
+    pub security: SecurityConfig,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 /// API Module configuration
 pub struct ApiConfig {
     #[serde(flatten)]
     /// [ComponentConfig], holding the configuration values
     config: ComponentConfig,
+    #[serde(default = "default_server_header")]
+    /// Value of the `Server` header sent with every API response. An empty
+    /// string omits the header. Defaults to `sonata`.
+    pub server_header: String,
+    #[serde(default = "default_hsts_max_age")]
+    /// `max-age` of the `Strict-Transport-Security` header in seconds, which is
+    /// only sent if TLS is enabled. Defaults to one year.
+    pub hsts_max_age: u64,
+    #[serde(default = "default_cors_allow_headers")]
+    /// Request headers browser clients may send in cross-origin requests, as
+    /// advertised in `Access-Control-Allow-Headers`. Defaults to the headers
+    /// used by the API: `Authorization`, `Content-Type`, `X-Api-Key` and
+    /// `Idempotency-Key`.
+    pub cors_allow_headers: Vec<String>,
+    #[serde(default)]
+    /// Response headers browser clients may read in cross-origin requests, as
+    /// advertised in `Access-Control-Expose-Headers`. Defaults to none.
+    pub cors_expose_headers: Vec<String>,
+    #[serde(default)]
+    /// Networks in CIDR notation, such as `10.0.0.0/8` or `192.168.1.10/32`,
+    /// from which the admin routes may be accessed. Requests from other
+    /// sources are rejected with `403 Forbidden`. An empty list, the default,
+    /// does not restrict access.
+    pub admin_ip_allowlist: Vec<IpNet>,
+    #[serde(default)]
+    /// Whether Prometheus metrics are served at `/metrics`. The endpoint
+    /// requires no authentication, so it should only be reachable from
+    /// trusted networks. Defaults to `false`.
+    pub metrics_enabled: bool,
+    #[serde(default = "default_base_path")]
+    /// Path under which the polyproto routes are served, for deployments
+    /// behind a path-rewriting reverse proxy. `/healthz`, `/readyz` and
+    /// `/metrics` are not affected. Defaults to `/.p2`.
+    pub base_path: String,
+    #[serde(default = "default_max_body_bytes")]
+    /// How many bytes the body of a request may have at most. Requests with
+    /// larger bodies are rejected with `413 Payload Too Large`, before the
+    /// body is deserialized. Must be greater than zero. Defaults to `65536`.
+    pub max_body_bytes: usize,
+    #[serde(default)]
+    /// For how many milliseconds the response to a failed login is delayed, to
+    /// slow down guessing passwords. Successful logins are not delayed.
+    /// Defaults to `0`, which disables the delay.
+    pub failed_login_delay_ms: u64,
+    #[serde(default)]
+    /// Rate limiting of the authentication routes, such as login and
+    /// registration. Unlike the rest of this section, it is applied when the
+    /// configuration is reloaded.
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    /// Page sizes of the listing endpoints.
+    pub pagination: PaginationConfig,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+/// Configuration of the per-client rate limit of the authentication routes.
+/// All values have defaults, which are used if the `[api.rate_limit]` section
+/// or any of its' values are omitted.
+pub struct RateLimitConfig {
+    #[serde(default = "default_true")]
+    /// Whether the authentication routes are rate limited. Defaults to `true`.
+    pub enabled: bool,
+    #[serde(default = "default_rate_limit_requests_per_minute")]
+    /// How many requests a single IP address may send to the authentication
+    /// routes per minute. Up to this many requests may be sent in a burst.
+    /// Defaults to `30`.
+    pub requests_per_minute: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self { enabled: true, requests_per_minute: default_rate_limit_requests_per_minute() }
+    }
+}
+
+impl RateLimitConfig {
+    /// Check the values, which cannot be expressed through their types alone.
+    fn validate(&self) -> StdResult<()> {
+        if self.requests_per_minute == 0 {
+            return Err("api.rate_limit.requests_per_minute must be greater than 0".into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+/// Configuration of the page sizes shared by all listing endpoints. All values
+/// have defaults, which are used if the `[api.pagination]` section or any of
+/// its' values are omitted.
+pub struct PaginationConfig {
+    #[serde(default = "default_page_size")]
+    /// How many items are returned, if the client does not request a page
+    /// size. Defaults to `50`.
+    pub default_page_size: u32,
+    #[serde(default = "default_max_page_size")]
+    /// How many items are returned at most, regardless of the requested page
+    /// size. Defaults to `100`.
+    pub max_page_size: u32,
+}
+
+impl Default for PaginationConfig {
+    fn default() -> Self {
+        Self { default_page_size: default_page_size(), max_page_size: default_max_page_size() }
+    }
+}
+
+impl PaginationConfig {
+    /// The page size to use for a request asking for `requested` items: the
+    /// [Self::default_page_size], if nothing has been requested, capped at the
+    /// [Self::max_page_size].
+    pub fn page_size(&self, requested: Option<u32>) -> u32 {
+        requested.unwrap_or(self.default_page_size).min(self.max_page_size)
+    }
+
+    /// Check the values, which cannot be expressed through their types alone.
+    fn validate(&self) -> StdResult<()> {
+        if self.default_page_size == 0 {
+            return Err("api.pagination.default_page_size must be greater than 0".into());
+        }
+        if self.default_page_size > self.max_page_size {
+            return Err("api.pagination.default_page_size must not be greater than \
+                        api.pagination.max_page_size"
+                .into());
+        }
+        Ok(())
+    }
+}
+
+impl ApiConfig {
+    /// The [Self::base_path] with a leading, but without a trailing slash, such
+    /// as `/polyproto/.p2`. The empty string stands for the root path.
+    pub fn effective_base_path(&self) -> String {
+        match self.base_path.trim_matches('/') {
+            "" => String::new(),
+            path => format!("/{path}"),
+        }
+    }
+
+    /// Check the values, which cannot be expressed through their types alone.
+    fn validate(&self) -> StdResult<()> {
+        if self.max_body_bytes == 0 {
+            return Err("api.max_body_bytes must be greater than 0".into());
+        }
+        Ok(())
+    }
 }
 
 impl Deref for ApiConfig {
@@ -54,12 +214,82 @@ impl Deref for ApiConfig {
     }
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+impl ApiConfig {
+    /// An enabled [ApiConfig] for tests, bound to `0.0.0.0:3011` without TLS,
+    /// with the defaults for all other values.
+    pub(crate) fn for_tests() -> Self {
+        toml::from_str("enabled = true\nport = 3011\nhost = \"0.0.0.0\"\ntls = false").unwrap()
+    }
+}
+
+#[cfg(test)]
+impl std::ops::DerefMut for ApiConfig {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.config
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 /// Gateway module configuration
 pub struct GatewayConfig {
     #[serde(flatten)]
     /// [ComponentConfig], holding the configuration values
     config: ComponentConfig,
+    #[serde(default = "default_gateway_max_connections")]
+    /// How many WebSocket connections the gateway keeps open at most. Further
+    /// connection attempts are rejected until a connection is closed. Defaults
+    /// to `1000`.
+    pub max_connections: usize,
+    #[serde(default = "default_heartbeat_interval_ms")]
+    /// The interval in milliseconds at which clients are asked to send
+    /// heartbeats, as announced in the `Hello` event. Defaults to `45000`.
+    pub heartbeat_interval_ms: u32,
+    #[serde(default = "default_heartbeat_ack_timeout_ms")]
+    /// How many milliseconds the gateway waits for a heartbeat past the
+    /// `heartbeat_interval_ms`, before closing the connection with
+    /// [crate::gateway::GatewayCloseCode::SessionTimeout]. Must be greater
+    /// than zero and less than twice the `heartbeat_interval_ms`. Defaults to
+    /// `10000`.
+    pub heartbeat_ack_timeout_ms: u32,
+    #[serde(default = "default_presence_debounce_ms")]
+    /// For how many milliseconds an actor, whose last gateway connection has
+    /// closed, is still considered online. Reconnecting within this time does
+    /// not announce the actor as offline and online again. Defaults to `5000`.
+    pub presence_debounce_ms: u32,
+    #[serde(default = "default_max_frame_size_bytes")]
+    /// How many bytes the payload of a frame received from a client may have
+    /// at most. Connections sending larger frames are closed with
+    /// [crate::gateway::GatewayCloseCode::InvalidPayload]. Must be greater
+    /// than zero. Defaults to `65536`.
+    pub max_frame_size_bytes: usize,
+}
+
+impl GatewayConfig {
+    /// Check that the heartbeat timings are sensible: The ack timeout must not
+    /// be zero, and must be shorter than two heartbeat intervals, as a client
+    /// missing two heartbeats in a row is considered gone.
+    fn validate(&self) -> StdResult<()> {
+        if self.heartbeat_interval_ms == 0 {
+            return Err("gateway.heartbeat_interval_ms must be greater than 0".into());
+        }
+        if self.max_frame_size_bytes == 0 {
+            return Err("gateway.max_frame_size_bytes must be greater than 0".into());
+        }
+        if self.heartbeat_ack_timeout_ms == 0
+            || u64::from(self.heartbeat_ack_timeout_ms)
+                >= u64::from(self.heartbeat_interval_ms).saturating_mul(2)
+        {
+            return Err(format!(
+                "gateway.heartbeat_ack_timeout_ms must be greater than 0 and less than twice \
+                 gateway.heartbeat_interval_ms ({}), but is {}",
+                self.heartbeat_interval_ms, self.heartbeat_ack_timeout_ms
+            )
+            .into());
+        }
+        Ok(())
+    }
 }
 
 impl Deref for GatewayConfig {
@@ -70,17 +300,75 @@ impl Deref for GatewayConfig {
     }
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+impl GatewayConfig {
+    /// An enabled [GatewayConfig] for tests, bound to `0.0.0.0:3012` without
+    /// TLS, with the defaults for all other values.
+    pub(crate) fn for_tests() -> Self {
+        toml::from_str("enabled = true\nport = 3012\nhost = \"0.0.0.0\"\ntls = false").unwrap()
+    }
+}
+
+#[cfg(test)]
+impl std::ops::DerefMut for GatewayConfig {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.config
+    }
+}
+
+#[serde_as]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 /// General configuration, consisting of database configuration
 pub struct GeneralConfig {
     /// Database configuration, including host, port, password, etc.
     pub database: DatabaseConfig,
     /// The domain of this Sonata server instance.
     pub server_domain: String,
+    #[serde(default)]
+    /// Further domains this sonata instance serves as a home server, next to
+    /// the `server_domain`. Requests are routed to the issuer matching their
+    /// `Host` header. Defaults to no further domains.
+    pub additional_server_domains: Vec<String>,
+    #[serde(default)]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    /// The log level of sonata. Defaults to `info`. The `-v` and `-q` command
+    /// line flags take precedence over this value. Can be changed at runtime.
+    pub log_level: Option<LevelFilter>,
+    #[serde(default)]
+    /// Whether every executed SQL statement is logged with its execution time
+    /// at the `debug` level, and slow ones at the `warn` level. The values
+    /// bound to the statements are never logged. Defaults to `false`.
+    pub log_sql: bool,
+    #[serde(default = "default_signing_key_file")]
+    /// Path to the file holding the private key this home server signs ID-Certs
+    /// with. If the file does not exist on startup, a new key is generated and
+    /// written to it. Defaults to `signing_key`.
+    pub signing_key_file: PathBuf,
+}
+
+impl GeneralConfig {
+    /// All domains this sonata instance serves, starting with the
+    /// `server_domain`.
+    pub fn served_domains(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.server_domain.as_str())
+            .chain(self.additional_server_domains.iter().map(String::as_str))
+    }
+
+    /// Check that all [served domains](Self::served_domains) are valid domain
+    /// names, and that the [DatabaseConfig] is sensible.
+    fn validate(&self) -> StdResult<()> {
+        for domain in self.served_domains() {
+            DomainName::new(domain).map_err(|e| {
+                format!("{domain:?} is not a valid domain name to serve as a home server: {e}")
+            })?;
+        }
+        self.database.validate()
+    }
 }
 
 #[serde_as]
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct DatabaseConfig {
     /// How many connections to allocate for this connection pool at maximum.
     /// PostgreSQLs default value is 100.
@@ -90,7 +378,7 @@ pub struct DatabaseConfig {
     /// The username with which to connect to the database to.
     pub username: String,
     /// The password with which to connect to the database to.
-    pub password: String,
+    pub password: Secret,
     /// The port on which the database is listening on.
     pub port: u16,
     /// The host URL/IP which the database is listening on.
@@ -99,20 +387,359 @@ pub struct DatabaseConfig {
     #[serde_as(as = "DisplayFromStr")]
     /// TLS connection settings for the database.
     pub tls: TlsConfig,
+    #[serde(default = "default_connect_max_attempts")]
+    /// How often connecting to the database is attempted on startup, before
+    /// giving up. Only attempts which fail because the database is not (yet)
+    /// accepting connections are retried. Defaults to `5`.
+    pub connect_max_attempts: u32,
+    #[serde(default = "default_connect_base_delay_ms")]
+    /// How many milliseconds to wait before the first retry of a failed
+    /// connection attempt. The delay doubles with every further retry.
+    /// Defaults to `500`.
+    pub connect_base_delay_ms: u64,
+    #[serde(default = "default_saturation_check_interval_secs")]
+    /// Every how many seconds it is checked, whether all connections of the
+    /// pool are in use. `0` disables the check. Defaults to `10`.
+    pub saturation_check_interval_secs: u64,
+    #[serde(default = "default_saturation_warning_secs")]
+    /// For how many seconds all connections of the pool have to be in use,
+    /// before a warning suggesting to raise `max_connections` is logged.
+    /// Defaults to `60`.
+    pub saturation_warning_secs: u64,
+    #[serde(default = "default_token_purge_interval_secs")]
+    /// Every how many seconds expired tokens are deleted from the database.
+    /// `0` disables purging, leaving the cleanup to the next token insertion.
+    /// Defaults to `3600`.
+    pub token_purge_interval_secs: u64,
+    #[serde(default)]
+    /// After how many milliseconds a single SQL statement is aborted by the
+    /// database, so that runaway queries cannot occupy a connection of the
+    /// pool indefinitely. `0` disables the timeout. Defaults to `0`.
+    pub statement_timeout_ms: u64,
+}
+
+impl DatabaseConfig {
+    /// Check that the pool may open at least one connection, and that there
+    /// is a host to connect to.
+    fn validate(&self) -> StdResult<()> {
+        if self.max_connections == 0 {
+            return Err("general.database.max_connections must be greater than 0".into());
+        }
+        if self.host.trim().is_empty() {
+            return Err("general.database.host must not be empty".into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+/// Security-related configuration. All values have defaults, which are used if
+/// the `[security]` section or any of its' values are omitted.
+pub struct SecurityConfig {
+    #[serde(default = "default_true")]
+    /// Whether a public key may only be registered once across all actors of
+    /// this server. Defaults to `true`.
+    pub enforce_globally_unique_keys: bool,
+    #[serde(default = "default_max_keys_per_actor")]
+    /// How many public keys a single actor may have registered at most.
+    /// Defaults to `32`.
+    pub max_keys_per_actor: u32,
+    #[serde(default = "default_max_invite_code_length")]
+    /// How long custom invite codes may be at most. Values larger than `16`,
+    /// the maximum length the database can store, are treated as `16`. As
+    /// custom invite codes are at least `8` characters long, values below `8`
+    /// forbid custom invite codes. Defaults to `16`.
+    pub max_invite_code_length: usize,
+    #[serde(default)]
+    /// Path to a file of known-breached passwords, one password per line. If
+    /// set, passwords of newly registering actors must not appear in this
+    /// file. The file is read once on startup. Defaults to no file.
+    pub breached_passwords_file: Option<PathBuf>,
+    #[serde(default)]
+    /// Whether new actors can only register with a valid invite. Defaults to
+    /// `false`.
+    pub invite_only_registration: bool,
+    #[serde(default = "default_login_lockout_threshold")]
+    /// After how many consecutive failed logins an account is locked for
+    /// [Self::login_lockout_cooldown_secs]. `0` disables the lockout. Defaults
+    /// to `5`.
+    pub login_lockout_threshold: u32,
+    #[serde(default = "default_login_lockout_cooldown_secs")]
+    /// For how many seconds an account is locked, once it has reached the
+    /// [Self::login_lockout_threshold]. Defaults to `900`.
+    pub login_lockout_cooldown_secs: u64,
+    #[serde(default = "default_idcert_validity_secs")]
+    /// For how many seconds newly issued actor ID-Certs are valid. Defaults to
+    /// `604800`, one week.
+    pub idcert_validity_secs: u64,
+    #[serde(default = "default_key_login_challenge_ttl_secs")]
+    /// For how many seconds a challenge issued to an actor logging in or
+    /// registering with a public key can be used. Defaults to `300`.
+    pub key_login_challenge_ttl_secs: u64,
+    #[serde(default = "default_token_validity_secs")]
+    /// For how many seconds newly issued auth tokens are valid. `0` issues
+    /// tokens which never expire. Defaults to `2592000`, 30 days.
+    pub token_validity_secs: u64,
+    #[serde(default)]
+    /// Whether local names differing only in case, such as `Alice` and
+    /// `alice`, are treated as the same name. If enabled, such names cannot be
+    /// registered next to each other, and actors are found regardless of the
+    /// case of their name. Defaults to `false`.
+    pub case_insensitive_local_names: bool,
+    #[serde(default)]
+    /// How random serial numbers of newly issued ID-Certs are kept below
+    /// `2^159`, so that they can be encoded with at most 20 octets. Either
+    /// `"project"` or `"reject-and-retry"`. Defaults to `"project"`.
+    pub serial_number_msb_strategy: SerialNumberMsbStrategy,
+    #[serde(default)]
+    /// Which requirements passwords of registering actors and changed
+    /// passwords have to meet. Either `"nist"` or `"strict"`. Defaults to
+    /// `"nist"`.
+    pub password_policy: PasswordPolicyKind,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self {
+            enforce_globally_unique_keys: true,
+            max_keys_per_actor: default_max_keys_per_actor(),
+            max_invite_code_length: default_max_invite_code_length(),
+            breached_passwords_file: None,
+            invite_only_registration: false,
+            login_lockout_threshold: default_login_lockout_threshold(),
+            login_lockout_cooldown_secs: default_login_lockout_cooldown_secs(),
+            idcert_validity_secs: default_idcert_validity_secs(),
+            key_login_challenge_ttl_secs: default_key_login_challenge_ttl_secs(),
+            token_validity_secs: default_token_validity_secs(),
+            case_insensitive_local_names: false,
+            serial_number_msb_strategy: SerialNumberMsbStrategy::default(),
+            password_policy: PasswordPolicyKind::default(),
+        }
+    }
+}
+
+impl SecurityConfig {
+    /// How long newly issued auth tokens are valid, or `None`, if they never
+    /// expire.
+    pub fn token_validity(&self) -> Option<Duration> {
+        (self.token_validity_secs > 0).then(|| Duration::from_secs(self.token_validity_secs))
+    }
+}
+
+/// Helper for `#[serde(default = "...")]` attributes, which need a function.
+fn default_true() -> bool {
+    true
+}
+
+/// Default value of [ApiConfig::base_path].
+fn default_base_path() -> String {
+    String::from("/.p2")
+}
+
+/// Default value of [ApiConfig::server_header].
+fn default_server_header() -> String {
+    String::from("sonata")
+}
+
+/// Default value of [ApiConfig::hsts_max_age].
+fn default_hsts_max_age() -> u64 {
+    31_536_000
+}
+
+/// Default value of [ApiConfig::cors_allow_headers].
+fn default_cors_allow_headers() -> Vec<String> {
+    ["Authorization", "Content-Type", "X-Api-Key", "Idempotency-Key"]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+/// Default value of [ApiConfig::max_body_bytes].
+fn default_max_body_bytes() -> usize {
+    65_536
+}
+
+/// Default value of [RateLimitConfig::requests_per_minute].
+fn default_rate_limit_requests_per_minute() -> u32 {
+    30
+}
+
+/// Default value of [PaginationConfig::default_page_size].
+fn default_page_size() -> u32 {
+    50
+}
+
+/// Default value of [PaginationConfig::max_page_size].
+fn default_max_page_size() -> u32 {
+    100
+}
+
+/// Default value of [GatewayConfig::max_connections].
+fn default_gateway_max_connections() -> usize {
+    1000
+}
+
+/// Default value of [GatewayConfig::heartbeat_interval_ms].
+fn default_heartbeat_interval_ms() -> u32 {
+    45_000
+}
+
+/// Default value of [GatewayConfig::heartbeat_ack_timeout_ms].
+fn default_heartbeat_ack_timeout_ms() -> u32 {
+    10_000
+}
+
+/// Default value of [GatewayConfig::presence_debounce_ms].
+fn default_presence_debounce_ms() -> u32 {
+    5000
 }
 
-#[derive(Deserialize, Debug, Clone)]
+/// Default value of [GatewayConfig::max_frame_size_bytes].
+fn default_max_frame_size_bytes() -> usize {
+    65_536
+}
+
+/// Default value of [GeneralConfig::signing_key_file].
+fn default_signing_key_file() -> PathBuf {
+    PathBuf::from("signing_key")
+}
+
+/// Default value of [DatabaseConfig::connect_max_attempts].
+fn default_connect_max_attempts() -> u32 {
+    5
+}
+
+/// Default value of [DatabaseConfig::connect_base_delay_ms].
+fn default_connect_base_delay_ms() -> u64 {
+    500
+}
+
+/// Default value of [DatabaseConfig::saturation_check_interval_secs].
+fn default_saturation_check_interval_secs() -> u64 {
+    10
+}
+
+/// Default value of [DatabaseConfig::saturation_warning_secs].
+fn default_saturation_warning_secs() -> u64 {
+    60
+}
+
+/// Default value of [DatabaseConfig::token_purge_interval_secs].
+fn default_token_purge_interval_secs() -> u64 {
+    3600
+}
+
+/// Default value of [SecurityConfig::max_keys_per_actor].
+fn default_max_keys_per_actor() -> u32 {
+    32
+}
+
+/// Default value of [SecurityConfig::max_invite_code_length].
+fn default_max_invite_code_length() -> usize {
+    16
+}
+
+/// Default value of [SecurityConfig::login_lockout_threshold].
+fn default_login_lockout_threshold() -> u32 {
+    5
+}
+
+/// Default value of [SecurityConfig::login_lockout_cooldown_secs].
+fn default_login_lockout_cooldown_secs() -> u64 {
+    900
+}
+
+/// Default value of [SecurityConfig::idcert_validity_secs].
+fn default_idcert_validity_secs() -> u64 {
+    604_800
+}
+
+/// Default value of [SecurityConfig::key_login_challenge_ttl_secs].
+fn default_key_login_challenge_ttl_secs() -> u64 {
+    300
+}
+
+/// Default value of [SecurityConfig::token_validity_secs].
+fn default_token_validity_secs() -> u64 {
+    2_592_000
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct ComponentConfig {
     /// Whether this component is enabled.
     pub enabled: bool,
-    /// Which port to bind to.
+    /// Which port to bind to, if [ComponentConfig::host] is a single host.
     pub port: u16,
-    /// Which host address to bind to.
-    pub host: String,
+    /// Which address(es) to bind to.
+    pub host: BindHost,
     /// Whether TLS is enabled or not.
     pub tls: bool,
 }
 
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+/// The address(es) a component binds to.
+pub enum BindHost {
+    /// A single host name or IP address, bound to at the
+    /// [ComponentConfig::port].
+    Single(String),
+    /// A list of socket addresses, such as `0.0.0.0:3011` or `[::]:3011`,
+    /// each bound to at its own port. The [ComponentConfig::port] is ignored.
+    Multiple(Vec<String>),
+}
+
+impl ComponentConfig {
+    /// Check that [ComponentConfig::host] holds at least one address, and that
+    /// all of the listed socket addresses are valid. Enabled components must
+    /// not bind to port `0`. `component` names the section of the
+    /// configuration file in error messages.
+    fn validate(&self, component: &str) -> StdResult<()> {
+        match &self.host {
+            BindHost::Single(host) if host.trim().is_empty() => {
+                Err(format!("{component}.host must not be empty").into())
+            }
+            BindHost::Single(_) if self.enabled && self.port == 0 => {
+                Err(format!("{component}.port must be greater than 0").into())
+            }
+            BindHost::Single(_) => Ok(()),
+            BindHost::Multiple(addresses) if addresses.is_empty() => {
+                Err(format!("{component}.host must list at least one address").into())
+            }
+            BindHost::Multiple(addresses) => {
+                for address in addresses {
+                    let socket_address = address.parse::<SocketAddr>().map_err(|e| {
+                        format!(
+                            "{component}.host contains {address:?}, which is not a socket \
+                             address such as \"0.0.0.0:3011\" or \"[::]:3011\": {e}"
+                        )
+                    })?;
+                    if self.enabled && socket_address.port() == 0 {
+                        return Err(format!(
+                            "{component}.host contains {address:?}, which has no port greater \
+                             than 0"
+                        )
+                        .into());
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The host and port of every address to bind to. Addresses which are not
+    /// valid socket addresses are skipped; see [ComponentConfig::validate].
+    pub fn bind_addresses(&self) -> Vec<(String, u16)> {
+        match &self.host {
+            BindHost::Single(host) => vec![(host.trim().to_owned(), self.port)],
+            BindHost::Multiple(addresses) => addresses
+                .iter()
+                .filter_map(|address| address.parse::<SocketAddr>().ok())
+                .map(|address| (address.ip().to_string(), address.port()))
+                .collect(),
+        }
+    }
+}
+
 impl SonataConfig {
     /// Initializes the [SonataConfig] by reading the configuration file, then
     /// storing it in a global variable. After calling this function
@@ -122,9 +749,47 @@ impl SonataConfig {
     /// This function may only be called once. Subsequent calls of this function
     /// will yield an Error.
     pub fn init(input: &str) -> StdResult<()> {
+        let cfg = Self::parse(input)?;
+        CONFIG.set(cfg.clone()).map_err(|_| String::from("config global was already set"))?;
+        ConfigReloader::init_global(cfg)
+    }
+
+    /// Parse a configuration file without initializing the global
+    /// [SonataConfig]. The parsed configuration is [validated](Self::validate).
+    pub fn parse(input: &str) -> StdResult<Self> {
         let cfg = toml::from_str::<Self>(input)?;
-        CONFIG.set(cfg).map_err(|_| String::from("config global was already set"))?;
-        Ok(())
+        cfg.validate()?;
+        Ok(cfg)
+    }
+
+    /// Check the values, which cannot be expressed through their types alone,
+    /// so that an invalid configuration is reported on startup, instead of
+    /// failing once the value is used.
+    pub fn validate(&self) -> StdResult<()> {
+        self.general.validate()?;
+        self.api.config.validate("api")?;
+        self.api.validate()?;
+        self.gateway.config.validate("gateway")?;
+        self.gateway.validate()?;
+        self.api.rate_limit.validate()?;
+        self.api.pagination.validate()
+    }
+
+    /// Zeroize all secret values of this configuration, such as the database
+    /// password.
+    pub fn zeroize_secrets(&self) {
+        self.general.database.password.zeroize();
+    }
+
+    /// Zeroize all secret values of the global [SonataConfig], if it has been
+    /// initialized. Meant to be called when sonata shuts down, as the global
+    /// configuration is never dropped. Since [Secret]s share their buffer with
+    /// their clones, this also zeroizes the copy held by the
+    /// [ConfigReloader].
+    pub fn zeroize_global_secrets() {
+        if let Some(config) = CONFIG.get() {
+            config.zeroize_secrets();
+        }
     }
 
     #[allow(clippy::expect_used)]
@@ -135,6 +800,33 @@ impl SonataConfig {
     }
 }
 
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// Selects the [PasswordRequirements](crate::api::models::PasswordRequirements)
+/// passwords are checked against. Regardless of the selection, passwords in the
+/// [SecurityConfig::breached_passwords_file] are rejected.
+pub enum PasswordPolicyKind {
+    /// [NISTPasswordRequirements](crate::api::models::NISTPasswordRequirements).
+    #[default]
+    Nist,
+    /// [StrictPasswordRequirements](crate::api::models::StrictPasswordRequirements),
+    /// which additionally enforce composition rules.
+    Strict,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// How a randomly generated serial number, whose first octet is larger than
+/// `127`, is made encodable with at most 20 octets.
+pub enum SerialNumberMsbStrategy {
+    /// Take the first octet modulo `128`.
+    #[default]
+    Project,
+    /// Discard the random octets and draw new ones, until the first octet is
+    /// at most `127`.
+    RejectAndRetry,
+}
+
 #[derive(Debug, Deserialize, Default, Clone, PartialEq)]
 /// TLS configuration modes. Also called `sslconfig` by PostgreSQL. See <https://www.postgresql.org/docs/current/libpq-ssl.html#:~:text=32.1.%C2%A0SSL%20Mode-,descriptions,-sslmode>
 /// for the security implications of this choice.
@@ -259,36 +951,235 @@ mod tests {
             config: ComponentConfig {
                 enabled: true,
                 port: 8080,
-                host: "localhost".to_owned(),
+                host: BindHost::Single("localhost".to_owned()),
                 tls: true,
             },
+            server_header: default_server_header(),
+            hsts_max_age: default_hsts_max_age(),
+            cors_allow_headers: default_cors_allow_headers(),
+            cors_expose_headers: Vec::new(),
+            admin_ip_allowlist: Vec::new(),
+            metrics_enabled: false,
+            base_path: default_base_path(),
+            max_body_bytes: default_max_body_bytes(),
+            failed_login_delay_ms: 0,
+            rate_limit: RateLimitConfig::default(),
+            pagination: PaginationConfig::default(),
         };
 
         // Test that deref works correctly
         assert!(config.enabled);
         assert_eq!(config.port, 8080);
-        assert_eq!(config.host, "localhost");
+        assert_eq!(config.host, BindHost::Single("localhost".to_owned()));
         assert!(config.tls);
     }
 
+    #[test]
+    fn test_api_config_header_defaults() {
+        let config: ApiConfig =
+            toml::from_str("enabled = true\nport = 3011\nhost = \"0.0.0.0\"\ntls = false").unwrap();
+        assert_eq!(config.server_header, "sonata");
+        assert_eq!(config.hsts_max_age, 31_536_000);
+        assert_eq!(
+            config.cors_allow_headers,
+            vec!["Authorization", "Content-Type", "X-Api-Key", "Idempotency-Key"]
+        );
+        assert!(config.cors_expose_headers.is_empty());
+        assert!(!config.metrics_enabled);
+        assert_eq!(config.effective_base_path(), "/.p2");
+        assert_eq!(config.max_body_bytes, 65_536);
+        assert_eq!(config.failed_login_delay_ms, 0);
+    }
+
+    #[test]
+    fn test_api_config_effective_base_path() {
+        for (base_path, expected) in [
+            ("/.p2", "/.p2"),
+            ("/polyproto/.p2/", "/polyproto/.p2"),
+            ("polyproto/.p2", "/polyproto/.p2"),
+            ("/", ""),
+            ("", ""),
+        ] {
+            let config: ApiConfig = toml::from_str(&format!(
+                "enabled = true\nport = 3011\nhost = \"0.0.0.0\"\ntls = false\nbase_path = \
+                 \"{base_path}\""
+            ))
+            .unwrap();
+            assert_eq!(config.effective_base_path(), expected, "base_path = {base_path:?}");
+        }
+    }
+
+    #[test]
+    fn test_served_domains() {
+        let mut config = SonataConfig::parse(
+            &std::fs::read_to_string(format!("{}/sonata.toml", std::env!("CARGO_MANIFEST_DIR")))
+                .unwrap(),
+        )
+        .unwrap()
+        .general;
+        assert_eq!(config.served_domains().collect::<Vec<_>>(), vec!["localhost"]);
+
+        config.additional_server_domains = vec!["example.com".to_owned(), "example.org".to_owned()];
+        assert_eq!(
+            config.served_domains().collect::<Vec<_>>(),
+            vec!["localhost", "example.com", "example.org"]
+        );
+    }
+
     #[test]
     fn test_gateway_config_deref() {
         let config = GatewayConfig {
             config: ComponentConfig {
                 enabled: false,
                 port: 9090,
-                host: "0.0.0.0".to_owned(),
+                host: BindHost::Single("0.0.0.0".to_owned()),
                 tls: false,
             },
+            max_connections: 1000,
+            heartbeat_interval_ms: 45_000,
+            heartbeat_ack_timeout_ms: 10_000,
+            presence_debounce_ms: 5000,
+            max_frame_size_bytes: 65_536,
         };
 
         // Test that deref works correctly
         assert!(!config.enabled);
         assert_eq!(config.port, 9090);
-        assert_eq!(config.host, "0.0.0.0");
+        assert_eq!(config.host, BindHost::Single("0.0.0.0".to_owned()));
         assert!(!config.tls);
     }
 
+    #[test]
+    fn test_component_config_bind_addresses() {
+        let sonata_toml =
+            std::fs::read_to_string(format!("{}/sonata.toml", std::env!("CARGO_MANIFEST_DIR")))
+                .unwrap();
+        let with_host = |host: toml::Value| {
+            let mut config: toml::Table = toml::from_str(&sonata_toml).unwrap();
+            let api = config.get_mut("api").unwrap().as_table_mut().unwrap();
+            api.insert("port".to_owned(), 3011.into());
+            api.insert("host".to_owned(), host);
+            SonataConfig::parse(&config.to_string())
+        };
+
+        let single = with_host("::".into()).unwrap();
+        assert_eq!(single.api.host, BindHost::Single("::".to_owned()));
+        assert_eq!(single.api.bind_addresses(), vec![("::".to_owned(), 3011)]);
+
+        let list = with_host(vec!["0.0.0.0:3011", "[::1]:3111"].into()).unwrap();
+        assert_eq!(
+            list.api.bind_addresses(),
+            vec![("0.0.0.0".to_owned(), 3011), ("::1".to_owned(), 3111)]
+        );
+
+        let error = with_host(vec!["0.0.0.0:3011", "localhost"].into()).unwrap_err();
+        assert!(error.to_string().contains("\"localhost\""), "{error}");
+        assert!(with_host(Vec::<String>::new().into()).is_err());
+        assert!(with_host(" ".into()).is_err());
+    }
+
+    #[test]
+    fn test_gateway_config_heartbeat_validation() {
+        let sonata_toml =
+            std::fs::read_to_string(format!("{}/sonata.toml", std::env!("CARGO_MANIFEST_DIR")))
+                .unwrap();
+        let with_heartbeat = |interval: i64, ack_timeout: i64| {
+            let mut config: toml::Table = toml::from_str(&sonata_toml).unwrap();
+            let gateway = config.get_mut("gateway").unwrap().as_table_mut().unwrap();
+            gateway.insert("heartbeat_interval_ms".to_owned(), interval.into());
+            gateway.insert("heartbeat_ack_timeout_ms".to_owned(), ack_timeout.into());
+            SonataConfig::parse(&config.to_string())
+        };
+
+        let config = with_heartbeat(30_000, 59_999).unwrap();
+        assert_eq!(config.gateway.heartbeat_interval_ms, 30_000);
+        assert_eq!(config.gateway.heartbeat_ack_timeout_ms, 59_999);
+        assert!(with_heartbeat(30_000, 60_000).is_err());
+        assert!(with_heartbeat(30_000, 0).is_err());
+        assert!(with_heartbeat(0, 1).is_err());
+    }
+
+    #[test]
+    fn test_api_rate_limit_config() {
+        let sonata_toml =
+            std::fs::read_to_string(format!("{}/sonata.toml", std::env!("CARGO_MANIFEST_DIR")))
+                .unwrap();
+        let mut config: toml::Table = toml::from_str(&sonata_toml).unwrap();
+        let api = config.get_mut("api").unwrap().as_table_mut().unwrap();
+
+        // The section is optional
+        api.remove("rate_limit");
+        let parsed = SonataConfig::parse(&config.to_string()).unwrap();
+        assert_eq!(parsed.api.rate_limit, RateLimitConfig::default());
+
+        let mut rate_limit = toml::Table::new();
+        rate_limit.insert("requests_per_minute".to_owned(), 0.into());
+        let api = config.get_mut("api").unwrap().as_table_mut().unwrap();
+        api.insert("rate_limit".to_owned(), rate_limit.into());
+        assert!(SonataConfig::parse(&config.to_string()).is_err());
+    }
+
+    #[test]
+    fn test_api_pagination_config() {
+        let config = PaginationConfig::default();
+        assert_eq!(config.page_size(None), 50);
+        assert_eq!(config.page_size(Some(10)), 10);
+        assert_eq!(config.page_size(Some(1000)), 100);
+
+        let parse = |toml: &str| -> StdResult<PaginationConfig> {
+            let config = toml::from_str::<PaginationConfig>(toml)?;
+            config.validate()?;
+            Ok(config)
+        };
+        assert_eq!(parse("").unwrap(), PaginationConfig::default());
+        assert_eq!(
+            parse("max_page_size = 20\ndefault_page_size = 20").unwrap().page_size(None),
+            20
+        );
+        assert!(parse("default_page_size = 0").is_err());
+        assert!(parse("default_page_size = 200").is_err());
+    }
+
+    #[test]
+    fn test_sonata_config_validate() {
+        let sonata_toml =
+            std::fs::read_to_string(format!("{}/sonata.toml", std::env!("CARGO_MANIFEST_DIR")))
+                .unwrap();
+        let with_value = |section: &str, key: &str, value: toml::Value| {
+            let mut config: toml::Table = toml::from_str(&sonata_toml).unwrap();
+            let mut table = &mut config;
+            for name in section.split('.') {
+                table = table.get_mut(name).unwrap().as_table_mut().unwrap();
+            }
+            table.insert(key.to_owned(), value);
+            SonataConfig::parse(&config.to_string())
+        };
+
+        assert!(SonataConfig::parse(&sonata_toml).unwrap().validate().is_ok());
+        for (section, key, value) in [
+            ("general", "server_domain", toml::Value::from("")),
+            ("general", "server_domain", "example.com/".into()),
+            ("general", "additional_server_domains", vec!["EXAMPLE.COM"].into()),
+            ("general.database", "max_connections", 0.into()),
+            ("general.database", "host", " ".into()),
+            ("api", "port", 0.into()),
+            ("api", "host", "".into()),
+            ("api", "host", vec!["0.0.0.0:0"].into()),
+            ("api", "max_body_bytes", 0.into()),
+            ("gateway", "port", 0.into()),
+            ("gateway", "host", "".into()),
+        ] {
+            assert!(with_value(section, key, value.clone()).is_err(), "{section}.{key} = {value}");
+        }
+
+        // Disabled components may leave their port unset
+        let mut config: toml::Table = toml::from_str(&sonata_toml).unwrap();
+        let api = config.get_mut("api").unwrap().as_table_mut().unwrap();
+        api.insert("enabled".to_owned(), false.into());
+        api.insert("port".to_owned(), 0.into());
+        assert!(SonataConfig::parse(&config.to_string()).is_ok());
+    }
+
     #[test]
     fn test_sonata_config_init() {
         let toml_str =
@@ -304,6 +1195,58 @@ mod tests {
         assert!(SonataConfig::init(toml_str).is_err());
     }
 
+    #[test]
+    fn test_sonata_config_zeroize_secrets() {
+        let toml_str =
+            &std::fs::read_to_string(format!("{}/sonata.toml", std::env!("CARGO_MANIFEST_DIR")))
+                .unwrap();
+        let config = SonataConfig::parse(toml_str).unwrap();
+        let reloader_copy = config.clone();
+        assert!(!config.general.database.password.expose().is_empty());
+
+        config.zeroize_secrets();
+        assert!(config.general.database.password.expose().is_empty());
+        assert!(reloader_copy.general.database.password.expose().is_empty());
+        assert_eq!(*config.general.database.password.expose(), "");
+    }
+
+    #[test]
+    fn test_security_config_defaults() {
+        let toml_str =
+            &std::fs::read_to_string(format!("{}/sonata.toml", std::env!("CARGO_MANIFEST_DIR")))
+                .unwrap();
+        let mut config: toml::Table = toml::from_str(toml_str).unwrap();
+        config.remove("security");
+        let config: SonataConfig = toml::from_str(&config.to_string()).unwrap();
+        assert_eq!(config.security, SecurityConfig::default());
+        assert!(config.security.enforce_globally_unique_keys);
+        assert_eq!(config.security.max_keys_per_actor, 32);
+    }
+
+    #[test]
+    fn test_security_config_max_keys_per_actor() {
+        let config: SecurityConfig = toml::from_str("max_keys_per_actor = 3").unwrap();
+        assert_eq!(config.max_keys_per_actor, 3);
+        assert!(config.enforce_globally_unique_keys);
+    }
+
+    #[test]
+    fn test_security_config_enforce_globally_unique_keys_off() {
+        let config: SecurityConfig =
+            toml::from_str("enforce_globally_unique_keys = false").unwrap();
+        assert!(!config.enforce_globally_unique_keys);
+    }
+
+    #[test]
+    fn test_security_config_token_validity() {
+        assert_eq!(
+            SecurityConfig::default().token_validity(),
+            Some(Duration::from_secs(2_592_000))
+        );
+        let config: SecurityConfig = toml::from_str("token_validity_secs = 0").unwrap();
+        assert_eq!(config.token_validity(), None);
+    }
+
     #[test]
     fn test_sonata_config_init_invalid_toml() {
         let invalid_toml = "this is not valid toml";
@@ -0,0 +1,215 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::sync::{Arc, OnceLock};
+
+use arc_swap::ArcSwap;
+use log::{LevelFilter, warn};
+
+use crate::{
+    StdResult,
+    config::{RateLimitConfig, SecurityConfig, SonataConfig},
+};
+
+/// Module-private "global" variable for storing the [ConfigReloader] once the
+/// configuration has been parsed.
+static RELOADER: OnceLock<ConfigReloader> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// The subset of the [SonataConfig] which is safe to change at runtime, for
+/// example by sending `SIGHUP` to sonata.
+pub struct ReloadableConfig {
+    /// See [GeneralConfig::log_level](crate::config::GeneralConfig::log_level).
+    pub log_level: Option<LevelFilter>,
+    /// Security-related configuration
+    pub security: SecurityConfig,
+    /// See [ApiConfig::rate_limit](crate::config::ApiConfig::rate_limit).
+    pub rate_limit: RateLimitConfig,
+}
+
+impl From<&SonataConfig> for ReloadableConfig {
+    fn from(value: &SonataConfig) -> Self {
+        Self {
+            log_level: value.general.log_level,
+            security: value.security.clone(),
+            rate_limit: value.api.rate_limit.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Shared access to the currently active [ReloadableConfig]. Handed to the
+/// parts of sonata which read the configuration while running, such as the
+/// API handlers, so that they pick up reloaded values. Clones share the same
+/// configuration.
+pub struct ReloadableConfigHandle(Arc<ArcSwap<ReloadableConfig>>);
+
+impl ReloadableConfigHandle {
+    /// Creates [Self], starting out with the `config`.
+    pub fn new(config: ReloadableConfig) -> Self {
+        Self(Arc::new(ArcSwap::from_pointee(config)))
+    }
+
+    /// Gets the currently active [ReloadableConfig].
+    pub fn current(&self) -> Arc<ReloadableConfig> {
+        self.0.load_full()
+    }
+
+    /// Replaces the active [ReloadableConfig] with `config`, for this handle
+    /// and all of its clones.
+    pub fn store(&self, config: ReloadableConfig) {
+        self.0.store(Arc::new(config));
+    }
+}
+
+/// Holds the currently active [ReloadableConfig], as well as the
+/// [SonataConfig] sonata has been started with.
+pub struct ConfigReloader {
+    /// The configuration sonata has been started with. Changes to values not
+    /// covered by [ReloadableConfig] are compared against this and ignored.
+    startup: SonataConfig,
+    /// The currently active [ReloadableConfig].
+    current: ReloadableConfigHandle,
+}
+
+impl ConfigReloader {
+    /// Creates [Self] from the configuration sonata has been started with.
+    pub fn new(startup: SonataConfig) -> Self {
+        let current = ReloadableConfigHandle::new(ReloadableConfig::from(&startup));
+        Self { startup, current }
+    }
+
+    /// Initializes the global [ConfigReloader]. Called by
+    /// [SonataConfig::init()].
+    pub(super) fn init_global(startup: SonataConfig) -> StdResult<()> {
+        RELOADER
+            .set(Self::new(startup))
+            .map_err(|_| String::from("config reloader global was already set"))?;
+        Ok(())
+    }
+
+    /// Gets a static reference to the global [ConfigReloader]. Will panic, if
+    /// the [SonataConfig] has not been initialized using
+    /// [SonataConfig::init()].
+    #[allow(clippy::expect_used)]
+    pub fn get_or_panic() -> &'static Self {
+        RELOADER.get().expect("config has not been initialized yet")
+    }
+
+    /// Gets the currently active [ReloadableConfig].
+    pub fn current(&self) -> Arc<ReloadableConfig> {
+        self.current.current()
+    }
+
+    /// Gets a [ReloadableConfigHandle], which follows the changes applied by
+    /// [Self::reload].
+    pub fn handle(&self) -> ReloadableConfigHandle {
+        self.current.clone()
+    }
+
+    /// Parses the configuration file contents given in `input` and hot-applies
+    /// the values covered by [ReloadableConfig]. Changes to all other values,
+    /// such as database connection settings, require a restart: They are
+    /// logged and ignored.
+    ///
+    /// ## Returns
+    ///
+    /// A human-readable description of each change which has been applied.
+    ///
+    /// ## Errors
+    ///
+    /// Errors, if `input` is not a valid configuration file. In this case, the
+    /// active configuration is left untouched.
+    pub fn reload(&self, input: &str) -> StdResult<Vec<String>> {
+        let new_config = SonataConfig::parse(input)?;
+        // The rate limit is the only reloadable part of [api]
+        let mut new_api = new_config.api.clone();
+        new_api.rate_limit = self.startup.api.rate_limit.clone();
+        let ignored = [
+            ("api", self.startup.api != new_api),
+            ("gateway", self.startup.gateway != new_config.gateway),
+            ("general.database", self.startup.general.database != new_config.general.database),
+            (
+                "general.server_domain",
+                self.startup.general.server_domain != new_config.general.server_domain,
+            ),
+            (
+                "general.additional_server_domains",
+                self.startup.general.additional_server_domains
+                    != new_config.general.additional_server_domains,
+            ),
+            (
+                "security.breached_passwords_file",
+                self.startup.security.breached_passwords_file
+                    != new_config.security.breached_passwords_file,
+            ),
+        ];
+        for (section, _) in ignored.iter().filter(|(_, changed)| *changed) {
+            warn!("Changes to [{section}] require a restart of sonata and have been ignored");
+        }
+
+        let old = self.current();
+        let new = ReloadableConfig::from(&new_config);
+        let mut changes = Vec::new();
+        if old.log_level != new.log_level {
+            changes.push(format!("log_level: {:?} -> {:?}", old.log_level, new.log_level));
+        }
+        if old.security != new.security {
+            changes.push(format!("security: {:?} -> {:?}", old.security, new.security));
+        }
+        if old.rate_limit != new.rate_limit {
+            changes.push(format!("api.rate_limit: {:?} -> {:?}", old.rate_limit, new.rate_limit));
+        }
+        self.current.store(new);
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn sonata_toml() -> String {
+        std::fs::read_to_string(format!("{}/sonata.toml", std::env!("CARGO_MANIFEST_DIR"))).unwrap()
+    }
+
+    #[test]
+    fn test_reload_updates_reloadable_fields_only() {
+        let startup = SonataConfig::parse(&sonata_toml()).unwrap();
+        let reloader = ConfigReloader::new(startup.clone());
+        assert_eq!(reloader.current().security.max_keys_per_actor, 32);
+
+        let mut changed: toml::Table = toml::from_str(&sonata_toml()).unwrap();
+        let security = changed.get_mut("security").and_then(toml::Value::as_table_mut).unwrap();
+        security.insert("max_keys_per_actor".to_owned(), toml::Value::Integer(5));
+        let general = changed.get_mut("general").and_then(toml::Value::as_table_mut).unwrap();
+        general.insert("log_level".to_owned(), toml::Value::String("debug".to_owned()));
+        let database = general.get_mut("database").and_then(toml::Value::as_table_mut).unwrap();
+        database.insert("port".to_owned(), toml::Value::Integer(6543));
+
+        let changes = reloader.reload(&changed.to_string()).unwrap();
+        assert_eq!(changes.len(), 2);
+        let current = reloader.current();
+        assert_eq!(current.security.max_keys_per_actor, 5);
+        assert_eq!(current.log_level, Some(LevelFilter::Debug));
+        // Connection-level fields are untouched
+        assert_eq!(reloader.startup.general.database.port, startup.general.database.port);
+    }
+
+    #[test]
+    fn test_reload_invalid_config_keeps_current() {
+        let reloader = ConfigReloader::new(SonataConfig::parse(&sonata_toml()).unwrap());
+        let before = reloader.current();
+
+        assert!(reloader.reload("this is not valid toml").is_err());
+        assert_eq!(reloader.current(), before);
+    }
+
+    #[test]
+    fn test_reload_without_changes() {
+        let reloader = ConfigReloader::new(SonataConfig::parse(&sonata_toml()).unwrap());
+        assert!(reloader.reload(&sonata_toml()).unwrap().is_empty());
+    }
+}
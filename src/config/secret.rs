@@ -0,0 +1,85 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::sync::{Arc, PoisonError, RwLock};
+
+use serde::Deserialize;
+use zeroize::{Zeroize, Zeroizing};
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(from = "String")]
+/// A secret configuration value, such as a password.
+///
+/// The global [SonataConfig](crate::config::SonataConfig) lives for the entire
+/// runtime of sonata and is never dropped. A [Secret] can therefore be
+/// zeroized through a shared reference using [Secret::zeroize]. Clones share
+/// the same buffer, so zeroizing one clone zeroizes all of them. The value is
+/// redacted from `Debug` output.
+pub struct Secret(Arc<RwLock<Zeroizing<String>>>);
+
+impl Secret {
+    /// Get a copy of the secret value, which is zeroized once dropped.
+    pub fn expose(&self) -> Zeroizing<String> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Overwrite the secret value with zeroes and clear it.
+    pub fn zeroize(&self) {
+        self.0.write().unwrap_or_else(PoisonError::into_inner).zeroize();
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Self(Arc::new(RwLock::new(Zeroizing::new(value))))
+    }
+}
+
+impl PartialEq for Secret {
+    fn eq(&self, other: &Self) -> bool {
+        *self.expose() == *other.expose()
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret(\"[redacted]\")")
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_zeroize_clears_all_clones() {
+        let secret = Secret::from(String::from("hunter22"));
+        let clone = secret.clone();
+        assert_eq!(*secret.expose(), "hunter22");
+
+        clone.zeroize();
+        assert_eq!(*secret.expose(), "");
+        assert_eq!(*clone.expose(), "");
+    }
+
+    #[test]
+    fn test_secret_debug_is_redacted() {
+        let secret = Secret::from(String::from("hunter22"));
+        assert!(!format!("{secret:?}").contains("hunter22"));
+    }
+
+    #[test]
+    fn test_secret_deserialize_and_eq() {
+        let secret: Secret = toml::from_str::<toml::Table>("password = \"hunter22\"")
+            .unwrap()
+            .get("password")
+            .unwrap()
+            .clone()
+            .try_into()
+            .unwrap();
+        assert_eq!(secret, Secret::from(String::from("hunter22")));
+        assert_ne!(secret, Secret::from(String::from("hunter23")));
+    }
+}
@@ -0,0 +1,104 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/// ECDSA P-256 private keys. sonata itself never signs with ECDSA keys, so
+/// these only exist for tests acting as a client.
+#[cfg(test)]
+pub(crate) mod private_key;
+/// ECDSA P-256 public keys, which actors can register and log in with
+pub(crate) mod public_key;
+/// ECDSA signatures using SHA-256
+pub(crate) mod signature;
+
+#[cfg(test)]
+use argon2::password_hash::rand_core;
+#[cfg(test)]
+use p256::ecdsa::SigningKey;
+#[cfg(test)]
+pub(crate) use private_key::*;
+pub(crate) use public_key::*;
+pub(crate) use signature::*;
+
+/// Generate an ECDSA P-256 keypair using an [rand_core::OsRng].
+#[cfg(test)]
+pub(crate) fn generate_keypair() -> (DigitalPrivateKey, DigitalPublicKey) {
+    let signing_key = SigningKey::random(&mut rand_core::OsRng);
+    let verifying_key = *signing_key.verifying_key();
+    let dpuk = DigitalPublicKey { key: verifying_key };
+    let dppk = DigitalPrivateKey { key: signing_key, pubkey: dpuk.clone() };
+    (dppk, dpuk)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used, clippy::indexing_slicing)]
+mod tests {
+    use polyproto::{
+        certs::PublicKeyInfo,
+        key::{PrivateKey, PublicKey},
+        signature::Signature,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_real_ecdsa_key_generation_and_pem_encoding() {
+        let (_private_key, public_key) = generate_keypair();
+
+        let pem_data = public_key
+            .public_key_info()
+            .to_pem(polyproto::der::pem::LineEnding::LF)
+            .expect("Failed to encode public key to PEM");
+        assert!(pem_data.starts_with("-----BEGIN PUBLIC KEY-----"));
+        assert!(pem_data.ends_with("-----END PUBLIC KEY-----\n"));
+
+        // Test round-trip: PEM -> PublicKeyInfo -> DigitalPublicKey
+        let reconstructed = PublicKeyInfo::from_pem(&pem_data)
+            .and_then(DigitalPublicKey::try_from_public_key_info)
+            .expect("Failed to reconstruct key from PEM");
+        assert_eq!(public_key, reconstructed, "Round-trip key conversion failed");
+    }
+
+    #[test]
+    fn test_ecdsa_sign_and_verify() {
+        let (private_key, public_key) = generate_keypair();
+        let data = b"polyproto over ECDSA P-256";
+
+        let signature = private_key.sign(data);
+        assert!(public_key.verify_signature(&signature, data).is_ok());
+        assert!(public_key.verify_signature(&signature, b"tampered data").is_err());
+
+        let (_, other_public_key) = generate_keypair();
+        assert!(other_public_key.verify_signature(&signature, data).is_err());
+    }
+
+    #[test]
+    fn test_ecdsa_signature_bytes_round_trip() {
+        let (private_key, _public_key) = generate_keypair();
+        let data = b"polyproto over ECDSA P-256";
+        let signature = private_key.sign(data);
+
+        // DER encoding, as used in certificates
+        let from_der = DigitalSignature::from_bytes(&signature.as_bytes());
+        assert_eq!(from_der, signature);
+        // Fixed-size encoding
+        let from_fixed =
+            DigitalSignature::from_bytes(&signature.as_signature().unwrap().to_bytes());
+        assert_eq!(from_fixed, signature);
+    }
+
+    #[test]
+    fn test_ecdsa_malformed_signature_is_rejected() {
+        let (private_key, public_key) = generate_keypair();
+        let data = b"polyproto over ECDSA P-256";
+        let signature = private_key.sign(data);
+
+        for malformed in [&[1, 2, 3][..], &[], &[0; 64], &signature.as_bytes()[1..]] {
+            let malformed = DigitalSignature::from_bytes(malformed);
+            assert!(malformed.as_signature().is_none());
+            assert!(malformed.as_bytes().is_empty());
+            assert_ne!(malformed, signature);
+            assert!(public_key.verify_signature(&malformed, data).is_err());
+        }
+    }
+}
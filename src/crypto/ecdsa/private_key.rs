@@ -0,0 +1,32 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use p256::ecdsa::{SigningKey, signature::Signer};
+use polyproto::key::PrivateKey;
+
+use crate::crypto::ecdsa::{DigitalPublicKey, DigitalSignature};
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+/// ECDSA P-256 private key, also containing information about the
+/// corresponding public key.
+pub(crate) struct DigitalPrivateKey {
+    /// The private key
+    pub(crate) key: SigningKey,
+    /// The corresponding public key
+    pub(crate) pubkey: DigitalPublicKey,
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl PrivateKey<DigitalSignature> for DigitalPrivateKey {
+    type PublicKey = DigitalPublicKey;
+
+    fn pubkey(&self) -> &Self::PublicKey {
+        &self.pubkey
+    }
+
+    fn sign(&self, data: &[u8]) -> DigitalSignature {
+        let signature = self.key.sign(data);
+        DigitalSignature { signature: Some(signature) }
+    }
+}
@@ -0,0 +1,86 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::str::FromStr;
+
+use p256::ecdsa::{VerifyingKey, signature::Verifier};
+use polyproto::{
+    der::asn1::{Any, BitString},
+    key::PublicKey,
+    spki::{AlgorithmIdentifierOwned, ObjectIdentifier},
+};
+
+use crate::crypto::ecdsa::DigitalSignature;
+
+/// The Object Identifier (OID) for elliptic curve public keys, as defined in
+/// RFC 5480
+const OID_EC_PUBLIC_KEY: &str = "1.2.840.10045.2.1";
+/// The Object Identifier (OID) for the NIST P-256 curve, also called
+/// `secp256r1` or `prime256v1`, as defined in RFC 5480
+const OID_SECP256R1: &str = "1.2.840.10045.3.1.7";
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+/// ECDSA P-256 public key
+pub(crate) struct DigitalPublicKey {
+    /// The public key
+    pub(crate) key: VerifyingKey,
+}
+
+impl DigitalPublicKey {
+    /// The `id-ecPublicKey` algorithm with the P-256 curve as parameter, which
+    /// identifies these keys in a
+    /// [PublicKeyInfo](polyproto::certs::PublicKeyInfo).
+    #[allow(clippy::unwrap_used)]
+    pub(crate) fn key_algorithm() -> AlgorithmIdentifierOwned {
+        // Unwraps are okay: The OIDs are valid constants.
+        AlgorithmIdentifierOwned {
+            oid: ObjectIdentifier::from_str(OID_EC_PUBLIC_KEY).unwrap(),
+            parameters: Some(
+                Any::encode_from(&ObjectIdentifier::from_str(OID_SECP256R1).unwrap()).unwrap(),
+            ),
+        }
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl PublicKey<DigitalSignature> for DigitalPublicKey {
+    fn verify_signature(
+        &self,
+        signature: &DigitalSignature,
+        data: &[u8],
+    ) -> Result<(), polyproto::errors::PublicKeyError> {
+        match signature.signature.map(|signature| self.key.verify(data, &signature)) {
+            Some(Ok(_)) => Ok(()),
+            _ => Err(polyproto::errors::composite::PublicKeyError::BadSignature),
+        }
+    }
+
+    /// The key is encoded as an uncompressed SEC1 point, using the
+    /// [DigitalPublicKey::key_algorithm].
+    fn public_key_info(&self) -> polyproto::certs::PublicKeyInfo {
+        #[allow(clippy::unwrap_used)]
+        polyproto::certs::PublicKeyInfo {
+            algorithm: Self::key_algorithm(),
+            // Unwrap is okay: An uncompressed P-256 point is always 65 bytes long.
+            public_key_bitstring: BitString::from_bytes(
+                self.key.to_encoded_point(false).as_bytes(),
+            )
+            .unwrap(),
+        }
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn try_from_public_key_info(
+        public_key_info: polyproto::certs::PublicKeyInfo,
+    ) -> Result<Self, polyproto::errors::CertificateConversionError> {
+        Ok(Self {
+            key: VerifyingKey::from_sec1_bytes(public_key_info.public_key_bitstring.raw_bytes())
+                .map_err(|e| {
+                    polyproto::errors::CertificateConversionError::InvalidInput(
+                        polyproto::errors::InvalidInput::Malformed(e.to_string()),
+                    )
+                })?,
+        })
+    }
+}
@@ -0,0 +1,73 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::str::FromStr;
+
+use p256::ecdsa::Signature;
+use polyproto::{
+    der::asn1::BitString,
+    signature::Signature as SignatureTrait,
+    spki::{AlgorithmIdentifierOwned, ObjectIdentifier, SignatureBitStringEncoding},
+};
+
+/// The Object Identifier (OID) for the ECDSA signature algorithm using SHA-256,
+/// as defined in RFC 5758
+const OID_ECDSA_WITH_SHA256: &str = "1.2.840.10045.4.3.2";
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// ECDSA P-256 signature, using SHA-256 as the digest algorithm.
+pub(crate) struct DigitalSignature {
+    /// The signature, or `None`, if the bytes this signature was created from
+    /// are not a valid ECDSA P-256 signature.
+    pub(super) signature: Option<Signature>,
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl SignatureBitStringEncoding for DigitalSignature {
+    fn to_bitstring(&self) -> polyproto::der::Result<BitString> {
+        BitString::from_bytes(&self.as_bytes())
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl std::fmt::Display for DigitalSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.as_hex())
+    }
+}
+
+impl SignatureTrait for DigitalSignature {
+    type Signature = Option<Signature>;
+
+    fn as_signature(&self) -> &Self::Signature {
+        &self.signature
+    }
+
+    fn algorithm_identifier() -> AlgorithmIdentifierOwned {
+        #[allow(clippy::unwrap_used)]
+        AlgorithmIdentifierOwned {
+            oid: ObjectIdentifier::from_str(OID_ECDSA_WITH_SHA256).unwrap(),
+            parameters: None,
+        }
+    }
+
+    /// Accepts both the ASN.1 DER encoding used in X.509 certificates and the
+    /// fixed-size 64 byte `r || s` encoding. As this conversion cannot fail,
+    /// malformed input results in a signature without a value, which is
+    /// rejected by
+    /// [DigitalPublicKey::verify_signature](crate::crypto::ecdsa::DigitalPublicKey)
+    /// and encodes to zero bytes.
+    fn from_bytes(signature: &[u8]) -> Self {
+        Self {
+            signature: Signature::from_der(signature)
+                .or_else(|_| Signature::from_slice(signature))
+                .ok(),
+        }
+    }
+
+    /// The ASN.1 DER encoding of the signature, as used in X.509 certificates.
+    fn as_bytes(&self) -> Vec<u8> {
+        self.signature.map(|signature| signature.to_der().as_bytes().to_vec()).unwrap_or_default()
+    }
+}
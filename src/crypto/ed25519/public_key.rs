@@ -10,6 +10,13 @@ pub(crate) struct DigitalPublicKey {
     pub(crate) key: VerifyingKey,
 }
 
+impl DigitalPublicKey {
+    /// The 32 bytes of the compressed Edwards point of this key.
+    pub(crate) fn to_bytes(&self) -> [u8; 32] {
+        self.key.to_bytes()
+    }
+}
+
 #[cfg_attr(coverage_nightly, coverage(off))]
 impl PublicKey<DigitalSignature> for DigitalPublicKey {
     fn verify_signature(
@@ -25,7 +32,7 @@ impl PublicKey<DigitalSignature> for DigitalPublicKey {
 
     fn public_key_info(&self) -> polyproto::certs::PublicKeyInfo {
         // Get the key as bytes
-        let key_bytes = self.key.to_bytes();
+        let key_bytes = self.to_bytes();
 
         // Create a 32-byte array, copying bytes into it
         let mut key_array = [0u8; 32];
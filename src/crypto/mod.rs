@@ -1,2 +1,130 @@
+use polyproto::{
+    certs::PublicKeyInfo, key::PublicKey, signature::Signature, spki::ObjectIdentifier,
+};
+
+/// polyproto over ECDSA using the NIST P-256 curve
+pub(crate) mod ecdsa;
 /// polyproto over ED25519
 pub(crate) mod ed25519;
+/// The key this home server signs ID-Certs with
+pub(crate) mod signing_key;
+
+/// The signature algorithms implemented in this module, as entries for
+/// [AlgorithmIdentifier::ensure_all](crate::database::AlgorithmIdentifier::ensure_all).
+/// These are registered on startup, so that public keys using them are
+/// accepted.
+pub(crate) fn known_algorithm_identifiers()
+-> [(ObjectIdentifier, Option<&'static str>, &'static [u8]); 2] {
+    [
+        (
+            ed25519::DigitalSignature::algorithm_identifier().oid,
+            Some("Edwards-curve Digital Signature Algorithm (EdDSA) Ed25519"),
+            &[],
+        ),
+        (
+            ecdsa::DigitalSignature::algorithm_identifier().oid,
+            Some("Elliptic Curve Digital Signature Algorithm (ECDSA) P-256 with SHA-256"),
+            &[],
+        ),
+    ]
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A public key of one of the signature algorithms implemented in this module,
+/// which actors can register and log in with.
+pub(crate) enum ActorPublicKey {
+    /// An `ed25519` public key
+    Ed25519(ed25519::DigitalPublicKey),
+    /// An ECDSA P-256 public key
+    Ecdsa(ecdsa::DigitalPublicKey),
+}
+
+impl ActorPublicKey {
+    /// Build the key from its `public_key_info`, such as one parsed from PEM.
+    /// Returns `None`, if the key is malformed or of another algorithm.
+    pub(crate) fn from_public_key_info(public_key_info: PublicKeyInfo) -> Option<Self> {
+        if public_key_info.algorithm == ed25519::DigitalSignature::algorithm_identifier() {
+            // Shorter keys would be zero-padded by the conversion
+            if public_key_info.public_key_bitstring.raw_bytes().len()
+                != ed25519_dalek::PUBLIC_KEY_LENGTH
+            {
+                return None;
+            }
+            ed25519::DigitalPublicKey::try_from_public_key_info(public_key_info)
+                .ok()
+                .map(Self::Ed25519)
+        } else if public_key_info.algorithm == ecdsa::DigitalPublicKey::key_algorithm() {
+            ecdsa::DigitalPublicKey::try_from_public_key_info(public_key_info).ok().map(Self::Ecdsa)
+        } else {
+            None
+        }
+    }
+
+    /// Check that `signature` is a valid signature of `data` made with the
+    /// corresponding private key. `ed25519` signatures have to be exactly
+    /// [ed25519_dalek::SIGNATURE_LENGTH] bytes long, while ECDSA signatures may
+    /// be DER-encoded or in the fixed-size `r || s` encoding.
+    pub(crate) fn verify(&self, signature: &[u8], data: &[u8]) -> bool {
+        match self {
+            Self::Ed25519(key) => {
+                signature.len() == ed25519_dalek::SIGNATURE_LENGTH
+                    && key
+                        .verify_signature(&ed25519::DigitalSignature::from_bytes(signature), data)
+                        .is_ok()
+            }
+            Self::Ecdsa(key) => {
+                key.verify_signature(&ecdsa::DigitalSignature::from_bytes(signature), data).is_ok()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::indexing_slicing)]
+mod tests {
+    use polyproto::key::PrivateKey;
+
+    use super::*;
+
+    #[test]
+    fn test_actor_public_key_from_public_key_info() {
+        let (_, ed25519_key) = ed25519::generate_keypair();
+        let (_, ecdsa_key) = ecdsa::generate_keypair();
+        assert_eq!(
+            ActorPublicKey::from_public_key_info(ed25519_key.public_key_info()),
+            Some(ActorPublicKey::Ed25519(ed25519_key.clone()))
+        );
+        assert_eq!(
+            ActorPublicKey::from_public_key_info(ecdsa_key.public_key_info()),
+            Some(ActorPublicKey::Ecdsa(ecdsa_key.clone()))
+        );
+
+        // An ECDSA key labelled as an ed25519 key, and vice versa
+        let mut mislabelled = ecdsa_key.public_key_info();
+        mislabelled.algorithm = ed25519::DigitalSignature::algorithm_identifier();
+        assert_eq!(ActorPublicKey::from_public_key_info(mislabelled), None);
+        let mut mislabelled = ed25519_key.public_key_info();
+        mislabelled.algorithm = ecdsa::DigitalPublicKey::key_algorithm();
+        assert_eq!(ActorPublicKey::from_public_key_info(mislabelled), None);
+    }
+
+    #[test]
+    fn test_actor_public_key_verify() {
+        let data = b"polyproto";
+        let (ed25519_private_key, ed25519_key) = ed25519::generate_keypair();
+        let ed25519_key = ActorPublicKey::Ed25519(ed25519_key);
+        let signature = ed25519_private_key.sign(data).as_bytes();
+        assert!(ed25519_key.verify(&signature, data));
+        assert!(!ed25519_key.verify(&signature, b"tampered data"));
+        // Truncated signatures are not zero-padded
+        assert!(!ed25519_key.verify(&signature[..63], data));
+
+        let (ecdsa_private_key, ecdsa_key) = ecdsa::generate_keypair();
+        let ecdsa_key = ActorPublicKey::Ecdsa(ecdsa_key);
+        let signature = ecdsa_private_key.sign(data);
+        assert!(ecdsa_key.verify(&signature.as_bytes(), data));
+        assert!(ecdsa_key.verify(&signature.as_signature().unwrap().to_bytes(), data));
+        assert!(!ecdsa_key.verify(&signature.as_bytes(), b"tampered data"));
+        assert!(!ed25519_key.verify(&signature.as_bytes(), data));
+    }
+}
@@ -0,0 +1,122 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{fs, io::Write, path::Path};
+
+use ed25519_dalek::SigningKey;
+use log::info;
+
+use crate::{
+    StdResult,
+    config::SecurityConfig,
+    crypto::ed25519::{DigitalPrivateKey, DigitalPublicKey, DigitalSignature, generate_keypair},
+    database::{Database, PublicKeyInfo},
+    errors::Error,
+};
+
+#[derive(Debug, Clone)]
+/// The private key this home server signs ID-Certs with, together with the ID
+/// of its public key in the `public_keys` table.
+pub(crate) struct HomeServerSigningKey {
+    /// The private key.
+    pub(crate) key: DigitalPrivateKey,
+    /// ID of the corresponding public key in the `public_keys` table.
+    pub(crate) public_key_id: i64,
+}
+
+impl HomeServerSigningKey {
+    /// Read the private key from the file at `path`, which holds the
+    /// hex-encoded 32 byte seed of an `ed25519` key. If there is no such file,
+    /// a new key is generated and written to it, readable only by the owner.
+    ///
+    /// ## Errors
+    ///
+    /// If the file cannot be read or written, or does not hold a valid key.
+    pub(crate) fn load_or_generate(path: &Path) -> StdResult<DigitalPrivateKey> {
+        if path.exists() {
+            let seed: [u8; 32] = hex::decode(fs::read_to_string(path)?.trim())?
+                .try_into()
+                .map_err(|_| format!("{path:?} does not hold a 32 byte ed25519 seed"))?;
+            let key = SigningKey::from_bytes(&seed);
+            let pubkey = DigitalPublicKey { key: key.verifying_key() };
+            return Ok(DigitalPrivateKey { key, pubkey });
+        }
+        let (private_key, _) = generate_keypair();
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(path)?.write_all(hex::encode(private_key.key.to_bytes()).as_bytes())?;
+        info!("Generated a new signing key at {path:?}");
+        Ok(private_key)
+    }
+
+    /// Store the public key of `key` in the `public_keys` table as a key not
+    /// belonging to any actor, unless it is stored already.
+    ///
+    /// ## Errors
+    ///
+    /// Any error of [PublicKeyInfo::insert], or if the key is stored as the
+    /// key of an actor.
+    pub(crate) async fn register(
+        db: &Database,
+        key: DigitalPrivateKey,
+        security_config: &SecurityConfig,
+    ) -> Result<Self, Error> {
+        let encoded = PublicKeyInfo::encode_public_key(&key.pubkey)?;
+        let public_key = match PublicKeyInfo::get_by(db, None, Some(encoded), None, None)
+            .await?
+            .into_iter()
+            .next()
+        {
+            Some(public_key) if public_key.uaid.is_none() => public_key,
+            Some(_) => {
+                return Err(Error::new_internal_error(Some(
+                    "The signing key of this home server is registered as the key of an actor",
+                )));
+            }
+            None => {
+                PublicKeyInfo::insert::<DigitalSignature, _>(db, &key.pubkey, None, security_config)
+                    .await?
+            }
+        };
+        Ok(Self { key, public_key_id: public_key.id() })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use sqlx::{Pool, Postgres};
+
+    use super::*;
+
+    #[test]
+    fn test_load_or_generate() {
+        let path = std::env::temp_dir().join(format!("sonata_signing_key_{}", std::process::id()));
+        _ = fs::remove_file(&path);
+
+        let generated = HomeServerSigningKey::load_or_generate(&path).unwrap();
+        let loaded = HomeServerSigningKey::load_or_generate(&path).unwrap();
+        assert_eq!(generated, loaded);
+
+        fs::write(&path, "not a key").unwrap();
+        assert!(HomeServerSigningKey::load_or_generate(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/full_state.sql"))]
+    async fn test_register_is_idempotent(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let (key, _) = generate_keypair();
+
+        let registered =
+            HomeServerSigningKey::register(&db, key.clone(), &SecurityConfig::default())
+                .await
+                .unwrap();
+        let again =
+            HomeServerSigningKey::register(&db, key, &SecurityConfig::default()).await.unwrap();
+        assert_eq!(registered.public_key_id, again.public_key_id);
+    }
+}
@@ -2,25 +2,58 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use sqlx::{query, query_as, types::Uuid};
+use polyproto::{key::PublicKey, signature::Signature};
+use sqlx::{PgConnection, query, query_as, types::Uuid};
 
 use crate::{
-    database::Database,
+    config::SecurityConfig,
+    database::{Database, Invite, PublicKeyInfo},
     errors::{Context, Errcode, Error},
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, sqlx::Type, serde::Serialize)]
+#[sqlx(type_name = "actor_type", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+/// Whether an [Actor] is registered on this home server, or on another one.
 pub enum ActorType {
+    /// A [LocalActor].
     Local,
+    /// An actor from another home server, discovered through federation.
     Foreign,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+/// Any actor in the `actors` table, be it local or foreign.
 pub struct Actor {
+    /// The unique actor identifier.
     pub unique_actor_identifier: Uuid,
     r#type: ActorType,
 }
 
+impl Actor {
+    /// Get the [Actor] with the unique actor identifier `uaid`, if there is
+    /// one.
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if something is wrong with the Database or Database
+    /// connection.
+    pub async fn by_uaid(db: &Database, uaid: &Uuid) -> Result<Option<Actor>, Error> {
+        Ok(query!(
+            r#"SELECT uaid, type AS "actor_type: ActorType" FROM actors WHERE uaid = $1"#,
+            uaid
+        )
+        .fetch_optional(&db.pool)
+        .await?
+        .map(|record| Self { unique_actor_identifier: record.uaid, r#type: record.actor_type }))
+    }
+
+    /// Whether this actor is local or foreign.
+    pub fn actor_type(&self) -> ActorType {
+        self.r#type
+    }
+}
+
 impl From<LocalActor> for Actor {
     #[cfg_attr(coverage_nightly, coverage(off))]
     fn from(value: LocalActor) -> Self {
@@ -28,7 +61,7 @@ impl From<LocalActor> for Actor {
     }
 }
 
-#[derive(Debug, sqlx::Decode, sqlx::Encode, sqlx::FromRow)]
+#[derive(Debug, Clone, sqlx::Decode, sqlx::Encode, sqlx::FromRow)]
 /// Actors from this home server. Does not include the `password_hash` column.
 pub struct LocalActor {
     /// The unique actor identifer. Does not change, even if the `local_name`
@@ -44,22 +77,79 @@ pub struct LocalActor {
     pub joined_at_timestamp: chrono::NaiveDateTime,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+/// The number of rows referencing a [LocalActor], which would be deleted along
+/// with it. See [LocalActor::deletion_impact].
+pub struct DeletionImpact {
+    /// Tokens of the actor in the `user_tokens` table.
+    pub tokens: i64,
+    /// Public keys of the actor in the `public_keys` table.
+    pub public_keys: i64,
+    /// ID-Certs issued to the actor in the `idcert` table.
+    pub certs: i64,
+    /// Invite links owned by the actor in the `invite_links` table.
+    pub owned_invites: i64,
+}
+
+#[serde_with::serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+/// The account of a [LocalActor] including its mutable profile fields, as it
+/// is shown to the actor itself.
+pub struct AccountInfo {
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    /// The unique actor identifier of the actor.
+    pub uaid: Uuid,
+    /// The "local name" part of the actor.
+    pub local_name: String,
+    /// When the actor has registered.
+    pub joined: chrono::NaiveDateTime,
+    /// The name the actor prefers to be displayed with.
+    pub display_name: Option<String>,
+    /// BCP 47 language tag of the language the actor prefers.
+    pub locale: Option<String>,
+    /// E-Mail address the actor can be contacted at to recover the account.
+    pub recovery_email: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Changes to the profile fields of a [LocalActor]. For every field, `None`
+/// leaves the field unchanged and `Some(None)` clears it. See
+/// [AccountInfo] for the meaning of the fields.
+pub struct ProfileUpdate {
+    /// New value of [AccountInfo::display_name].
+    pub display_name: Option<Option<String>>,
+    /// New value of [AccountInfo::locale].
+    pub locale: Option<Option<String>>,
+    /// New value of [AccountInfo::recovery_email].
+    pub recovery_email: Option<Option<String>>,
+}
+
 impl LocalActor {
     /// Tries to find an actor from the [Database] where `local_name` is equal
-    /// to `name`, returning `None`, if such an actor does not exist.
+    /// to `name`, returning `None`, if such an actor does not exist. If
+    /// `case_insensitive` is set, names differing from `name` only in case
+    /// match as well, though an exact match is preferred.
     ///
     /// ## Errors
     ///
     /// Will error on Database connection issues and on other errors with the
     /// database, all of which are not in scope for this function to handle.
-    pub async fn by_local_name(db: &Database, name: &str) -> Result<Option<LocalActor>, Error> {
+    pub async fn by_local_name(
+        db: &Database,
+        name: &str,
+        case_insensitive: bool,
+    ) -> Result<Option<LocalActor>, Error> {
         Ok(query!(
             "
             SELECT uaid, local_name, deactivated, joined
             FROM local_actors
-            WHERE local_name = $1
+            WHERE local_name = $1 OR ($2 AND local_name_normalized = lower($1))
+            ORDER BY local_name = $1 DESC
             LIMIT 1",
-            name
+            name,
+            case_insensitive
         )
         .fetch_optional(&db.pool)
         .await?
@@ -71,32 +161,452 @@ impl LocalActor {
         }))
     }
 
+    /// The `uaid` of the actor called `local_name` on the given connection, if
+    /// there is one. See [LocalActor::by_local_name] for `case_insensitive`.
+    async fn uaid_by_local_name_on(
+        connection: &mut PgConnection,
+        local_name: &str,
+        case_insensitive: bool,
+    ) -> Result<Option<Uuid>, Error> {
+        Ok(query!(
+            "SELECT uaid FROM local_actors
+            WHERE local_name = $1 OR ($2 AND local_name_normalized = lower($1))
+            LIMIT 1",
+            local_name,
+            case_insensitive
+        )
+        .fetch_optional(&mut *connection)
+        .await?
+        .map(|record| record.uaid))
+    }
+
+    /// If `case_insensitive` is set, serialize the transaction on `connection`
+    /// with all other transactions claiming a name differing from `local_name`
+    /// only in case, until it ends. The unique constraint on `local_name` can
+    /// not catch such names, so without this lock, concurrent transactions
+    /// could all pass the check of [LocalActor::uaid_by_local_name_on].
+    async fn lock_local_name_on(
+        connection: &mut PgConnection,
+        local_name: &str,
+        case_insensitive: bool,
+    ) -> Result<(), Error> {
+        if case_insensitive {
+            query!("SELECT pg_advisory_xact_lock(hashtext(lower($1)))", local_name)
+                .execute(&mut *connection)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Tries to find an actor from the [Database] where `uaid` is equal to
+    /// `uaid`, returning `None`, if such an actor does not exist.
+    ///
+    /// ## Errors
+    ///
+    /// Will error on Database connection issues and on other errors with the
+    /// database, all of which are not in scope for this function to handle.
+    pub async fn by_uaid(db: &Database, uaid: &Uuid) -> Result<Option<LocalActor>, Error> {
+        Ok(query_as!(
+            LocalActor,
+            "
+            SELECT uaid AS unique_actor_identifier, local_name, deactivated AS is_deactivated, joined AS joined_at_timestamp
+            FROM local_actors
+            WHERE uaid = $1",
+            uaid
+        )
+        .fetch_optional(&db.pool)
+        .await?)
+    }
+
     /// Returns the `password_hash` of an actor from the [Database] where
     /// `local_name` is equal to `name`, returning `None`, if such an actor
-    /// does not exist.
+    /// does not exist or has no password. The actor is found like in
+    /// [LocalActor::by_local_name].
     ///
     /// ## Errors
     ///
     /// Will error on Database connection issues and on other errors with the
     /// database, all of which are not in scope for this function to handle.
-    pub async fn get_password_hash(db: &Database, name: &str) -> Result<Option<String>, Error> {
+    pub async fn get_password_hash(
+        db: &Database,
+        name: &str,
+        case_insensitive: bool,
+    ) -> Result<Option<String>, Error> {
         Ok(query!(
             "
             SELECT password_hash
             FROM local_actors
-            WHERE local_name = $1
+            WHERE local_name = $1 OR ($2 AND local_name_normalized = lower($1))
+            ORDER BY local_name = $1 DESC
             LIMIT 1",
-            name
+            name,
+            case_insensitive
+        )
+        .fetch_optional(&db.pool)
+        .await?
+        .and_then(|record| record.password_hash))
+    }
+
+    /// Deactivate (`deactivated = true`) or reactivate (`deactivated = false`)
+    /// the [LocalActor] identified by `uaid`. Deactivated actors can neither
+    /// log in nor use existing tokens.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an [Errcode::NotFound]-type error, if no [LocalActor] with
+    /// the given `uaid` exists. Other than that, this method will error, if
+    /// something is wrong with the Database or Database connection.
+    pub async fn set_deactivated(
+        db: &Database,
+        uaid: &Uuid,
+        deactivated: bool,
+    ) -> Result<(), Error> {
+        match query!("UPDATE local_actors SET deactivated = $1 WHERE uaid = $2", deactivated, uaid)
+            .execute(&db.pool)
+            .await?
+            .rows_affected()
+        {
+            0 => Err(Error::new(
+                Errcode::NotFound,
+                Some(Context::new(Some("uaid"), Some(&uaid.to_string()), None, None)),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Count the rows referencing the [LocalActor] identified by `uaid`, which
+    /// would be deleted along with it, so that the deletion can be confirmed
+    /// beforehand.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an [Errcode::NotFound]-type error, if no [LocalActor] with
+    /// the given `uaid` exists. Other than that, this method will error, if
+    /// something is wrong with the Database or Database connection.
+    pub async fn deletion_impact(db: &Database, uaid: &Uuid) -> Result<DeletionImpact, Error> {
+        let record = query!(
+            r#"
+            SELECT
+                EXISTS (SELECT 1 FROM local_actors WHERE uaid = $1) AS "exists!",
+                (SELECT COUNT(*) FROM user_tokens WHERE uaid = $1) AS "tokens!",
+                (SELECT COUNT(*) FROM public_keys WHERE uaid = $1) AS "public_keys!",
+                (
+                    SELECT COUNT(*)
+                    FROM idcert
+                    JOIN idcsr ON idcsr.id = idcert.idcsr_id
+                    WHERE idcsr.uaid = $1
+                ) AS "certs!",
+                (SELECT COUNT(*) FROM invite_links WHERE invite_link_owner = $1) AS "owned_invites!"
+            "#,
+            uaid
+        )
+        .fetch_one(&db.pool)
+        .await?;
+        if !record.exists {
+            return Err(Error::new(
+                Errcode::NotFound,
+                Some(Context::new(Some("uaid"), Some(&uaid.to_string()), None, None)),
+            ));
+        }
+        Ok(DeletionImpact {
+            tokens: record.tokens,
+            public_keys: record.public_keys,
+            certs: record.certs,
+            owned_invites: record.owned_invites,
+        })
+    }
+
+    /// Permanently delete the [LocalActor] identified by `uaid`, together
+    /// with its tokens, public keys, certificates, invite links and all other
+    /// rows referencing it. Everything is deleted in a single transaction, so
+    /// that either all or none of the data is gone afterwards.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an [Errcode::NotFound]-type error, if no [LocalActor] with
+    /// the given `uaid` exists. Other than that, this method will error, if
+    /// something is wrong with the Database or Database connection.
+    pub async fn delete(db: &Database, uaid: &Uuid) -> Result<(), Error> {
+        let mut transaction = db.pool.begin().await?;
+        // Rows referencing the actor without ON DELETE CASCADE have to be
+        // deleted first, children before their parents
+        query!("DELETE FROM user_tokens WHERE uaid = $1", uaid).execute(&mut *transaction).await?;
+        query!(
+            "DELETE FROM idcert_cached WHERE idcert_id IN (SELECT id FROM idcsr WHERE uaid = $1)",
+            uaid
+        )
+        .execute(&mut *transaction)
+        .await?;
+        query!("DELETE FROM idcert WHERE idcsr_id IN (SELECT id FROM idcsr WHERE uaid = $1)", uaid)
+            .execute(&mut *transaction)
+            .await?;
+        query!("DELETE FROM idcsr WHERE uaid = $1", uaid).execute(&mut *transaction).await?;
+        query!("DELETE FROM public_keys WHERE uaid = $1", uaid).execute(&mut *transaction).await?;
+        query!(
+            "DELETE FROM invitations WHERE invite_id IN (SELECT id FROM invite_links WHERE \
+             invite_link_owner = $1)",
+            uaid
+        )
+        .execute(&mut *transaction)
+        .await?;
+        // Everything else referencing the actor is removed by ON DELETE CASCADE
+        query!("DELETE FROM local_actors WHERE uaid = $1", uaid).execute(&mut *transaction).await?;
+        let deleted = query!("DELETE FROM actors WHERE uaid = $1 AND type = 'local'", uaid)
+            .execute(&mut *transaction)
+            .await?
+            .rows_affected();
+        if deleted == 0 {
+            // Dropping the transaction rolls it back
+            return Err(Error::new(
+                Errcode::NotFound,
+                Some(Context::new(Some("uaid"), Some(&uaid.to_string()), None, None)),
+            ));
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Replace the password hash `old_hash` of the [LocalActor] identified by
+    /// `uaid` with `new_hash`, which must be a PHC string, on the given
+    /// `connection`. Comparing and replacing the hash happens in a single
+    /// statement, so that of two concurrent password changes, which have both
+    /// verified the same old password, only one succeeds.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an [Errcode::Unauthorized]-type error, if no [LocalActor] with
+    /// the given `uaid` has the password hash `old_hash`, for example because
+    /// the password has been changed in the meantime. Other than that, this
+    /// method will error, if something is wrong with the Database or Database
+    /// connection.
+    pub(crate) async fn replace_password_hash_on(
+        connection: &mut PgConnection,
+        uaid: &Uuid,
+        old_hash: &str,
+        new_hash: &str,
+    ) -> Result<(), Error> {
+        match query!(
+            "UPDATE local_actors SET password_hash = $1 WHERE uaid = $2 AND password_hash = $3",
+            new_hash,
+            uaid,
+            old_hash
+        )
+        .execute(&mut *connection)
+        .await?
+        .rows_affected()
+        {
+            0 => Err(Error::new_invalid_login()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Apply the `update` to the profile fields of the [LocalActor] identified
+    /// by `uaid` and return its [AccountInfo] afterwards. The values are not
+    /// validated here.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an [Errcode::NotFound]-type error, if no [LocalActor] with
+    /// the given `uaid` exists. Other than that, this method will error, if
+    /// something is wrong with the Database or Database connection.
+    pub async fn update_profile(
+        db: &Database,
+        uaid: &Uuid,
+        update: &ProfileUpdate,
+    ) -> Result<AccountInfo, Error> {
+        query_as!(
+            AccountInfo,
+            r#"
+            UPDATE local_actors SET
+                display_name = CASE WHEN $2 THEN $3 ELSE display_name END,
+                locale = CASE WHEN $4 THEN $5 ELSE locale END,
+                recovery_email = CASE WHEN $6 THEN $7 ELSE recovery_email END
+            WHERE uaid = $1
+            RETURNING uaid, local_name, joined, display_name, locale, recovery_email
+            "#,
+            uaid,
+            update.display_name.is_some(),
+            update.display_name.clone().flatten(),
+            update.locale.is_some(),
+            update.locale.clone().flatten(),
+            update.recovery_email.is_some(),
+            update.recovery_email.clone().flatten(),
         )
         .fetch_optional(&db.pool)
         .await?
-        .map(|record| record.password_hash))
+        .ok_or(Error::new(
+            Errcode::NotFound,
+            Some(Context::new(Some("uaid"), Some(&uaid.to_string()), None, None)),
+        ))
+    }
+
+    /// Whether logins to the [LocalActor] identified by `uaid` are currently
+    /// rejected, because of too many consecutive failed logins. See
+    /// [LocalActor::record_failed_login].
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if something is wrong with the Database or Database
+    /// connection.
+    pub async fn is_login_locked(db: &Database, uaid: &Uuid) -> Result<bool, Error> {
+        Ok(query!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM failed_login_attempts WHERE uaid = $1 AND locked_until > now()
+            ) AS "locked!"
+            "#,
+            uaid
+        )
+        .fetch_one(&db.pool)
+        .await?
+        .locked)
+    }
+
+    /// Count a failed login to the [LocalActor] identified by `uaid`. Once
+    /// `threshold` consecutive logins have failed, logins to the actor are
+    /// locked for `cooldown_secs` seconds and counting starts anew. Returns
+    /// whether this failure has locked the account.
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if no [LocalActor] with the given `uaid` exists, or if
+    /// something is wrong with the Database or Database connection.
+    pub async fn record_failed_login(
+        db: &Database,
+        uaid: &Uuid,
+        threshold: u32,
+        cooldown_secs: u64,
+    ) -> Result<bool, Error> {
+        let threshold = i32::try_from(threshold).unwrap_or(i32::MAX);
+        // Keep the lock within the range of PostgreSQL timestamps
+        let cooldown_secs =
+            i64::try_from(cooldown_secs).unwrap_or(i64::MAX).min(i64::from(i32::MAX));
+        Ok(query!(
+            r#"
+            INSERT INTO failed_login_attempts AS f (uaid, consecutive_failures, locked_until)
+            VALUES (
+                $1,
+                CASE WHEN 1 >= $2 THEN 0 ELSE 1 END,
+                CASE WHEN 1 >= $2 THEN now() + $3::bigint * interval '1 second' END
+            )
+            ON CONFLICT (uaid) DO UPDATE SET
+                consecutive_failures = CASE
+                    WHEN f.consecutive_failures + 1 >= $2 THEN 0
+                    ELSE f.consecutive_failures + 1
+                END,
+                locked_until = CASE
+                    WHEN f.consecutive_failures + 1 >= $2 THEN now() + $3::bigint * interval '1 second'
+                    ELSE f.locked_until
+                END
+            RETURNING consecutive_failures = 0 AS "locked!"
+            "#,
+            uaid,
+            threshold,
+            cooldown_secs
+        )
+        .fetch_one(&db.pool)
+        .await?
+        .locked)
+    }
+
+    /// Reset the count of consecutive failed logins of the [LocalActor]
+    /// identified by `uaid`, lifting a lock as well. Called after a successful
+    /// login.
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if something is wrong with the Database or Database
+    /// connection.
+    pub async fn clear_failed_logins(db: &Database, uaid: &Uuid) -> Result<(), Error> {
+        query!("DELETE FROM failed_login_attempts WHERE uaid = $1", uaid).execute(&db.pool).await?;
+        Ok(())
+    }
+
+    /// Change the `local_name` of the [LocalActor] identified by `uaid` to
+    /// `new_name` and return the updated [LocalActor]. The `uaid` and the
+    /// `joined_at_timestamp` stay the same. Renaming an actor to its current
+    /// name is a no-op.
+    ///
+    /// ## Errors
+    ///
+    /// - [Errcode::Duplicate], if another actor already has the `new_name`, or
+    ///   a name differing from it only in case, if `case_insensitive` is set
+    /// - [Errcode::NotFound], if no [LocalActor] with the given `uaid` exists
+    /// - [Errcode::IllegalInput], if `new_name` is empty, whitespace-only or
+    ///   contains control characters
+    /// - If something is wrong with the Database or Database connection
+    pub async fn rename(
+        db: &Database,
+        uaid: &Uuid,
+        new_name: &str,
+        case_insensitive: bool,
+    ) -> Result<LocalActor, Error> {
+        LocalActor::validate_local_name(new_name)?;
+        let mut transaction = db.pool.begin().await?;
+        LocalActor::lock_local_name_on(&mut transaction, new_name, case_insensitive).await?;
+        // Only changing the case of the own name is not a conflict
+        if LocalActor::uaid_by_local_name_on(&mut transaction, new_name, case_insensitive)
+            .await?
+            .is_some_and(|existing| existing != *uaid)
+        {
+            return Err(Error::new(
+                Errcode::Duplicate,
+                Some(Context::new(Some("local_name"), Some(new_name), None, None)),
+            ));
+        }
+        let actor = query_as!(
+            LocalActor,
+            "UPDATE local_actors SET local_name = $1 WHERE uaid = $2 RETURNING uaid AS unique_actor_identifier, local_name, deactivated AS is_deactivated, joined AS joined_at_timestamp",
+            new_name,
+            uaid
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        .ok_or_else(|| {
+            Error::new(
+                Errcode::NotFound,
+                Some(Context::new(
+                    Some("uaid"),
+                    Some(&uaid.to_string()),
+                    None,
+                    None,
+                )),
+            )
+        })?;
+        transaction.commit().await?;
+        Ok(actor)
+    }
+
+    /// Reject `local_name`s which are empty, consist only of whitespace or
+    /// contain control characters. The latter includes null bytes, which
+    /// Postgres refuses to store in `text` columns.
+    #[allow(clippy::result_large_err)]
+    fn validate_local_name(local_name: &str) -> Result<(), Error> {
+        let expected = if local_name.trim().is_empty() {
+            "At least one non-whitespace character"
+        } else if local_name.chars().any(char::is_control) {
+            "No control characters"
+        } else {
+            return Ok(());
+        };
+        Err(Error::new(
+            Errcode::IllegalInput,
+            Some(Context::new(Some("local_name"), Some(local_name), Some(expected), None)),
+        ))
     }
 
     /// Create a new [LocalActor] in the `local_actors` table of the [Database].
     /// Before creating, checks, if a user specified by `local_name` already
     /// exists in the table, returning an [Errcode::Duplicate]-type error, if
-    /// this is the case.
+    /// this is the case. If `case_insensitive` is set, names differing from
+    /// `local_name` only in case count as existing as well.
+    ///
+    /// ## Invariants
+    ///
+    /// A `local_name` must contain at least one non-whitespace character and no
+    /// control characters. Empty and whitespace-only names, as well as names
+    /// containing control characters, are rejected with an
+    /// [Errcode::IllegalInput]-type error.
     ///
     /// ## Errors
     ///
@@ -106,38 +616,351 @@ impl LocalActor {
         db: &Database,
         local_name: &str,
         password_hash: &str,
+        case_insensitive: bool,
+    ) -> Result<LocalActor, Error> {
+        let mut transaction = db.pool.begin().await?;
+        let actor = LocalActor::create_on(
+            &mut transaction,
+            local_name,
+            Some(password_hash),
+            case_insensitive,
+        )
+        .await?;
+        transaction.commit().await?;
+        Ok(actor)
+    }
+
+    /// Create a new [LocalActor] without a password, which logs in with the
+    /// `public_key` instead, and register the key for it. If `invite_code` is
+    /// given, one usage of the invite is consumed. All of this happens in a
+    /// single transaction, so that no actor is left without a way to log in.
+    ///
+    /// ## Errors
+    ///
+    /// Any error of [LocalActor::create], [Database::register_with_invite] or
+    /// [PublicKeyInfo::insert]. Local names are compared as configured by
+    /// [SecurityConfig::case_insensitive_local_names].
+    pub async fn create_with_key<S: Signature, P: PublicKey<S>>(
+        db: &Database,
+        local_name: &str,
+        public_key: &P,
+        invite_code: Option<&str>,
+        security_config: &SecurityConfig,
+    ) -> Result<LocalActor, Error> {
+        let mut transaction = db.pool.begin().await?;
+        let actor = match invite_code {
+            Some(invite_code) => {
+                Invite::register_on(
+                    &mut transaction,
+                    invite_code,
+                    local_name,
+                    None,
+                    security_config.case_insensitive_local_names,
+                )
+                .await?
+            }
+            None => {
+                LocalActor::create_on(
+                    &mut transaction,
+                    local_name,
+                    None,
+                    security_config.case_insensitive_local_names,
+                )
+                .await?
+            }
+        };
+        PublicKeyInfo::insert_on(
+            &mut transaction,
+            public_key,
+            Some(actor.unique_actor_identifier),
+            security_config,
+        )
+        .await?;
+        transaction.commit().await?;
+        Ok(actor)
+    }
+
+    /// Create a new [LocalActor] on the given connection, which is usually a
+    /// transaction that the caller commits or rolls back together with other
+    /// changes. Actors without a `password_hash` can only log in with one of
+    /// their public keys. Returns an [Errcode::Duplicate]-type error, if a
+    /// user with the given `local_name` already exists, and an
+    /// [Errcode::IllegalInput]-type error, if the `local_name` is empty or
+    /// whitespace-only. See [LocalActor::create] for `case_insensitive`.
+    pub(super) async fn create_on(
+        connection: &mut PgConnection,
+        local_name: &str,
+        password_hash: Option<&str>,
+        case_insensitive: bool,
     ) -> Result<LocalActor, Error> {
-        if LocalActor::by_local_name(db, local_name).await?.is_some() {
-            Err(Error::new(
+        LocalActor::validate_local_name(local_name)?;
+        LocalActor::lock_local_name_on(&mut *connection, local_name, case_insensitive).await?;
+        if LocalActor::uaid_by_local_name_on(&mut *connection, local_name, case_insensitive)
+            .await?
+            .is_some()
+        {
+            return Err(Error::new(
                 Errcode::Duplicate,
                 Some(Context::new(Some("local_name"), Some(local_name), None, None)),
-            ))
-        } else {
-            let uaid = query!("INSERT INTO actors (type) VALUES ('local') RETURNING uaid")
-                .fetch_one(&db.pool)
-                .await?;
-            Ok(query_as!(
-			LocalActor,
-			"INSERT INTO local_actors (uaid, local_name, password_hash) VALUES ($1, $2, $3) RETURNING uaid AS unique_actor_identifier, local_name, deactivated AS is_deactivated, joined AS joined_at_timestamp",
-			uaid.uaid,
-			local_name,
-			password_hash
-		).fetch_one(&db.pool).await?)
+            ));
+        }
+        let uaid = query!("INSERT INTO actors (type) VALUES ('local') RETURNING uaid")
+            .fetch_one(&mut *connection)
+            .await?;
+        Ok(query_as!(
+            LocalActor,
+            "INSERT INTO local_actors (uaid, local_name, password_hash) VALUES ($1, $2, $3) RETURNING uaid AS unique_actor_identifier, local_name, deactivated AS is_deactivated, joined AS joined_at_timestamp",
+            uaid.uaid,
+            local_name,
+            password_hash
+        )
+        .fetch_one(&mut *connection)
+        .await?)
+    }
+
+    /// Create a new [LocalActor] with a given, precomputed `uaid`, for example
+    /// when importing actors. Inserts the `actors` and `local_actors` rows in
+    /// a single transaction.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an [Errcode::Duplicate]-type error, if an actor with the given
+    /// `uaid` or `local_name` already exists, and an [Errcode::IllegalInput]-
+    /// type error, if the `local_name` is empty or whitespace-only. Other than
+    /// that, this method will error, if something is wrong with the Database
+    /// or Database connection. See [LocalActor::create] for
+    /// `case_insensitive`.
+    pub async fn create_with_uaid(
+        db: &Database,
+        uaid: Uuid,
+        local_name: &str,
+        password_hash: &str,
+        case_insensitive: bool,
+    ) -> Result<LocalActor, Error> {
+        LocalActor::validate_local_name(local_name)?;
+        let mut transaction = db.pool.begin().await?;
+        LocalActor::lock_local_name_on(&mut transaction, local_name, case_insensitive).await?;
+        if query!("SELECT uaid FROM actors WHERE uaid = $1", uaid)
+            .fetch_optional(&mut *transaction)
+            .await?
+            .is_some()
+        {
+            return Err(Error::new(
+                Errcode::Duplicate,
+                Some(Context::new(Some("uaid"), Some(&uaid.to_string()), None, None)),
+            ));
+        }
+        if LocalActor::uaid_by_local_name_on(&mut transaction, local_name, case_insensitive)
+            .await?
+            .is_some()
+        {
+            return Err(Error::new(
+                Errcode::Duplicate,
+                Some(Context::new(Some("local_name"), Some(local_name), None, None)),
+            ));
         }
+        query!("INSERT INTO actors (uaid, type) VALUES ($1, 'local')", uaid)
+            .execute(&mut *transaction)
+            .await?;
+        let actor = query_as!(
+            LocalActor,
+            "INSERT INTO local_actors (uaid, local_name, password_hash) VALUES ($1, $2, $3) RETURNING uaid AS unique_actor_identifier, local_name, deactivated AS is_deactivated, joined AS joined_at_timestamp",
+            uaid,
+            local_name,
+            password_hash
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(actor)
+    }
+
+    /// Get a page of at most `limit` [LocalActor]s, skipping the first `offset`
+    /// ones, ordered by their join timestamp. Actors which have joined at the
+    /// same time are ordered by their `uaid`, so that pages do not overlap.
+    /// `limit` and `offset` are expected to come from
+    /// [PaginationParams](crate::api::extractors::PaginationParams), which
+    /// validates and caps them.
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if something is wrong with the Database or Database
+    /// connection.
+    pub async fn list(db: &Database, limit: i64, offset: i64) -> Result<Vec<LocalActor>, Error> {
+        Ok(query_as!(
+            LocalActor,
+            "
+            SELECT uaid AS unique_actor_identifier, local_name, deactivated AS is_deactivated, joined AS joined_at_timestamp
+            FROM local_actors
+            ORDER BY joined, uaid
+            LIMIT $1 OFFSET $2",
+            limit,
+            offset
+        )
+        .fetch_all(&db.pool)
+        .await?)
+    }
+
+    /// Count all [LocalActor]s, including deactivated ones.
+    ///
+    /// ## Errors
+    ///
+    /// Will error on Database connection issues and on other errors with the
+    /// database, all of which are not in scope for this function to handle.
+    pub async fn count(db: &Database) -> Result<i64, Error> {
+        Ok(query!(r#"SELECT COUNT(*) AS "count!" FROM local_actors"#)
+            .fetch_one(&db.pool)
+            .await?
+            .count)
+    }
+
+    /// Count all [LocalActor]s, which are not deactivated.
+    ///
+    /// ## Errors
+    ///
+    /// Will error on Database connection issues and on other errors with the
+    /// database, all of which are not in scope for this function to handle.
+    pub async fn count_active(db: &Database) -> Result<i64, Error> {
+        Ok(query!(r#"SELECT COUNT(*) AS "count!" FROM local_actors WHERE NOT deactivated"#)
+            .fetch_one(&db.pool)
+            .await?
+            .count)
+    }
+
+    /// Get all [LocalActor]s which have joined in the half-open time interval
+    /// `[start, end)`, ordered by their join timestamp.
+    ///
+    /// ## Errors
+    ///
+    /// Will error on Database connection issues and on other errors with the
+    /// database, all of which are not in scope for this function to handle.
+    pub async fn joined_between(
+        db: &Database,
+        start: chrono::NaiveDateTime,
+        end: chrono::NaiveDateTime,
+    ) -> Result<Vec<LocalActor>, Error> {
+        Ok(query_as!(
+            LocalActor,
+            "
+            SELECT uaid AS unique_actor_identifier, local_name, deactivated AS is_deactivated, joined AS joined_at_timestamp
+            FROM local_actors
+            WHERE joined >= $1 AND joined < $2
+            ORDER BY joined",
+            start,
+            end
+        )
+        .fetch_all(&db.pool)
+        .await?)
+    }
+
+    /// Count the [LocalActor]s which have joined in the half-open time interval
+    /// `[start, end)`.
+    ///
+    /// ## Errors
+    ///
+    /// Will error on Database connection issues and on other errors with the
+    /// database, all of which are not in scope for this function to handle.
+    pub async fn count_joined_between(
+        db: &Database,
+        start: chrono::NaiveDateTime,
+        end: chrono::NaiveDateTime,
+    ) -> Result<i64, Error> {
+        Ok(query!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM local_actors
+            WHERE joined >= $1 AND joined < $2"#,
+            start,
+            end
+        )
+        .fetch_one(&db.pool)
+        .await?
+        .count)
     }
 }
 
+/// Find the unique actor identifiers of all "orphaned" local actors: Rows in
+/// the `actors` table of type `local`, which have no matching row in the
+/// `local_actors` table. Such rows can be left behind, if actor creation has
+/// been interrupted.
+///
+/// ## Errors
+///
+/// Will error on Database connection issues and on other errors with the
+/// database, all of which are not in scope for this function to handle.
+pub async fn find_orphan_actors(db: &Database) -> Result<Vec<Uuid>, Error> {
+    Ok(query!(
+        "
+        SELECT a.uaid
+        FROM actors a
+        LEFT JOIN local_actors l ON l.uaid = a.uaid
+        WHERE a.type = 'local' AND l.uaid IS NULL
+        ORDER BY a.uaid"
+    )
+    .fetch_all(&db.pool)
+    .await?
+    .into_iter()
+    .map(|row| row.uaid)
+    .collect())
+}
+
+/// Delete all orphaned local actors, as found by [find_orphan_actors], from
+/// the `actors` table. Returns the unique actor identifiers of the deleted
+/// rows.
+///
+/// ## Errors
+///
+/// Will error on Database connection issues and on other errors with the
+/// database, all of which are not in scope for this function to handle.
+pub async fn purge_orphan_actors(db: &Database) -> Result<Vec<Uuid>, Error> {
+    let mut purged = query!(
+        "
+        DELETE FROM actors a
+        WHERE a.type = 'local'
+        AND NOT EXISTS (SELECT 1 FROM local_actors l WHERE l.uaid = a.uaid)
+        RETURNING a.uaid"
+    )
+    .fetch_all(&db.pool)
+    .await?
+    .into_iter()
+    .map(|row| row.uaid)
+    .collect::<Vec<_>>();
+    purged.sort();
+    Ok(purged)
+}
+
 #[cfg(test)]
 mod tests {
     use sqlx::{Pool, Postgres};
 
     use super::*;
+    use crate::database::test_helpers::insert_session;
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_actor_by_uaid(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let uaid = Uuid::from_u128(1);
+
+        let actor = Actor::by_uaid(&db, &uaid).await.unwrap().unwrap();
+        assert_eq!(actor.unique_actor_identifier, uaid);
+        assert_eq!(actor.actor_type(), ActorType::Local);
+        let local_actor = LocalActor::by_uaid(&db, &uaid).await.unwrap().unwrap();
+        assert_eq!(Actor::from(local_actor), actor);
+
+        assert!(Actor::by_uaid(&db, &Uuid::from_u128(0xdead)).await.unwrap().is_none());
+
+        let federation_id = polyproto::types::FederationId::new("bob@foreign.example.com").unwrap();
+        let foreign_actor =
+            crate::database::ForeignActor::upsert(&db, &federation_id, "cert").await.unwrap();
+        let actor = Actor::by_uaid(&db, &foreign_actor.uaid).await.unwrap().unwrap();
+        assert_eq!(actor.actor_type(), ActorType::Foreign);
+    }
 
     #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
     async fn test_by_local_name_finds_existing_user(pool: Pool<Postgres>) {
         let db = Database { pool };
 
-        let result = LocalActor::by_local_name(&db, "alice").await.unwrap();
+        let result = LocalActor::by_local_name(&db, "alice", false).await.unwrap();
         assert!(result.is_some());
 
         let actor = result.unwrap();
@@ -153,7 +976,7 @@ mod tests {
     async fn test_by_local_name_finds_deactivated_user(pool: Pool<Postgres>) {
         let db = Database { pool };
 
-        let result = LocalActor::by_local_name(&db, "deactivated_user").await.unwrap();
+        let result = LocalActor::by_local_name(&db, "deactivated_user", false).await.unwrap();
         assert!(result.is_some());
 
         let actor = result.unwrap();
@@ -169,7 +992,7 @@ mod tests {
     async fn test_by_local_name_finds_user_with_special_characters(pool: Pool<Postgres>) {
         let db = Database { pool };
 
-        let result = LocalActor::by_local_name(&db, "user_with_underscores").await.unwrap();
+        let result = LocalActor::by_local_name(&db, "user_with_underscores", false).await.unwrap();
         assert!(result.is_some());
 
         let actor = result.unwrap();
@@ -185,7 +1008,7 @@ mod tests {
     async fn test_by_local_name_returns_none_for_nonexistent_user(pool: Pool<Postgres>) {
         let db = Database { pool };
 
-        let result = LocalActor::by_local_name(&db, "nonexistent_user").await.unwrap();
+        let result = LocalActor::by_local_name(&db, "nonexistent_user", false).await.unwrap();
         assert!(result.is_none());
     }
 
@@ -193,7 +1016,7 @@ mod tests {
     async fn test_by_local_name_returns_none_for_empty_string(pool: Pool<Postgres>) {
         let db = Database { pool };
 
-        let result = LocalActor::by_local_name(&db, "").await.unwrap();
+        let result = LocalActor::by_local_name(&db, "", false).await.unwrap();
         assert!(result.is_none());
     }
 
@@ -202,22 +1025,74 @@ mod tests {
         let db = Database { pool };
 
         // Should find exact match
-        let result_exact = LocalActor::by_local_name(&db, "alice").await.unwrap();
+        let result_exact = LocalActor::by_local_name(&db, "alice", false).await.unwrap();
         assert!(result_exact.is_some());
 
         // Should not find case-different match
-        let result_upper = LocalActor::by_local_name(&db, "ALICE").await.unwrap();
+        let result_upper = LocalActor::by_local_name(&db, "ALICE", false).await.unwrap();
         assert!(result_upper.is_none());
 
-        let result_mixed = LocalActor::by_local_name(&db, "Alice").await.unwrap();
+        let result_mixed = LocalActor::by_local_name(&db, "Alice", false).await.unwrap();
         assert!(result_mixed.is_none());
     }
 
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_by_local_name_case_insensitive(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        for name in ["alice", "ALICE", "Alice"] {
+            let found = LocalActor::by_local_name(&db, name, true).await.unwrap().unwrap();
+            assert_eq!(found.local_name, "alice");
+        }
+        assert_eq!(
+            LocalActor::get_password_hash(&db, "ALICE", true).await.unwrap(),
+            LocalActor::get_password_hash(&db, "alice", false).await.unwrap()
+        );
+        assert!(LocalActor::by_local_name(&db, "ALICIA", true).await.unwrap().is_none());
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_case_insensitive_names_stay_unique(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        let error = LocalActor::create(&db, "Alice", "hash", true).await.unwrap_err();
+        assert_eq!(error.code, Errcode::Duplicate);
+        let uaid = Uuid::from_u128(0x1032);
+        let error =
+            LocalActor::create_with_uaid(&db, uaid, "ALICE", "hash", true).await.unwrap_err();
+        assert_eq!(error.code, Errcode::Duplicate);
+        let bob = LocalActor::by_local_name(&db, "bob", true).await.unwrap().unwrap();
+        let error =
+            LocalActor::rename(&db, &bob.unique_actor_identifier, "Alice", true).await.unwrap_err();
+        assert_eq!(error.code, Errcode::Duplicate);
+
+        // Changing only the case of the own name is allowed
+        let alice = LocalActor::by_local_name(&db, "alice", true).await.unwrap().unwrap();
+        let renamed =
+            LocalActor::rename(&db, &alice.unique_actor_identifier, "Alice", true).await.unwrap();
+        assert_eq!(renamed.local_name, "Alice");
+
+        // With the option disabled, names differing in case are distinct
+        LocalActor::create(&db, "BOB", "hash", false).await.unwrap();
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_by_uaid_finds_existing_user(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        let uaid = Uuid::parse_str("00000000-0000-0000-0000-000000000002").unwrap();
+        let actor = LocalActor::by_uaid(&db, &uaid).await.unwrap().unwrap();
+        assert_eq!(actor.local_name, "bob");
+        assert_eq!(actor.unique_actor_identifier, uaid);
+
+        assert!(LocalActor::by_uaid(&db, &Uuid::nil()).await.unwrap().is_none());
+    }
+
     #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
     async fn test_create_new_user_success(pool: Pool<Postgres>) {
         let db = Database { pool };
 
-        let result = LocalActor::create(&db, "new_user", "hash").await;
+        let result = LocalActor::create(&db, "new_user", "hash", false).await;
         assert!(result.is_ok());
 
         let actor = result.unwrap();
@@ -226,7 +1101,7 @@ mod tests {
         assert!(actor.unique_actor_identifier != sqlx::types::Uuid::nil());
 
         // Verify the user was actually created in the database
-        let found = LocalActor::by_local_name(&db, "new_user").await.unwrap();
+        let found = LocalActor::by_local_name(&db, "new_user", false).await.unwrap();
         assert!(found.is_some());
         let found_actor = found.unwrap();
         assert_eq!(found_actor.unique_actor_identifier, actor.unique_actor_identifier);
@@ -236,7 +1111,7 @@ mod tests {
     async fn test_create_duplicate_user_returns_error(pool: Pool<Postgres>) {
         let db = Database { pool };
 
-        let result = LocalActor::create(&db, "alice", "hash").await;
+        let result = LocalActor::create(&db, "alice", "hash", false).await;
         assert!(result.is_err());
 
         match result.unwrap_err() {
@@ -254,7 +1129,7 @@ mod tests {
     async fn test_create_duplicate_deactivated_user_returns_error(pool: Pool<Postgres>) {
         let db = Database { pool };
 
-        let result = LocalActor::create(&db, "deactivated_user", "hash").await;
+        let result = LocalActor::create(&db, "deactivated_user", "hash", false).await;
         assert!(result.is_err());
 
         match result.unwrap_err() {
@@ -272,14 +1147,14 @@ mod tests {
     async fn test_create_user_with_special_characters(pool: Pool<Postgres>) {
         let db = Database { pool };
 
-        let result = LocalActor::create(&db, "user.with-special_chars", "hash").await;
+        let result = LocalActor::create(&db, "user.with-special_chars", "hash", false).await;
         assert!(result.is_ok());
 
         let actor = result.unwrap();
         assert_eq!(actor.local_name, "user.with-special_chars");
         assert!(!actor.is_deactivated);
 
-        let found = LocalActor::by_local_name(&db, "user.with-special_chars").await.unwrap();
+        let found = LocalActor::by_local_name(&db, "user.with-special_chars", false).await.unwrap();
         assert!(found.is_some());
     }
 
@@ -287,24 +1162,43 @@ mod tests {
     async fn test_create_user_with_empty_name(pool: Pool<Postgres>) {
         let db = Database { pool };
 
-        let result = LocalActor::create(&db, "", "hash").await;
-        assert!(result.is_ok());
+        for local_name in ["", " ", "\t\n"] {
+            let error = LocalActor::create(&db, local_name, "hash", false).await.unwrap_err();
+            assert_eq!(error.code, Errcode::IllegalInput);
+            let error =
+                LocalActor::create_with_uaid(&db, Uuid::from_u128(1000), local_name, "hash", false)
+                    .await
+                    .unwrap_err();
+            assert_eq!(error.code, Errcode::IllegalInput);
+        }
 
-        let actor = result.unwrap();
-        assert_eq!(actor.local_name, "");
-        assert!(!actor.is_deactivated);
+        let found = LocalActor::by_local_name(&db, "", false).await.unwrap();
+        assert!(found.is_none());
+    }
 
-        let found = LocalActor::by_local_name(&db, "").await.unwrap();
-        assert!(found.is_some());
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_create_user_with_control_characters(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        for local_name in ["null\0byte", "\0", "bell\u{7}", "new\nline"] {
+            let error = LocalActor::create(&db, local_name, "hash", false).await.unwrap_err();
+            assert_eq!(error.code, Errcode::IllegalInput);
+            let context = error.context.unwrap();
+            assert_eq!(context.field_name, "local_name");
+            assert_eq!(context.expected, "No control characters");
+        }
+        let error =
+            LocalActor::rename(&db, &Uuid::from_u128(1000), "null\0byte", false).await.unwrap_err();
+        assert_eq!(error.context.unwrap().expected, "No control characters");
     }
 
     #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
     async fn test_create_multiple_users_have_different_uuids(pool: Pool<Postgres>) {
         let db = Database { pool };
 
-        let user1 = LocalActor::create(&db, "user1", "hash").await.unwrap();
-        let user2 = LocalActor::create(&db, "user2", "hash").await.unwrap();
-        let user3 = LocalActor::create(&db, "user3", "hash").await.unwrap();
+        let user1 = LocalActor::create(&db, "user1", "hash", false).await.unwrap();
+        let user2 = LocalActor::create(&db, "user2", "hash", false).await.unwrap();
+        let user3 = LocalActor::create(&db, "user3", "hash", false).await.unwrap();
 
         assert_ne!(user1.unique_actor_identifier, user2.unique_actor_identifier);
         assert_ne!(user1.unique_actor_identifier, user3.unique_actor_identifier);
@@ -320,10 +1214,429 @@ mod tests {
         let db = Database { pool };
 
         let before_create = chrono::Utc::now().naive_utc();
-        let actor = LocalActor::create(&db, "timestamped_user", "hash").await.unwrap();
+        let actor = LocalActor::create(&db, "timestamped_user", "hash", false).await.unwrap();
         let after_create = chrono::Utc::now().naive_utc();
 
         assert!(actor.joined_at_timestamp >= before_create);
         assert!(actor.joined_at_timestamp <= after_create);
     }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_create_with_uaid_fresh_uaid(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let uaid = Uuid::parse_str("00000000-0000-0000-0000-0000000000aa").unwrap();
+
+        let actor =
+            LocalActor::create_with_uaid(&db, uaid, "imported_user", "hash", false).await.unwrap();
+        assert_eq!(actor.unique_actor_identifier, uaid);
+        assert_eq!(actor.local_name, "imported_user");
+
+        let found = LocalActor::by_uaid(&db, &uaid).await.unwrap().unwrap();
+        assert_eq!(found.local_name, "imported_user");
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_create_with_uaid_colliding_uaid(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let uaid = Uuid::parse_str("00000000-0000-0000-0000-000000000001").unwrap();
+
+        let error = LocalActor::create_with_uaid(&db, uaid, "imported_user", "hash", false)
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, Errcode::Duplicate);
+        assert_eq!(error.context.unwrap().field_name, "uaid");
+        assert!(LocalActor::by_local_name(&db, "imported_user", false).await.unwrap().is_none());
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_create_with_uaid_colliding_name(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let uaid = Uuid::parse_str("00000000-0000-0000-0000-0000000000aa").unwrap();
+
+        let error =
+            LocalActor::create_with_uaid(&db, uaid, "alice", "hash", false).await.unwrap_err();
+        assert_eq!(error.code, Errcode::Duplicate);
+        assert_eq!(error.context.unwrap().field_name, "local_name");
+        // The transaction has been rolled back, so no orphaned `actors` row is left
+        let actor_row = query!("SELECT uaid FROM actors WHERE uaid = $1", uaid)
+            .fetch_optional(&db.pool)
+            .await
+            .unwrap();
+        assert!(actor_row.is_none());
+    }
+
+    fn timestamp(s: &str) -> chrono::NaiveDateTime {
+        chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_joined_between_uses_half_open_interval(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        // bob joined exactly at `start`, deactivated_user exactly at `end`
+        let actors = LocalActor::joined_between(
+            &db,
+            timestamp("2023-01-02 12:00:00"),
+            timestamp("2023-01-04 12:00:00"),
+        )
+        .await
+        .unwrap();
+        let names = actors.iter().map(|a| a.local_name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["bob", "charlie"]);
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_joined_between_includes_deactivated_and_orders_by_joined(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        let actors = LocalActor::joined_between(
+            &db,
+            timestamp("2023-01-01 00:00:00"),
+            timestamp("2024-01-01 00:00:00"),
+        )
+        .await
+        .unwrap();
+        let names = actors.iter().map(|a| a.local_name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec!["alice", "bob", "charlie", "deactivated_user", "user_with_underscores"]
+        );
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_count_joined_between(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        let count = LocalActor::count_joined_between(
+            &db,
+            timestamp("2023-01-02 12:00:00"),
+            timestamp("2023-01-04 12:00:00"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(count, 2);
+
+        let count = LocalActor::count_joined_between(
+            &db,
+            timestamp("2023-01-01 12:00:00"),
+            timestamp("2023-01-01 12:00:00"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(count, 0);
+
+        let count = LocalActor::count_joined_between(
+            &db,
+            timestamp("2022-01-01 00:00:00"),
+            timestamp("2023-01-05 12:00:01"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(count, 5);
+    }
+
+    async fn insert_orphan_actors(db: &Database) -> Vec<Uuid> {
+        let orphans = vec![
+            Uuid::parse_str("00000000-0000-0000-0000-0000000000a1").unwrap(),
+            Uuid::parse_str("00000000-0000-0000-0000-0000000000a2").unwrap(),
+        ];
+        for uaid in orphans.iter() {
+            query!("INSERT INTO actors (uaid, type) VALUES ($1, 'local')", uaid)
+                .execute(&db.pool)
+                .await
+                .unwrap();
+        }
+        // Foreign actors never have a `local_actors` row and are not orphans
+        query!(
+            "INSERT INTO actors (uaid, type) VALUES ('00000000-0000-0000-0000-0000000000f1', \
+             'foreign')"
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        orphans
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_find_orphan_actors(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        assert!(find_orphan_actors(&db).await.unwrap().is_empty());
+        let orphans = insert_orphan_actors(&db).await;
+        assert_eq!(find_orphan_actors(&db).await.unwrap(), orphans);
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_purge_orphan_actors(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        let orphans = insert_orphan_actors(&db).await;
+        assert_eq!(purge_orphan_actors(&db).await.unwrap(), orphans);
+        assert!(find_orphan_actors(&db).await.unwrap().is_empty());
+        assert!(purge_orphan_actors(&db).await.unwrap().is_empty());
+
+        // Healthy local actors and foreign actors are untouched
+        let remaining = query!("SELECT COUNT(*) AS \"count!\" FROM actors")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap()
+            .count;
+        assert_eq!(remaining, 6);
+        assert!(LocalActor::by_local_name(&db, "alice", false).await.unwrap().is_some());
+        assert_eq!(
+            LocalActor::count_joined_between(
+                &db,
+                timestamp("2000-01-01 00:00:00"),
+                timestamp("2100-01-01 00:00:00"),
+            )
+            .await
+            .unwrap(),
+            5
+        );
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_set_deactivated_toggles_state(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let uaid = Uuid::from_u128(4);
+
+        LocalActor::set_deactivated(&db, &uaid, false).await.unwrap();
+        assert!(!LocalActor::by_uaid(&db, &uaid).await.unwrap().unwrap().is_deactivated);
+        // Setting the current state again is a no-op
+        LocalActor::set_deactivated(&db, &uaid, false).await.unwrap();
+        assert!(!LocalActor::by_uaid(&db, &uaid).await.unwrap().unwrap().is_deactivated);
+
+        LocalActor::set_deactivated(&db, &uaid, true).await.unwrap();
+        assert!(LocalActor::by_uaid(&db, &uaid).await.unwrap().unwrap().is_deactivated);
+        // Other actors are not affected
+        let alice = LocalActor::by_local_name(&db, "alice", false).await.unwrap().unwrap();
+        assert!(!alice.is_deactivated);
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_set_deactivated_unknown_uaid(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        let error =
+            LocalActor::set_deactivated(&db, &Uuid::from_u128(1000), true).await.unwrap_err();
+        assert_eq!(error.code, Errcode::NotFound);
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_list(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let names = |actors: Vec<LocalActor>| {
+            actors.into_iter().map(|actor| actor.local_name).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(LocalActor::list(&db, 10, 0).await.unwrap()),
+            ["alice", "bob", "charlie", "deactivated_user", "user_with_underscores"]
+        );
+        assert_eq!(names(LocalActor::list(&db, 2, 0).await.unwrap()), ["alice", "bob"]);
+        assert_eq!(
+            names(LocalActor::list(&db, 2, 2).await.unwrap()),
+            ["charlie", "deactivated_user"]
+        );
+        assert_eq!(names(LocalActor::list(&db, 2, 4).await.unwrap()), ["user_with_underscores"]);
+        assert!(LocalActor::list(&db, 2, 5).await.unwrap().is_empty());
+        assert_eq!(LocalActor::count(&db).await.unwrap(), 5);
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_deletion_impact(pool: Pool<Postgres>) {
+        insert_session(&pool, "token_a", 1, Uuid::from_u128(1), None).await;
+        insert_session(&pool, "token_b", 5, Uuid::from_u128(1), None).await;
+        insert_session(&pool, "token_c", 2, Uuid::from_u128(2), None).await;
+        query!(
+            "INSERT INTO invite_links (invite_link_owner, usages_current, usages_maximum, invite, invalid)
+            VALUES ('00000000-0000-0000-0000-000000000001', 0, 1, 'INVITE0000000001', FALSE)"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let db = Database { pool };
+
+        // test_user_1 has two keys, each with a cert and a token, and owns an invite
+        assert_eq!(
+            LocalActor::deletion_impact(&db, &Uuid::from_u128(1)).await.unwrap(),
+            DeletionImpact { tokens: 2, public_keys: 2, certs: 2, owned_invites: 1 }
+        );
+        // test_user_3 has a key and an ID-CSR, but no cert
+        assert_eq!(
+            LocalActor::deletion_impact(&db, &Uuid::from_u128(3)).await.unwrap(),
+            DeletionImpact { tokens: 0, public_keys: 1, certs: 0, owned_invites: 0 }
+        );
+        let error = LocalActor::deletion_impact(&db, &Uuid::from_u128(1000)).await.unwrap_err();
+        assert_eq!(error.code, Errcode::NotFound);
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_delete(pool: Pool<Postgres>) {
+        insert_session(&pool, "token_a", 1, Uuid::from_u128(1), None).await;
+        insert_session(&pool, "token_c", 2, Uuid::from_u128(2), None).await;
+        query!(
+            "INSERT INTO invite_links (invite_link_owner, usages_current, usages_maximum, invite, invalid)
+            VALUES ('00000000-0000-0000-0000-000000000001', 0, 1, 'INVITE0000000001', FALSE)"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let db = Database { pool };
+        let test_user_1 = Uuid::from_u128(1);
+
+        LocalActor::delete(&db, &test_user_1).await.unwrap();
+
+        let remaining = query!(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM actors WHERE uaid = $1) AS "actors!",
+                (SELECT COUNT(*) FROM local_actors WHERE uaid = $1) AS "local_actors!",
+                (SELECT COUNT(*) FROM user_tokens WHERE uaid = $1) AS "tokens!",
+                (SELECT COUNT(*) FROM public_keys WHERE uaid = $1) AS "public_keys!",
+                (SELECT COUNT(*) FROM idcsr WHERE uaid = $1) AS "idcsrs!",
+                (SELECT COUNT(*) FROM idcert WHERE idcsr_id IN (1, 5)) AS "certs!",
+                (SELECT COUNT(*) FROM invite_links WHERE invite_link_owner = $1) AS "invites!"
+            "#,
+            test_user_1
+        )
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            [
+                remaining.actors,
+                remaining.local_actors,
+                remaining.tokens,
+                remaining.public_keys,
+                remaining.idcsrs,
+                remaining.certs,
+                remaining.invites
+            ],
+            [0; 7]
+        );
+        // Other actors are left untouched
+        assert_eq!(LocalActor::deletion_impact(&db, &Uuid::from_u128(2)).await.unwrap().tokens, 1);
+
+        for uaid in [test_user_1, Uuid::from_u128(1000)] {
+            let error = LocalActor::delete(&db, &uaid).await.unwrap_err();
+            assert_eq!(error.code, Errcode::NotFound);
+        }
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_replace_password_hash(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let alice = LocalActor::by_local_name(&db, "alice", false).await.unwrap().unwrap();
+        let mut connection = db.pool.acquire().await.unwrap();
+
+        LocalActor::replace_password_hash_on(
+            &mut connection,
+            &alice.unique_actor_identifier,
+            "hash",
+            "new_hash",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            LocalActor::get_password_hash(&db, "alice", false).await.unwrap().unwrap(),
+            "new_hash"
+        );
+
+        // The old hash has been replaced already, and unknown actors have no hash
+        for uaid in [alice.unique_actor_identifier, Uuid::from_u128(1000)] {
+            let error =
+                LocalActor::replace_password_hash_on(&mut connection, &uaid, "hash", "newer_hash")
+                    .await
+                    .unwrap_err();
+            assert_eq!(error.code, Errcode::Unauthorized);
+        }
+        assert_eq!(
+            LocalActor::get_password_hash(&db, "alice", false).await.unwrap().unwrap(),
+            "new_hash"
+        );
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_rename_success(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let alice = LocalActor::by_local_name(&db, "alice", false).await.unwrap().unwrap();
+
+        let renamed =
+            LocalActor::rename(&db, &alice.unique_actor_identifier, "alicia", false).await.unwrap();
+        assert_eq!(renamed.local_name, "alicia");
+        assert_eq!(renamed.unique_actor_identifier, alice.unique_actor_identifier);
+        assert_eq!(renamed.joined_at_timestamp, alice.joined_at_timestamp);
+        assert!(LocalActor::by_local_name(&db, "alice", false).await.unwrap().is_none());
+        let found = LocalActor::by_local_name(&db, "alicia", false).await.unwrap().unwrap();
+        assert_eq!(found.unique_actor_identifier, alice.unique_actor_identifier);
+
+        // Renaming to the current name changes nothing
+        let unchanged =
+            LocalActor::rename(&db, &alice.unique_actor_identifier, "alicia", false).await.unwrap();
+        assert_eq!(unchanged.local_name, "alicia");
+        assert_eq!(unchanged.joined_at_timestamp, alice.joined_at_timestamp);
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_rename_to_taken_name_returns_duplicate(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let alice = LocalActor::by_local_name(&db, "alice", false).await.unwrap().unwrap();
+
+        let error = LocalActor::rename(&db, &alice.unique_actor_identifier, "bob", false)
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, Errcode::Duplicate);
+        assert_eq!(error.context.unwrap().field_name, "local_name");
+        let unchanged =
+            LocalActor::by_uaid(&db, &alice.unique_actor_identifier).await.unwrap().unwrap();
+        assert_eq!(unchanged.local_name, "alice");
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_rename_nonexistent_uaid(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        let error =
+            LocalActor::rename(&db, &Uuid::from_u128(1000), "newcomer", false).await.unwrap_err();
+        assert_eq!(error.code, Errcode::NotFound);
+        assert!(LocalActor::by_local_name(&db, "newcomer", false).await.unwrap().is_none());
+    }
+
+    /// Claim the name "NEWCOMER" in a transaction, then check that `claim`,
+    /// which claims "newcomer", waits for that transaction and fails once it
+    /// has been committed.
+    async fn assert_claim_waits_for_case_variant(
+        db: &Database,
+        claim: impl Future<Output = Result<LocalActor, Error>>,
+    ) {
+        let mut blocker = db.pool.begin().await.unwrap();
+        LocalActor::create_on(&mut blocker, "NEWCOMER", Some("hash"), true).await.unwrap();
+        let mut claim = Box::pin(claim);
+
+        assert!(
+            tokio::time::timeout(std::time::Duration::from_millis(300), &mut claim).await.is_err()
+        );
+        blocker.commit().await.unwrap();
+        assert_eq!(claim.await.unwrap_err().code, Errcode::Duplicate);
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_create_waits_for_case_insensitive_name(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        assert_claim_waits_for_case_variant(&db, LocalActor::create(&db, "newcomer", "hash", true))
+            .await;
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/local_actor_tests.sql"))]
+    async fn test_rename_waits_for_case_insensitive_name(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let alice = Uuid::from_u128(1);
+
+        assert_claim_waits_for_case_variant(&db, LocalActor::rename(&db, &alice, "newcomer", true))
+            .await;
+        let unchanged = LocalActor::by_uaid(&db, &alice).await.unwrap().unwrap();
+        assert_eq!(unchanged.local_name, "alice");
+    }
 }
@@ -3,11 +3,11 @@ use polyproto::{
     der::Encode,
     spki::{AlgorithmIdentifierOwned, ObjectIdentifier},
 };
-use sqlx::query;
+use sqlx::{PgConnection, query};
 
 use crate::{
     database::Database,
-    errors::{ALGORITHM_IDENTIFER_TO_DER_ERROR_MESSAGE, Error},
+    errors::{ALGORITHM_IDENTIFER_TO_DER_ERROR_MESSAGE, Context, Errcode, Error},
 };
 
 pub(crate) struct AlgorithmIdentifier {
@@ -34,18 +34,34 @@ impl AlgorithmIdentifier {
     ///
     /// ## Errors
     ///
-    /// The function will error, if
-    ///
-    /// - The database or database connection is broken
-    /// - Any entry in the set of elements queried from the database contains
-    ///   text in the `algorithm_identifier` column, which is not in valid,
-    ///   dot-delimited OID string form.
+    /// The function will error, if the database or database connection is
+    /// broken. Entries, whose `algorithm_identifier` column does not contain a
+    /// valid, dot-delimited OID, are logged and skipped.
     pub(crate) async fn get_by_query(
         db: &Database,
         id: Option<i32>,
         common_name: Option<&str>,
         algorithm_identifier: Option<&ObjectIdentifier>,
         parameters_der_encoded: &[u8],
+    ) -> Result<Vec<Self>, Error> {
+        Self::get_by_query_on(
+            &mut *db.pool.acquire().await?,
+            id,
+            common_name,
+            algorithm_identifier,
+            parameters_der_encoded,
+        )
+        .await
+    }
+
+    /// Like [Self::get_by_query], but queries the given `connection`, such as
+    /// an open transaction.
+    pub(crate) async fn get_by_query_on(
+        connection: &mut PgConnection,
+        id: Option<i32>,
+        common_name: Option<&str>,
+        algorithm_identifier: Option<&ObjectIdentifier>,
+        parameters_der_encoded: &[u8],
     ) -> Result<Vec<Self>, Error> {
         if common_name.is_none()
             && id.is_none()
@@ -76,30 +92,75 @@ impl AlgorithmIdentifier {
             common_name,
             parameters_for_query,
         )
-        .fetch_all(&db.pool)
+        .fetch_all(&mut *connection)
         .await?;
-        let algorithm_identifiers_mapped = record
-			.into_iter()
-			.flat_map(|r| {
-				Ok(AlgorithmIdentifier {
-					id: r.id,
-					algorithm_identifier: match ObjectIdentifier::new(&r.algorithm_identifier) {
-						Ok(oid) => oid,
-						Err(e) => {
-							error!(
-								"Found invalid algorithm_identifier in table algorithm_identifiers: {e}"
-							);
-							return Err(Error::new_internal_error(None));
-						}
-					},
-					common_name: r.common_name,
-					parameters_der_encoded: r
-						.parameters_der_encoded
-						.map(|v| v.into_iter().map(|num| num as u8).collect::<Vec<_>>()),
-				})
-			})
-			.collect::<Vec<_>>();
-        Ok(algorithm_identifiers_mapped)
+        Ok(record
+            .into_iter()
+            .filter_map(|r| {
+                Self::from_row(
+                    r.id,
+                    &r.algorithm_identifier,
+                    r.common_name,
+                    r.parameters_der_encoded,
+                )
+            })
+            .collect())
+    }
+
+    /// Get all entries of the `algorithm_identifiers` table, ordered by their
+    /// ID. Entries, whose `algorithm_identifier` column does not contain a
+    /// valid, dot-delimited OID, are logged and skipped.
+    ///
+    /// ## Errors
+    ///
+    /// The function will error, if the database or database connection is
+    /// broken.
+    pub(crate) async fn list_all(db: &Database) -> Result<Vec<Self>, Error> {
+        Ok(query!(
+            "SELECT id, algorithm_identifier, common_name, parameters_der_encoded
+            FROM algorithm_identifiers
+            ORDER BY id"
+        )
+        .fetch_all(&db.pool)
+        .await?
+        .into_iter()
+        .filter_map(|r| {
+            Self::from_row(r.id, &r.algorithm_identifier, r.common_name, r.parameters_der_encoded)
+        })
+        .collect())
+    }
+
+    /// Map the columns of a row of the `algorithm_identifiers` table into
+    /// [Self]. Returns `None` and logs an error, if `algorithm_identifier` is
+    /// not in valid, dot-delimited OID string form, or if
+    /// `parameters_der_encoded` contains a value which is not a byte.
+    fn from_row(
+        id: i32,
+        algorithm_identifier: &str,
+        common_name: Option<String>,
+        parameters_der_encoded: Option<Vec<i16>>,
+    ) -> Option<Self> {
+        let algorithm_identifier = ObjectIdentifier::new(algorithm_identifier)
+            .inspect_err(|e| {
+                error!("Found invalid algorithm_identifier in table algorithm_identifiers: {e}")
+            })
+            .ok()?;
+        let parameters_der_encoded = match parameters_der_encoded {
+            Some(parameters) => Some(
+                parameters
+                    .into_iter()
+                    .map(u8::try_from)
+                    .collect::<Result<Vec<_>, _>>()
+                    .inspect_err(|e| {
+                        error!(
+                            "Found invalid parameters_der_encoded in table algorithm_identifiers: {e}"
+                        )
+                    })
+                    .ok()?,
+            ),
+            None => None,
+        };
+        Some(AlgorithmIdentifier { id, algorithm_identifier, common_name, parameters_der_encoded })
     }
 
     /// Tries to get the row entry [AlgorithmIdentifier] matching an
@@ -116,6 +177,16 @@ impl AlgorithmIdentifier {
     pub(crate) async fn get_by_algorithm_identifier(
         db: &Database,
         algorithm_identifier: &AlgorithmIdentifierOwned,
+    ) -> Result<Option<Self>, Error> {
+        Self::get_by_algorithm_identifier_on(&mut *db.pool.acquire().await?, algorithm_identifier)
+            .await
+    }
+
+    /// Like [Self::get_by_algorithm_identifier], but queries the given
+    /// `connection`, such as an open transaction.
+    pub(crate) async fn get_by_algorithm_identifier_on(
+        connection: &mut PgConnection,
+        algorithm_identifier: &AlgorithmIdentifierOwned,
     ) -> Result<Option<Self>, Error> {
         let parameters_der_encoded = algorithm_identifier.parameters.to_der().map_err(|e| {
             error!("{ALGORITHM_IDENTIFER_TO_DER_ERROR_MESSAGE}: {e}");
@@ -123,10 +194,59 @@ impl AlgorithmIdentifier {
         })?;
         let oid = algorithm_identifier.oid;
         let mut result =
-            Self::get_by_query(db, None, None, Some(&oid), &parameters_der_encoded).await?;
+            Self::get_by_query_on(connection, None, None, Some(&oid), &parameters_der_encoded)
+                .await?;
         Ok(if !result.is_empty() { Some(result.swap_remove(0)) } else { None })
     }
 
+    /// The dot-delimited OIDs of all algorithms in the `algorithm_identifiers`
+    /// table, which are the algorithms supported by this server.
+    ///
+    /// ## Errors
+    ///
+    /// The function will error, if the database or database connection is
+    /// broken.
+    pub(crate) async fn supported_oids(db: &Database) -> Result<Vec<String>, Error> {
+        Self::supported_oids_on(&mut *db.pool.acquire().await?).await
+    }
+
+    /// Like [Self::supported_oids], but queries the given `connection`, such
+    /// as an open transaction.
+    async fn supported_oids_on(connection: &mut PgConnection) -> Result<Vec<String>, Error> {
+        Ok(query!("SELECT DISTINCT algorithm_identifier FROM algorithm_identifiers ORDER BY 1")
+            .fetch_all(&mut *connection)
+            .await?
+            .into_iter()
+            .map(|row| row.algorithm_identifier)
+            .collect())
+    }
+
+    /// An [Errcode::IllegalInput] error for the unsupported `algorithm`, so
+    /// that clients can tell which OID was rejected. The [Context] names the
+    /// `algorithm` as found, and the [Self::supported_oids] as expected.
+    /// `message` describes what contained the `algorithm`. The supported OIDs
+    /// are looked up on the given `connection`, such as an open transaction.
+    /// If they cannot be looked up, that error is returned instead.
+    pub(crate) async fn unsupported_error_on(
+        connection: &mut PgConnection,
+        field_name: Option<&str>,
+        algorithm: &ObjectIdentifier,
+        message: &str,
+    ) -> Error {
+        match Self::supported_oids_on(connection).await {
+            Ok(supported_oids) => Error::new(
+                Errcode::IllegalInput,
+                Some(Context::new(
+                    field_name,
+                    Some(&algorithm.to_string()),
+                    Some(&supported_oids.join(", ")),
+                    Some(message),
+                )),
+            ),
+            Err(error) => error,
+        }
+    }
+
     /// Tries to insert a new row into the `algorithm_identifiers` table.
     ///
     /// ## Errors
@@ -164,24 +284,160 @@ impl AlgorithmIdentifier {
 		.await?;
 
         match record {
-            Some(row) => Ok(AlgorithmIdentifier {
-                id: row.id,
-                algorithm_identifier: match ObjectIdentifier::new(&row.algorithm_identifier) {
-                    Ok(oid) => oid,
-                    Err(e) => {
-                        return Err(Error::new_internal_error(Some(&format!(
-                            "Found invalid algorithm_identifier in table algorithm_identifiers: {e}"
-                        ))));
-                    }
-                },
-                common_name: row.common_name,
-                parameters_der_encoded: row
-                    .parameters_der_encoded
-                    .map(|inner| inner.into_iter().map(|num| num as u8).collect::<Vec<_>>()),
-            }),
+            Some(row) => Self::from_row(
+                row.id,
+                &row.algorithm_identifier,
+                row.common_name,
+                row.parameters_der_encoded,
+            )
+            .ok_or_else(|| Error::new_internal_error(None)),
             None => Err(Error::new_duplicate_error(Some(
                 "The provided algorithm identifier is already present in the database",
             ))),
         }
     }
+
+    /// Idempotently insert each of the `entries`, made up of an algorithm
+    /// identifier, its common name and its DER-encoded parameters, into the
+    /// `algorithm_identifiers` table, using [Self::try_insert]. Entries, which
+    /// are already present, are left untouched.
+    ///
+    /// ## Returns
+    ///
+    /// The IDs of the `entries` in the `algorithm_identifiers` table, in the
+    /// order of the `entries`.
+    ///
+    /// ## Errors
+    ///
+    /// The function will error, if
+    ///
+    /// - The database or database connection is broken
+    /// - An entry cannot be inserted, because it conflicts with a different
+    ///   entry, such as one with the same common name, but another OID. The
+    ///   entries before it are inserted nonetheless.
+    pub(crate) async fn ensure_all(
+        db: &Database,
+        entries: &[(ObjectIdentifier, Option<&str>, &[u8])],
+    ) -> Result<Vec<i32>, Error> {
+        let mut ids = Vec::with_capacity(entries.len());
+        for (algorithm_identifier, common_name, parameters) in entries {
+            let id =
+                match Self::try_insert(db, algorithm_identifier, *common_name, parameters).await {
+                    Ok(inserted) => inserted.id,
+                    Err(e) if e.code == Errcode::Duplicate => {
+                        Self::get_by_query(db, None, None, Some(algorithm_identifier), parameters)
+                            .await?
+                            .first()
+                            .map(Self::id)
+                            .ok_or(e)?
+                    }
+                    Err(e) => return Err(e),
+                };
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use sqlx::{Pool, Postgres};
+
+    use super::*;
+
+    #[sqlx::test(fixtures("../../fixtures/idcert_integration_tests.sql"))]
+    async fn test_list_all(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let rsa = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+        // The fixture inserts explicit IDs, which the ID sequence does not know about
+        query!(
+            "INSERT INTO algorithm_identifiers (id, algorithm_identifier, common_name)
+            VALUES (4, $1, 'RSA encryption')",
+            rsa.to_string()
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        // Parameters outside of the byte range cannot be DER-encoded
+        query!(
+            "INSERT INTO algorithm_identifiers (id, algorithm_identifier, common_name, parameters_der_encoded)
+            VALUES (5, '1.3.101.113', 'Ed448', '{5, 300}')"
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+        let listed = AlgorithmIdentifier::list_all(&db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.algorithm_identifier.to_string(), entry.common_name))
+            .collect::<Vec<_>>();
+        // The fixture rows "rsaEncryption" and "id-ecPublicKey" are no valid OIDs
+        // and are skipped, as is the row with the invalid parameters
+        assert_eq!(
+            listed,
+            vec![
+                (
+                    "1.3.101.112".to_owned(),
+                    Some("Edwards-curve Digital Signature Algorithm (EdDSA) Ed25519".to_owned())
+                ),
+                (rsa.to_string(), Some("RSA encryption".to_owned())),
+            ]
+        );
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_unsupported_error_names_algorithm(pool: Pool<Postgres>) {
+        let ed25519 = ObjectIdentifier::new_unwrap("1.3.101.112");
+
+        let error = AlgorithmIdentifier::unsupported_error_on(
+            &mut pool.acquire().await.unwrap(),
+            Some("algorithm"),
+            &ed25519,
+            "The key uses an unsupported algorithm",
+        )
+        .await;
+        assert_eq!(error.code, Errcode::IllegalInput);
+        let context = error.context.unwrap();
+        assert_eq!(context.field_name, "algorithm");
+        assert_eq!(context.found, ed25519.to_string());
+        // Ed25519 is not in the fixture
+        assert_eq!(context.expected, "id-ecPublicKey, rsaEncryption");
+    }
+
+    #[sqlx::test]
+    async fn test_ensure_all(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let ed25519 = ObjectIdentifier::new_unwrap("1.3.101.112");
+        let p256 = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2");
+        let entries: [(ObjectIdentifier, Option<&str>, &[u8]); 2] =
+            [(ed25519, Some("Ed25519"), &[]), (p256, Some("P-256"), &[])];
+
+        let ids = AlgorithmIdentifier::ensure_all(&db, &entries).await.unwrap();
+        assert_eq!(ids.len(), 2);
+        assert_ne!(ids.first(), ids.get(1));
+        assert_eq!(AlgorithmIdentifier::ensure_all(&db, &entries).await.unwrap(), ids);
+        assert_eq!(AlgorithmIdentifier::list_all(&db).await.unwrap().len(), 2);
+
+        // Entries can be added later on, without changing the IDs of the others
+        let rsa = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+        let extended = AlgorithmIdentifier::ensure_all(
+            &db,
+            &[(rsa, Some("RSA encryption"), &[]), (ed25519, Some("Ed25519"), &[])],
+        )
+        .await
+        .unwrap();
+        assert_eq!(extended.get(1), ids.first());
+
+        // A different OID with an existing common name is a conflict
+        let error = AlgorithmIdentifier::ensure_all(
+            &db,
+            &[(ObjectIdentifier::new_unwrap("1.3.101.113"), Some("Ed25519"), &[])],
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, Errcode::Duplicate);
+    }
 }
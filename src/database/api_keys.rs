@@ -4,13 +4,18 @@
 
 use std::ops::Deref;
 
+use chrono::NaiveDateTime;
 use rand::{
     distr::{Alphanumeric, SampleString},
     prelude::ThreadRng,
 };
-use sqlx::query;
+use sqlx::{query, query_as};
 
-use crate::{StdError, database::Database, errors::Error};
+use crate::{
+    StdError,
+    database::Database,
+    errors::{Context, Errcode, Error},
+};
 
 /// Constant used to determine how long auto-generated tokens are supposed to
 /// be.
@@ -53,6 +58,44 @@ impl ApiKey {
         &self.token
     }
 
+    /// Whether `token` is an active API key stored in the database, meaning
+    /// that it has neither been revoked nor expired.
+    pub(crate) async fn is_active_in_database(db: &Database, token: &str) -> Result<bool, Error> {
+        Ok(query!(
+            r#"SELECT EXISTS (
+                SELECT 1 FROM api_keys
+                WHERE token = $1 AND NOT revoked AND (expires_at IS NULL OR expires_at > NOW())
+            ) AS "exists!""#,
+            token
+        )
+        .fetch_one(&db.pool)
+        .await?
+        .exists)
+    }
+
+    /// Revoke the API key `token`, so that it does not authenticate anymore.
+    /// Revoking a key which has been revoked already does nothing.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an [Errcode::IllegalInput]-type error, if `token` is not an API
+    /// key stored in the database. Other than that, this method will error, if
+    /// something is wrong with the Database or Database connection.
+    pub async fn revoke(db: &Database, token: &str) -> Result<(), Error> {
+        match query!("UPDATE api_keys SET revoked = TRUE WHERE token = $1", token)
+            .execute(&db.pool)
+            .await?
+            .rows_affected()
+        {
+            // The token is deliberately not included in the error
+            0 => Err(Error::new(
+                Errcode::IllegalInput,
+                Some(Context::new(Some("token"), None, Some("An existing API key"), None)),
+            )),
+            _ => Ok(()),
+        }
+    }
+
     /// Generates a new, random [ApiKey] which is [STANDARD_TOKEN_LENGTH]
     /// characters in length.
     pub fn new_random(rng: &mut ThreadRng) -> Self {
@@ -72,12 +115,50 @@ pub(crate) async fn add_api_key_to_database(
     token: &str,
     database: &Database,
 ) -> Result<ApiKey, Error> {
-    let key = ApiKey::new(token).map_err(|_| Error::new(crate::errors::Errcode::Internal, None))?;
+    let key = ApiKey::new(token).map_err(|_| Error::new(Errcode::Internal, None))?;
     query!("INSERT INTO api_keys (token) VALUES ($1)", key.token()).execute(&database.pool).await?;
     Ok(key)
 }
 
+/// Count the active API keys stored in the database, which have neither been
+/// revoked nor expired.
+pub(crate) async fn count_active_api_keys(database: &Database) -> Result<i64, Error> {
+    Ok(query!(
+        r#"SELECT COUNT(*) AS "count!" FROM api_keys
+        WHERE NOT revoked AND (expires_at IS NULL OR expires_at > NOW())"#
+    )
+    .fetch_one(&database.pool)
+    .await?
+    .count)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Information about an [ApiKey] stored in the database. Deliberately does not
+/// contain the token itself.
+pub(crate) struct ApiKeyInfo {
+    /// The ID of the key in the `api_keys` table.
+    pub id: i32,
+    /// When the key was created. `None` for keys created before this
+    /// information was recorded.
+    pub created_at: Option<NaiveDateTime>,
+    /// Whether the key has been revoked.
+    pub revoked: bool,
+    /// When the key expires. `None` for keys which do not expire.
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+/// List all API keys stored in the database, oldest first.
+pub(crate) async fn list_api_keys(database: &Database) -> Result<Vec<ApiKeyInfo>, Error> {
+    Ok(query_as!(
+        ApiKeyInfo,
+        "SELECT id, created_at, revoked, expires_at FROM api_keys ORDER BY id"
+    )
+    .fetch_all(&database.pool)
+    .await?)
+}
+
 #[cfg(test)]
+#[allow(clippy::unwrap_used)]
 mod test {
     use rand::rng;
     use sqlx::{Pool, Postgres};
@@ -102,4 +183,78 @@ mod test {
         let key = ApiKey::new_random(&mut rng());
         assert!(add_api_key_to_database(key.token(), &Database { pool: db }).await.is_ok());
     }
+
+    #[sqlx::test]
+    async fn known_key_in_db(db: Pool<Postgres>) {
+        let database = Database { pool: db };
+        let key = ApiKey::new_random(&mut rng());
+        assert!(!ApiKey::is_active_in_database(&database, key.token()).await.unwrap());
+        add_api_key_to_database(key.token(), &database).await.unwrap();
+        assert!(ApiKey::is_active_in_database(&database, key.token()).await.unwrap());
+    }
+
+    #[sqlx::test]
+    async fn count_keys_in_db(db: Pool<Postgres>) {
+        let database = Database { pool: db };
+        assert_eq!(count_active_api_keys(&database).await.unwrap(), 0);
+        let key = ApiKey::new_random(&mut rng());
+        add_api_key_to_database(key.token(), &database).await.unwrap();
+        assert_eq!(count_active_api_keys(&database).await.unwrap(), 1);
+        ApiKey::revoke(&database, key.token()).await.unwrap();
+        assert_eq!(count_active_api_keys(&database).await.unwrap(), 0);
+    }
+
+    #[sqlx::test]
+    async fn revoke_key(db: Pool<Postgres>) {
+        let database = Database { pool: db };
+        let key = ApiKey::new_random(&mut rng());
+        add_api_key_to_database(key.token(), &database).await.unwrap();
+
+        ApiKey::revoke(&database, key.token()).await.unwrap();
+        assert!(!ApiKey::is_active_in_database(&database, key.token()).await.unwrap());
+        // Revoking twice is fine, revoking an unknown key is not
+        ApiKey::revoke(&database, key.token()).await.unwrap();
+        let error =
+            ApiKey::revoke(&database, ApiKey::new_random(&mut rng()).token()).await.unwrap_err();
+        assert_eq!(error.code, Errcode::IllegalInput);
+        assert!(list_api_keys(&database).await.unwrap().iter().all(|key| key.revoked));
+    }
+
+    #[sqlx::test]
+    async fn expired_key(db: Pool<Postgres>) {
+        let database = Database { pool: db };
+        let expired = ApiKey::new_random(&mut rng());
+        let expiring = ApiKey::new_random(&mut rng());
+        for (key, expires_in) in [(&expired, "-1 minute"), (&expiring, "1 hour")] {
+            add_api_key_to_database(key.token(), &database).await.unwrap();
+            query!(
+                "UPDATE api_keys SET expires_at = NOW() + $1::TEXT::INTERVAL WHERE token = $2",
+                expires_in,
+                key.token()
+            )
+            .execute(&database.pool)
+            .await
+            .unwrap();
+        }
+
+        assert!(!ApiKey::is_active_in_database(&database, expired.token()).await.unwrap());
+        assert!(ApiKey::is_active_in_database(&database, expiring.token()).await.unwrap());
+        assert_eq!(count_active_api_keys(&database).await.unwrap(), 1);
+    }
+
+    #[sqlx::test]
+    async fn list_keys_in_db(db: Pool<Postgres>) {
+        let database = Database { pool: db };
+        assert!(list_api_keys(&database).await.unwrap().is_empty());
+        for _ in 0..2 {
+            add_api_key_to_database(ApiKey::new_random(&mut rng()).token(), &database)
+                .await
+                .unwrap();
+        }
+        let keys = list_api_keys(&database).await.unwrap();
+        assert_eq!(keys.len(), 2);
+        assert!(keys.is_sorted_by_key(|key| key.id));
+        assert!(keys.iter().all(|key| key.created_at.is_some()));
+        assert!(keys.iter().all(|key| !key.revoked && key.expires_at.is_none()));
+    }
 }
@@ -0,0 +1,174 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use polyproto::{
+    certs::capabilities::{
+        BasicConstraints, KeyUsage, KeyUsages, OID_BASIC_CONSTRAINTS, OID_KEY_USAGE,
+    },
+    der::Decode,
+};
+use x509_cert::ext::Extensions;
+
+use crate::errors::{Context, Errcode, Error};
+
+/// Every [KeyUsage] together with the name it is stored as in the
+/// `idcsr.key_usages` column. The names are those of the X.509 specification,
+/// in snake case.
+const KEY_USAGE_NAMES: [(KeyUsage, &str); 9] = [
+    (KeyUsage::DigitalSignature, "digital_signature"),
+    (KeyUsage::ContentCommitment, "content_commitment"),
+    (KeyUsage::KeyEncipherment, "key_encipherment"),
+    (KeyUsage::DataEncipherment, "data_encipherment"),
+    (KeyUsage::KeyAgreement, "key_agreement"),
+    (KeyUsage::KeyCertSign, "key_cert_sign"),
+    (KeyUsage::CrlSign, "crl_sign"),
+    (KeyUsage::EncipherOnly, "encipher_only"),
+    (KeyUsage::DecipherOnly, "decipher_only"),
+];
+
+/// The name a [KeyUsage] is stored as. See [KEY_USAGE_NAMES].
+pub(crate) fn key_usage_name(key_usage: KeyUsage) -> &'static str {
+    KEY_USAGE_NAMES
+        .iter()
+        .find(|(usage, _)| *usage == key_usage)
+        .map(|(_, name)| *name)
+        .unwrap_or_default()
+}
+
+/// The [KeyUsage] stored as `name`, if it is a known one. See
+/// [KEY_USAGE_NAMES].
+pub(crate) fn key_usage_by_name(name: &str) -> Option<KeyUsage> {
+    KEY_USAGE_NAMES.iter().find(|(_, usage_name)| *usage_name == name).map(|(usage, _)| *usage)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// The extensions of an ID-CSR, parsed into the ones relevant to polyproto.
+/// Stored in normalized form next to the raw, hex-encoded DER of the
+/// extensions in the `idcsr` table.
+pub(crate) struct CsrExtensions {
+    /// The [KeyUsage]s of the `keyUsage` extension, sorted. Empty, if the
+    /// extension is absent.
+    pub(crate) key_usages: Vec<KeyUsage>,
+    /// The `basicConstraints` extension, if present.
+    pub(crate) basic_constraints: Option<BasicConstraints>,
+    /// The object identifiers of all other extensions, which are stored in the
+    /// raw copy only.
+    pub(crate) unrecognized: Vec<String>,
+}
+
+impl CsrExtensions {
+    /// Parse the hex-encoded DER of the [Extensions] of an ID-CSR, as stored
+    /// in the `idcsr.extensions` column.
+    ///
+    /// ## Errors
+    ///
+    /// [Errcode::IllegalInput], if `der_hex` is not hex-encoded DER, or if the
+    /// `keyUsage` or `basicConstraints` extension is malformed.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn from_der_hex(der_hex: &str) -> Result<Self, Error> {
+        let extensions = hex::decode(der_hex)
+            .ok()
+            .and_then(|der| Extensions::from_der(&der).ok())
+            .ok_or_else(|| malformed("Hex-encoded DER of the extensions of an ID-CSR"))?;
+        Self::parse(&extensions)
+    }
+
+    /// Sort the `extensions` into the recognized ones and the others.
+    ///
+    /// ## Errors
+    ///
+    /// [Errcode::IllegalInput], if the `keyUsage` or `basicConstraints`
+    /// extension is malformed.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn parse(extensions: &Extensions) -> Result<Self, Error> {
+        let mut parsed = Self::default();
+        for extension in extensions {
+            match extension.extn_id.to_string().as_str() {
+                OID_KEY_USAGE => {
+                    parsed.key_usages = KeyUsages::try_from(extension.clone())
+                        .map_err(|_| malformed("A well-formed keyUsage extension"))?
+                        .key_usages;
+                    parsed.key_usages.sort();
+                    parsed.key_usages.dedup();
+                }
+                OID_BASIC_CONSTRAINTS => {
+                    parsed.basic_constraints = Some(
+                        BasicConstraints::try_from(extension.clone())
+                            .map_err(|_| malformed("A well-formed basicConstraints extension"))?,
+                    );
+                }
+                oid => parsed.unrecognized.push(oid.to_owned()),
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// The names of the [Self::key_usages], as stored in the `idcsr.key_usages`
+    /// column.
+    pub(crate) fn key_usage_names(&self) -> Vec<String> {
+        self.key_usages.iter().map(|usage| key_usage_name(*usage).to_owned()).collect()
+    }
+}
+
+/// An [Errcode::IllegalInput] error for malformed `extensions`.
+fn malformed(expected: &str) -> Error {
+    Error::new(
+        Errcode::IllegalInput,
+        Some(Context::new(Some("extensions"), None, Some(expected), None)),
+    )
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use polyproto::{
+        certs::capabilities::Capabilities,
+        der::{Encode, asn1::OctetString},
+        spki::ObjectIdentifier,
+    };
+    use x509_cert::ext::Extension;
+
+    use super::*;
+
+    #[test]
+    fn test_key_usage_names() {
+        for (usage, name) in KEY_USAGE_NAMES {
+            assert_eq!(key_usage_name(usage), name);
+            assert_eq!(key_usage_by_name(name), Some(usage));
+        }
+        assert_eq!(key_usage_by_name("digitalSignature"), None);
+    }
+
+    #[test]
+    fn test_parse_known_extensions() {
+        let mut extensions = Extensions::try_from(Capabilities::default_home_server()).unwrap();
+        extensions.push(Extension {
+            extn_id: ObjectIdentifier::new_unwrap("1.2.3.4"),
+            critical: false,
+            extn_value: OctetString::new(vec![0x05, 0x00]).unwrap(),
+        });
+        let der_hex = hex::encode(extensions.to_der().unwrap());
+
+        let parsed = CsrExtensions::from_der_hex(&der_hex).unwrap();
+        assert_eq!(parsed.key_usages, vec![KeyUsage::KeyCertSign]);
+        assert_eq!(parsed.key_usage_names(), vec!["key_cert_sign".to_owned()]);
+        assert!(parsed.basic_constraints.unwrap().ca);
+        assert_eq!(parsed.unrecognized, vec!["1.2.3.4".to_owned()]);
+
+        let parsed =
+            CsrExtensions::parse(&Extensions::try_from(Capabilities::default_actor()).unwrap())
+                .unwrap();
+        assert_eq!(parsed.key_usages, vec![KeyUsage::DigitalSignature]);
+        assert!(!parsed.basic_constraints.unwrap().ca);
+        assert!(parsed.unrecognized.is_empty());
+    }
+
+    #[test]
+    fn test_parse_malformed_extensions() {
+        for der_hex in ["not hex", "00ff"] {
+            let error = CsrExtensions::from_der_hex(der_hex).unwrap_err();
+            assert_eq!(error.code, Errcode::IllegalInput);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use polyproto::types::FederationId;
+use sqlx::{query, query_as, types::Uuid};
+
+use crate::{
+    database::Database,
+    errors::{Context, Errcode, Error},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An actor from another home server, which has been discovered through
+/// federation, along with a cached copy of its ID-Cert. The public keys of
+/// foreign actors are stored as [PublicKeyInfo](super::PublicKeyInfo)s
+/// without a `uaid`.
+pub(crate) struct ForeignActor {
+    /// The unique actor identifier this home server has assigned to the actor.
+    pub(crate) uaid: Uuid,
+    /// The federation ID of the actor, such as `alice@example.com`.
+    pub(crate) federation_id: String,
+    /// The domain of the home server of the actor.
+    pub(crate) domain: String,
+    /// The PEM-encoded ID-Cert of the actor.
+    pub(crate) idcert_pem: String,
+    /// When the ID-Cert has last been fetched from the home server of the
+    /// actor.
+    pub(crate) cached_at: NaiveDateTime,
+}
+
+impl ForeignActor {
+    /// Cache the `idcert_pem` of the actor identified by `federation_id`. If
+    /// the actor is not known yet, it is created with a new `uaid`; otherwise,
+    /// its cached ID-Cert is replaced and [Self::cached_at] is reset.
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if something is wrong with the Database or Database
+    /// connection.
+    pub(crate) async fn upsert(
+        db: &Database,
+        federation_id: &FederationId,
+        idcert_pem: &str,
+    ) -> Result<Self, Error> {
+        let federation_id = federation_id.to_string();
+        // A FederationId is split at its first `@`, which is also where its
+        // `Display` implementation puts it
+        let Some((_, domain)) = federation_id.split_once('@') else {
+            return Err(Error::new(
+                Errcode::IllegalInput,
+                Some(Context::new(
+                    Some("federation_id"),
+                    Some(&federation_id),
+                    Some("A federation ID of the form local_name@domain"),
+                    None,
+                )),
+            ));
+        };
+        let cached_at = Utc::now().naive_utc();
+        let mut transaction = db.pool.begin().await?;
+        // A new actor is only created, if the federation ID is not known yet
+        let record = query!(
+            r#"WITH existing AS (
+                SELECT uaid FROM foreign_actors WHERE federation_id = $1
+            ), new_actor AS (
+                INSERT INTO actors (type)
+                SELECT 'foreign'::actor_type WHERE NOT EXISTS (SELECT 1 FROM existing)
+                RETURNING uaid
+            )
+            INSERT INTO foreign_actors (uaid, federation_id, domain, idcert_pem, cached_at)
+            SELECT uaid, $1, $2, $3, $4
+            FROM (SELECT uaid FROM existing UNION ALL SELECT uaid FROM new_actor) AS actor
+            ON CONFLICT (federation_id) DO UPDATE
+            SET idcert_pem = EXCLUDED.idcert_pem, cached_at = EXCLUDED.cached_at
+            RETURNING uaid, federation_id, domain, idcert_pem, cached_at,
+                (SELECT uaid FROM new_actor) AS created_uaid"#,
+            federation_id,
+            domain,
+            idcert_pem,
+            cached_at
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+        // A concurrent upsert of the same federation ID has won the race, so
+        // the actor created by this one is not needed
+        if let Some(created_uaid) = record.created_uaid
+            && created_uaid != record.uaid
+        {
+            query!("DELETE FROM actors WHERE uaid = $1", created_uaid)
+                .execute(&mut *transaction)
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(ForeignActor {
+            uaid: record.uaid,
+            federation_id: record.federation_id,
+            domain: record.domain,
+            idcert_pem: record.idcert_pem,
+            cached_at: record.cached_at,
+        })
+    }
+
+    /// Get the cached [ForeignActor] identified by `federation_id`, if there
+    /// is one.
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if something is wrong with the Database or Database
+    /// connection.
+    pub(crate) async fn by_federation_id(
+        db: &Database,
+        federation_id: &FederationId,
+    ) -> Result<Option<Self>, Error> {
+        Ok(query_as!(
+            ForeignActor,
+            "SELECT uaid, federation_id, domain, idcert_pem, cached_at
+            FROM foreign_actors
+            WHERE federation_id = $1",
+            federation_id.to_string()
+        )
+        .fetch_optional(&db.pool)
+        .await?)
+    }
+
+    /// Whether the cached ID-Cert is older than `max_age` at `now`, and should
+    /// be fetched from the home server of the actor again.
+    pub(crate) fn is_stale(&self, max_age: TimeDelta, now: NaiveDateTime) -> bool {
+        now.signed_duration_since(self.cached_at) > max_age
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use sqlx::{Pool, Postgres};
+
+    use super::*;
+
+    fn alice() -> FederationId {
+        FederationId::new("alice@foreign.example.com").unwrap()
+    }
+
+    #[sqlx::test]
+    async fn test_upsert_then_fetch(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        assert!(ForeignActor::by_federation_id(&db, &alice()).await.unwrap().is_none());
+
+        let inserted = ForeignActor::upsert(&db, &alice(), "first cert").await.unwrap();
+        assert_eq!(inserted.federation_id, "alice@foreign.example.com");
+        assert_eq!(inserted.domain, "foreign.example.com");
+        let fetched = ForeignActor::by_federation_id(&db, &alice()).await.unwrap().unwrap();
+        assert_eq!(fetched, inserted);
+        let actor_type = query!(
+            r#"SELECT type AS "actor_type: String" FROM actors WHERE uaid = $1"#,
+            inserted.uaid
+        )
+        .fetch_one(&db.pool)
+        .await
+        .unwrap()
+        .actor_type;
+        assert_eq!(actor_type, "foreign");
+
+        // Upserting again refreshes the cache, but keeps the uaid
+        let updated = ForeignActor::upsert(&db, &alice(), "second cert").await.unwrap();
+        assert_eq!(updated.uaid, inserted.uaid);
+        assert_eq!(updated.idcert_pem, "second cert");
+        assert!(updated.cached_at >= inserted.cached_at);
+        assert_eq!(ForeignActor::by_federation_id(&db, &alice()).await.unwrap().unwrap(), updated);
+    }
+
+    #[sqlx::test]
+    async fn test_concurrent_upserts_create_one_actor(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let alice = alice();
+        let (first, second) = tokio::join!(
+            ForeignActor::upsert(&db, &alice, "first cert"),
+            ForeignActor::upsert(&db, &alice, "second cert")
+        );
+        assert_eq!(first.unwrap().uaid, second.unwrap().uaid);
+        let foreign_actors =
+            query!(r#"SELECT COUNT(*) AS "count!" FROM actors WHERE type = 'foreign'"#)
+                .fetch_one(&db.pool)
+                .await
+                .unwrap()
+                .count;
+        assert_eq!(foreign_actors, 1);
+    }
+
+    #[sqlx::test]
+    async fn test_is_stale(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let foreign_actor = ForeignActor::upsert(&db, &alice(), "cert").await.unwrap();
+        let max_age = TimeDelta::hours(1);
+
+        let after = |delta| foreign_actor.cached_at.checked_add_signed(delta).unwrap();
+
+        assert!(!foreign_actor.is_stale(max_age, foreign_actor.cached_at));
+        assert!(!foreign_actor.is_stale(max_age, after(max_age)));
+        assert!(foreign_actor.is_stale(max_age, after(TimeDelta::hours(2))));
+
+        query!(
+            "UPDATE foreign_actors SET cached_at = cached_at - INTERVAL '2 hours' WHERE uaid = $1",
+            foreign_actor.uaid
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        let fetched = ForeignActor::by_federation_id(&db, &alice()).await.unwrap().unwrap();
+        assert!(fetched.is_stale(max_age, Utc::now().naive_utc()));
+    }
+}
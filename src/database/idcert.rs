@@ -1,23 +1,230 @@
 use std::fmt::Debug;
 
 use chrono::NaiveDateTime;
-use log::{debug, error, warn};
+use log::error;
 use polyproto::{
-    certs::{PublicKeyInfo, idcert::IdCert},
-    der::Encode,
+    certs::{
+        PublicKeyInfo, SessionId,
+        capabilities::{BasicConstraints, KeyUsage},
+        idcert::IdCert,
+    },
     key::PublicKey,
     signature::Signature,
     types::DomainName,
 };
-use sqlx::{query, types::Uuid};
+use sqlx::{query, query_as, types::Uuid};
 
 use crate::{
-    database::{AlgorithmIdentifier, Database, Issuer, SerialNumber},
-    errors::{ALGORITHM_IDENTIFER_TO_DER_ERROR_MESSAGE, Context, Error},
+    database::{CsrExtensions, Database, SerialNumber, key_usage_by_name, key_usage_name},
+    errors::Error,
 };
 
 pub(crate) struct HomeServerCert;
 
+#[derive(Debug, Clone)]
+/// The values of a newly issued ID-Cert and the ID-CSR it has been issued for,
+/// as stored in the `idcsr` and `idcert` tables. See
+/// [super::PublicKeyInfo::insert_with_cert].
+pub(crate) struct NewIdCert {
+    /// Serial number of the ID-Cert.
+    pub(crate) serial_number: SerialNumber,
+    /// Session ID of the ID-CSR, identifying the client of the actor it has
+    /// been submitted by. Only one ID-Cert, which has not been invalidated,
+    /// can exist per session of an actor.
+    pub(crate) session_id: SessionId,
+    /// Signature of the ID-CSR, made by the subject.
+    pub(crate) subject_signature: String,
+    /// Extensions of the ID-CSR.
+    pub(crate) extensions: String,
+    /// PEM encoding of the ID-CSR.
+    pub(crate) csr_pem: String,
+    /// Start of the validity period of the ID-Cert.
+    pub(crate) valid_not_before: NaiveDateTime,
+    /// End of the validity period of the ID-Cert.
+    pub(crate) valid_not_after: NaiveDateTime,
+    /// ID of the issuer in the `issuers` table.
+    pub(crate) issuer_info_id: i64,
+    /// ID of the home server public key in the `public_keys` table, which has
+    /// been used to sign the ID-Cert.
+    pub(crate) home_server_public_key_id: i64,
+    /// Signature of the ID-Cert, made by the home server.
+    pub(crate) home_server_signature: String,
+    /// PEM encoding of the ID-Cert.
+    pub(crate) cert_pem: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// An ID-CSR as stored in the `idcsr` table.
+pub(crate) struct StoredIdCsr {
+    /// ID of the ID-CSR, which is also the ID of the ID-Cert issued for it.
+    pub(crate) id: i64,
+    /// Serial number of the ID-Cert issued for the ID-CSR.
+    pub(crate) serial_number: SerialNumber,
+    /// The actor, which has submitted the ID-CSR, if it is a local actor.
+    pub(crate) uaid: Option<Uuid>,
+    /// ID of the subject public key in the `public_keys` table.
+    pub(crate) subject_public_key_id: i64,
+    /// Signature of the ID-CSR, made by the subject.
+    pub(crate) subject_signature: String,
+    /// Session ID of the ID-CSR.
+    pub(crate) session_id: String,
+    /// Start of the requested validity period.
+    pub(crate) valid_not_before: Option<NaiveDateTime>,
+    /// End of the requested validity period.
+    pub(crate) valid_not_after: Option<NaiveDateTime>,
+    /// Extensions of the ID-CSR, as hex-encoded DER.
+    pub(crate) extensions: String,
+    /// The [Self::extensions], parsed. `None` for ID-CSRs stored before
+    /// extensions have been parsed.
+    pub(crate) parsed_extensions: Option<CsrExtensions>,
+    /// PEM encoding of the ID-CSR.
+    pub(crate) pem_encoded: String,
+    /// ID of the `invalidated_certs` entry, if the ID-Cert has been
+    /// invalidated.
+    pub(crate) invalidation_info: Option<i64>,
+}
+
+/// A row of the `idcsr` table, from which a [StoredIdCsr] is built.
+struct IdCsrRow {
+    /// See [StoredIdCsr::id].
+    id: i64,
+    /// See [StoredIdCsr::serial_number].
+    serial_number: SerialNumber,
+    /// See [StoredIdCsr::uaid].
+    uaid: Option<Uuid>,
+    /// See [StoredIdCsr::subject_public_key_id].
+    subject_public_key_id: i64,
+    /// See [StoredIdCsr::subject_signature].
+    subject_signature: String,
+    /// See [StoredIdCsr::session_id].
+    session_id: String,
+    /// See [StoredIdCsr::valid_not_before].
+    valid_not_before: Option<NaiveDateTime>,
+    /// See [StoredIdCsr::valid_not_after].
+    valid_not_after: Option<NaiveDateTime>,
+    /// See [StoredIdCsr::extensions].
+    extensions: String,
+    /// Names of the key usages of the `keyUsage` extension, if parsed.
+    key_usages: Option<Vec<String>>,
+    /// The `cA` flag of the `basicConstraints` extension, if present.
+    basic_constraints_ca: Option<bool>,
+    /// The `pathLenConstraint` of the `basicConstraints` extension, if present.
+    basic_constraints_path_length: Option<i64>,
+    /// OIDs of the extensions which have not been recognized, if parsed.
+    unrecognized_extensions: Option<Vec<String>>,
+    /// See [StoredIdCsr::pem_encoded].
+    pem_encoded: String,
+    /// See [StoredIdCsr::invalidation_info].
+    invalidation_info: Option<i64>,
+}
+
+impl From<IdCsrRow> for StoredIdCsr {
+    fn from(row: IdCsrRow) -> Self {
+        // The parsed columns are either all set, or all NULL
+        let parsed_extensions =
+            row.key_usages.zip(row.unrecognized_extensions).map(|(key_usages, unrecognized)| {
+                CsrExtensions {
+                    key_usages: key_usages
+                        .iter()
+                        .filter_map(|name| key_usage_by_name(name))
+                        .collect(),
+                    basic_constraints: row.basic_constraints_ca.map(|ca| BasicConstraints {
+                        ca,
+                        path_length: row
+                            .basic_constraints_path_length
+                            .and_then(|path_length| u64::try_from(path_length).ok()),
+                    }),
+                    unrecognized,
+                }
+            });
+        Self {
+            id: row.id,
+            serial_number: row.serial_number,
+            uaid: row.uaid,
+            subject_public_key_id: row.subject_public_key_id,
+            subject_signature: row.subject_signature,
+            session_id: row.session_id,
+            valid_not_before: row.valid_not_before,
+            valid_not_after: row.valid_not_after,
+            extensions: row.extensions,
+            parsed_extensions,
+            pem_encoded: row.pem_encoded,
+            invalidation_info: row.invalidation_info,
+        }
+    }
+}
+
+impl StoredIdCsr {
+    /// Get the ID-CSR, for which the ID-Cert with the given `serial_number`
+    /// has been issued. Returns `Ok(None)`, if no such ID-CSR exists.
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if something is wrong with the Database or Database
+    /// connection.
+    pub(crate) async fn by_serial(
+        db: &Database,
+        serial_number: &SerialNumber,
+    ) -> Result<Option<Self>, Error> {
+        Ok(query_as!(
+            IdCsrRow,
+            r#"
+            SELECT id, serial_number AS "serial_number: SerialNumber", uaid, subject_public_key_id,
+                subject_signature, session_id, valid_not_before, valid_not_after, extensions,
+                key_usages, basic_constraints_ca, basic_constraints_path_length,
+                unrecognized_extensions, pem_encoded, invalidation_info
+            FROM idcsr
+            WHERE serial_number = $1
+            "#,
+            serial_number.as_bigdecimal()
+        )
+        .fetch_optional(&db.pool)
+        .await?
+        .map(StoredIdCsr::from))
+    }
+
+    /// Get all ID-CSRs, whose `keyUsage` extension contains `key_usage`,
+    /// ordered by their ID. ID-CSRs stored before extensions have been parsed
+    /// are never returned.
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if something is wrong with the Database or Database
+    /// connection.
+    pub(crate) async fn by_key_usage(
+        db: &Database,
+        key_usage: KeyUsage,
+    ) -> Result<Vec<Self>, Error> {
+        Ok(query_as!(
+            IdCsrRow,
+            r#"
+            SELECT id, serial_number AS "serial_number: SerialNumber", uaid, subject_public_key_id,
+                subject_signature, session_id, valid_not_before, valid_not_after, extensions,
+                key_usages, basic_constraints_ca, basic_constraints_path_length,
+                unrecognized_extensions, pem_encoded, invalidation_info
+            FROM idcsr
+            WHERE key_usages @> ARRAY[$1]
+            ORDER BY id
+            "#,
+            key_usage_name(key_usage)
+        )
+        .fetch_all(&db.pool)
+        .await?
+        .into_iter()
+        .map(StoredIdCsr::from)
+        .collect())
+    }
+}
+
+/// An ID-Cert as stored in the `idcert` table.
+struct StoredIdCert {
+    /// PEM encoding of the ID-Cert.
+    pem_encoded: String,
+    /// ID of the home server public key in the `public_keys` table, which has
+    /// been used to sign the ID-Cert.
+    home_server_public_key_id: i64,
+}
+
 impl HomeServerCert {
     /// Try to get a [HomeServerCert] from the database, filtered by the
     /// [DomainName] and a [NaiveDateTime] timestamp, at which the certificate
@@ -27,47 +234,27 @@ impl HomeServerCert {
         issuer_domain_name: &DomainName,
         timestamp: &NaiveDateTime,
     ) -> Result<Option<IdCert<S, P>>, Error> {
-        let issuer_components =
-            issuer_domain_name.to_string().split('.').map(|s| s.to_owned()).collect::<Vec<_>>();
-        let Some(idcert_table_record) = query!(
-            r#"
-        WITH issuer AS (
-            SELECT id
-            FROM issuers
-            WHERE domain_components = $1
-        )
-        SELECT idcert.pem_encoded, idcert.home_server_public_key_id
-        FROM idcert
-        JOIN issuer i ON idcert.issuer_info_id = i.id
-        WHERE (
-            $2 >= valid_not_before AND $2 <= valid_not_after
-        )
-    "#,
-            issuer_components.as_slice(),
-            timestamp
-        )
-        .fetch_optional(&db.pool)
-        .await?
+        let Some(idcert_table_record) =
+            Self::get_stored_by(db, issuer_domain_name, None, timestamp).await?.into_iter().next()
         else {
             return Ok(None);
         };
 
-        let pem_encoded_pubkey_info = query!(
-            r#"
-        SELECT pubkey
-        FROM public_keys
-        WHERE id = $1
-    "#,
-            idcert_table_record.home_server_public_key_id
-        )
-        .fetch_one(&db.pool)
-        .await?;
+        let Some(home_server_public_key) =
+            super::PublicKeyInfo::by_id(db, idcert_table_record.home_server_public_key_id).await?
+        else {
+            error!(
+                "The public key {} of a home server certificate does not exist",
+                idcert_table_record.home_server_public_key_id
+            );
+            return Err(Error::new_internal_error(None));
+        };
         IdCert::from_pem(
             &idcert_table_record.pem_encoded,
             polyproto::certs::Target::HomeServer,
             timestamp.and_utc().timestamp() as u64,
             &P::try_from_public_key_info(
-                PublicKeyInfo::from_pem(&pem_encoded_pubkey_info.pubkey).map_err(|e| {
+                PublicKeyInfo::from_pem(&home_server_public_key.pubkey).map_err(|e| {
                     error!("Error parsing public key info: {e}");
                     Error::new_internal_error(None)
                 })?,
@@ -84,153 +271,93 @@ impl HomeServerCert {
         .map(Some)
     }
 
-    /// Insert an [IdCert] into the database without performing __any__
-    /// validation checks.
-    ///
-    /// This function bypasses certificate validation and directly inserts the
-    /// certificate into the database. It extracts the signature algorithm
-    /// information from the certificate and ensures it's supported by the
-    /// server before insertion.
-    ///
-    /// ## ⚠️ Warning ⚠️
-    ///
-    /// **Never** use this function standalone, without any additional
-    /// verification. At least the following things must be ensured, before
-    /// calling this function:
-    ///
-    /// 1. The entity trying to perform the INSERT operation is who they say
-    ///    they are, and are permitted to perform the operation
-    /// 2. The [IdCert] is valid and well-formed.
-    ///
-    /// Calling this function without proper verification will likely enable an
-    /// attack vector enabling hostile takeover of actor accounts or the entire
-    /// home server altogether. The method exists purely to be used in
-    /// conjunction with those steps.
-    ///
-    /// ## Returns
-    /// * `Ok(())` if the certificate was successfully inserted
-    /// * `Err(Error)` if the signature algorithm is unsupported or insertion
-    ///   fails
-    pub(super) async fn insert_idcert_unchecked<S: Signature + Debug, P: PublicKey<S> + Debug>(
+    /// Get the PEM encoding of the ID-Cert of the home server, issued by the
+    /// [DomainName] and valid at the [NaiveDateTime] timestamp. If there are
+    /// multiple such ID-Certs, the most recent one is returned.
+    pub(crate) async fn get_pem_by(
         db: &Database,
-        cert: IdCert<S, P>,
-        uaid: Option<&Uuid>,
-    ) -> Result<(), Error> {
-        let oid_signature_algo = S::algorithm_identifier().oid;
-        let params_signature_algo = match S::algorithm_identifier().parameters {
-            Some(params) => params.to_der().map_err(|e| {
-                error!("{ALGORITHM_IDENTIFER_TO_DER_ERROR_MESSAGE} {e}");
-                Error::new_internal_error(None)
-            })?,
-            None => Vec::new(),
-        };
-        let Some(algorithm_identifier) = AlgorithmIdentifier::get_by_query(
-            db,
-            None,
-            None,
-            Some(&oid_signature_algo),
-            &params_signature_algo,
-        )
-        .await?
-        .first() else {
-            return Err(Error::new(
-                crate::errors::Errcode::IllegalInput,
-                Some(Context::new(
-                    None,
-                    None,
-                    None,
-                    Some("ID-Cert contains cryptographic algorithms not supported by this server"),
-                )),
-            ));
-        };
-        #[allow(clippy::expect_used)]
-		// This event should never happen and, as far as I am aware, cannot be triggered by any
-		// user. As such, I see it ok to unwrap here.
-		let issuer = Issuer::get_own(db).await?.expect(
-			"The issuer entry for this sonata instance should have been added to the database on startup!",
-		);
-        let subject_public_key_pem = cert.id_cert_tbs.subject_public_key.public_key_info().to_pem(polyproto::der::pem::LineEnding::LF).map_err(|e| {
-             debug!("Received a public key which triggered an error when trying to convert it into PEM. Error: {e}; Public Key: {:?}", cert.id_cert_tbs.subject_public_key);
-            Error::new(crate::errors::Errcode::IllegalInput, Some(Context::new(None, None, None, Some("Public Key could not be converted to PEM representation"))))
-        })?;
-        let subject_key_algorithm_identifier =
-            match AlgorithmIdentifier::get_by_algorithm_identifier(
-                db,
-                &cert.id_cert_tbs.signature_algorithm,
-            )
+        issuer_domain_name: &DomainName,
+        timestamp: &NaiveDateTime,
+    ) -> Result<Option<String>, Error> {
+        Ok(Self::get_stored_by(db, issuer_domain_name, None, timestamp)
             .await?
-            {
-                Some(algo) => algo,
-                None => {
-                    return Err(Error::new(
-                        crate::errors::Errcode::IllegalInput,
-                        Some(Context::new(
-                            None,
-                            None,
-                            None,
-                            Some(
-                                "ID-Cert contains cryptographic algorithms not supported by this server",
-                            ),
-                        )),
-                    ));
-                }
-            };
-        let subject_public_keys = super::PublicKeyInfo::get_by(
-            db,
-            uaid.cloned(),
-            Some(subject_public_key_pem),
-            Some(subject_key_algorithm_identifier.id()),
-            None,
+            .into_iter()
+            .next()
+            .map(|stored| stored.pem_encoded))
+    }
+
+    /// Get the PEM encodings of all ID-Certs of the local actor called
+    /// `local_name`, issued by the [DomainName] and valid at the
+    /// [NaiveDateTime] timestamp, the most recent one first.
+    pub(crate) async fn get_actor_pems_by(
+        db: &Database,
+        issuer_domain_name: &DomainName,
+        local_name: &str,
+        timestamp: &NaiveDateTime,
+    ) -> Result<Vec<String>, Error> {
+        Ok(Self::get_stored_by(db, issuer_domain_name, Some(local_name), timestamp)
+            .await?
+            .into_iter()
+            .map(|stored| stored.pem_encoded)
+            .collect())
+    }
+
+    /// Get all ID-Certs issued by the [DomainName], which are valid at the
+    /// [NaiveDateTime] timestamp and have not been invalidated, the most
+    /// recent one first. The ID-Certs are those of the local actor called
+    /// `local_name`, or those of the home server itself, if `local_name` is
+    /// `None`.
+    async fn get_stored_by(
+        db: &Database,
+        issuer_domain_name: &DomainName,
+        local_name: Option<&str>,
+        timestamp: &NaiveDateTime,
+    ) -> Result<Vec<StoredIdCert>, Error> {
+        let issuer_components =
+            issuer_domain_name.to_string().split('.').map(|s| s.to_owned()).collect::<Vec<_>>();
+        Ok(query!(
+            r#"
+        SELECT idcert.pem_encoded, idcert.home_server_public_key_id
+        FROM idcert
+        JOIN issuers ON idcert.issuer_info_id = issuers.id
+        JOIN idcsr ON idcert.idcsr_id = idcsr.id
+        LEFT JOIN local_actors ON idcsr.uaid = local_actors.uaid
+        WHERE issuers.domain_components = $1
+        AND local_actors.local_name IS NOT DISTINCT FROM $2
+        AND (
+            $3 >= idcert.valid_not_before AND $3 <= idcert.valid_not_after
         )
-        .await?;
-        let subject_public_key = match subject_public_keys.len() {
-            0 => {
-                return Err(Error::new(
-                    crate::errors::Errcode::IllegalInput,
-                    Some(Context::new_message("Your public key is not known by this server.")),
-                ));
-            }
-            1 => subject_public_keys[0],
-            _ => {
-                warn!(
-                    "Subject public key with PEM encoding {} has multiple matching entries in the database",
-                    subject_public_key_pem
-                );
-                return Err(Error::new_internal_error(None));
-            }
-        };
-        let cert_serial = SerialNumber::from(cert.id_cert_tbs.serial_number.clone());
-        if query!(
-            "SELECT serial_number FROM idcsr WHERE serial_number = $1",
-            cert_serial.as_bigdecimal()
+        AND NOT EXISTS (
+            SELECT 1 FROM invalidated_certs WHERE invalidated_certs.cert_id = idcsr.id
         )
-        .fetch_optional(&db.pool)
+        ORDER BY idcert.valid_not_before DESC
+    "#,
+            issuer_components.as_slice(),
+            local_name,
+            timestamp
+        )
+        .fetch_all(&db.pool)
         .await?
-        .is_some()
-        {
-            debug!(
-                "ID-Cert with serial_number {} already exists; aborting",
-                cert_serial.as_bigdecimal()
-            );
-            return Err(Error::new_duplicate_error(Some(&format!(
-                "An ID-Cert with serial number {} already exists",
-                cert_serial.as_bigdecimal()
-            ))));
-        };
-        let pem_encoded = cert.clone().to_pem(polyproto::der::pem::LineEnding::LF).map_err(|e| {
-            debug!("Received a certificate which triggered an error when trying to convert it into PEM. Error: {e}; Certificate: {cert:?}");
-            Error::new(crate::errors::Errcode::IllegalInput, Some(Context::new(None, None, None, Some("Certificate could not be converted to PEM representation"))))
-        })?;
-        cert.id_cert_tbs.subject; // TODO WE NEED THE SUBJECT;
-        let record = query!("
-        INSERT INTO idcsr (serial_number, uaid, subject_public_key_id, subject_signature, session_id, valid_not_before, valid_not_after, extensions, pem_encoded) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING idcsr
-        ", cert_serial, uaid, subject_public_key.id(), cert.signature.as_hex(), );
-        // TODO: get pem_encoded string of cert and then I think we can actually insert
-        // it into the DB!
-        todo!()
+        .into_iter()
+        .map(|record| StoredIdCert {
+            pem_encoded: record.pem_encoded,
+            home_server_public_key_id: record.home_server_public_key_id,
+        })
+        .collect())
     }
 }
 
+/// Count the ID-Certs stored in the `idcert` table, which have been issued by
+/// this home server, including expired ones.
+///
+/// ## Errors
+///
+/// Will error on Database connection issues and on other errors with the
+/// database, all of which are not in scope for this function to handle.
+pub(crate) async fn count_issued_idcerts(db: &Database) -> Result<i64, Error> {
+    Ok(query!(r#"SELECT COUNT(*) AS "count!" FROM idcert"#).fetch_one(&db.pool).await?.count)
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::{NaiveDate, Utc};
@@ -287,6 +414,29 @@ mod tests {
         }
     }
 
+    #[sqlx::test(fixtures("../../fixtures/full_state.sql"))]
+    async fn test_stored_idcsr_by_serial(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let serial_number =
+            SerialNumber::from(sqlx::types::BigDecimal::from(90_000_000_000_000_001_002_i128));
+
+        let csr = StoredIdCsr::by_serial(&db, &serial_number).await.unwrap().unwrap();
+        assert_eq!(csr.id, 1002);
+        assert_eq!(csr.serial_number, serial_number);
+        assert_eq!(csr.uaid, Some(Uuid::from_u128(0x1002)));
+        assert_eq!(csr.subject_public_key_id, 1002);
+        assert_eq!(csr.subject_signature, "full_state_signature_bob");
+        assert_eq!(csr.session_id, "full_state_session_bob");
+        assert!(csr.valid_not_before < csr.valid_not_after);
+        assert_eq!(csr.extensions, "full_state_extensions_bob");
+        assert_eq!(csr.parsed_extensions, None);
+        assert_eq!(csr.pem_encoded, "full_state_csr_pem_bob");
+        assert_eq!(csr.invalidation_info, None);
+
+        let unknown = SerialNumber::from(sqlx::types::BigDecimal::from(1));
+        assert!(StoredIdCsr::by_serial(&db, &unknown).await.unwrap().is_none());
+    }
+
     #[sqlx::test(fixtures("../../fixtures/idcert_integration_tests.sql"))]
     async fn test_get_idcert_by_nonexistent_domain(pool: Pool<Postgres>) {
         setup_real_keys_mock_certs(&pool).await;
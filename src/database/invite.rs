@@ -1,10 +1,234 @@
-use sqlx::types::Uuid;
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-#[derive(sqlx::Decode, sqlx::Encode, sqlx::FromRow)]
+use sqlx::{PgConnection, query, query_as, types::Uuid};
+
+use crate::{
+    database::{Database, LocalActor},
+    errors::{Context, Errcode, Error},
+};
+
+#[serde_with::serde_as]
+#[derive(Debug, sqlx::Decode, sqlx::Encode, sqlx::FromRow, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Invite {
+    pub id: i64,
+    #[serde_as(as = "Option<serde_with::DisplayFromStr>")]
     pub invite_link_owner: Option<Uuid>,
     pub usages_current: i32,
     pub usages_maximum: i32,
     pub invite_code: String,
     pub invalid: bool,
 }
+
+impl Invite {
+    /// Consume one usage of the invite identified by `invite_code` on the given
+    /// connection, which is usually a transaction that the caller commits or
+    /// rolls back together with other changes, and return the invite with its
+    /// updated `usages_current`. Checking and consuming the invite happens in
+    /// one statement, so concurrent registrations cannot use an invite more
+    /// often than allowed.
+    ///
+    /// ## Errors
+    ///
+    /// - [Errcode::Unauthorized], if the invite does not exist, is invalid or
+    ///   has no usages left
+    /// - If something is wrong with the Database or Database connection
+    pub(super) async fn try_consume_on(
+        connection: &mut PgConnection,
+        invite_code: &str,
+    ) -> Result<Invite, Error> {
+        query_as!(
+            Invite,
+            "UPDATE invite_links
+            SET usages_current = usages_current + 1
+            WHERE invite = $1 AND invalid = FALSE AND usages_current < usages_maximum
+            RETURNING
+                id,
+                invite_link_owner,
+                usages_current,
+                usages_maximum,
+                invite AS invite_code,
+                invalid",
+            invite_code
+        )
+        .fetch_optional(&mut *connection)
+        .await?
+        .ok_or(Error::new(
+            Errcode::Unauthorized,
+            Some(Context::new(
+                Some("invite"),
+                Some(invite_code),
+                None,
+                Some("This invite does not exist or has already been used up"),
+            )),
+        ))
+    }
+}
+
+impl Database {
+    /// Consume one usage of the invite identified by `invite_code` and create a
+    /// new [LocalActor] in a single transaction. If the invite has an owner,
+    /// the invitation is recorded in the `invitations` table. If any step
+    /// fails, neither the invite is consumed, nor the actor created. See
+    /// [LocalActor::create] for `case_insensitive`.
+    ///
+    /// ## Errors
+    ///
+    /// - [Errcode::Unauthorized], if the invite does not exist, is invalid or
+    ///   has no usages left
+    /// - [Errcode::Duplicate], if an actor with the given `local_name` already
+    ///   exists
+    /// - If something is wrong with the Database or Database connection
+    pub async fn register_with_invite(
+        &self,
+        invite_code: &str,
+        local_name: &str,
+        password_hash: &str,
+        case_insensitive: bool,
+    ) -> Result<LocalActor, Error> {
+        let mut transaction = self.pool.begin().await?;
+        let actor = Invite::register_on(
+            &mut transaction,
+            invite_code,
+            local_name,
+            Some(password_hash),
+            case_insensitive,
+        )
+        .await?;
+        transaction.commit().await?;
+        Ok(actor)
+    }
+}
+
+impl Invite {
+    /// Like [Database::register_with_invite], but on the given connection,
+    /// which is usually a transaction that the caller commits or rolls back
+    /// together with other changes. See [LocalActor::create_on] for actors
+    /// without a `password_hash`.
+    pub(super) async fn register_on(
+        connection: &mut PgConnection,
+        invite_code: &str,
+        local_name: &str,
+        password_hash: Option<&str>,
+        case_insensitive: bool,
+    ) -> Result<LocalActor, Error> {
+        let invite = Invite::try_consume_on(&mut *connection, invite_code).await?;
+        let actor =
+            LocalActor::create_on(&mut *connection, local_name, password_hash, case_insensitive)
+                .await?;
+        if let Some(owner) = invite.invite_link_owner {
+            query!(
+                "INSERT INTO invitations (invite_id, uaid_inviter, uaid_invited) VALUES ($1, $2, $3)",
+                invite.id,
+                owner,
+                actor.unique_actor_identifier
+            )
+            .execute(&mut *connection)
+            .await?;
+        }
+        Ok(actor)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use sqlx::{Pool, Postgres};
+
+    use super::*;
+
+    async fn usages_current(db: &Database, code: &str) -> i32 {
+        query!("SELECT usages_current FROM invite_links WHERE invite = $1", code)
+            .fetch_one(&db.pool)
+            .await
+            .unwrap()
+            .usages_current
+    }
+
+    #[sqlx::test(fixtures(
+        "../../fixtures/local_actor_tests.sql",
+        "../../fixtures/invite_tests.sql"
+    ))]
+    async fn test_register_with_invite_success(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let actor = db
+            .register_with_invite("INVITE0000000001", "invited_user", "hash", false)
+            .await
+            .unwrap();
+        assert_eq!(actor.local_name, "invited_user");
+        assert_eq!(usages_current(&db, "INVITE0000000001").await, 1);
+        let invitation = query!(
+            "SELECT uaid_inviter FROM invitations WHERE uaid_invited = $1",
+            actor.unique_actor_identifier
+        )
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            invitation.uaid_inviter,
+            Uuid::parse_str("00000000-0000-0000-0000-000000000001").unwrap()
+        );
+    }
+
+    #[sqlx::test(fixtures(
+        "../../fixtures/local_actor_tests.sql",
+        "../../fixtures/invite_tests.sql"
+    ))]
+    async fn test_register_with_invite_name_collision_rolls_back(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let error =
+            db.register_with_invite("INVITE0000000002", "alice", "hash", false).await.unwrap_err();
+        assert_eq!(error.code, Errcode::Duplicate);
+        // The invite has not been consumed, and can still be used
+        assert_eq!(usages_current(&db, "INVITE0000000002").await, 0);
+        db.register_with_invite("INVITE0000000002", "not_alice", "hash", false).await.unwrap();
+    }
+
+    #[sqlx::test(fixtures(
+        "../../fixtures/local_actor_tests.sql",
+        "../../fixtures/invite_tests.sql"
+    ))]
+    async fn test_register_with_invite_used_up_or_unknown(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let error = db
+            .register_with_invite("INVITE0000000003", "invited_user", "hash", false)
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, Errcode::Unauthorized);
+        let error = db
+            .register_with_invite("UNKNOWN000000000", "invited_user", "hash", false)
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, Errcode::Unauthorized);
+        assert!(LocalActor::by_local_name(&db, "invited_user", false).await.unwrap().is_none());
+    }
+
+    #[sqlx::test(fixtures(
+        "../../fixtures/local_actor_tests.sql",
+        "../../fixtures/invite_tests.sql"
+    ))]
+    async fn test_try_consume(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let mut connection = db.pool.acquire().await.unwrap();
+        let invite = Invite::try_consume_on(&mut connection, "INVITE0000000001").await.unwrap();
+        assert_eq!((invite.usages_current, invite.usages_maximum), (1, 2));
+        let invite = Invite::try_consume_on(&mut connection, "INVITE0000000001").await.unwrap();
+        assert_eq!(invite.usages_current, 2);
+        // Used up
+        let error = Invite::try_consume_on(&mut connection, "INVITE0000000001").await.unwrap_err();
+        assert_eq!(error.code, Errcode::Unauthorized);
+        assert_eq!(usages_current(&db, "INVITE0000000001").await, 2);
+
+        query!(
+            "UPDATE invite_links SET invalid = TRUE, usages_current = 0 WHERE invite = $1",
+            "INVITE0000000001"
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        let error = Invite::try_consume_on(&mut connection, "INVITE0000000001").await.unwrap_err();
+        assert_eq!(error.code, Errcode::Unauthorized);
+    }
+}
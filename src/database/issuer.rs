@@ -1,6 +1,6 @@
 use log::error;
 use polyproto::types::DomainName;
-use sqlx::query;
+use sqlx::{query, query_as};
 
 use crate::{
     config::SonataConfig,
@@ -9,6 +9,7 @@ use crate::{
 };
 
 /// Represents an issuer row in the database table with the same name.
+#[derive(Debug)]
 pub(crate) struct Issuer {
     /// ID of this issuer
     id: i64,
@@ -16,6 +17,26 @@ pub(crate) struct Issuer {
     pub(crate) domain_components: DomainName,
 }
 
+/// A row of the `issuers` table, from which an [Issuer] is built.
+struct IssuerRow {
+    /// See [Issuer::id].
+    id: i64,
+    /// The labels of the domain of the issuer.
+    domain_components: Vec<String>,
+}
+
+impl TryFrom<IssuerRow> for Issuer {
+    type Error = Error;
+
+    fn try_from(row: IssuerRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            domain_components: Self::vec_string_to_domain_name(row.domain_components)
+                .map_err(|e| *e)?,
+        })
+    }
+}
+
 #[cfg_attr(coverage_nightly, coverage(off))]
 impl Issuer {
     /// Read-only access to the inner ID field, referencing the ID column in the
@@ -51,19 +72,86 @@ impl Issuer {
         })
     }
 
-    /// Create (insert) the issuer entry for this sonata instance.
-    pub(crate) async fn create_own(db: &Database) -> Result<Option<Self>, Error> {
-        let config_domain = &SonataConfig::get_or_panic().general.server_domain;
-        let domain_name = Self::str_to_domain_name(config_domain).map_err(|e| *e)?;
+    /// Normalize a domain name string, so that equivalent spellings of the
+    /// same domain (differing only in case, surrounding whitespace or a
+    /// trailing dot) compare equal.
+    pub(crate) fn normalize_domain(domain: &str) -> String {
+        domain.trim().trim_end_matches('.').to_lowercase()
+    }
+
+    /// Normalize and validate `domain`. Unlike [DomainName::new], which only
+    /// requires the domain to end in valid labels, every label of the domain
+    /// must be non-empty and consist of ASCII letters, digits and hyphens.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an [Errcode::IllegalInput](crate::errors::Errcode::IllegalInput)
+    /// error, if `domain` is not a valid domain name.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn parse_domain(domain: &str) -> Result<DomainName, Error> {
+        let normalized_domain = Self::normalize_domain(domain);
+        if normalized_domain.split('.').any(|label| {
+            label.is_empty() || !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        }) {
+            return Err(Error::new(
+                crate::errors::Errcode::IllegalInput,
+                Some(Context::new(
+                    None,
+                    Some(domain),
+                    None,
+                    Some(
+                        "Domain labels must be non-empty and consist of letters, digits and hyphens",
+                    ),
+                )),
+            ));
+        }
+        Self::str_to_domain_name(&normalized_domain).map_err(|e| *e)
+    }
+
+    /// Create (insert) the issuer entries for all domains served by this sonata
+    /// instance. Returns the newly created entries; entries which already
+    /// existed are left untouched and not returned.
+    pub(crate) async fn create_own(db: &Database) -> Result<Vec<Self>, Error> {
+        Self::create_many(db, SonataConfig::get_or_panic().general.served_domains()).await
+    }
+
+    /// Create (insert) an issuer entry for each of the `domains`, using
+    /// [Self::create]. Returns the newly created entries.
+    pub(crate) async fn create_many(
+        db: &Database,
+        domains: impl IntoIterator<Item = &str>,
+    ) -> Result<Vec<Self>, Error> {
+        let mut created = Vec::new();
+        for domain in domains {
+            if let Some(issuer) = Self::create(db, domain).await? {
+                created.push(issuer);
+            }
+        }
+        Ok(created)
+    }
+
+    /// Create (insert) an issuer entry for `domain`. The domain is normalized
+    /// before being stored. Returns `Ok(None)`, if an issuer entry for this
+    /// domain, or a normalized equivalent of it, already exists.
+    pub(crate) async fn create(db: &Database, domain: &str) -> Result<Option<Self>, Error> {
+        let normalized_domain = Self::normalize_domain(domain);
+        let domain_name = Self::str_to_domain_name(&normalized_domain).map_err(|e| *e)?;
         let domain_name_separated = Self::domain_name_to_vec_string(domain_name);
+        // Checking for equivalent rows and inserting happens in one statement,
+        // so that concurrent calls cannot both insert the same domain
         let record = query!(
             r#"
 			INSERT INTO issuers (domain_components)
-			VALUES ($1)
+			SELECT $1::text[]
+			WHERE NOT EXISTS (
+				SELECT 1 FROM issuers
+				WHERE lower(array_to_string(domain_components, '.')) = $2
+			)
 			ON CONFLICT (domain_components) DO NOTHING
 			RETURNING id, domain_components
 		"#,
-            &domain_name_separated
+            &domain_name_separated,
+            normalized_domain
         )
         .fetch_optional(&db.pool)
         .await?;
@@ -77,19 +165,31 @@ impl Issuer {
         }
     }
 
-    /// Get the issuer entryfor this sonata instance from the database. Returns
-    /// `Ok(None)`, if the item does not exist.
-    pub(crate) async fn get_own(db: &Database) -> Result<Option<Self>, Error> {
-        let domain_name =
-            Self::str_to_domain_name(&SonataConfig::get_or_panic().general.server_domain)
-                .map_err(|e| *e)?;
+    /// Get the issuer entry with the given `id` from the database. Returns
+    /// `Ok(None)`, if no such item exists.
+    pub(crate) async fn get_by_id(db: &Database, id: i64) -> Result<Option<Self>, Error> {
+        query_as!(IssuerRow, "SELECT id, domain_components FROM issuers WHERE id = $1", id)
+            .fetch_optional(&db.pool)
+            .await?
+            .map(Self::try_from)
+            .transpose()
+    }
+
+    /// Get the issuer entry for `domain` from the database. Entries whose
+    /// domain is a normalized equivalent of `domain` are matched as well.
+    /// Returns `Ok(None)`, if no such item exists.
+    pub(crate) async fn get_by_domain(db: &Database, domain: &str) -> Result<Option<Self>, Error> {
+        let normalized_domain = Self::normalize_domain(domain);
+        Self::str_to_domain_name(&normalized_domain).map_err(|e| *e)?;
         let record = query!(
             r#"
 			SELECT id, domain_components
 			FROM issuers
-			WHERE domain_components = $1
+			WHERE lower(array_to_string(domain_components, '.')) = $1
+			ORDER BY id
+			LIMIT 1
 		"#,
-            &Self::domain_name_to_vec_string(domain_name)
+            normalized_domain
         )
         .fetch_optional(&db.pool)
         .await?;
@@ -103,3 +203,102 @@ impl Issuer {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{Pool, Postgres};
+
+    use super::*;
+
+    #[test]
+    fn test_normalize_domain() {
+        assert_eq!(Issuer::normalize_domain("Example.COM."), "example.com");
+        assert_eq!(Issuer::normalize_domain(" example.com "), "example.com");
+        assert_eq!(Issuer::normalize_domain("example.com"), "example.com");
+    }
+
+    #[sqlx::test]
+    async fn test_create_normalized_equivalents_create_one_row(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        let issuer = Issuer::create(&db, "Sonata.Example.COM.").await.unwrap().unwrap();
+        assert_eq!(issuer.domain_components.to_string(), "sonata.example.com");
+        assert!(Issuer::create(&db, "sonata.example.com").await.unwrap().is_none());
+        assert!(Issuer::create(&db, "SONATA.EXAMPLE.COM").await.unwrap().is_none());
+
+        let count =
+            query!("SELECT COUNT(*) AS count FROM issuers").fetch_one(&db.pool).await.unwrap();
+        assert_eq!(count.count, Some(1));
+    }
+
+    #[sqlx::test]
+    async fn test_create_matches_existing_unnormalized_row(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        query!("INSERT INTO issuers (domain_components) VALUES ('{Sonata,Example,com}')")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        assert!(Issuer::create(&db, "sonata.example.com.").await.unwrap().is_none());
+        let found = Issuer::get_by_domain(&db, "sonata.example.com").await.unwrap();
+        assert!(found.is_some());
+
+        let count =
+            query!("SELECT COUNT(*) AS count FROM issuers").fetch_one(&db.pool).await.unwrap();
+        assert_eq!(count.count, Some(1));
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/full_state.sql"))]
+    async fn test_get_by_id(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        let issuer = Issuer::get_by_id(&db, 1000).await.unwrap().unwrap();
+        assert_eq!(issuer.id(), 1000);
+        assert_eq!(issuer.domain_components.to_string(), "full.example.com");
+        assert!(Issuer::get_by_id(&db, 1001).await.unwrap().is_none());
+    }
+
+    #[sqlx::test]
+    async fn test_create_concurrently_creates_one_row(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        let created = futures_util::future::join_all(
+            ["sonata.example.com", "Sonata.Example.com", "SONATA.EXAMPLE.COM."]
+                .map(|domain| Issuer::create(&db, domain)),
+        )
+        .await;
+        assert_eq!(
+            created.into_iter().filter(|issuer| issuer.as_ref().unwrap().is_some()).count(),
+            1
+        );
+
+        let count =
+            query!("SELECT COUNT(*) AS count FROM issuers").fetch_one(&db.pool).await.unwrap();
+        assert_eq!(count.count, Some(1));
+    }
+
+    #[sqlx::test]
+    async fn test_get_by_domain_returns_none_for_unknown_domain(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        Issuer::create(&db, "sonata.example.com").await.unwrap();
+
+        assert!(Issuer::get_by_domain(&db, "other.example.com").await.unwrap().is_none());
+    }
+
+    #[sqlx::test]
+    async fn test_create_many_creates_one_row_per_domain(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        Issuer::create(&db, "b.example.com").await.unwrap();
+
+        let created = Issuer::create_many(&db, ["a.example.com", "B.example.com", "c.example.com"])
+            .await
+            .unwrap();
+        assert_eq!(
+            created.iter().map(|issuer| issuer.domain_components.to_string()).collect::<Vec<_>>(),
+            vec!["a.example.com", "c.example.com"]
+        );
+        for domain in ["a.example.com", "b.example.com", "c.example.com"] {
+            assert!(Issuer::get_by_domain(&db, domain).await.unwrap().is_some());
+        }
+    }
+}
@@ -0,0 +1,228 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use chrono::NaiveDateTime;
+use rand::RngCore;
+use serde::Serialize;
+use sqlx::{query, types::Uuid};
+
+use crate::{database::Database, errors::Error};
+
+/// How many random bytes a [KeyLoginChallenge] consists of.
+const CHALLENGE_BYTES: usize = 32;
+
+/// How many unexpired challenges an actor can have at once. Issuing another
+/// challenge removes the oldest ones.
+const MAX_OUTSTANDING_CHALLENGES: i64 = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+/// A random challenge, which a [LocalActor](super::LocalActor) logging in with
+/// a public key has to sign with the corresponding private key. Clients
+/// registering with a public key sign one as well. Each challenge can only be
+/// used once.
+pub(crate) struct KeyLoginChallenge {
+    /// The hex-encoded random bytes to sign.
+    pub(crate) challenge: String,
+    /// Until when the challenge can be used.
+    pub(crate) expires: NaiveDateTime,
+}
+
+impl KeyLoginChallenge {
+    /// Generate the hex-encoded random bytes of a new challenge.
+    fn random_challenge() -> String {
+        let mut bytes = [0u8; CHALLENGE_BYTES];
+        rand::rng().fill_bytes(&mut bytes);
+        hex::encode(bytes)
+    }
+
+    /// Generate and store a new [KeyLoginChallenge] for the actor identified
+    /// by `uaid`, which expires in `ttl_secs` seconds. Expired challenges of
+    /// the actor are removed, as are the oldest ones beyond
+    /// [MAX_OUTSTANDING_CHALLENGES].
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if no actor with the given `uaid` exists, or if something
+    /// is wrong with the Database or Database connection.
+    pub(crate) async fn issue(db: &Database, uaid: &Uuid, ttl_secs: u64) -> Result<Self, Error> {
+        let challenge = Self::random_challenge();
+        let ttl_secs = i64::try_from(ttl_secs).unwrap_or(i64::MAX);
+        let mut transaction = db.pool.begin().await?;
+        let expires = query!(
+            "INSERT INTO key_login_challenges (uaid, challenge, expires)
+            VALUES ($1, $2, now() + make_interval(secs => $3::bigint))
+            RETURNING expires",
+            uaid,
+            challenge,
+            ttl_secs
+        )
+        .fetch_one(&mut *transaction)
+        .await?
+        .expires;
+        query!(
+            "DELETE FROM key_login_challenges
+            WHERE uaid = $1 AND (expires <= now() OR id NOT IN (
+                SELECT id FROM key_login_challenges WHERE uaid = $1 ORDER BY id DESC LIMIT $2
+            ))",
+            uaid,
+            MAX_OUTSTANDING_CHALLENGES
+        )
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(Self { challenge, expires })
+    }
+
+    /// Use up the `challenge` issued to the actor identified by `uaid`.
+    /// Returns `false`, if there is no such challenge, or it has expired.
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if something is wrong with the Database or Database
+    /// connection.
+    pub(crate) async fn consume(
+        db: &Database,
+        uaid: &Uuid,
+        challenge: &str,
+    ) -> Result<bool, Error> {
+        Ok(query!(
+            "DELETE FROM key_login_challenges
+            WHERE uaid = $1 AND challenge = $2 AND expires > now()
+            RETURNING id",
+            uaid,
+            challenge
+        )
+        .fetch_optional(&db.pool)
+        .await?
+        .is_some())
+    }
+
+    /// Generate and store a new [KeyLoginChallenge] for a client registering
+    /// with a public key, which expires in `ttl_secs` seconds. As the actor
+    /// does not exist yet, the challenge is not tied to one. Expired
+    /// registration challenges are removed.
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if something is wrong with the Database or Database
+    /// connection.
+    pub(crate) async fn issue_for_registration(
+        db: &Database,
+        ttl_secs: u64,
+    ) -> Result<Self, Error> {
+        let challenge = Self::random_challenge();
+        let ttl_secs = i64::try_from(ttl_secs).unwrap_or(i64::MAX);
+        query!("DELETE FROM registration_challenges WHERE expires <= now()")
+            .execute(&db.pool)
+            .await?;
+        let expires = query!(
+            "INSERT INTO registration_challenges (challenge, expires)
+            VALUES ($1, now() + make_interval(secs => $2::bigint))
+            RETURNING expires",
+            challenge,
+            ttl_secs
+        )
+        .fetch_one(&db.pool)
+        .await?
+        .expires;
+        Ok(Self { challenge, expires })
+    }
+
+    /// Use up the registration `challenge`. Returns `false`, if there is no
+    /// such challenge, or it has expired.
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if something is wrong with the Database or Database
+    /// connection.
+    pub(crate) async fn consume_for_registration(
+        db: &Database,
+        challenge: &str,
+    ) -> Result<bool, Error> {
+        Ok(query!(
+            "DELETE FROM registration_challenges
+            WHERE challenge = $1 AND expires > now()
+            RETURNING id",
+            challenge
+        )
+        .fetch_optional(&db.pool)
+        .await?
+        .is_some())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use sqlx::{Pool, Postgres};
+
+    use super::*;
+
+    const ALICE: Uuid = Uuid::from_u128(0x1001);
+
+    #[sqlx::test(fixtures("../../fixtures/full_state.sql"))]
+    async fn test_challenge_can_be_used_once(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let challenge = KeyLoginChallenge::issue(&db, &ALICE, 300).await.unwrap();
+        assert_eq!(challenge.challenge.len(), 2 * CHALLENGE_BYTES);
+
+        assert!(
+            !KeyLoginChallenge::consume(&db, &Uuid::from_u128(0x1002), &challenge.challenge)
+                .await
+                .unwrap()
+        );
+        assert!(KeyLoginChallenge::consume(&db, &ALICE, &challenge.challenge).await.unwrap());
+        assert!(!KeyLoginChallenge::consume(&db, &ALICE, &challenge.challenge).await.unwrap());
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/full_state.sql"))]
+    async fn test_expired_challenge_is_rejected(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let challenge = KeyLoginChallenge::issue(&db, &ALICE, 0).await.unwrap();
+        assert!(!KeyLoginChallenge::consume(&db, &ALICE, &challenge.challenge).await.unwrap());
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/full_state.sql"))]
+    async fn test_oldest_challenges_are_removed(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let oldest = KeyLoginChallenge::issue(&db, &ALICE, 300).await.unwrap();
+        let mut newest = Vec::new();
+        for _ in 0..MAX_OUTSTANDING_CHALLENGES {
+            newest.push(KeyLoginChallenge::issue(&db, &ALICE, 300).await.unwrap());
+        }
+        let stored = query!(
+            "SELECT COUNT(*) AS \"count!\" FROM key_login_challenges WHERE uaid = $1",
+            ALICE
+        )
+        .fetch_one(&db.pool)
+        .await
+        .unwrap()
+        .count;
+        assert_eq!(stored, MAX_OUTSTANDING_CHALLENGES);
+
+        assert!(!KeyLoginChallenge::consume(&db, &ALICE, &oldest.challenge).await.unwrap());
+        for challenge in newest {
+            assert!(KeyLoginChallenge::consume(&db, &ALICE, &challenge.challenge).await.unwrap());
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_registration_challenge_can_be_used_once(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let challenge = KeyLoginChallenge::issue_for_registration(&db, 300).await.unwrap();
+        assert!(!KeyLoginChallenge::consume(&db, &ALICE, &challenge.challenge).await.unwrap());
+        assert!(
+            KeyLoginChallenge::consume_for_registration(&db, &challenge.challenge).await.unwrap()
+        );
+        assert!(
+            !KeyLoginChallenge::consume_for_registration(&db, &challenge.challenge).await.unwrap()
+        );
+
+        let expired = KeyLoginChallenge::issue_for_registration(&db, 0).await.unwrap();
+        assert!(
+            !KeyLoginChallenge::consume_for_registration(&db, &expired.challenge).await.unwrap()
+        );
+    }
+}
@@ -0,0 +1,165 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use log::info;
+use sqlx::query;
+
+use crate::{
+    database::Database,
+    errors::{Context, Errcode, Error},
+};
+
+/// Key of the PostgreSQL advisory lock, which is held while
+/// [Database::run_maintenance] is running.
+const MAINTENANCE_LOCK_KEY: i64 = 0x736f_6e61_7461;
+
+/// The tables [Database::run_maintenance] keeps the statistics of fresh. These
+/// are the ones which see the most writes.
+const MAINTAINED_TABLES: &[&str] = &[
+    "actors",
+    "local_actors",
+    "foreign_actors",
+    "public_keys",
+    "idcsr",
+    "idcert",
+    "user_tokens",
+    "invite_links",
+];
+
+impl Database {
+    /// Run `ANALYZE` on the [MAINTAINED_TABLES], so that the query planner
+    /// works with up-to-date statistics. If `vacuum` is set, the tables are
+    /// vacuumed as well, reclaiming the space of deleted rows.
+    ///
+    /// ## Errors
+    ///
+    /// [Errcode::Duplicate], if maintenance is already running, which is
+    /// tracked across all sonata instances using this database. Will also
+    /// error, if something is wrong with the Database or Database connection.
+    pub(crate) async fn run_maintenance(&self, vacuum: bool) -> Result<(), Error> {
+        // Advisory locks are held by a session, so locking, maintaining and
+        // unlocking all have to happen on the same connection. Should this
+        // future be dropped before unlocking, the connection is closed instead
+        // of being returned to the pool, which releases the lock with it
+        let mut connection = self.pool.acquire().await?;
+        connection.close_on_drop();
+        let locked = query!("SELECT pg_try_advisory_lock($1) AS locked", MAINTENANCE_LOCK_KEY)
+            .fetch_one(&mut *connection)
+            .await?
+            .locked
+            .unwrap_or_default();
+        if !locked {
+            return Err(Error::new(
+                Errcode::Duplicate,
+                Some(Context::new_message("Database maintenance is already running")),
+            ));
+        }
+        let command = if vacuum { "VACUUM (ANALYZE)" } else { "ANALYZE" };
+        info!("Running {command} on {} tables", MAINTAINED_TABLES.len());
+        // VACUUM cannot run inside of a transaction block, so the tables are
+        // maintained one at a time
+        let mut result = Ok(());
+        for table in MAINTAINED_TABLES {
+            let statement = format!("{command} {table}");
+            if let Err(e) = query(&statement).execute(&mut *connection).await {
+                result = Err(e);
+                break;
+            }
+        }
+        query!("SELECT pg_advisory_unlock($1)", MAINTENANCE_LOCK_KEY)
+            .fetch_one(&mut *connection)
+            .await?;
+        result?;
+        info!("Finished {command}");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::time::Duration;
+
+    use sqlx::{Pool, Postgres};
+
+    use super::*;
+
+    /// Whether any session holds the maintenance lock on the database.
+    async fn maintenance_locked(db: &Database) -> bool {
+        query!(
+            r#"SELECT EXISTS(
+                SELECT 1 FROM pg_locks
+                WHERE locktype = 'advisory'
+                AND database = (SELECT oid FROM pg_database WHERE datname = current_database())
+                AND ((classid::bigint << 32) | objid::bigint) = $1
+            ) AS "locked!""#,
+            MAINTENANCE_LOCK_KEY
+        )
+        .fetch_one(&db.pool)
+        .await
+        .unwrap()
+        .locked
+    }
+
+    #[sqlx::test]
+    async fn test_run_maintenance(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        db.run_maintenance(false).await.unwrap();
+        db.run_maintenance(true).await.unwrap();
+    }
+
+    #[sqlx::test]
+    async fn test_run_maintenance_is_exclusive(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let mut connection = db.pool.acquire().await.unwrap();
+        query!("SELECT pg_advisory_lock($1)", MAINTENANCE_LOCK_KEY)
+            .fetch_one(&mut *connection)
+            .await
+            .unwrap();
+
+        let error = db.run_maintenance(false).await.unwrap_err();
+        assert_eq!(error.code, Errcode::Duplicate);
+
+        query!("SELECT pg_advisory_unlock($1)", MAINTENANCE_LOCK_KEY)
+            .fetch_one(&mut *connection)
+            .await
+            .unwrap();
+        db.run_maintenance(false).await.unwrap();
+    }
+
+    #[sqlx::test]
+    async fn test_dropped_maintenance_releases_lock(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        // Block the first ANALYZE, so that maintenance is still running with
+        // the lock held when it gets dropped
+        let mut blocker = db.pool.begin().await.unwrap();
+        query!("LOCK TABLE actors IN SHARE UPDATE EXCLUSIVE MODE")
+            .execute(&mut *blocker)
+            .await
+            .unwrap();
+        let mut maintenance = Box::pin(db.run_maintenance(false));
+        let mut locked = false;
+        for _ in 0..500 {
+            locked = maintenance_locked(&db).await;
+            if locked {
+                break;
+            }
+            assert!(
+                tokio::time::timeout(Duration::from_millis(10), &mut maintenance).await.is_err()
+            );
+        }
+        assert!(locked, "maintenance never took the lock");
+        drop(maintenance);
+        blocker.commit().await.unwrap();
+
+        for _ in 0..500 {
+            if !maintenance_locked(&db).await {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(!maintenance_locked(&db).await, "the lock of dropped maintenance was leaked");
+        db.run_maintenance(false).await.unwrap();
+    }
+}
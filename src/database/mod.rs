@@ -2,9 +2,13 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use std::time::Duration;
+
+use log::{LevelFilter, warn};
 use sqlx::{
-    PgPool,
+    ConnectOptions, PgPool,
     postgres::{PgConnectOptions, PgPoolOptions},
+    query,
 };
 
 use crate::{StdResult, config::DatabaseConfig};
@@ -12,22 +16,35 @@ use crate::{StdResult, config::DatabaseConfig};
 pub(crate) mod actor;
 pub(crate) mod algorithm_identifier;
 pub(crate) mod api_keys;
+pub(crate) mod csr_extensions;
+pub(crate) mod foreign_actor;
 pub(crate) mod idcert;
 pub(crate) mod invite;
 pub(crate) mod issuer;
+pub(crate) mod key_login_challenge;
 pub(crate) mod keytrials;
+pub(crate) mod maintenance;
+pub(crate) mod pool_stats;
 pub(crate) mod public_key_info;
+pub(crate) mod repository;
 pub(crate) mod serial_number;
+#[cfg(test)]
+pub(crate) mod test_helpers;
 pub(crate) mod tokens;
 
 pub(crate) use actor::*;
 pub(crate) use algorithm_identifier::*;
 pub(crate) use api_keys::*;
+pub(crate) use csr_extensions::*;
+pub(crate) use foreign_actor::*;
 pub(crate) use idcert::*;
 pub(crate) use invite::*;
 pub(crate) use issuer::*;
+pub(crate) use key_login_challenge::*;
 pub(crate) use keytrials::*;
+pub(crate) use pool_stats::*;
 pub(crate) use public_key_info::*;
+pub(crate) use repository::*;
 pub(crate) use serial_number::*;
 pub(crate) use tokens::*;
 
@@ -42,13 +59,23 @@ impl Database {
     /// Connect to the PostgreSQL Database using configuration options provided
     /// through [DatabaseConfig], which is most commonly derived by parsing a
     /// [SonataConfiguration].
+    ///
+    /// If the database is not (yet) accepting connections, for example because
+    /// it is still starting up, or does not answer a query within
+    /// [CONNECT_PING_TIMEOUT] after connecting, connecting is retried with
+    /// exponential backoff, up to [DatabaseConfig::connect_max_attempts]
+    /// attempts in total. Other errors are returned immediately.
+    ///
+    /// If `log_sql` is set, every executed SQL statement is logged; see
+    /// [statement_log_levels].
     #[cfg_attr(coverage_nightly, coverage(off))]
-    pub async fn connect_with_config(config: &DatabaseConfig) -> StdResult<Self> {
+    pub async fn connect_with_config(config: &DatabaseConfig, log_sql: bool) -> StdResult<Self> {
+        let (statements_level, slow_statements_level) = statement_log_levels(log_sql);
         let connect_options = PgConnectOptions::new()
             .host(&config.host)
             .database(&config.database)
             .application_name("sonata")
-            .password(&config.password)
+            .password(&config.password.expose())
             .port(config.port)
             .ssl_mode(match config.tls {
                 crate::config::TlsConfig::Disable => sqlx::postgres::PgSslMode::Disable,
@@ -58,12 +85,67 @@ impl Database {
                 crate::config::TlsConfig::VerifyCa => sqlx::postgres::PgSslMode::VerifyCa,
                 crate::config::TlsConfig::VerifyFull => sqlx::postgres::PgSslMode::VerifyFull,
             })
-            .username(&config.username);
-        let pool = PgPoolOptions::new()
-            .max_connections(config.max_connections)
-            .connect_with(connect_options)
-            .await?;
-        Ok(Self { pool })
+            .username(&config.username)
+            .log_statements(statements_level)
+            .log_slow_statements(slow_statements_level, SLOW_STATEMENT_THRESHOLD);
+        let connect_options = with_statement_timeout(connect_options, config.statement_timeout_ms);
+        let max_attempts = config.connect_max_attempts.max(1);
+        let mut attempt = 1;
+        loop {
+            let error = match PgPoolOptions::new()
+                .max_connections(config.max_connections)
+                .connect_with(connect_options.clone())
+                .await
+            {
+                Ok(pool) => {
+                    let database = Self { pool };
+                    if database.ping_with_timeout(CONNECT_PING_TIMEOUT).await {
+                        return Ok(database);
+                    }
+                    database.pool.close().await;
+                    format!(
+                        "The database did not answer a query within {} seconds",
+                        CONNECT_PING_TIMEOUT.as_secs()
+                    )
+                }
+                Err(e) if is_transient_connect_error(&e) => e.to_string(),
+                Err(e) => return Err(e.into()),
+            };
+            if attempt >= max_attempts {
+                return Err(error.into());
+            }
+            let delay = connect_retry_delay(config.connect_base_delay_ms, attempt);
+            warn!(
+                "Couldn't connect to the database (attempt {attempt} of {max_attempts}): {error}. \
+                 Retrying in {} ms...",
+                delay.as_millis()
+            );
+            tokio::time::sleep(delay).await;
+            attempt = attempt.saturating_add(1);
+        }
+    }
+
+    /// Cheap liveness probe: Checks whether the database answers a trivial
+    /// query within `timeout`. Returns `false` if the query fails or the
+    /// deadline is exceeded.
+    pub async fn ping_with_timeout(&self, timeout: Duration) -> bool {
+        matches!(
+            tokio::time::timeout(timeout, query!("SELECT 1 AS ping").fetch_one(&self.pool)).await,
+            Ok(Ok(_))
+        )
+    }
+
+    /// Close all connections of the pool and drop its copy of the database
+    /// password. Meant to be called when sonata shuts down.
+    ///
+    /// `sqlx` keeps a copy of the password in the connect options of the pool
+    /// and offers no way to overwrite it, so this copy can only be released,
+    /// not zeroized. The password held by the
+    /// [SonataConfig](crate::config::SonataConfig) is zeroized separately.
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    pub async fn close(&self) {
+        self.pool.close().await;
+        self.pool.set_connect_options(PgConnectOptions::new_without_pgpass());
     }
 
     /// Applies the migrations.
@@ -72,10 +154,73 @@ impl Database {
     }
 }
 
+/// How long [Database::connect_with_config] waits for a new connection to
+/// answer a query, before the attempt counts as failed.
+const CONNECT_PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a SQL statement has to take to be logged as slow.
+const SLOW_STATEMENT_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// The levels at which `sqlx` logs all executed SQL statements and those
+/// taking longer than [SLOW_STATEMENT_THRESHOLD], along with their execution
+/// time, if `log_sql` is set. Statements are logged without the values bound
+/// to their parameters, so that secrets such as password hashes never end up
+/// in the log. Like all other log messages, they are subject to the configured
+/// log level.
+fn statement_log_levels(log_sql: bool) -> (LevelFilter, LevelFilter) {
+    match log_sql {
+        true => (LevelFilter::Debug, LevelFilter::Warn),
+        false => (LevelFilter::Off, LevelFilter::Off),
+    }
+}
+
+/// Set the `statement_timeout` of every connection made with `options` to
+/// `statement_timeout_ms` milliseconds, after which the database aborts a
+/// statement. `0` leaves the `options` untouched, so that statements can run
+/// for as long as the database permits.
+fn with_statement_timeout(
+    options: PgConnectOptions,
+    statement_timeout_ms: u64,
+) -> PgConnectOptions {
+    match statement_timeout_ms {
+        0 => options,
+        timeout => options.options([("statement_timeout", timeout.to_string())]),
+    }
+}
+
+/// Whether `error` indicates that the database is not accepting connections
+/// right now, but might be soon, for example because it is still starting up.
+fn is_transient_connect_error(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Io(e) => matches!(
+            e.kind(),
+            std::io::ErrorKind::ConnectionRefused
+                | std::io::ErrorKind::ConnectionReset
+                | std::io::ErrorKind::ConnectionAborted
+        ),
+        // cannot_connect_now: "the database system is starting up"
+        sqlx::Error::Database(e) => e.code().as_deref() == Some("57P03"),
+        _ => false,
+    }
+}
+
+/// How long to wait after the failed connection attempt number `attempt`,
+/// starting at `1`: `base_delay_ms`, doubled for every further attempt.
+fn connect_retry_delay(base_delay_ms: u64, attempt: u32) -> Duration {
+    Duration::from_millis(
+        base_delay_ms.saturating_mul(2u64.saturating_pow(attempt.saturating_sub(1))),
+    )
+}
+
 #[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
 mod tests {
+    use std::time::Instant;
+
+    use sqlx::{Pool, Postgres};
+
     use super::*;
-    use crate::config::TlsConfig;
+    use crate::config::{Secret, TlsConfig};
 
     #[test]
     fn test_database_debug() {
@@ -99,14 +244,20 @@ mod tests {
             max_connections: 1,
             database: "nonexistent".to_owned(),
             username: "invalid".to_owned(),
-            password: "invalid".to_owned(),
+            password: Secret::from("invalid".to_owned()),
             port: 5432,
             host: "invalid_host".to_owned(),
             tls: TlsConfig::Disable,
+            connect_max_attempts: 1,
+            connect_base_delay_ms: 0,
+            saturation_check_interval_secs: 0,
+            saturation_warning_secs: 0,
+            token_purge_interval_secs: 0,
+            statement_timeout_ms: 0,
         };
 
         // This should fail to connect
-        let result = Database::connect_with_config(&config).await;
+        let result = Database::connect_with_config(&config, false).await;
         assert!(result.is_err());
     }
 
@@ -116,18 +267,127 @@ mod tests {
             max_connections: 0, // Zero connections should cause a panic during pool creation
             database: "test".to_owned(),
             username: "test".to_owned(),
-            password: "test".to_owned(),
+            password: Secret::from("test".to_owned()),
             port: 5432,
             host: "localhost".to_owned(),
             tls: TlsConfig::Disable,
+            connect_max_attempts: 1,
+            connect_base_delay_ms: 0,
+            saturation_check_interval_secs: 0,
+            saturation_warning_secs: 0,
+            token_purge_interval_secs: 0,
+            statement_timeout_ms: 0,
         };
 
         // This should panic or error due to zero max_connections
         let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
             tokio::runtime::Runtime::new()
                 .unwrap()
-                .block_on(async { Database::connect_with_config(&config).await })
+                .block_on(async { Database::connect_with_config(&config, false).await })
         }));
         assert!(result.is_err());
     }
+
+    #[sqlx::test]
+    async fn test_ping_with_timeout_live_database(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        assert!(db.ping_with_timeout(Duration::from_secs(5)).await);
+    }
+
+    #[sqlx::test]
+    async fn test_ping_with_timeout_closed_pool(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        db.pool.close().await;
+        assert!(!db.ping_with_timeout(Duration::from_secs(5)).await);
+    }
+
+    #[tokio::test]
+    async fn test_ping_with_timeout_exceeded() {
+        // Non-routable address: Connecting hangs until the acquire timeout, which is
+        // much longer than the ping timeout.
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_secs(30))
+            .connect_lazy_with(PgConnectOptions::new().host("10.255.255.1").port(5432));
+        let db = Database { pool };
+
+        let start = Instant::now();
+        assert!(!db.ping_with_timeout(Duration::from_millis(100)).await);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_statement_log_levels() {
+        assert_eq!(statement_log_levels(false), (LevelFilter::Off, LevelFilter::Off));
+        let (statements_level, slow_statements_level) = statement_log_levels(true);
+        assert_eq!(statements_level, LevelFilter::Debug);
+        // Slow statements stand out from the others
+        assert!(slow_statements_level < statements_level);
+        assert_ne!(slow_statements_level, LevelFilter::Off);
+    }
+
+    #[test]
+    fn test_connect_retry_delay_doubles() {
+        assert_eq!(connect_retry_delay(500, 1), Duration::from_millis(500));
+        assert_eq!(connect_retry_delay(500, 2), Duration::from_millis(1000));
+        assert_eq!(connect_retry_delay(500, 4), Duration::from_millis(4000));
+        assert_eq!(connect_retry_delay(500, 100), Duration::from_millis(u64::MAX));
+    }
+
+    #[tokio::test]
+    async fn test_connect_with_config_gives_up_after_max_attempts() {
+        // Nothing listens on port 1, so every attempt is refused and retried
+        let config = DatabaseConfig {
+            max_connections: 1,
+            database: "nonexistent".to_owned(),
+            username: "invalid".to_owned(),
+            password: Secret::from("invalid".to_owned()),
+            port: 1,
+            host: "127.0.0.1".to_owned(),
+            tls: TlsConfig::Disable,
+            connect_max_attempts: 3,
+            connect_base_delay_ms: 50,
+            saturation_check_interval_secs: 0,
+            saturation_warning_secs: 0,
+            token_purge_interval_secs: 0,
+            statement_timeout_ms: 0,
+        };
+
+        let start = Instant::now();
+        let result = tokio::time::timeout(
+            Duration::from_secs(30),
+            Database::connect_with_config(&config, false),
+        )
+        .await
+        .expect("Connecting should give up instead of retrying forever");
+        assert!(result.is_err());
+        // Two retries, waiting 50 ms and 100 ms
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+
+    #[sqlx::test]
+    async fn test_with_statement_timeout(pool: Pool<Postgres>) {
+        let connect = async |statement_timeout_ms| {
+            PgPoolOptions::new()
+                .max_connections(1)
+                .connect_with(with_statement_timeout(
+                    (*pool.connect_options()).clone(),
+                    statement_timeout_ms,
+                ))
+                .await
+                .unwrap()
+        };
+
+        let limited = connect(100).await;
+        let timeout = query!(r#"SELECT current_setting('statement_timeout') AS "timeout!""#)
+            .fetch_one(&limited)
+            .await
+            .unwrap();
+        assert_eq!(timeout.timeout, "100ms");
+        let error = sqlx::query("SELECT pg_sleep(2)").execute(&limited).await.unwrap_err();
+        // query_canceled
+        assert_eq!(error.as_database_error().unwrap().code().as_deref(), Some("57014"));
+
+        let unlimited = connect(0).await;
+        sqlx::query("SELECT pg_sleep(0.2)").execute(&unlimited).await.unwrap();
+    }
 }
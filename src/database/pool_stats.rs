@@ -0,0 +1,164 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::{config::DatabaseConfig, database::Database};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+/// A snapshot of the connections of the [Database] pool.
+pub(crate) struct PoolStats {
+    /// How many connections are currently open, idle or in use.
+    pub(crate) size: u32,
+    /// How many of the open connections are idle.
+    pub(crate) idle: usize,
+    /// How many connections the pool may open at most.
+    pub(crate) max_connections: u32,
+}
+
+impl PoolStats {
+    /// Whether every connection the pool may open is in use, so that further
+    /// queries have to wait for a connection to be returned.
+    pub(crate) fn is_saturated(&self) -> bool {
+        self.idle == 0 && self.size >= self.max_connections
+    }
+}
+
+impl Database {
+    /// Take a [PoolStats] snapshot of the connection pool.
+    pub(crate) fn pool_stats(&self) -> PoolStats {
+        PoolStats {
+            size: self.pool.size(),
+            idle: self.pool.num_idle(),
+            max_connections: self.pool.options().get_max_connections(),
+        }
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    /// Spawn a task, which checks the [PoolStats] every
+    /// [DatabaseConfig::saturation_check_interval_secs] and logs a warning, if
+    /// the pool has stayed saturated for at least
+    /// [DatabaseConfig::saturation_warning_secs]. Does nothing, if the check
+    /// interval is `0`.
+    pub(crate) fn spawn_saturation_monitor(&self, config: &DatabaseConfig) {
+        if config.saturation_check_interval_secs == 0 {
+            return;
+        }
+        let database = self.clone();
+        let check_interval = Duration::from_secs(config.saturation_check_interval_secs);
+        let mut monitor =
+            SaturationMonitor::new(Duration::from_secs(config.saturation_warning_secs));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(check_interval);
+            while !database.pool.is_closed() {
+                interval.tick().await;
+                let stats = database.pool_stats();
+                match monitor.observe(&stats, Instant::now()) {
+                    Some(Saturation::Persisting(duration)) => warn!(
+                        "All {} database connections have been in use for {} seconds. Queries \
+                         are waiting for a free connection; consider raising \
+                         general.database.max_connections",
+                        stats.max_connections,
+                        duration.as_secs()
+                    ),
+                    Some(Saturation::Resolved) => {
+                        info!("Database connections are available again")
+                    }
+                    None => (),
+                }
+            }
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A change in the saturation of the pool, which is worth reporting.
+pub(crate) enum Saturation {
+    /// The pool has been saturated for the contained duration, which is at
+    /// least the warning threshold.
+    Persisting(Duration),
+    /// The pool is no longer saturated, after a [Saturation::Persisting] has
+    /// been reported.
+    Resolved,
+}
+
+#[derive(Debug, Clone)]
+/// Keeps track of how long the pool has been saturated, across a series of
+/// [PoolStats] snapshots.
+pub(crate) struct SaturationMonitor {
+    /// For how long the pool has to be saturated to be reported.
+    warn_after: Duration,
+    /// Since when the pool has been saturated, if it currently is.
+    saturated_since: Option<Instant>,
+    /// Whether the current saturation has already been reported.
+    reported: bool,
+}
+
+impl SaturationMonitor {
+    /// Create a [SaturationMonitor], which reports saturations lasting at least
+    /// `warn_after`.
+    pub(crate) fn new(warn_after: Duration) -> Self {
+        Self { warn_after, saturated_since: None, reported: false }
+    }
+
+    /// Record the `stats` taken at `now`. Each saturation lasting at least the
+    /// warning threshold is reported once, as is its end.
+    pub(crate) fn observe(&mut self, stats: &PoolStats, now: Instant) -> Option<Saturation> {
+        if !stats.is_saturated() {
+            self.saturated_since = None;
+            return std::mem::take(&mut self.reported).then_some(Saturation::Resolved);
+        }
+        let saturated_since = *self.saturated_since.get_or_insert(now);
+        let duration = now.saturating_duration_since(saturated_since);
+        if self.reported || duration < self.warn_after {
+            return None;
+        }
+        self.reported = true;
+        Some(Saturation::Persisting(duration))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SATURATED: PoolStats = PoolStats { size: 10, idle: 0, max_connections: 10 };
+    const HEALTHY: PoolStats = PoolStats { size: 10, idle: 3, max_connections: 10 };
+
+    #[test]
+    fn test_is_saturated() {
+        assert!(SATURATED.is_saturated());
+        assert!(!HEALTHY.is_saturated());
+        // The pool can still open further connections
+        assert!(!PoolStats { size: 4, idle: 0, max_connections: 10 }.is_saturated());
+    }
+
+    #[test]
+    fn test_saturation_monitor() {
+        let mut monitor = SaturationMonitor::new(Duration::from_secs(60));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(monitor.observe(&HEALTHY, at(0)), None);
+        assert_eq!(monitor.observe(&SATURATED, at(10)), None);
+        assert_eq!(monitor.observe(&SATURATED, at(60)), None);
+        assert_eq!(
+            monitor.observe(&SATURATED, at(70)),
+            Some(Saturation::Persisting(Duration::from_secs(60)))
+        );
+        // Every saturation is only reported once...
+        assert_eq!(monitor.observe(&SATURATED, at(80)), None);
+        assert_eq!(monitor.observe(&HEALTHY, at(90)), Some(Saturation::Resolved));
+        assert_eq!(monitor.observe(&HEALTHY, at(100)), None);
+
+        // ...and short saturations are not reported at all
+        assert_eq!(monitor.observe(&SATURATED, at(110)), None);
+        assert_eq!(monitor.observe(&HEALTHY, at(160)), None);
+        assert_eq!(monitor.observe(&SATURATED, at(170)), None);
+    }
+}
@@ -1,9 +1,14 @@
 use log::error;
-use polyproto::{der::Encode, key::PublicKey, signature::Signature};
-use sqlx::{query, types::Uuid};
+use polyproto::{
+    der::{Decode, Encode, asn1::BitString},
+    key::PublicKey,
+    signature::Signature,
+};
+use sqlx::{PgConnection, query, query_as, types::Uuid};
 
 use crate::{
-    database::{AlgorithmIdentifier, Database},
+    config::SecurityConfig,
+    database::{AlgorithmIdentifier, CsrExtensions, Database, NewIdCert, SerialNumber},
     errors::{
         ALGORITHM_IDENTIFER_TO_DER_ERROR_MESSAGE, CONTAINS_UNKNOWN_CRYPTO_ALGOS_ERROR_MESSAGE,
         Context, Errcode, Error,
@@ -76,6 +81,81 @@ impl PublicKeyInfo {
             .collect())
     }
 
+    /// Get the entry of the `public_keys` table with the given `id`, if there
+    /// is one.
+    ///
+    /// ## Errors
+    ///
+    /// The function will error, if
+    ///
+    /// - The database or database connection is broken
+    pub(crate) async fn by_id(db: &Database, id: i64) -> Result<Option<Self>, Error> {
+        Ok(query_as!(
+            PublicKeyInfo,
+            "SELECT id, uaid, pubkey, algorithm_identifier FROM public_keys WHERE id = $1",
+            id
+        )
+        .fetch_optional(&db.pool)
+        .await?)
+    }
+
+    /// Like [Self::get_by], but additionally returns the common name of each
+    /// key's algorithm, as stored in the `algorithm_identifiers` table, which
+    /// spares callers a second lookup. The common name is `None`, if none is
+    /// stored for the algorithm.
+    ///
+    /// If all given parameters evaluate to `None`, this function has a fast
+    /// path returning an `Ok(Vec::new())`.
+    ///
+    /// ## Errors
+    ///
+    /// The function will error, if
+    ///
+    /// - The database or database connection is broken
+    pub(crate) async fn get_by_with_algorithm(
+        db: &Database,
+        uaid: Option<Uuid>,
+        pubkey: Option<String>,
+        algorithm_identifier: Option<i32>,
+        id: Option<i32>,
+    ) -> Result<Vec<(Self, Option<String>)>, Error> {
+        if uaid.is_none() && pubkey.is_none() && algorithm_identifier.is_none() && id.is_none() {
+            return Ok(Vec::new());
+        }
+        let record = query!(
+            r#"
+            SELECT pk.id, pk.uaid, pk.pubkey, pk.algorithm_identifier, ai.common_name
+            FROM public_keys pk
+            JOIN algorithm_identifiers ai ON ai.id = pk.algorithm_identifier
+            WHERE
+                ($1::int IS NULL OR pk.id = $1)
+                AND ($2::uuid IS NULL OR pk.uaid = $2)
+                AND ($3::text IS NULL OR pk.pubkey = $3)
+                AND ($4::int IS NULL OR pk.algorithm_identifier = $4)
+        "#,
+            id,
+            uaid,
+            pubkey,
+            algorithm_identifier
+        )
+        .fetch_all(&db.pool)
+        .await?;
+        Ok(record
+            .into_iter()
+            .map(|row| {
+                (
+                    PublicKeyInfo {
+                        id: row.id,
+                        uaid: row.uaid,
+                        pubkey: row.pubkey,
+                        algorithm_identifier: row.algorithm_identifier,
+                    },
+                    row.common_name,
+                )
+            })
+            .collect())
+    }
+
     /// Insert a public key into the `public_keys` table.
     ///
     /// This function extracts algorithm information from the provided public
@@ -87,6 +167,10 @@ impl PublicKeyInfo {
     /// - `db` - Database connection reference
     /// - `public_key` - The public key to insert
     /// - `uaid` - Optional user actor ID to associate with the public key
+    /// - `security_config` - If `enforce_globally_unique_keys` is set, the key
+    ///   is rejected if any actor has already registered it. If `uaid` is
+    ///   provided, the key is rejected if the actor already has
+    ///   `max_keys_per_actor` keys registered
     ///
     /// ## Returns
     ///
@@ -97,41 +181,96 @@ impl PublicKeyInfo {
     /// The function will error if:
     ///
     /// - The public key uses an unsupported cryptographic algorithm
-    /// - The public key already exists in the database
+    /// - The public key already exists in the database. If
+    ///   `enforce_globally_unique_keys` is set, this is reported as an
+    ///   [Errcode::Duplicate]
+    /// - The actor identified by `uaid` already has `max_keys_per_actor` keys
+    ///   registered, reported as an [Errcode::IllegalInput]
     /// - The associated user does not exist (when UAID is provided)
     /// - Database connection or operation fails
     pub(crate) async fn insert<S: Signature, P: PublicKey<S>>(
         db: &Database,
         public_key: &P,
         uaid: Option<Uuid>,
+        security_config: &SecurityConfig,
+    ) -> Result<Self, Error> {
+        let mut transaction = db.pool.begin().await?;
+        let public_key_info =
+            Self::insert_on(&mut transaction, public_key, uaid, security_config).await?;
+        transaction.commit().await?;
+        Ok(public_key_info)
+    }
+
+    /// Like [Self::insert], but performs all checks and the insertion on the
+    /// given `connection`, which has to be a transaction for
+    /// `max_keys_per_actor` and `enforce_globally_unique_keys` to hold under
+    /// concurrent insertions.
+    pub(super) async fn insert_on<S: Signature, P: PublicKey<S>>(
+        connection: &mut PgConnection,
+        public_key: &P,
+        uaid: Option<Uuid>,
+        security_config: &SecurityConfig,
     ) -> Result<Self, Error> {
         let public_key_algo = public_key.algorithm_identifier();
-        let public_key_info = hex::encode(
-            public_key.public_key_info().public_key_bitstring.to_der().map_err(|e| {
-                error!("{ALGORITHM_IDENTIFER_TO_DER_ERROR_MESSAGE}: {e}");
-                Error::new_internal_error(None)
-            })?,
-        );
+        let public_key_info = Self::encode_public_key(public_key)?;
         let Some(algorithm_identifiers_row) =
-            AlgorithmIdentifier::get_by_algorithm_identifier(db, &public_key_algo).await?
+            AlgorithmIdentifier::get_by_algorithm_identifier_on(connection, &public_key_algo)
+                .await?
         else {
-            error!("Public Key {CONTAINS_UNKNOWN_CRYPTO_ALGOS_ERROR_MESSAGE}");
-            return Err(Error::new_internal_error(None));
+            return Err(AlgorithmIdentifier::unsupported_error_on(
+                connection,
+                Some("public_key"),
+                &public_key_algo.oid,
+                &format!("Public Key {CONTAINS_UNKNOWN_CRYPTO_ALGOS_ERROR_MESSAGE}"),
+            )
+            .await);
         };
+        if security_config.enforce_globally_unique_keys {
+            // Serializes concurrent insertions of the same key until the end of the
+            // transaction, so that only one of them can pass this check
+            query!("SELECT pg_advisory_xact_lock(hashtext($1))", public_key_info)
+                .execute(&mut *connection)
+                .await?;
+            if query!("SELECT id FROM public_keys WHERE pubkey = $1 LIMIT 1", public_key_info)
+                .fetch_optional(&mut *connection)
+                .await?
+                .is_some()
+            {
+                return Err(Error::new(
+                    Errcode::Duplicate,
+                    Some(Context::new(
+                        Some("public_key"),
+                        None,
+                        None,
+                        Some("This public key has already been registered"),
+                    )),
+                ));
+            }
+        }
+        if let Some(uaid) = uaid {
+            // Serializes concurrent insertions for the same actor, so that each
+            // of them counts the keys committed by the others
+            query!("SELECT uaid FROM local_actors WHERE uaid = $1 FOR NO KEY UPDATE", uaid)
+                .fetch_optional(&mut *connection)
+                .await?;
+        }
+        // The key is only inserted, if the actor has fewer than
+        // `max_keys_per_actor` keys registered
         let result = query!(
             r#"
             INSERT INTO public_keys (uaid, pubkey, algorithm_identifier)
-            VALUES ($1, $2, $3)
+            SELECT $1, $2, $3
+            WHERE $1::uuid IS NULL
+                OR (SELECT COUNT(*) FROM public_keys WHERE uaid = $1) < $4
             RETURNING id
         "#,
             uaid,
             public_key_info,
-            algorithm_identifiers_row.id()
+            algorithm_identifiers_row.id(),
+            i64::from(security_config.max_keys_per_actor)
         )
-        .fetch_optional(&db.pool)
+        .fetch_optional(&mut *connection)
         .await?;
-        // Actually not fully sure of the semantics here: If there is a duplicate, will
-        // this throw an error, or will it just return None?
         match result {
             Some(record) => Ok(Self {
                 id: record.id,
@@ -142,26 +281,191 @@ impl PublicKeyInfo {
             None => Err(Error::new(
                 Errcode::IllegalInput,
                 Some(Context::new(
+                    Some("public_key"),
                     None,
+                    Some(&format!(
+                        "Not more than {} registered public keys",
+                        security_config.max_keys_per_actor
+                    )),
                     None,
-                    None,
-                    Some(
-                        "Either this public key already has been stored, or the requested user does not exist",
-                    ),
                 )),
             )),
         }
     }
+
+    /// Insert the `public_key`, unless the actor identified by `uaid` already
+    /// has it registered, and store the ID-CSR and ID-Cert described by `cert`
+    /// for it in a single transaction. If any of these steps fails, none of
+    /// the changes are kept. The extensions of the ID-CSR are stored both raw
+    /// and parsed into [CsrExtensions].
+    ///
+    /// ## Returns
+    ///
+    /// The [SerialNumber] of the newly stored ID-Cert.
+    ///
+    /// ## Errors
+    ///
+    /// - Any error of [Self::insert], if the `public_key` is new
+    /// - Any error of [CsrExtensions::from_der_hex], if the extensions of the
+    ///   ID-CSR are malformed
+    /// - [Errcode::Duplicate], if the actor identified by `uaid` already has an
+    ///   ID-Cert for the session of the ID-CSR, which has not been invalidated
+    /// - If the ID-CSR or ID-Cert cannot be stored, e.g. because the serial
+    ///   number is already taken or the issuer does not exist
+    /// - Database connection or operation fails
+    pub(crate) async fn insert_with_cert<S: Signature, P: PublicKey<S>>(
+        db: &Database,
+        public_key: &P,
+        uaid: Option<Uuid>,
+        security_config: &SecurityConfig,
+        cert: &NewIdCert,
+    ) -> Result<SerialNumber, Error> {
+        let extensions = CsrExtensions::from_der_hex(&cert.extensions)?;
+        let session_id = cert.session_id.to_string();
+        let mut transaction = db.pool.begin().await?;
+        if uaid.is_some()
+            && query!(
+                "SELECT id FROM idcsr
+                WHERE uaid = $1 AND session_id = $2 AND invalidation_info IS NULL",
+                uaid,
+                session_id
+            )
+            .fetch_optional(&mut *transaction)
+            .await?
+            .is_some()
+        {
+            return Err(Error::new(
+                Errcode::Duplicate,
+                Some(Context::new(
+                    Some("session_id"),
+                    Some(&session_id),
+                    None,
+                    Some("This session already has an ID-Cert, which has not been invalidated"),
+                )),
+            ));
+        }
+        let public_key_info = Self::encode_public_key(public_key)?;
+        let public_key_id = match query!(
+            "SELECT id FROM public_keys WHERE pubkey = $1 AND uaid IS NOT DISTINCT FROM $2",
+            public_key_info,
+            uaid
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        {
+            Some(existing) => existing.id,
+            None => Self::insert_on(&mut transaction, public_key, uaid, security_config).await?.id,
+        };
+        let idcsr = query!(
+            r#"
+            INSERT INTO idcsr (
+                serial_number, uaid, subject_public_key_id, subject_signature, session_id,
+                valid_not_before, valid_not_after, extensions, pem_encoded, key_usages,
+                basic_constraints_ca, basic_constraints_path_length, unrecognized_extensions
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            RETURNING id, serial_number
+        "#,
+            cert.serial_number.as_bigdecimal(),
+            uaid,
+            public_key_id,
+            cert.subject_signature,
+            session_id,
+            cert.valid_not_before,
+            cert.valid_not_after,
+            cert.extensions,
+            cert.csr_pem,
+            &extensions.key_usage_names(),
+            extensions.basic_constraints.map(|constraints| constraints.ca),
+            extensions
+                .basic_constraints
+                .and_then(|constraints| constraints.path_length)
+                .map(|path_length| i64::try_from(path_length).unwrap_or(i64::MAX)),
+            &extensions.unrecognized
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+        query!(
+            r#"
+            INSERT INTO idcert (
+                idcsr_id, issuer_info_id, valid_not_before, valid_not_after,
+                home_server_public_key_id, home_server_signature, pem_encoded
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+            idcsr.id,
+            cert.issuer_info_id,
+            cert.valid_not_before,
+            cert.valid_not_after,
+            cert.home_server_public_key_id,
+            cert.home_server_signature,
+            cert.cert_pem
+        )
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(SerialNumber::from(idcsr.serial_number))
+    }
+
+    /// Decode the stored [Self::pubkey] into a [PublicKey] of the algorithm of
+    /// `S`. The inverse of [Self::encode_public_key].
+    ///
+    /// ## Errors
+    ///
+    /// An internal error, if the stored value is not a key of that algorithm.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn to_public_key<S: Signature, P: PublicKey<S>>(&self) -> Result<P, Error> {
+        let bitstring = hex::decode(&self.pubkey)
+            .map_err(|e| e.to_string())
+            .and_then(|der| BitString::from_der(&der).map_err(|e| e.to_string()))
+            .map_err(|e| {
+                error!("Stored public key {} is malformed: {e}", self.id);
+                Error::new_internal_error(None)
+            })?;
+        P::try_from_public_key_info(polyproto::certs::PublicKeyInfo {
+            algorithm: S::algorithm_identifier(),
+            public_key_bitstring: bitstring,
+        })
+        .map_err(|e| {
+            error!("Stored public key {} is not a valid key: {e}", self.id);
+            Error::new_internal_error(None)
+        })
+    }
+
+    /// Hex-encode the DER-encoded bit string of the `public_key`, which is how
+    /// public keys are stored in the `public_keys` table.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn encode_public_key<S: Signature, P: PublicKey<S>>(
+        public_key: &P,
+    ) -> Result<String, Error> {
+        Ok(hex::encode(public_key.public_key_info().public_key_bitstring.to_der().map_err(
+            |e| {
+                error!("{ALGORITHM_IDENTIFER_TO_DER_ERROR_MESSAGE}: {e}");
+                Error::new_internal_error(None)
+            },
+        )?))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
 
+    use polyproto::{
+        certs::{
+            SessionId,
+            capabilities::{Capabilities, KeyUsage},
+        },
+        der::Encode,
+    };
     use sqlx::{Pool, Postgres};
+    use x509_cert::ext::Extensions;
 
     use super::*;
-    use crate::crypto::ed25519::{DigitalPublicKey, DigitalSignature, generate_keypair};
+    use crate::{
+        crypto::ed25519::{DigitalPublicKey, DigitalSignature, generate_keypair},
+        database::StoredIdCsr,
+    };
 
     #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
     async fn test_get_by_empty_parameters(pool: Pool<Postgres>) {
@@ -188,6 +492,18 @@ mod tests {
         assert_eq!(result[0].algorithm_identifier, 1);
     }
 
+    #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_by_id(pool: Pool<Postgres>) {
+        let db = Database { pool };
+
+        let key = PublicKeyInfo::by_id(&db, 1).await.unwrap().unwrap();
+        assert_eq!(key.id(), 1);
+        assert_eq!(key.pubkey, "test_pubkey_1");
+        assert_eq!(key, PublicKeyInfo::get_by(&db, None, None, None, Some(1)).await.unwrap()[0]);
+
+        assert!(PublicKeyInfo::by_id(&db, 999999).await.unwrap().is_none());
+    }
+
     #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
     async fn test_get_by_uaid(pool: Pool<Postgres>) {
         let db = Database { pool };
@@ -246,6 +562,63 @@ mod tests {
         }
     }
 
+    #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_get_by_with_algorithm(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let test_uaid = Uuid::from_str("00000000-0000-0000-0000-000000000002").unwrap();
+        query!(
+            "INSERT INTO public_keys (id, uaid, pubkey, algorithm_identifier) VALUES (100, $1, \
+             'test_pubkey_ec', 2)",
+            test_uaid
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+        let mut result =
+            PublicKeyInfo::get_by_with_algorithm(&db, Some(test_uaid), None, None, None)
+                .await
+                .unwrap();
+        result.sort_by_key(|(key, _)| key.algorithm_identifier);
+        let keys = result
+            .iter()
+            .map(|(key, name)| (key.pubkey.as_str(), key.algorithm_identifier, name.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![("test_pubkey_2", 1, Some("RSA")), ("test_pubkey_ec", 2, Some("EC"))]
+        );
+
+        // The keys themselves match those returned by `get_by`
+        let plain = PublicKeyInfo::get_by(&db, None, None, None, Some(1)).await.unwrap();
+        let joined =
+            PublicKeyInfo::get_by_with_algorithm(&db, None, None, None, Some(1)).await.unwrap();
+        assert_eq!(
+            joined,
+            plain.into_iter().map(|key| (key, Some("RSA".to_owned()))).collect::<Vec<_>>()
+        );
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_get_by_with_algorithm_empty_parameters_and_missing_name(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        assert!(
+            PublicKeyInfo::get_by_with_algorithm(&db, None, None, None, None)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        query!("UPDATE algorithm_identifiers SET common_name = NULL WHERE id = 1")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        let result =
+            PublicKeyInfo::get_by_with_algorithm(&db, None, None, None, Some(3)).await.unwrap();
+        assert_eq!(result.len(), 1);
+        assert!(result.iter().all(|(key, name)| key.pubkey == "test_pubkey_3" && name.is_none()));
+    }
+
     #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
     async fn test_get_by_nonexistent_data(pool: Pool<Postgres>) {
         let db = Database { pool };
@@ -267,6 +640,7 @@ mod tests {
             &db,
             &public_key,
             Some(test_uaid),
+            &SecurityConfig::default(),
         )
         .await;
 
@@ -279,14 +653,39 @@ mod tests {
         let db = Database { pool };
         let (_private_key, public_key) = generate_keypair();
 
-        let result =
-            PublicKeyInfo::insert::<DigitalSignature, DigitalPublicKey>(&db, &public_key, None)
-                .await;
+        let result = PublicKeyInfo::insert::<DigitalSignature, DigitalPublicKey>(
+            &db,
+            &public_key,
+            None,
+            &SecurityConfig::default(),
+        )
+        .await;
 
         // This should fail because Ed25519 is not in the base fixture
         assert!(result.is_err(), "Expected error because Ed25519 algorithm is not in the fixture");
     }
 
+    #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_insert_unsupported_algorithm_names_oid(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let (_private_key, public_key) = generate_keypair();
+
+        let error = PublicKeyInfo::insert::<DigitalSignature, DigitalPublicKey>(
+            &db,
+            &public_key,
+            None,
+            &SecurityConfig::default(),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(error.code, Errcode::IllegalInput);
+        let context = error.context.unwrap();
+        assert_eq!(context.field_name, "public_key");
+        assert_eq!(context.found, public_key.algorithm_identifier().oid.to_string());
+        assert_eq!(context.expected, "id-ecPublicKey, rsaEncryption");
+    }
+
     #[sqlx::test(fixtures("../../fixtures/idcert_integration_tests.sql"))]
     async fn test_insert_ed25519_key_success(pool: Pool<Postgres>) {
         let db = Database { pool };
@@ -297,6 +696,7 @@ mod tests {
             &db,
             &public_key,
             Some(test_uaid),
+            &SecurityConfig::default(),
         )
         .await;
 
@@ -312,6 +712,29 @@ mod tests {
         }
     }
 
+    #[sqlx::test]
+    async fn test_insert_ecdsa_key_success(pool: Pool<Postgres>) {
+        use crate::crypto::{ecdsa, known_algorithm_identifiers};
+
+        let db = Database { pool };
+        let (_private_key, public_key) = ecdsa::generate_keypair();
+
+        // ECDSA keys are only accepted once the algorithm is known, as done on startup
+        assert!(
+            PublicKeyInfo::insert(&db, &public_key, None, &SecurityConfig::default())
+                .await
+                .is_err()
+        );
+        let ids =
+            AlgorithmIdentifier::ensure_all(&db, &known_algorithm_identifiers()).await.unwrap();
+
+        let key_info = PublicKeyInfo::insert(&db, &public_key, None, &SecurityConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(key_info.uaid, None);
+        assert_eq!(Some(&key_info.algorithm_identifier), ids.get(1));
+    }
+
     #[sqlx::test(fixtures("../../fixtures/idcert_integration_tests.sql"))]
     async fn test_insert_duplicate_key_error(pool: Pool<Postgres>) {
         let db = Database { pool };
@@ -323,6 +746,7 @@ mod tests {
             &db,
             &public_key,
             Some(test_uaid),
+            &SecurityConfig::default(),
         )
         .await;
         assert!(first_result.is_ok(), "First insertion should succeed");
@@ -332,6 +756,7 @@ mod tests {
             &db,
             &public_key,
             Some(test_uaid),
+            &SecurityConfig::default(),
         )
         .await;
         assert!(second_result.is_err(), "Second insertion should fail due to duplicate");
@@ -347,6 +772,7 @@ mod tests {
             &db,
             &public_key,
             Some(nonexistent_uaid),
+            &SecurityConfig::default(),
         )
         .await;
 
@@ -364,6 +790,7 @@ mod tests {
             &db,
             &public_key,
             Some(test_uaid),
+            &SecurityConfig::default(),
         )
         .await
         .unwrap();
@@ -381,4 +808,337 @@ mod tests {
         assert_eq!(retrieved_key.pubkey, inserted_key.pubkey);
         assert_eq!(retrieved_key.algorithm_identifier, inserted_key.algorithm_identifier);
     }
+
+    #[sqlx::test(fixtures("../../fixtures/idcert_integration_tests.sql"))]
+    async fn test_insert_shared_key_globally_unique_keys_enforced(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let (_private_key, public_key) = generate_keypair();
+        let security_config =
+            SecurityConfig { enforce_globally_unique_keys: true, ..Default::default() };
+
+        PublicKeyInfo::insert::<DigitalSignature, DigitalPublicKey>(
+            &db,
+            &public_key,
+            Some(Uuid::from_str("00000000-0000-0000-0000-000000000010").unwrap()),
+            &security_config,
+        )
+        .await
+        .unwrap();
+        let error = PublicKeyInfo::insert::<DigitalSignature, DigitalPublicKey>(
+            &db,
+            &public_key,
+            Some(Uuid::from_str("00000000-0000-0000-0000-000000000011").unwrap()),
+            &security_config,
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(error.code, Errcode::Duplicate);
+        assert_eq!(error.context.unwrap().field_name, "public_key");
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/idcert_integration_tests.sql"))]
+    async fn test_insert_shared_key_globally_unique_keys_not_enforced(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let (_private_key, public_key) = generate_keypair();
+        let security_config =
+            SecurityConfig { enforce_globally_unique_keys: false, ..Default::default() };
+        let first_uaid = Uuid::from_str("00000000-0000-0000-0000-000000000010").unwrap();
+        let second_uaid = Uuid::from_str("00000000-0000-0000-0000-000000000011").unwrap();
+
+        let first = PublicKeyInfo::insert::<DigitalSignature, DigitalPublicKey>(
+            &db,
+            &public_key,
+            Some(first_uaid),
+            &security_config,
+        )
+        .await
+        .unwrap();
+        let second = PublicKeyInfo::insert::<DigitalSignature, DigitalPublicKey>(
+            &db,
+            &public_key,
+            Some(second_uaid),
+            &security_config,
+        )
+        .await
+        .unwrap();
+        assert_ne!(first.id(), second.id());
+        assert_eq!(first.pubkey, second.pubkey);
+
+        // The same actor still cannot register the same key twice
+        assert!(
+            PublicKeyInfo::insert::<DigitalSignature, DigitalPublicKey>(
+                &db,
+                &public_key,
+                Some(first_uaid),
+                &security_config,
+            )
+            .await
+            .is_err()
+        );
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/idcert_integration_tests.sql"))]
+    async fn test_insert_concurrent_shared_key_globally_unique_keys_enforced(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let (_private_key, public_key) = generate_keypair();
+        let security_config = SecurityConfig::default();
+        let insert = |uaid: &str| {
+            PublicKeyInfo::insert::<DigitalSignature, DigitalPublicKey>(
+                &db,
+                &public_key,
+                Some(Uuid::from_str(uaid).unwrap()),
+                &security_config,
+            )
+        };
+
+        let (first, second) = tokio::join!(
+            insert("00000000-0000-0000-0000-000000000010"),
+            insert("00000000-0000-0000-0000-000000000011")
+        );
+        assert!(first.is_ok() != second.is_ok());
+        assert_eq!(first.and(second).unwrap_err().code, Errcode::Duplicate);
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/idcert_integration_tests.sql"))]
+    async fn test_insert_shared_key_without_actor_globally_unique_keys_not_enforced(
+        pool: Pool<Postgres>,
+    ) {
+        let db = Database { pool };
+        let (_private_key, public_key) = generate_keypair();
+        let security_config =
+            SecurityConfig { enforce_globally_unique_keys: false, ..Default::default() };
+
+        PublicKeyInfo::insert::<DigitalSignature, DigitalPublicKey>(
+            &db,
+            &public_key,
+            None,
+            &security_config,
+        )
+        .await
+        .unwrap();
+        // Keys without an actor are deduplicated by the database as well
+        assert!(
+            PublicKeyInfo::insert::<DigitalSignature, DigitalPublicKey>(
+                &db,
+                &public_key,
+                None,
+                &security_config,
+            )
+            .await
+            .is_err()
+        );
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/idcert_integration_tests.sql"))]
+    async fn test_insert_respects_max_keys_per_actor(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let security_config = SecurityConfig { max_keys_per_actor: 3, ..Default::default() };
+        let uaid = Uuid::from_str("00000000-0000-0000-0000-000000000010").unwrap();
+
+        // The fixture already contains one public key for this actor
+        for _ in 0..2 {
+            let (_private_key, public_key) = generate_keypair();
+            PublicKeyInfo::insert::<DigitalSignature, DigitalPublicKey>(
+                &db,
+                &public_key,
+                Some(uaid),
+                &security_config,
+            )
+            .await
+            .unwrap();
+        }
+        let (_private_key, public_key) = generate_keypair();
+        let error = PublicKeyInfo::insert::<DigitalSignature, DigitalPublicKey>(
+            &db,
+            &public_key,
+            Some(uaid),
+            &security_config,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, Errcode::IllegalInput);
+        assert_eq!(error.context.unwrap().field_name, "public_key");
+
+        // The cap is per actor
+        PublicKeyInfo::insert::<DigitalSignature, DigitalPublicKey>(
+            &db,
+            &public_key,
+            Some(Uuid::from_str("00000000-0000-0000-0000-000000000011").unwrap()),
+            &security_config,
+        )
+        .await
+        .unwrap();
+    }
+
+    fn new_id_cert(serial_number: u64, issuer_info_id: i64) -> NewIdCert {
+        let now = chrono::Utc::now().naive_utc();
+        NewIdCert {
+            serial_number: SerialNumber::from(sqlx::types::BigDecimal::from(serial_number)),
+            session_id: SessionId::new_validated("session_new_cert").unwrap(),
+            subject_signature: "subject_signature_new_cert".to_owned(),
+            extensions: hex::encode(
+                Extensions::try_from(Capabilities::default_actor()).unwrap().to_der().unwrap(),
+            ),
+            csr_pem: "csr_pem_new_cert".to_owned(),
+            valid_not_before: now,
+            valid_not_after: now.checked_add_days(chrono::Days::new(30)).unwrap(),
+            issuer_info_id,
+            home_server_public_key_id: 200,
+            home_server_signature: "home_server_signature_new_cert".to_owned(),
+            cert_pem: "cert_pem_new_cert".to_owned(),
+        }
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/idcert_integration_tests.sql"))]
+    async fn test_concurrent_inserts_respect_max_keys_per_actor(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let security_config = SecurityConfig { max_keys_per_actor: 3, ..Default::default() };
+        let uaid = Uuid::from_str("00000000-0000-0000-0000-000000000010").unwrap();
+
+        let public_keys = (0..6).map(|_| generate_keypair().1).collect::<Vec<_>>();
+        let results = futures_util::future::join_all(public_keys.iter().map(|public_key| {
+            PublicKeyInfo::insert::<DigitalSignature, DigitalPublicKey>(
+                &db,
+                public_key,
+                Some(uaid),
+                &security_config,
+            )
+        }))
+        .await;
+        // The fixture already contains one public key for this actor
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 2);
+        let count = query!(r#"SELECT COUNT(*) AS "count!" FROM public_keys WHERE uaid = $1"#, uaid)
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(count.count, 3);
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/idcert_integration_tests.sql"))]
+    async fn test_insert_with_cert(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let (_private_key, public_key) = generate_keypair();
+        let uaid = Uuid::from_str("00000000-0000-0000-0000-000000000010").unwrap();
+
+        let serial_number = PublicKeyInfo::insert_with_cert::<DigitalSignature, DigitalPublicKey>(
+            &db,
+            &public_key,
+            Some(uaid),
+            &SecurityConfig::default(),
+            &new_id_cert(42, 100),
+        )
+        .await
+        .unwrap();
+        assert_eq!(serial_number.as_bigdecimal(), &sqlx::types::BigDecimal::from(42));
+
+        let stored = query!(
+            r#"SELECT idcert.issuer_info_id, public_keys.uaid
+            FROM idcsr
+            JOIN idcert ON idcert.idcsr_id = idcsr.id
+            JOIN public_keys ON public_keys.id = idcsr.subject_public_key_id
+            WHERE idcsr.serial_number = 42"#
+        )
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!((stored.issuer_info_id, stored.uaid), (100, Some(uaid)));
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/idcert_integration_tests.sql"))]
+    async fn test_insert_with_cert_rolls_back_on_failure(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let (_private_key, public_key) = generate_keypair();
+        let uaid = Uuid::from_str("00000000-0000-0000-0000-000000000010").unwrap();
+        let count_keys = async || {
+            query!(r#"SELECT COUNT(*) AS "count!" FROM public_keys"#)
+                .fetch_one(&db.pool)
+                .await
+                .unwrap()
+                .count
+        };
+        let keys_before = count_keys().await;
+
+        // There is no issuer with ID 999, so inserting the ID-Cert fails after both the
+        // public key and the ID-CSR have been inserted
+        PublicKeyInfo::insert_with_cert::<DigitalSignature, DigitalPublicKey>(
+            &db,
+            &public_key,
+            Some(uaid),
+            &SecurityConfig::default(),
+            &new_id_cert(42, 999),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(count_keys().await, keys_before);
+        assert!(
+            query!("SELECT id FROM idcsr WHERE serial_number = 42")
+                .fetch_optional(&db.pool)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/idcert_integration_tests.sql"))]
+    async fn test_insert_with_cert_parses_extensions(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let uaid = Uuid::from_str("00000000-0000-0000-0000-000000000010").unwrap();
+        let mut home_server_cert = new_id_cert(43, 100);
+        home_server_cert.session_id = SessionId::new_validated("session_home_server").unwrap();
+        home_server_cert.subject_signature = "subject_signature_home_server_cert".to_owned();
+        home_server_cert.csr_pem = "csr_pem_home_server_cert".to_owned();
+        home_server_cert.home_server_signature = "home_server_signature_home_server".to_owned();
+        home_server_cert.cert_pem = "cert_pem_home_server_cert".to_owned();
+        home_server_cert.extensions = hex::encode(
+            Extensions::try_from(Capabilities::default_home_server()).unwrap().to_der().unwrap(),
+        );
+        for cert in [new_id_cert(42, 100), home_server_cert] {
+            PublicKeyInfo::insert_with_cert::<DigitalSignature, DigitalPublicKey>(
+                &db,
+                &generate_keypair().1,
+                Some(uaid),
+                &SecurityConfig::default(),
+                &cert,
+            )
+            .await
+            .unwrap();
+        }
+
+        let serial_numbers = async |key_usage| {
+            StoredIdCsr::by_key_usage(&db, key_usage)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|csr| csr.serial_number)
+                .collect::<Vec<_>>()
+        };
+        let serial_number = |value: u64| SerialNumber::from(sqlx::types::BigDecimal::from(value));
+        assert_eq!(serial_numbers(KeyUsage::KeyCertSign).await, vec![serial_number(43)]);
+        assert_eq!(serial_numbers(KeyUsage::DigitalSignature).await, vec![serial_number(42)]);
+        assert!(serial_numbers(KeyUsage::CrlSign).await.is_empty());
+
+        let csr = StoredIdCsr::by_serial(&db, &serial_number(43)).await.unwrap().unwrap();
+        let extensions = csr.parsed_extensions.clone().unwrap();
+        assert_eq!(extensions.key_usages, vec![KeyUsage::KeyCertSign]);
+        assert!(extensions.basic_constraints.unwrap().ca);
+        assert!(extensions.unrecognized.is_empty());
+        // The raw copy is kept
+        assert_eq!(CsrExtensions::from_der_hex(&csr.extensions).unwrap(), extensions);
+
+        // Malformed extensions are rejected
+        let mut malformed = new_id_cert(44, 100);
+        malformed.extensions = "extensions_new_cert".to_owned();
+        let error = PublicKeyInfo::insert_with_cert::<DigitalSignature, DigitalPublicKey>(
+            &db,
+            &generate_keypair().1,
+            Some(uaid),
+            &SecurityConfig::default(),
+            &malformed,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, Errcode::IllegalInput);
+    }
 }
@@ -0,0 +1,121 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use sqlx::types::Uuid;
+
+use crate::{
+    database::{Database, LocalActor},
+    errors::Error,
+};
+
+/// The [LocalActor] operations needed by the registration and login logic.
+/// Implemented by [Database]; abstracted so that the branching of these
+/// handlers can be tested against an in-memory implementation, without a
+/// running database.
+pub(crate) trait ActorRepository {
+    /// Find the [LocalActor] called `local_name`. See
+    /// [LocalActor::by_local_name].
+    async fn local_actor_by_name(
+        &self,
+        local_name: &str,
+        case_insensitive: bool,
+    ) -> Result<Option<LocalActor>, Error>;
+
+    /// Get the password hash of the [LocalActor] called `local_name`. See
+    /// [LocalActor::get_password_hash].
+    async fn password_hash(
+        &self,
+        local_name: &str,
+        case_insensitive: bool,
+    ) -> Result<Option<String>, Error>;
+
+    /// Create a new [LocalActor]. See [LocalActor::create].
+    async fn create_local_actor(
+        &self,
+        local_name: &str,
+        password_hash: &str,
+        case_insensitive: bool,
+    ) -> Result<LocalActor, Error>;
+
+    /// Create a new [LocalActor], consuming one usage of the invite identified
+    /// by `invite_code`. See [Database::register_with_invite].
+    async fn create_local_actor_with_invite(
+        &self,
+        invite_code: &str,
+        local_name: &str,
+        password_hash: &str,
+        case_insensitive: bool,
+    ) -> Result<LocalActor, Error>;
+
+    /// Whether logins to the [LocalActor] identified by `uaid` are locked. See
+    /// [LocalActor::is_login_locked].
+    async fn is_login_locked(&self, uaid: &Uuid) -> Result<bool, Error>;
+
+    /// Count a failed login to the [LocalActor] identified by `uaid`. See
+    /// [LocalActor::record_failed_login].
+    async fn record_failed_login(
+        &self,
+        uaid: &Uuid,
+        threshold: u32,
+        cooldown_secs: u64,
+    ) -> Result<bool, Error>;
+
+    /// Reset the failed logins of the [LocalActor] identified by `uaid`. See
+    /// [LocalActor::clear_failed_logins].
+    async fn clear_failed_logins(&self, uaid: &Uuid) -> Result<(), Error>;
+}
+
+impl ActorRepository for Database {
+    async fn local_actor_by_name(
+        &self,
+        local_name: &str,
+        case_insensitive: bool,
+    ) -> Result<Option<LocalActor>, Error> {
+        LocalActor::by_local_name(self, local_name, case_insensitive).await
+    }
+
+    async fn password_hash(
+        &self,
+        local_name: &str,
+        case_insensitive: bool,
+    ) -> Result<Option<String>, Error> {
+        LocalActor::get_password_hash(self, local_name, case_insensitive).await
+    }
+
+    async fn create_local_actor(
+        &self,
+        local_name: &str,
+        password_hash: &str,
+        case_insensitive: bool,
+    ) -> Result<LocalActor, Error> {
+        LocalActor::create(self, local_name, password_hash, case_insensitive).await
+    }
+
+    async fn create_local_actor_with_invite(
+        &self,
+        invite_code: &str,
+        local_name: &str,
+        password_hash: &str,
+        case_insensitive: bool,
+    ) -> Result<LocalActor, Error> {
+        self.register_with_invite(invite_code, local_name, password_hash, case_insensitive).await
+    }
+
+    async fn is_login_locked(&self, uaid: &Uuid) -> Result<bool, Error> {
+        LocalActor::is_login_locked(self, uaid).await
+    }
+
+    async fn record_failed_login(
+        &self,
+        uaid: &Uuid,
+        threshold: u32,
+        cooldown_secs: u64,
+    ) -> Result<bool, Error> {
+        LocalActor::record_failed_login(self, uaid, threshold, cooldown_secs).await
+    }
+
+    async fn clear_failed_logins(&self, uaid: &Uuid) -> Result<(), Error> {
+        LocalActor::clear_failed_logins(self, uaid).await
+    }
+}
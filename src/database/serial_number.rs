@@ -1,9 +1,25 @@
+use std::{collections::HashSet, str::FromStr};
+
 use bigdecimal::num_bigint::BigUint;
 use log::error;
 use rand::TryRngCore;
 use sqlx::{Decode, Encode, Postgres, Type, query, types::BigDecimal};
 
-use crate::{database::Database, errors::Error};
+use crate::{
+    config::SerialNumberMsbStrategy,
+    database::Database,
+    errors::{Context, Errcode, Error},
+};
+
+/// How many decimal digits a serial number of at most 20 octets (160 bits) can
+/// have: `2^160 - 1` has 49 digits.
+const MAX_DECIMAL_DIGITS: usize = 49;
+/// How many octets a serial number is encoded with. See
+/// [SerialNumber::normalize_first_byte].
+const ENCODED_OCTETS: usize = 20;
+/// How many octets a serial number may be decoded from. See
+/// [SerialNumber::normalize_first_byte].
+const MAX_DECODED_OCTETS: usize = 21;
 
 // TODO: This could be in polyproto instead
 
@@ -15,7 +31,7 @@ impl SerialNumber {
     /// Tries to generate a [SerialNumber] which does not yet exist in the
     /// `idcsr` table and its' `serial_number` column.
     ///
-    /// Calls [Self::try_generate_random] internally.
+    /// Calls [Self::try_generate_unique_batch] internally.
     ///
     /// ## Errors
     ///
@@ -27,35 +43,94 @@ impl SerialNumber {
     /// - The database or database connection is unavailable for any reason.
     pub(crate) async fn try_generate_unique_random(
         db: &Database,
-        rng: &mut rand::rngs::ThreadRng,
+        strategy: SerialNumberMsbStrategy,
     ) -> Result<Self, Error> {
-        let mut serial_number =
-            SerialNumber::try_generate_random(&mut rand::rng()).map_err(|e| {
-                error!("Error while trying to generate serial_number: {e}");
-                Error::new_internal_error(None)
-            })?;
-        while (query!(
-            "
-                SELECT serial_number
-                FROM idcsr
-                WHERE serial_number = $1
-            ",
-            serial_number.as_bigdecimal()
+        Self::try_generate_unique_batch(db, 1, strategy)
+            .await?
+            .pop()
+            .ok_or_else(|| Error::new_internal_error(None))
+    }
+
+    /// Generate `n` distinct serial numbers, none of which exist in the `idcsr`
+    /// table and its' `serial_number` column yet, for example to pre-provision
+    /// serial numbers when issuing many certificates at once.
+    ///
+    /// Calls [Self::try_generate_batch] internally.
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if:
+    ///
+    /// - The [ThreadRng] fails to generate randomness. Depending on the
+    ///   implementation of `ThreadRng`, this method may cause a panic in these
+    ///   cases.
+    /// - The database or database connection is unavailable for any reason.
+    pub(crate) async fn try_generate_unique_batch(
+        db: &Database,
+        n: usize,
+        strategy: SerialNumberMsbStrategy,
+    ) -> Result<Vec<Self>, Error> {
+        let mut batch = Vec::with_capacity(n);
+        while batch.len() < n {
+            // The `ThreadRng` is not `Send`, so it must not be held across the query
+            let candidates =
+                Self::try_generate_batch(&mut rand::rng(), n.saturating_sub(batch.len()), strategy)
+                    .map_err(|e| {
+                        error!("Error while trying to generate serial_number: {e}");
+                        Error::new_internal_error(None)
+                    })?
+                    .into_iter()
+                    .filter(|candidate| !batch.contains(candidate))
+                    .collect();
+            batch.extend(Self::exclude_taken(db, candidates).await?);
+        }
+        Ok(batch)
+    }
+
+    /// Remove all serial numbers from `candidates`, which exist in the `idcsr`
+    /// table and its' `serial_number` column already.
+    async fn exclude_taken(db: &Database, candidates: Vec<Self>) -> Result<Vec<Self>, Error> {
+        let taken = query!(
+            "SELECT serial_number FROM idcsr WHERE serial_number = ANY($1)",
+            &candidates.iter().map(|candidate| candidate.0.clone()).collect::<Vec<_>>()
         )
-        .fetch_optional(&db.pool)
-        .await?)
-            .is_some()
-        {
-            serial_number = SerialNumber::try_generate_random(rng).map_err(|e| {
-                error!("Error while trying to generate serial_number: {e}");
-                Error::new_internal_error(None)
-            })?;
+        .fetch_all(&db.pool)
+        .await?
+        .into_iter()
+        .map(|record| Self(record.serial_number))
+        .collect::<HashSet<_>>();
+        Ok(candidates.into_iter().filter(|candidate| !taken.contains(candidate)).collect())
+    }
+
+    /// Generate `n` distinct serial numbers using [Self::try_generate_random].
+    /// Coincidental duplicates within the batch are replaced by newly generated
+    /// serial numbers.
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if the [ThreadRng] fails to generate randomness. Depending
+    /// on the implementation of `ThreadRng`, this method may cause a panic in
+    /// these cases.
+    pub fn try_generate_batch(
+        rng: &mut rand::rngs::ThreadRng,
+        n: usize,
+        strategy: SerialNumberMsbStrategy,
+    ) -> Result<Vec<Self>, crate::errors::StdError> {
+        let mut seen = HashSet::with_capacity(n);
+        let mut batch = Vec::with_capacity(n);
+        while batch.len() < n {
+            let serial_number = Self::try_generate_random(rng, strategy)?;
+            if seen.insert(serial_number.clone()) {
+                batch.push(serial_number);
+            }
         }
-        Ok(serial_number)
+        Ok(batch)
     }
 
     /// From a [ThreadRng], get 20 octets (160 bits) of entropy and construct a
-    /// serial number out of it.
+    /// serial number out of it. Draws with a first octet larger than 127 are
+    /// handled according to the [SerialNumberMsbStrategy]. See
+    /// [Self::normalize_first_byte].
     ///
     /// ## Errors
     ///
@@ -64,10 +139,13 @@ impl SerialNumber {
     /// these cases.
     pub fn try_generate_random(
         rng: &mut rand::rngs::ThreadRng,
+        strategy: SerialNumberMsbStrategy,
     ) -> Result<Self, crate::errors::StdError> {
         let mut buf = [0u8; 20];
         rng.try_fill_bytes(&mut buf)?;
-        Self::normalize_first_byte(&mut buf);
+        while !Self::normalize_first_byte(&mut buf, strategy) {
+            rng.try_fill_bytes(&mut buf)?;
+        }
         Ok(Self(BigDecimal::from_biguint(BigUint::from_bytes_be(&buf), 0)))
     }
 
@@ -112,9 +190,15 @@ impl SerialNumber {
     ///
     /// ## The (hacky) solution
     ///
-    /// To adjust for this, we simply take the modulo 128 of the first octet in
-    /// the array. This way, we have a lossy projection from the CSPRNG
-    /// generated first-maybe-valid-octet to a definitely-valid first octet.
+    /// With [SerialNumberMsbStrategy::Project], we simply take the modulo 128
+    /// of the first octet in the array. This way, we have a lossy projection
+    /// from the CSPRNG generated first-maybe-valid-octet to a definitely-valid
+    /// first octet.
+    ///
+    /// With [SerialNumberMsbStrategy::RejectAndRetry], the octets are left
+    /// untouched and `false` is returned for a first octet larger than 127, so
+    /// that the caller draws new octets instead. This way, the serial number
+    /// is exactly what the CSPRNG produced.
     ///
     /// ## Is this cryptographically safe?
     ///
@@ -125,14 +209,67 @@ impl SerialNumber {
     /// should likely just be random *enough*. In my head, the worst case is
     /// that instead of 160 bits of entropy, there will still be 159
     /// bits of entropy left, and that is still a lot of entropy.
-    fn normalize_first_byte(buf: &mut [u8; 20]) {
-        buf[0] %= 128;
+    ///
+    /// ## Returns
+    ///
+    /// Whether `buf` can be used as a serial number after normalization.
+    fn normalize_first_byte(buf: &mut [u8; 20], strategy: SerialNumberMsbStrategy) -> bool {
+        match strategy {
+            SerialNumberMsbStrategy::Project => {
+                buf[0] %= 128;
+                true
+            }
+            SerialNumberMsbStrategy::RejectAndRetry => buf[0] <= 127,
+        }
     }
 
     /// Returns a reference to the inner [BigDecimal] field.
     pub fn as_bigdecimal(&self) -> &BigDecimal {
         &self.0
     }
+
+    /// Encode the serial number as lowercase hex, zero-padded to
+    /// [ENCODED_OCTETS] octets.
+    pub fn to_hex(&self) -> String {
+        let bytes = self.0.with_scale(0).into_bigint_and_scale().0.to_bytes_be().1;
+        let padding = ENCODED_OCTETS.saturating_sub(bytes.len());
+        format!("{}{}", "00".repeat(padding), hex::encode(bytes))
+    }
+
+    /// Decode a serial number from hex, as produced by [Self::to_hex]. Like the
+    /// `x509-cert` crate, up to [MAX_DECODED_OCTETS] octets are accepted on
+    /// decoding.
+    ///
+    /// ## Errors
+    ///
+    /// [Errcode::IllegalInput], if `s` is not valid hex of 1 to
+    /// [MAX_DECODED_OCTETS] octets.
+    #[allow(clippy::result_large_err)]
+    pub fn from_hex(s: &str) -> Result<Self, Error> {
+        let bytes = (!s.is_empty() && s.len() <= MAX_DECODED_OCTETS * 2)
+            .then(|| hex::decode(s).ok())
+            .flatten();
+        let Some(bytes) = bytes else {
+            return Err(Error::new(
+                Errcode::IllegalInput,
+                Some(Context::new(
+                    Some("serial_number"),
+                    None,
+                    Some(&format!("Hex-encoded bytes of at most {MAX_DECODED_OCTETS} octets")),
+                    None,
+                )),
+            ));
+        };
+        Ok(Self(BigDecimal::from_biguint(BigUint::from_bytes_be(&bytes), 0)))
+    }
+}
+
+impl std::fmt::Display for SerialNumber {
+    /// Formats the serial number in its canonical decimal notation, which
+    /// [SerialNumber::from_str] parses.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.with_scale(0).into_bigint_and_scale().0)
+    }
 }
 
 impl From<polyproto::types::x509_cert::SerialNumber> for SerialNumber {
@@ -153,6 +290,34 @@ impl From<BigDecimal> for SerialNumber {
     }
 }
 
+impl FromStr for SerialNumber {
+    type Err = Error;
+
+    /// Parse a [SerialNumber] from its decimal notation, such as a path segment
+    /// of a request. The length of `s` is checked before it is parsed, so that
+    /// gigantic numeric strings are rejected cheaply.
+    ///
+    /// ## Errors
+    ///
+    /// [Errcode::IllegalInput], if `s` is empty, longer than
+    /// [MAX_DECIMAL_DIGITS] or contains anything other than ASCII digits.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s.len() > MAX_DECIMAL_DIGITS || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(Error::new(
+                Errcode::IllegalInput,
+                Some(Context::new(
+                    Some("serial_number"),
+                    // Only echo a bounded prefix of the input back
+                    Some(s.char_indices().nth(MAX_DECIMAL_DIGITS).map_or(s, |(i, _)| &s[..i])),
+                    Some(&format!("A decimal number of at most {MAX_DECIMAL_DIGITS} digits")),
+                    None,
+                )),
+            ));
+        }
+        BigDecimal::from_str(s).map(Self).map_err(|_| Error::new_internal_error(None))
+    }
+}
+
 impl Type<Postgres> for SerialNumber {
     fn type_info() -> <Postgres as sqlx::Database>::TypeInfo {
         BigDecimal::type_info()
@@ -162,16 +327,180 @@ impl Type<Postgres> for SerialNumber {
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod test {
+    use std::str::FromStr;
+
     use rand::rng;
+    use sqlx::{Pool, Postgres, types::BigDecimal};
+
+    use crate::{config::SerialNumberMsbStrategy, database::Database, errors::Errcode};
 
     #[test]
     fn generate_random_serials() {
         let mut rng = rng();
         for _ in 0..5000 {
-            super::SerialNumber::try_generate_random(&mut rng).unwrap();
+            super::SerialNumber::try_generate_random(&mut rng, SerialNumberMsbStrategy::Project)
+                .unwrap();
         }
         for _ in 0..3 {
-            dbg!(super::SerialNumber::try_generate_random(&mut rng).unwrap());
+            dbg!(
+                super::SerialNumber::try_generate_random(
+                    &mut rng,
+                    SerialNumberMsbStrategy::Project
+                )
+                .unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn project_takes_first_byte_modulo_128() {
+        for first in 0..=u8::MAX {
+            let mut buf = [first; 20];
+            assert!(super::SerialNumber::normalize_first_byte(
+                &mut buf,
+                SerialNumberMsbStrategy::Project
+            ));
+            let [msb, rest @ ..] = buf;
+            assert_eq!(msb, first % 128);
+            assert_eq!(rest, [first; 19]);
+        }
+    }
+
+    #[test]
+    fn reject_and_retry_never_yields_high_msb() {
+        for first in 0..=u8::MAX {
+            let mut buf = [first; 20];
+            assert_eq!(
+                super::SerialNumber::normalize_first_byte(
+                    &mut buf,
+                    SerialNumberMsbStrategy::RejectAndRetry
+                ),
+                first <= 127
+            );
+            assert_eq!(buf, [first; 20]);
+        }
+        let mut rng = rng();
+        for _ in 0..5000 {
+            let serial_number = super::SerialNumber::try_generate_random(
+                &mut rng,
+                SerialNumberMsbStrategy::RejectAndRetry,
+            )
+            .unwrap();
+            let hex = serial_number.to_hex();
+            assert!(u8::from_str_radix(hex.get(..2).unwrap(), 16).unwrap() <= 127);
+        }
+    }
+
+    #[test]
+    fn generate_batch_is_unique_and_encodable() {
+        let batch = super::SerialNumber::try_generate_batch(
+            &mut rng(),
+            500,
+            SerialNumberMsbStrategy::Project,
+        )
+        .unwrap();
+        assert_eq!(batch.len(), 500);
+        assert_eq!(batch.iter().collect::<std::collections::HashSet<_>>().len(), 500);
+        for serial_number in batch {
+            let p2_serial_number = polyproto::types::x509_cert::SerialNumber::from(serial_number);
+            assert!(p2_serial_number.as_bytes().len() <= 20);
+        }
+        assert!(
+            super::SerialNumber::try_generate_batch(
+                &mut rng(),
+                0,
+                SerialNumberMsbStrategy::Project
+            )
+            .unwrap()
+            .is_empty()
+        );
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
+    async fn unique_batch_excludes_taken_serials(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let taken =
+            super::SerialNumber::from(BigDecimal::from_str("12345678901234567890").unwrap());
+        let mut candidates = super::SerialNumber::try_generate_batch(
+            &mut rng(),
+            3,
+            SerialNumberMsbStrategy::Project,
+        )
+        .unwrap();
+        candidates.insert(1, taken.clone());
+
+        let remaining = super::SerialNumber::exclude_taken(&db, candidates.clone()).await.unwrap();
+        assert_eq!(remaining.len(), 3);
+        assert!(!remaining.contains(&taken));
+
+        let batch = super::SerialNumber::try_generate_unique_batch(
+            &db,
+            50,
+            SerialNumberMsbStrategy::Project,
+        )
+        .await
+        .unwrap();
+        assert_eq!(batch.len(), 50);
+        assert_eq!(batch.iter().collect::<std::collections::HashSet<_>>().len(), 50);
+        assert_eq!(super::SerialNumber::exclude_taken(&db, batch).await.unwrap().len(), 50);
+    }
+
+    #[test]
+    fn from_str_parses_valid_serial() {
+        let serial_number = super::SerialNumber::from_str("12345678901234567890").unwrap();
+        assert_eq!(
+            serial_number.as_bigdecimal(),
+            &BigDecimal::from_str("12345678901234567890").unwrap()
+        );
+        // The largest 160 bit serial number is accepted
+        let max = super::SerialNumber::new_from_bytes([0xff; 20]);
+        assert_eq!(super::SerialNumber::from_str(&max.as_bigdecimal().to_string()).unwrap(), max);
+    }
+
+    #[test]
+    fn from_str_rejects_over_length_serial() {
+        let error = super::SerialNumber::from_str(&"9".repeat(50)).unwrap_err();
+        assert_eq!(error.code, Errcode::IllegalInput);
+        assert!(super::SerialNumber::from_str(&"1".repeat(1_000_000)).is_err());
+    }
+
+    #[test]
+    fn from_str_rejects_non_numeric_serial() {
+        for input in ["", "abc", "-1", "+1", "1.5", "1e5000", " 1"] {
+            let error = super::SerialNumber::from_str(input).unwrap_err();
+            assert_eq!(error.code, Errcode::IllegalInput, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn decimal_and_hex_round_trip() {
+        for _ in 0..1000 {
+            let serial_number = super::SerialNumber::try_generate_random(
+                &mut rng(),
+                SerialNumberMsbStrategy::Project,
+            )
+            .unwrap();
+            let decimal = serial_number.to_string();
+            assert_eq!(decimal, serial_number.as_bigdecimal().to_string());
+            assert_eq!(super::SerialNumber::from_str(&decimal).unwrap(), serial_number);
+            let hex = serial_number.to_hex();
+            assert_eq!(hex.len(), 40);
+            assert_eq!(super::SerialNumber::from_hex(&hex).unwrap(), serial_number);
+        }
+        let small = super::SerialNumber::from(BigDecimal::from(255));
+        assert_eq!(small.to_string(), "255");
+        assert_eq!(small.to_hex(), format!("{}ff", "00".repeat(19)));
+    }
+
+    #[test]
+    fn from_hex_rejects_invalid_input() {
+        // 21 octets are accepted on decoding...
+        let max = super::SerialNumber::from_hex(&"ff".repeat(21)).unwrap();
+        assert_eq!(max.to_hex(), "ff".repeat(21));
+        // ...but no more
+        for input in ["", "f", "zz", &"ff".repeat(22), &"1".repeat(1_000_000)] {
+            let error = super::SerialNumber::from_hex(input).unwrap_err();
+            assert_eq!(error.code, Errcode::IllegalInput);
         }
     }
 
@@ -189,7 +518,11 @@ mod test {
         let converted_back = super::SerialNumber::from(p2_serial_number);
         assert_eq!(converted_back, serial_number);
         for _ in 0..5000 {
-            let serial_number = super::SerialNumber::try_generate_random(&mut rng()).unwrap();
+            let serial_number = super::SerialNumber::try_generate_random(
+                &mut rng(),
+                SerialNumberMsbStrategy::Project,
+            )
+            .unwrap();
             let p2_serial_number =
                 polyproto::types::x509_cert::SerialNumber::from(serial_number.clone());
             let converted_back = super::SerialNumber::from(p2_serial_number);
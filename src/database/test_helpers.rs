@@ -0,0 +1,212 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Test doubles and helpers for code depending on the database.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use chrono::{TimeDelta, Utc};
+use sqlx::{Pool, Postgres, query, types::Uuid};
+
+use crate::{
+    database::{ActorRepository, LocalActor, tokens::hash_auth_token},
+    errors::{Context, Errcode, Error},
+};
+
+/// Store a session of the actor `uaid` for the ID-Cert with the ID `cert_id`
+/// in the `user_tokens` table, which is authenticated by the plaintext auth
+/// `token`. The session expires `valid_for` from now, which may be negative
+/// for an already expired session, or never, if `valid_for` is `None`.
+pub(crate) async fn insert_session(
+    pool: &Pool<Postgres>,
+    token: &str,
+    cert_id: i64,
+    uaid: Uuid,
+    valid_for: Option<TimeDelta>,
+) {
+    query!(
+        "INSERT INTO user_tokens (token_hash, cert_id, uaid, valid_not_after)
+        VALUES ($1, $2, $3, NOW() + make_interval(secs => $4))",
+        hash_auth_token(token),
+        cert_id,
+        uaid,
+        valid_for.map(|valid_for| valid_for.as_seconds_f64())
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+#[derive(Debug, Default)]
+/// An in-memory [ActorRepository], for unit testing handler logic without a
+/// database. Actors are numbered in the order they are created, starting at
+/// `1`.
+pub(crate) struct MockActorRepository {
+    /// The known actors together with their password hashes.
+    actors: Mutex<Vec<(LocalActor, String)>>,
+    /// Invite codes which can each be used exactly once.
+    invites: Mutex<Vec<String>>,
+    /// Consecutive failed logins and the end of the lock, per actor.
+    failed_logins: Mutex<HashMap<Uuid, (u32, Option<Instant>)>>,
+}
+
+impl MockActorRepository {
+    /// Add an actor called `local_name`, with the argon2 `password_hash`.
+    pub(crate) fn with_actor(
+        self,
+        local_name: &str,
+        password_hash: &str,
+        deactivated: bool,
+    ) -> Self {
+        self.insert(local_name, password_hash, deactivated);
+        self
+    }
+
+    /// Add a single-use invite with the code `invite_code`.
+    pub(crate) fn with_invite(self, invite_code: &str) -> Self {
+        self.invites.lock().unwrap().push(invite_code.to_owned());
+        self
+    }
+
+    /// Whether an actor called `local_name` exists.
+    pub(crate) fn contains(&self, local_name: &str) -> bool {
+        self.actors.lock().unwrap().iter().any(|(actor, _)| actor.local_name == local_name)
+    }
+
+    /// Whether an actor is called `local_name`, ignoring case, if
+    /// `case_insensitive` is set.
+    fn is_called(actor: &LocalActor, local_name: &str, case_insensitive: bool) -> bool {
+        actor.local_name == local_name
+            || (case_insensitive && actor.local_name.to_lowercase() == local_name.to_lowercase())
+    }
+
+    /// Whether an actor called `local_name` exists, compared like
+    /// [LocalActor::by_local_name] does.
+    fn contains_matching(&self, local_name: &str, case_insensitive: bool) -> bool {
+        self.actors
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(actor, _)| Self::is_called(actor, local_name, case_insensitive))
+    }
+
+    /// Add an actor and return a copy of it.
+    fn insert(&self, local_name: &str, password_hash: &str, deactivated: bool) -> LocalActor {
+        let mut actors = self.actors.lock().unwrap();
+        let uaid = Uuid::from_u128(u128::try_from(actors.len()).unwrap().saturating_add(1));
+        let actor = LocalActor {
+            unique_actor_identifier: uaid,
+            local_name: local_name.to_owned(),
+            is_deactivated: deactivated,
+            joined_at_timestamp: Utc::now().naive_utc(),
+        };
+        actors.push((actor.clone(), password_hash.to_owned()));
+        actor
+    }
+}
+
+impl ActorRepository for MockActorRepository {
+    async fn local_actor_by_name(
+        &self,
+        local_name: &str,
+        case_insensitive: bool,
+    ) -> Result<Option<LocalActor>, Error> {
+        Ok(self
+            .actors
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(actor, _)| Self::is_called(actor, local_name, case_insensitive))
+            .map(|(actor, _)| actor.clone()))
+    }
+
+    async fn password_hash(
+        &self,
+        local_name: &str,
+        case_insensitive: bool,
+    ) -> Result<Option<String>, Error> {
+        Ok(self
+            .actors
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(actor, _)| Self::is_called(actor, local_name, case_insensitive))
+            .map(|(_, hash)| hash.clone()))
+    }
+
+    async fn create_local_actor(
+        &self,
+        local_name: &str,
+        password_hash: &str,
+        case_insensitive: bool,
+    ) -> Result<LocalActor, Error> {
+        if self.contains_matching(local_name, case_insensitive) {
+            return Err(Error::new(
+                Errcode::Duplicate,
+                Some(Context::new(Some("local_name"), Some(local_name), None, None)),
+            ));
+        }
+        Ok(self.insert(local_name, password_hash, false))
+    }
+
+    async fn create_local_actor_with_invite(
+        &self,
+        invite_code: &str,
+        local_name: &str,
+        password_hash: &str,
+        case_insensitive: bool,
+    ) -> Result<LocalActor, Error> {
+        let mut invites = self.invites.lock().unwrap();
+        let Some(position) = invites.iter().position(|invite| invite == invite_code) else {
+            return Err(Error::new(
+                Errcode::Unauthorized,
+                Some(Context::new(Some("invite"), Some(invite_code), None, None)),
+            ));
+        };
+        if self.contains_matching(local_name, case_insensitive) {
+            return Err(Error::new(
+                Errcode::Duplicate,
+                Some(Context::new(Some("local_name"), Some(local_name), None, None)),
+            ));
+        }
+        invites.remove(position);
+        Ok(self.insert(local_name, password_hash, false))
+    }
+
+    async fn is_login_locked(&self, uaid: &Uuid) -> Result<bool, Error> {
+        Ok(self
+            .failed_logins
+            .lock()
+            .unwrap()
+            .get(uaid)
+            .and_then(|(_, locked_until)| *locked_until)
+            .is_some_and(|locked_until| locked_until > Instant::now()))
+    }
+
+    async fn record_failed_login(
+        &self,
+        uaid: &Uuid,
+        threshold: u32,
+        cooldown_secs: u64,
+    ) -> Result<bool, Error> {
+        let mut failed_logins = self.failed_logins.lock().unwrap();
+        let (failures, locked_until) = failed_logins.entry(*uaid).or_default();
+        *failures = failures.saturating_add(1);
+        if *failures < threshold {
+            return Ok(false);
+        }
+        *failures = 0;
+        *locked_until = Instant::now().checked_add(Duration::from_secs(cooldown_secs));
+        Ok(true)
+    }
+
+    async fn clear_failed_logins(&self, uaid: &Uuid) -> Result<(), Error> {
+        self.failed_logins.lock().unwrap().remove(uaid);
+        Ok(())
+    }
+}
@@ -1,17 +1,27 @@
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use log::{debug, error};
 use rand::distr::{Alphanumeric, SampleString};
-use sqlx::{query, query_as, types::Uuid};
-use zeroize::Zeroizing;
+use serde::Serialize;
+use sqlx::{PgConnection, query, query_as, types::Uuid};
+use subtle::ConstantTimeEq;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
 use crate::{
+    config::DatabaseConfig,
     database::{Database, serial_number::SerialNumber},
     errors::Error,
 };
 
+/// Length of the raw, alphanumeric auth tokens handed out to clients.
+const AUTH_TOKEN_LENGTH: usize = 96;
+
 #[derive(Debug, Clone)]
-/// A [HashMap] mapping a [SerialNumber] to a [String] token.
-/// Only allows access to the inner store via methods implemented
-/// on this type for reasons of additional data consistency and security
-/// guarantees, that can only be provided this way. Implements [Zeroize] and
+/// A [HashMap](std::collections::HashMap) mapping a [SerialNumber] to a
+/// [String] token. Only allows access to the inner store via methods
+/// implemented on this type for reasons of additional data consistency and
+/// security guarantees, that can only be provided this way. Implements [Zeroize] and
 /// [ZeroizeOnDrop] on all values (not keys!) of the HashMap, ensuring no token
 /// is left in memory after the application exits.
 pub struct TokenStore {
@@ -22,14 +32,43 @@ pub struct TokenStore {
 /// A pair of an API access token and a unique actor identifier (uaid), where
 /// the access token belongs to that actor. Does not distinguish between
 /// different clients/sessions.
-#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// The token is zeroized when the pair, or any clone of it, is dropped. [Clone]
+/// is only implemented, because poem requires it for request data; avoid
+/// cloning the pair elsewhere.
+#[derive(Debug, Clone, PartialEq, Eq, Zeroize, ZeroizeOnDrop)]
 pub struct TokenActorIdPair {
     /// API access token
     pub token: Zeroizing<String>,
     /// Unique Actor Identifier (uaid), unique per local actor.
+    #[zeroize(skip)]
     pub uaid: Uuid,
 }
 
+/// Information about one session (one row in the `user_tokens` table) of an
+/// actor. Deliberately does not contain the token hash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    /// The polyproto session ID of the ID-Cert this token is bound to. `None`,
+    /// if the token is not bound to an ID-Cert.
+    pub session_id: Option<String>,
+    /// The ID of the ID-Cert this token is bound to. `None`, if the token is
+    /// not bound to an ID-Cert.
+    pub cert_id: Option<i64>,
+    /// The serial number of the ID-Cert this token is bound to, in decimal
+    /// notation. `None`, if the token is not bound to an ID-Cert.
+    pub serial_number: Option<String>,
+    /// When the token for this session has been created.
+    pub created_at: NaiveDateTime,
+    /// When the token for this session has last been used to authenticate.
+    pub last_seen: Option<NaiveDateTime>,
+    /// When the token for this session expires. `None` means never.
+    pub valid_not_after: Option<NaiveDateTime>,
+    /// Whether the token for this session has expired.
+    pub is_expired: bool,
+}
+
 impl TokenStore {
     /// Create a new TokenStore with the given database connection.
     pub fn new(database: Database) -> Self {
@@ -44,7 +83,7 @@ impl TokenStore {
         &self,
         serial_number: &SerialNumber,
     ) -> Result<Option<TokenActorIdPair>, Error> {
-        let record = query_as!(
+        Ok(query_as!(
             TokenActorIdPair,
             r#"
                 WITH csr_id AS (
@@ -74,11 +113,7 @@ impl TokenStore {
             serial_number.as_bigdecimal()
         )
         .fetch_optional(&self.p.pool)
-        .await?;
-        match record {
-            Some(record) => Ok(Some(TokenActorIdPair { token: record.token, uaid: record.uaid })),
-            None => Ok(None),
-        }
+        .await?)
     }
 
     /// Given a `token_hash`, find out the `serial_number` of the `IdCert` of
@@ -103,7 +138,8 @@ impl TokenStore {
 
     /// Generate a CSPRNG generated alphanumerical token, suitable for
     /// authentication purposes, hash it, then upsert (insert or update, if
-    /// exists) the token hash into the database.
+    /// exists) the token hash into the database. The token expires after
+    /// `validity`, or never, if `validity` is `None`.
     ///
     /// ## Returns
     ///
@@ -120,21 +156,251 @@ impl TokenStore {
         &self,
         actor_id: &Uuid,
         cert_id: Option<i64>,
+        validity: Option<Duration>,
+    ) -> Result<String, Error> {
+        Self::generate_upsert_token_on(
+            &mut *self.p.pool.acquire().await?,
+            actor_id,
+            cert_id,
+            validity,
+        )
+        .await
+    }
+
+    /// Like [Self::generate_upsert_token], but upserts the token on the given
+    /// `connection`, such as an open transaction.
+    pub async fn generate_upsert_token_on(
+        connection: &mut PgConnection,
+        actor_id: &Uuid,
+        cert_id: Option<i64>,
+        validity: Option<Duration>,
     ) -> Result<String, Error> {
-        let token_hash = hash_auth_token(&Alphanumeric.sample_string(&mut rand::rng(), 96));
+        let token_hash =
+            hash_auth_token(&Alphanumeric.sample_string(&mut rand::rng(), AUTH_TOKEN_LENGTH));
         query!(
-			"INSERT INTO user_tokens (token_hash, uaid, cert_id) VALUES ($1, $2, $3) ON CONFLICT (cert_id, uaid) DO UPDATE SET token_hash = EXCLUDED.token_hash",
-			&token_hash,
-			actor_id,
-			cert_id
-		)
-		.execute(&self.p.pool)
-		.await?;
+            "INSERT INTO user_tokens (token_hash, uaid, cert_id, valid_not_after)
+                VALUES ($1, $2, $3, NOW() + make_interval(secs => $4))
+                ON CONFLICT (cert_id, uaid) DO UPDATE
+                SET token_hash = EXCLUDED.token_hash, valid_not_after = EXCLUDED.valid_not_after",
+            &token_hash,
+            actor_id,
+            cert_id,
+            validity.map(|validity| validity.as_secs_f64())
+        )
+        .execute(&mut *connection)
+        .await?;
         Ok(token_hash)
     }
+
+    /// Replace the token identified by `old_hash` with a freshly generated
+    /// token for the same actor and cert, keeping its expiry. Checking the
+    /// old token and replacing it happens in a single transaction.
+    ///
+    /// ## Returns
+    ///
+    /// Returns the new, raw (unhashed) token, or `None`, if `old_hash` does not
+    /// belong to a valid, non-expired token. In the latter case, the database
+    /// is not modified.
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if the database or database connection is broken.
+    pub async fn rotate_token(&self, old_hash: &str) -> Result<Option<String>, Error> {
+        let mut transaction = self.p.pool.begin().await?;
+        if query!(
+            "SELECT token_hash
+                FROM user_tokens
+                WHERE token_hash = $1
+                AND (valid_not_after >= NOW() OR valid_not_after IS NULL)
+                FOR UPDATE",
+            old_hash
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        .is_none()
+        {
+            return Ok(None);
+        }
+        let new_token = Alphanumeric.sample_string(&mut rand::rng(), AUTH_TOKEN_LENGTH);
+        query!(
+            "UPDATE user_tokens SET token_hash = $1 WHERE token_hash = $2",
+            hash_auth_token(&new_token),
+            old_hash
+        )
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(Some(new_token))
+    }
+
+    /// List up to `limit` sessions of the actor identified by `uaid`, oldest
+    /// first, skipping the first `offset` ones. There is one session per row in
+    /// the `user_tokens` table. Expired tokens, which have not been cleaned up
+    /// yet, are listed with [SessionInfo::is_expired] set.
+    pub async fn list_sessions(
+        &self,
+        uaid: &Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<SessionInfo>, Error> {
+        Ok(query_as!(
+            SessionInfo,
+            r#"
+                SELECT
+                    idcsr.session_id AS "session_id?",
+                    ut.cert_id,
+                    idcsr.serial_number::TEXT AS "serial_number?",
+                    ut.created_at,
+                    ut.last_seen,
+                    ut.valid_not_after,
+                    COALESCE(ut.valid_not_after < NOW(), false) AS "is_expired!"
+                FROM user_tokens ut
+                LEFT JOIN idcsr ON idcsr.id = ut.cert_id
+                WHERE ut.uaid = $1
+                ORDER BY ut.created_at, idcsr.session_id
+                LIMIT $2 OFFSET $3
+            "#,
+            uaid,
+            limit,
+            offset
+        )
+        .fetch_all(&self.p.pool)
+        .await?)
+    }
+
+    /// Revoke the token identified by `token_hash` by deleting it from the
+    /// `user_tokens` table. Other tokens of the same actor are not affected.
+    ///
+    /// Returns `false`, if no such token existed.
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if the database or database connection is broken.
+    pub async fn revoke_token(&self, token_hash: &str) -> Result<bool, Error> {
+        Ok(query!("DELETE FROM user_tokens WHERE token_hash = $1", token_hash)
+            .execute(&self.p.pool)
+            .await?
+            .rows_affected()
+            > 0)
+    }
+
+    /// Revoke all tokens of the actor identified by `uaid` on the given
+    /// `connection`, logging them out of every session. Returns the number of
+    /// revoked tokens.
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if the database or database connection is broken.
+    pub async fn revoke_all_tokens_on(
+        connection: &mut PgConnection,
+        uaid: &Uuid,
+    ) -> Result<u64, Error> {
+        Ok(query!("DELETE FROM user_tokens WHERE uaid = $1", uaid)
+            .execute(&mut *connection)
+            .await?
+            .rows_affected())
+    }
+
+    /// Set the `last_seen` timestamp of the token identified by `token_hash`
+    /// to the current time.
+    pub async fn update_last_seen(&self, token_hash: &str) -> Result<(), Error> {
+        query!("UPDATE user_tokens SET last_seen = NOW() WHERE token_hash = $1", token_hash)
+            .execute(&self.p.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Resolve multiple token hashes at once, using a single query. The
+    /// returned [HashMap] maps each valid (existing and non-expired) token hash
+    /// to its [TokenActorIdPair]. Invalid token hashes are not contained in the
+    /// map. Only compiled for tests, until the gateway authenticates its
+    /// connections in batches.
+    #[cfg(test)]
+    pub async fn validate_many(
+        &self,
+        hashes: &[String],
+    ) -> Result<std::collections::HashMap<String, TokenActorIdPair>, Error> {
+        if hashes.is_empty() {
+            return Ok(std::collections::HashMap::new());
+        }
+        Ok(query!(
+            r#"
+                SELECT token_hash, uaid
+                FROM user_tokens
+                WHERE token_hash = ANY($1)
+                AND (valid_not_after >= NOW() OR valid_not_after IS NULL)
+            "#,
+            hashes
+        )
+        .fetch_all(&self.p.pool)
+        .await?
+        .into_iter()
+        .map(|record| {
+            let pair = TokenActorIdPair {
+                token: Zeroizing::new(record.token_hash.clone()),
+                uaid: record.uaid,
+            };
+            (record.token_hash, pair)
+        })
+        .collect())
+    }
+
+    /// Count the valid (non-expired) tokens in the `user_tokens` table.
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if the database or database connection is broken.
+    pub async fn count_active(&self) -> Result<i64, Error> {
+        Ok(query!(
+            r#"SELECT COUNT(*) AS "count!" FROM user_tokens
+            WHERE valid_not_after >= NOW() OR valid_not_after IS NULL"#
+        )
+        .fetch_one(&self.p.pool)
+        .await?
+        .count)
+    }
+
+    /// Delete all expired tokens from the `user_tokens` table. Tokens without
+    /// an expiry never expire, and are not deleted. Returns the number of
+    /// deleted tokens.
+    ///
+    /// ## Errors
+    ///
+    /// Will error, if the database or database connection is broken.
+    pub async fn purge_expired(&self) -> Result<u64, Error> {
+        Ok(query!(
+            "DELETE FROM user_tokens WHERE valid_not_after IS NOT NULL AND valid_not_after < NOW()"
+        )
+        .execute(&self.p.pool)
+        .await?
+        .rows_affected())
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    /// Spawn a task, which calls [TokenStore::purge_expired] every
+    /// [DatabaseConfig::token_purge_interval_secs]. Does nothing, if the
+    /// interval is `0`.
+    pub(crate) fn spawn_purge_task(&self, config: &DatabaseConfig) {
+        if config.token_purge_interval_secs == 0 {
+            return;
+        }
+        let token_store = self.clone();
+        let purge_interval = Duration::from_secs(config.token_purge_interval_secs);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(purge_interval);
+            while !token_store.p.pool.is_closed() {
+                interval.tick().await;
+                match token_store.purge_expired().await {
+                    Ok(0) => (),
+                    Ok(purged) => debug!("Purged {purged} expired tokens"),
+                    Err(e) => error!("Couldn't purge expired tokens: {e}"),
+                }
+            }
+        });
+    }
 }
 
-impl zeroize::ZeroizeOnDrop for TokenStore {}
+impl ZeroizeOnDrop for TokenStore {}
 
 /// Hashes an auth token using a deterministic hash function (currently:
 /// blake3), then returns the hash as a string.
@@ -142,15 +408,24 @@ pub fn hash_auth_token(auth_token: &str) -> String {
     blake3::hash(auth_token.as_bytes()).to_string()
 }
 
+/// Compares two strings, such as hashes from [hash_auth_token], in constant
+/// time, so that the comparison does not leak how many leading bytes match.
+/// Only the length of the strings may be inferred from the timing.
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod test {
     use std::str::FromStr;
 
     use bigdecimal::BigDecimal;
-    use sqlx::{Pool, Postgres};
+    use chrono::TimeDelta;
+    use sqlx::{Pool, Postgres, query};
 
     use super::*;
+    use crate::database::{Issuer, LocalActor, test_helpers::insert_session};
 
     #[test]
     fn eq_tokens() {
@@ -335,14 +610,9 @@ mod test {
         .unwrap();
 
         // Insert only expired tokens
-        sqlx::query!(
-            "INSERT INTO user_tokens (token_hash, cert_id, uaid, valid_not_after) VALUES
-            ('expired_token_hash_7_1', 7, '00000000-0000-0000-0000-000000000005', NOW() - INTERVAL '2 hours'),
-            ('expired_token_hash_9_1', 9, '00000000-0000-0000-0000-000000000005', NOW() - INTERVAL '1 hour')"
-        )
-        .execute(&pool)
-        .await
-        .unwrap();
+        let uaid = Uuid::from_u128(5);
+        insert_session(&pool, "expired_token_7_1", 7, uaid, Some(TimeDelta::hours(-2))).await;
+        insert_session(&pool, "expired_token_9_1", 9, uaid, Some(TimeDelta::hours(-1))).await;
 
         let db = Database { pool };
         let token_store = TokenStore::new(db);
@@ -409,13 +679,7 @@ mod test {
 
         // Insert a token with NULL valid_not_after (should be treated as never
         // expiring)
-        sqlx::query!(
-            "INSERT INTO user_tokens (token_hash, cert_id, uaid, valid_not_after) VALUES
-            ('never_expires_token_hash', 8, '00000000-0000-0000-0000-000000000006', NULL)"
-        )
-        .execute(&pool)
-        .await
-        .unwrap();
+        insert_session(&pool, "never_expires_token", 8, Uuid::from_u128(6), None).await;
 
         let db = Database { pool };
         let token_store = TokenStore::new(db);
@@ -424,7 +688,7 @@ mod test {
         let result = token_store.get_token_userid(&serial_number).await.unwrap();
 
         assert!(result.is_some());
-        assert_eq!(result.unwrap().token.as_str(), "never_expires_token_hash");
+        assert_eq!(*result.unwrap().token, hash_auth_token("never_expires_token"));
     }
 
     // Tests for get_token_serial_number method
@@ -583,4 +847,333 @@ mod test {
         assert!(result_lower.is_some());
         assert!(result_upper.is_none()); // Should not match due to case sensitivity
     }
+
+    #[sqlx::test(fixtures(
+        "../../fixtures/tokens_base_fixture.sql",
+        "../../fixtures/token_validation_specific.sql"
+    ))]
+    async fn test_rotate_token_replaces_valid_token(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let token_store = TokenStore::new(db.clone());
+
+        let new_token = token_store.rotate_token("valid_token_hash_1").await.unwrap().unwrap();
+        assert_eq!(new_token.len(), AUTH_TOKEN_LENGTH);
+
+        // The old hash must be gone...
+        assert!(token_store.get_token_serial_number("valid_token_hash_1").await.unwrap().is_none());
+        // ...and the new one must point to the same cert and actor.
+        let record = query!(
+            "SELECT uaid, cert_id FROM user_tokens WHERE token_hash = $1",
+            hash_auth_token(&new_token)
+        )
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(record.cert_id, Some(1));
+        assert_eq!(record.uaid, Uuid::from_str("00000000-0000-0000-0000-000000000001").unwrap());
+    }
+
+    #[sqlx::test(fixtures(
+        "../../fixtures/tokens_base_fixture.sql",
+        "../../fixtures/token_serial_lookup_specific.sql"
+    ))]
+    async fn test_rotate_token_invalid_token_is_noop(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let token_store = TokenStore::new(db.clone());
+
+        assert!(token_store.rotate_token("nonexistent_token_hash").await.unwrap().is_none());
+        assert!(token_store.rotate_token("expired_token_hash_user_4").await.unwrap().is_none());
+
+        // The expired token must not have been touched
+        assert!(
+            token_store
+                .get_token_serial_number("expired_token_hash_user_4")
+                .await
+                .unwrap()
+                .is_some()
+        );
+        let count = query!("SELECT COUNT(*) AS count FROM user_tokens")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap()
+            .count;
+        assert_eq!(count, Some(5));
+    }
+
+    #[sqlx::test(fixtures(
+        "../../fixtures/tokens_base_fixture.sql",
+        "../../fixtures/token_serial_lookup_specific.sql"
+    ))]
+    async fn test_validate_many_returns_only_valid_tokens(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let token_store = TokenStore::new(db);
+
+        let hashes = [
+            "token_hash_user_1_a",
+            "token_hash_user_2_a",
+            "expired_token_hash_user_4",
+            "nonexistent_token_hash",
+            "token_hash_user_1_a",
+        ]
+        .map(String::from);
+        let result = token_store.validate_many(&hashes).await.unwrap();
+
+        assert_eq!(result.len(), 2);
+        let user_1 = result.get("token_hash_user_1_a").unwrap();
+        assert_eq!(user_1.token.as_str(), "token_hash_user_1_a");
+        assert_eq!(user_1.uaid, Uuid::from_str("00000000-0000-0000-0000-000000000001").unwrap());
+        let user_2 = result.get("token_hash_user_2_a").unwrap();
+        assert_eq!(user_2.uaid, Uuid::from_str("00000000-0000-0000-0000-000000000002").unwrap());
+        assert!(!result.contains_key("expired_token_hash_user_4"));
+        assert!(!result.contains_key("nonexistent_token_hash"));
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_validate_many_empty_input(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let token_store = TokenStore::new(db);
+
+        assert!(token_store.validate_many(&[]).await.unwrap().is_empty());
+    }
+
+    #[sqlx::test(fixtures(
+        "../../fixtures/tokens_base_fixture.sql",
+        "../../fixtures/token_serial_lookup_specific.sql"
+    ))]
+    async fn test_revoke_token_only_affects_revoked_session(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let token_store = TokenStore::new(db);
+
+        assert!(token_store.revoke_token("token_hash_user_1_a").await.unwrap());
+        assert!(
+            token_store.get_token_serial_number("token_hash_user_1_a").await.unwrap().is_none()
+        );
+        // The other session of the same actor and other actors' sessions remain valid
+        let remaining = token_store
+            .validate_many(&["token_hash_user_1_b", "token_hash_user_2_a"].map(String::from))
+            .await
+            .unwrap();
+        assert_eq!(remaining.len(), 2);
+
+        // Revoking again, or revoking an unknown token, is a no-op
+        assert!(!token_store.revoke_token("token_hash_user_1_a").await.unwrap());
+        assert!(!token_store.revoke_token("nonexistent_token_hash").await.unwrap());
+    }
+
+    #[sqlx::test(fixtures(
+        "../../fixtures/tokens_base_fixture.sql",
+        "../../fixtures/token_serial_lookup_specific.sql"
+    ))]
+    async fn test_list_sessions_one_per_cert(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let token_store = TokenStore::new(db);
+
+        let sessions = token_store
+            .list_sessions(&Uuid::from_str("00000000-0000-0000-0000-000000000001").unwrap(), 100, 0)
+            .await
+            .unwrap();
+        assert_eq!(sessions.len(), 2);
+        let mut certs = sessions
+            .iter()
+            .map(|session| (session.cert_id, session.serial_number.as_deref()))
+            .collect::<Vec<_>>();
+        certs.sort();
+        assert_eq!(
+            certs,
+            vec![(Some(1), Some("12345678901234567890")), (Some(5), Some("12345678901234567891"))]
+        );
+        assert!(sessions.iter().all(|session| !session.is_expired));
+    }
+
+    #[sqlx::test(fixtures(
+        "../../fixtures/tokens_base_fixture.sql",
+        "../../fixtures/token_serial_lookup_specific.sql"
+    ))]
+    async fn test_list_sessions_flags_expired_tokens(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let token_store = TokenStore::new(db);
+
+        let sessions = token_store
+            .list_sessions(&Uuid::from_str("00000000-0000-0000-0000-000000000004").unwrap(), 100, 0)
+            .await
+            .unwrap();
+        assert_eq!(sessions.len(), 2);
+        let expired = sessions.iter().find(|session| session.cert_id == Some(6)).unwrap();
+        assert!(expired.is_expired);
+        assert_eq!(expired.serial_number.as_deref(), Some("55555555555555555556"));
+        let active = sessions.iter().find(|session| session.cert_id == Some(4)).unwrap();
+        assert!(!active.is_expired);
+    }
+
+    #[sqlx::test(fixtures(
+        "../../fixtures/tokens_base_fixture.sql",
+        "../../fixtures/token_purge_specific.sql"
+    ))]
+    async fn test_purge_expired_keeps_valid_and_never_expiring_tokens(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let token_store = TokenStore::new(db.clone());
+
+        assert_eq!(token_store.purge_expired().await.unwrap(), 2);
+        let mut remaining: Vec<String> = query!("SELECT token_hash FROM user_tokens")
+            .fetch_all(&db.pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|record| record.token_hash)
+            .collect();
+        remaining.sort();
+        assert_eq!(remaining, ["never_expiring_token_hash_4", "valid_token_hash_1"]);
+
+        // Purging again finds nothing left to delete
+        assert_eq!(token_store.purge_expired().await.unwrap(), 0);
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_generate_upsert_token_with_validity_expires(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let token_store = TokenStore::new(db);
+        let uaid = Uuid::from_str("00000000-0000-0000-0000-000000000001").unwrap();
+        let serial_number =
+            SerialNumber::from(BigDecimal::from_str("12345678901234567890").unwrap());
+
+        let token_hash = token_store
+            .generate_upsert_token(&uaid, Some(1), Some(Duration::from_millis(100)))
+            .await
+            .unwrap();
+        let pair = token_store.get_token_userid(&serial_number).await.unwrap().unwrap();
+        assert_eq!(pair.token.as_str(), token_hash);
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(token_store.get_token_userid(&serial_number).await.unwrap().is_none());
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_generate_upsert_token_without_validity_never_expires(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let token_store = TokenStore::new(db);
+        let uaid = Uuid::from_str("00000000-0000-0000-0000-000000000001").unwrap();
+        let serial_number =
+            SerialNumber::from(BigDecimal::from_str("12345678901234567890").unwrap());
+
+        // Upserting replaces the expiry of the previous token as well
+        token_store
+            .generate_upsert_token(&uaid, Some(1), Some(Duration::from_millis(100)))
+            .await
+            .unwrap();
+        let token_hash = token_store.generate_upsert_token(&uaid, Some(1), None).await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let pair = token_store.get_token_userid(&serial_number).await.unwrap().unwrap();
+        assert_eq!(pair.token.as_str(), token_hash);
+        let sessions = token_store.list_sessions(&uaid, 100, 0).await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions.first().unwrap().valid_not_after, None);
+    }
+
+    #[test]
+    fn test_token_actor_id_pair_zeroizes_token() {
+        fn assert_zeroize_on_drop<T: ZeroizeOnDrop>() {}
+        assert_zeroize_on_drop::<TokenActorIdPair>();
+
+        // Dropping the pair runs the same zeroization before the buffer is freed,
+        // which cannot be observed without reading freed memory
+        let mut pair = TokenActorIdPair {
+            token: Zeroizing::new(String::from("secret_token_hash")),
+            uaid: Uuid::nil(),
+        };
+        let capacity = pair.token.capacity();
+        let buffer = pair.token.as_ptr();
+        pair.zeroize();
+        assert!(pair.token.is_empty());
+        assert_eq!(pair.token.as_ptr(), buffer);
+        // SAFETY: The buffer is still allocated with `capacity` bytes, all of which
+        // have been overwritten by the zeroization.
+        let bytes = unsafe { std::slice::from_raw_parts(buffer, capacity) };
+        assert!(bytes.iter().all(|byte| *byte == 0));
+        assert_eq!(pair.uaid, Uuid::nil());
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        let hash = hash_auth_token("token");
+        assert!(constant_time_eq(&hash, &hash_auth_token("token")));
+        assert!(!constant_time_eq(&hash, &hash_auth_token("other token")));
+        assert!(!constant_time_eq(&hash, &hash[..hash.len() - 1]));
+        assert!(!constant_time_eq("", &hash));
+        assert!(constant_time_eq("", ""));
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/full_state.sql"))]
+    async fn test_full_state_token_to_actor(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let token_store = TokenStore::new(db.clone());
+
+        // token -> serial number
+        let serial_number = token_store
+            .get_token_serial_number("full_state_token_hash_alice")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(serial_number.as_bigdecimal().to_string(), "90000000000000001001");
+        // serial number -> valid token and actor ID
+        let token_actor_id_pair =
+            token_store.get_token_userid(&serial_number).await.unwrap().unwrap();
+        assert_eq!(token_actor_id_pair.token.as_str(), "full_state_token_hash_alice");
+        // actor ID -> actor
+        let actor = LocalActor::by_uaid(&db, &token_actor_id_pair.uaid).await.unwrap().unwrap();
+        assert_eq!(actor.local_name, "full_state_alice");
+        // serial number -> cert -> issuer
+        let issuer = query!(
+            "SELECT issuers.id FROM idcsr
+            JOIN idcert ON idcert.idcsr_id = idcsr.id
+            JOIN issuers ON issuers.id = idcert.issuer_info_id
+            WHERE idcsr.serial_number = $1",
+            serial_number.as_bigdecimal()
+        )
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        let own_issuer = Issuer::get_by_domain(&db, "full.example.com").await.unwrap().unwrap();
+        assert_eq!(issuer.id, own_issuer.id());
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/full_state.sql"))]
+    async fn test_full_state_expired_token_is_not_valid(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let token_store = TokenStore::new(db);
+
+        let serial_number = token_store
+            .get_token_serial_number("full_state_token_hash_carol")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(token_store.get_token_userid(&serial_number).await.unwrap().is_none());
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/full_state.sql"))]
+    async fn test_full_state_invites_and_sessions(pool: Pool<Postgres>) {
+        let db = Database { pool };
+        let alice = Uuid::from_str("00000000-0000-0000-0000-000000001001").unwrap();
+
+        let invited = query!(
+            "SELECT local_actors.local_name FROM invitations
+            JOIN invite_links ON invite_links.id = invitations.invite_id
+            JOIN local_actors ON local_actors.uaid = invitations.uaid_invited
+            WHERE invite_links.invite_link_owner = $1",
+            alice
+        )
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            invited.iter().map(|record| record.local_name.as_str()).collect::<Vec<_>>(),
+            vec!["full_state_bob"]
+        );
+
+        let sessions = TokenStore::new(db).list_sessions(&alice, 100, 0).await.unwrap();
+        assert_eq!(
+            sessions.iter().map(|session| session.session_id.as_deref()).collect::<Vec<_>>(),
+            vec![Some("full_state_session_alice")]
+        );
+    }
 }
@@ -0,0 +1,324 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{io::IsTerminal, path::Path, time::Duration};
+
+use sqlx::query_as;
+
+use crate::{
+    config::{DatabaseConfig, SonataConfig},
+    database::{Database, Issuer, api_keys},
+};
+
+/// How long the `doctor` subcommand waits for the database connection, before
+/// reporting it as unreachable.
+const DATABASE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The outcome of a single diagnostic check.
+pub(crate) enum Outcome {
+    /// The check passed.
+    Passed(String),
+    /// The check found something worth knowing about, which does not prevent
+    /// sonata from starting, for example because sonata fixes it on startup.
+    Warning(String),
+    /// The check found a problem, which prevents sonata from starting.
+    Failed(String),
+}
+
+impl Outcome {
+    /// Whether this outcome is [Outcome::Failed].
+    pub(crate) fn is_failed(&self) -> bool {
+        matches!(self, Outcome::Failed(_))
+    }
+}
+
+/// Check, that `input` is a valid sonata configuration file.
+pub(crate) fn check_config(input: &str) -> (Outcome, Option<SonataConfig>) {
+    match SonataConfig::parse(input) {
+        Ok(config) => (Outcome::Passed(String::from("Configuration file is valid")), Some(config)),
+        Err(e) => (Outcome::Failed(format!("Configuration file is invalid: {e}")), None),
+    }
+}
+
+/// Check, that `domain` is a valid domain name for the own issuer.
+pub(crate) fn check_domain(domain: &str) -> Outcome {
+    match Issuer::parse_domain(domain) {
+        Ok(domain_name) => Outcome::Passed(format!("Server domain {domain_name} is valid")),
+        Err(e) => Outcome::Failed(format!(
+            "Server domain {domain:?} is invalid: {}",
+            e.context.map(|context| context.message).unwrap_or(e.message)
+        )),
+    }
+}
+
+/// Check, that the database described by `config` is reachable.
+pub(crate) async fn check_database(config: &DatabaseConfig) -> (Outcome, Option<Database>) {
+    let database =
+        match tokio::time::timeout(DATABASE_TIMEOUT, Database::connect_with_config(config, false))
+            .await
+        {
+            Ok(Ok(database)) => database,
+            Ok(Err(e)) => {
+                return (Outcome::Failed(format!("Could not connect to the database: {e}")), None);
+            }
+            Err(_) => {
+                return (
+                    Outcome::Failed(format!(
+                        "Could not connect to the database within {} seconds",
+                        DATABASE_TIMEOUT.as_secs()
+                    )),
+                    None,
+                );
+            }
+        };
+    match database.ping_with_timeout(DATABASE_TIMEOUT).await {
+        true => (
+            Outcome::Passed(format!(
+                "Database {:?} at {}:{} is reachable",
+                config.database, config.host, config.port
+            )),
+            Some(database),
+        ),
+        false => (Outcome::Failed(String::from("The database does not answer queries")), None),
+    }
+}
+
+/// Check, that the migrations of this sonata version can be applied to the
+/// database: Already applied migrations must be known to this version and
+/// unchanged. Pending migrations are applied on startup.
+pub(crate) async fn check_migrations(database: &Database) -> Outcome {
+    let migrator = sqlx::migrate!();
+    let applied = match query_as::<_, (i64, Vec<u8>, bool)>(
+        "SELECT version, checksum, success FROM _sqlx_migrations ORDER BY version",
+    )
+    .fetch_all(&database.pool)
+    .await
+    {
+        Ok(applied) => applied,
+        // The table is created when the first migration is applied
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("42P01") => Vec::new(),
+        Err(e) => return Outcome::Failed(format!("Could not read applied migrations: {e}")),
+    };
+    for (version, checksum, success) in applied.iter() {
+        match migrator.iter().find(|migration| migration.version == *version) {
+            None => {
+                return Outcome::Failed(format!(
+                    "Migration {version} has been applied to the database, but is unknown to this \
+                     version of sonata. Was the database used with a newer version?"
+                ));
+            }
+            Some(migration) if *migration.checksum != **checksum => {
+                return Outcome::Failed(format!(
+                    "Migration {version} ({}) has been modified after it was applied",
+                    migration.description
+                ));
+            }
+            Some(_) if !success => {
+                return Outcome::Failed(format!("Migration {version} has previously failed"));
+            }
+            Some(_) => (),
+        }
+    }
+    let pending = migrator
+        .iter()
+        .filter(|migration| !applied.iter().any(|(version, _, _)| *version == migration.version))
+        .count();
+    match pending {
+        0 => Outcome::Passed(String::from("All migrations are applied")),
+        pending => {
+            Outcome::Warning(format!("{pending} pending migrations will be applied on startup"))
+        }
+    }
+}
+
+/// Check, that at least one active API key exists.
+pub(crate) async fn check_api_key(database: &Database) -> Outcome {
+    match api_keys::count_active_api_keys(database).await {
+        Ok(0) => Outcome::Warning(String::from(
+            "No active API key exists. A new one will be generated and logged on startup",
+        )),
+        Ok(count) => Outcome::Passed(format!("{count} active API keys exist")),
+        Err(e) => Outcome::Failed(format!("Could not count API keys: {e}")),
+    }
+}
+
+/// Check, that the issuer entry for the own `domain` exists.
+pub(crate) async fn check_issuer(database: &Database, domain: &str) -> Outcome {
+    match Issuer::get_by_domain(database, domain).await {
+        Ok(Some(issuer)) => {
+            Outcome::Passed(format!("Issuer entry for {} exists", issuer.domain_components))
+        }
+        Ok(None) => Outcome::Warning(format!(
+            "No issuer entry for {domain:?} exists. It will be created on startup"
+        )),
+        Err(e) => Outcome::Failed(format!("Could not look up the issuer entry: {e}")),
+    }
+}
+
+/// Run all checks against the configuration file at `config_location`, print
+/// a checklist of the results and return the exit code: `0`, if no critical
+/// check has failed, `1` otherwise. Checks which depend on a failed check are
+/// skipped.
+#[cfg_attr(coverage_nightly, coverage(off))]
+pub(crate) async fn run(config_location: &Path) -> i32 {
+    let mut results = Vec::new();
+    let input = match std::fs::read_to_string(config_location) {
+        Ok(input) => input,
+        Err(e) => {
+            results.push((
+                "Configuration",
+                Outcome::Failed(format!("Could not read {config_location:?}: {e}")),
+            ));
+            return print_checklist(&results);
+        }
+    };
+    let (outcome, config) = check_config(&input);
+    results.push(("Configuration", outcome));
+    let Some(config) = config else {
+        return print_checklist(&results);
+    };
+    for domain in config.general.served_domains() {
+        results.push(("Server domain", check_domain(domain)));
+    }
+    let (outcome, database) = check_database(&config.general.database).await;
+    results.push(("Database", outcome));
+    if let Some(database) = database {
+        let migrations = check_migrations(&database).await;
+        let migrations_complete = matches!(migrations, Outcome::Passed(_));
+        results.push(("Migrations", migrations));
+        // The tables checked below might not exist yet
+        if migrations_complete {
+            results.push(("API key", check_api_key(&database).await));
+            for domain in config.general.served_domains() {
+                results.push(("Issuer", check_issuer(&database, domain).await));
+            }
+        }
+    }
+    print_checklist(&results)
+}
+
+/// Print the `results` as a checklist, colored if stdout is a terminal, and
+/// return the exit code: `0`, if no check has failed, `1` otherwise.
+#[cfg_attr(coverage_nightly, coverage(off))]
+fn print_checklist(results: &[(&str, Outcome)]) -> i32 {
+    let colored = std::io::stdout().is_terminal();
+    for (name, outcome) in results {
+        let (symbol, color, message) = match outcome {
+            Outcome::Passed(message) => ("✔", "\x1b[32m", message),
+            Outcome::Warning(message) => ("!", "\x1b[33m", message),
+            Outcome::Failed(message) => ("✘", "\x1b[31m", message),
+        };
+        match colored {
+            true => println!("{color}{symbol}\x1b[0m {name}: {message}"),
+            false => println!("{symbol} {name}: {message}"),
+        }
+    }
+    match results.iter().any(|(_, outcome)| outcome.is_failed()) {
+        true => {
+            println!("\nAt least one critical check failed. sonata will not start correctly.");
+            1
+        }
+        false => {
+            println!("\nAll critical checks passed.");
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use sqlx::{Pool, Postgres, query};
+
+    use super::*;
+
+    fn sonata_toml() -> String {
+        std::fs::read_to_string(format!("{}/sonata.toml", std::env!("CARGO_MANIFEST_DIR"))).unwrap()
+    }
+
+    #[test]
+    fn test_check_config() {
+        let (outcome, config) = check_config(&sonata_toml());
+        assert!(matches!(outcome, Outcome::Passed(_)));
+        assert!(config.is_some());
+
+        let (outcome, config) = check_config("[api]\nenabled = true");
+        assert!(outcome.is_failed());
+        assert!(config.is_none());
+    }
+
+    #[test]
+    fn test_check_domain() {
+        assert!(matches!(check_domain("example.com"), Outcome::Passed(_)));
+        assert!(matches!(check_domain("Example.COM."), Outcome::Passed(_)));
+        assert!(check_domain("not a domain").is_failed());
+        assert!(check_domain("example..com").is_failed());
+        assert!(check_domain("").is_failed());
+    }
+
+    #[tokio::test]
+    async fn test_check_database_unreachable() {
+        let mut config = SonataConfig::parse(&sonata_toml()).unwrap().general.database;
+        config.host = String::from("invalid_host");
+        let (outcome, database) = check_database(&config).await;
+        assert!(outcome.is_failed());
+        assert!(database.is_none());
+    }
+
+    #[sqlx::test]
+    async fn test_check_migrations(pool: Pool<Postgres>) {
+        let database = Database { pool };
+        assert!(matches!(check_migrations(&database).await, Outcome::Passed(_)));
+
+        // A pending migration is applied on startup
+        query!("DELETE FROM _sqlx_migrations WHERE version = (SELECT MAX(version) FROM _sqlx_migrations)")
+            .execute(&database.pool)
+            .await
+            .unwrap();
+        assert!(matches!(check_migrations(&database).await, Outcome::Warning(_)));
+
+        // An applied migration unknown to this version cannot be handled
+        query!(
+            "INSERT INTO _sqlx_migrations (version, description, success, checksum, \
+             execution_time) VALUES (99999999, 'from the future', true, '\\x00', 0)"
+        )
+        .execute(&database.pool)
+        .await
+        .unwrap();
+        assert!(check_migrations(&database).await.is_failed());
+    }
+
+    #[sqlx::test]
+    async fn test_check_migrations_modified(pool: Pool<Postgres>) {
+        let database = Database { pool };
+        query!("UPDATE _sqlx_migrations SET checksum = '\\x00' WHERE version = (SELECT MIN(version) FROM _sqlx_migrations)")
+            .execute(&database.pool)
+            .await
+            .unwrap();
+        assert!(check_migrations(&database).await.is_failed());
+    }
+
+    #[sqlx::test]
+    async fn test_check_api_key(pool: Pool<Postgres>) {
+        let database = Database { pool };
+        assert!(matches!(check_api_key(&database).await, Outcome::Warning(_)));
+        api_keys::add_api_key_to_database(
+            api_keys::ApiKey::new_random(&mut rand::rng()).token(),
+            &database,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(check_api_key(&database).await, Outcome::Passed(_)));
+    }
+
+    #[sqlx::test]
+    async fn test_check_issuer(pool: Pool<Postgres>) {
+        let database = Database { pool };
+        assert!(matches!(check_issuer(&database, "example.com").await, Outcome::Warning(_)));
+        Issuer::create(&database, "example.com").await.unwrap();
+        assert!(matches!(check_issuer(&database, "Example.com").await, Outcome::Passed(_)));
+        assert!(check_issuer(&database, "").await.is_failed());
+    }
+}
@@ -76,8 +76,24 @@ impl ResponseError for Error {
     fn status(&self) -> StatusCode {
         self.code.status()
     }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn as_response(&self) -> Response {
+        Response::builder()
+            .content_type("application/json")
+            .status(self.code.status())
+            .body(self.to_json())
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
 }
 
+impl std::error::Error for Error {}
+
 impl From<sqlx::Error> for Error {
     #[cfg_attr(coverage_nightly, coverage(off))]
     fn from(value: sqlx::Error) -> Self {
@@ -86,13 +102,23 @@ impl From<sqlx::Error> for Error {
     }
 }
 
-impl From<Error> for poem::Error {
-    #[cfg_attr(coverage_nightly, coverage(off))]
-    fn from(value: Error) -> Self {
-        poem::Error::from_response(value.into_response())
+impl From<argon2::password_hash::Error> for Error {
+    /// A password not matching its hash is an [Error::new_invalid_login]. Any
+    /// other error, such as a malformed hash, is an [Errcode::Internal] error.
+    fn from(value: argon2::password_hash::Error) -> Self {
+        match value {
+            argon2::password_hash::Error::Password => Error::new_invalid_login(),
+            other => {
+                log::error!("Failed to hash or verify a password: {other}");
+                Error::new(Errcode::Internal, None)
+            }
+        }
     }
 }
 
+/// Media type of RFC 9457 "problem details" error responses.
+pub const PROBLEM_DETAILS_CONTENT_TYPE: &str = "application/problem+json";
+
 /// Error message for a wrong username or password.
 pub const ERROR_WRONG_LOGIN: &str = "The provided login name or password was incorrect.";
 
@@ -104,10 +130,40 @@ impl Error {
         json!(self).to_string()
     }
 
+    /// Converts a shared reference to [Self] into an RFC 9457 "problem
+    /// details" JSON object. Next to the standard members, the object carries
+    /// the polyproto error `code` and, if present, the error `context` as
+    /// extension members.
+    #[must_use]
+    pub fn to_problem_details(&self) -> serde_json::Value {
+        let status = self.code.status();
+        let mut problem_details = json!({
+            "type": "about:blank",
+            "title": status.canonical_reason().unwrap_or_default(),
+            "status": status.as_u16(),
+            "detail": self.message,
+            "code": self.code,
+        });
+        if let (Some(context), Some(members)) = (&self.context, problem_details.as_object_mut()) {
+            members.insert("context".to_owned(), json!(context));
+        }
+        problem_details
+    }
+
+    /// Creates an `application/problem+json` [Response] from [Self]. See
+    /// [Self::to_problem_details].
+    #[must_use]
+    pub fn to_problem_details_response(&self) -> Response {
+        Response::builder()
+            .content_type(PROBLEM_DETAILS_CONTENT_TYPE)
+            .status(self.code.status())
+            .body(self.to_problem_details().to_string())
+    }
+
     /// Creates [Self].
     #[must_use]
     pub fn new(code: Errcode, context: Option<Context>) -> Self {
-        Self { code, message: code.message(), context }
+        Self { message: code.message(), code, context }
     }
 
     /// Creates a variant of [Self] which indicates to a client, that the
@@ -137,17 +193,16 @@ impl Error {
     pub fn new_duplicate_error(message: Option<&str>) -> Self {
         Self::new(Errcode::Duplicate, Some(Context::new(None, None, None, message)))
     }
+
+    /// Creates a variant of [Self] with an [Errcode] of `Errcode::NotFound`
+    /// and an optional, given message.
+    pub fn new_not_found_error(message: Option<&str>) -> Self {
+        Self::new(Errcode::NotFound, Some(Context::new(None, None, None, message)))
+    }
 }
 
 #[derive(
-    Debug,
-    Clone,
-    Copy,
-    PartialEq,
-    DeserializeFromStr,
-    SerializeDisplay,
-    strum::Display,
-    strum::EnumString,
+    Debug, Clone, PartialEq, DeserializeFromStr, SerializeDisplay, strum::Display, strum::EnumIter,
 )]
 /// Standardized polyproto core error codes, giving a rough idea of what went
 /// wrong.
@@ -166,6 +221,40 @@ pub enum Errcode {
     /// One or many parts of the given input did not succeed validation against
     /// context-specific criteria
     IllegalInput,
+    #[strum(serialize = "P2_CORE_NOT_FOUND")]
+    /// The requested resource does not exist
+    NotFound,
+    #[strum(to_string = "{0}")]
+    /// A `P2_CORE_*` error code unknown to this server, for example one
+    /// received from a remote server implementing a newer version of the
+    /// polyproto specification. Holds the error code as received.
+    Unknown(String),
+}
+
+/// Prefix all polyproto core error codes share.
+const ERRCODE_PREFIX: &str = "P2_CORE_";
+
+impl std::str::FromStr for Errcode {
+    type Err = strum::ParseError;
+
+    /// Parse an error code. Unlike unknown strings in general, unknown error
+    /// codes starting with `P2_CORE_` are tolerated and parsed into
+    /// [Errcode::Unknown], so that error responses of remote servers stay
+    /// parseable, even if they use error codes introduced after this version
+    /// of sonata.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use strum::IntoEnumIterator;
+
+        if let Some(errcode) = Errcode::iter()
+            .find(|errcode| !matches!(errcode, Errcode::Unknown(_)) && errcode.to_string() == s)
+        {
+            Ok(errcode)
+        } else if s.starts_with(ERRCODE_PREFIX) {
+            Ok(Errcode::Unknown(s.to_owned()))
+        } else {
+            Err(strum::ParseError::VariantNotFound)
+        }
+    }
 }
 
 impl Errcode {
@@ -183,6 +272,8 @@ impl Errcode {
 				"Creation of the resource is not possible, as it already exists".to_owned()
 			}
     Errcode::IllegalInput => "The overall input is well-formed, but one or more of the input fields fail validation criteria".to_owned(),
+    Errcode::NotFound => "The requested resource does not exist".to_owned(),
+    Errcode::Unknown(_) => "An error has occurred, the error code of which is not known to this server".to_owned(),
             }
     }
 }
@@ -195,6 +286,8 @@ impl ResponseError for Errcode {
             Errcode::Unauthorized => StatusCode::UNAUTHORIZED,
             Errcode::Duplicate => StatusCode::CONFLICT,
             Errcode::IllegalInput => StatusCode::BAD_REQUEST,
+            Errcode::NotFound => StatusCode::NOT_FOUND,
+            Errcode::Unknown(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
@@ -255,6 +348,24 @@ impl Context {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_from_password_hash_error_mismatch() {
+        let error = Error::from(argon2::password_hash::Error::Password);
+        assert_eq!(error.code, Errcode::Unauthorized);
+        assert_eq!(error.context.unwrap().message, ERROR_WRONG_LOGIN);
+    }
+
+    #[test]
+    fn test_from_password_hash_error_internal() {
+        for error in [
+            argon2::password_hash::Error::PhcStringField,
+            argon2::password_hash::Error::Algorithm,
+            argon2::password_hash::Error::Crypto,
+        ] {
+            assert_eq!(Error::from(error).code, Errcode::Internal);
+        }
+    }
+
     #[test]
     fn test_error_serialization() {
         let context = Context::new(Some("field"), Some("value"), Some("expected"), Some("message"));
@@ -333,6 +444,16 @@ mod tests {
         assert_eq!(ctx.message, "User already exists");
     }
 
+    #[test]
+    fn test_error_new_not_found_error() {
+        let error = Error::new_not_found_error(Some("No such actor"));
+
+        assert_eq!(error.code, Errcode::NotFound);
+        assert!(error.context.is_some());
+        let ctx = error.context.unwrap();
+        assert_eq!(ctx.message, "No such actor");
+    }
+
     #[test]
     fn test_errcode_messages() {
         assert_eq!(
@@ -351,6 +472,7 @@ mod tests {
             Errcode::IllegalInput.message(),
             "The overall input is well-formed, but one or more of the input fields fail validation criteria"
         );
+        assert_eq!(Errcode::NotFound.message(), "The requested resource does not exist");
     }
 
     #[test]
@@ -361,6 +483,7 @@ mod tests {
         assert_eq!(Errcode::Unauthorized.status(), StatusCode::UNAUTHORIZED);
         assert_eq!(Errcode::Duplicate.status(), StatusCode::CONFLICT);
         assert_eq!(Errcode::IllegalInput.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(Errcode::NotFound.status(), StatusCode::NOT_FOUND);
     }
 
     #[test]
@@ -373,6 +496,65 @@ mod tests {
         assert_eq!(deserialized, Errcode::Internal);
     }
 
+    #[test]
+    fn test_errcode_from_str_known_code() {
+        assert_eq!("P2_CORE_DUPLICATE".parse::<Errcode>().unwrap(), Errcode::Duplicate);
+        let deserialized: Errcode = serde_json::from_str("\"P2_CORE_ILLEGAL_INPUT\"").unwrap();
+        assert_eq!(deserialized, Errcode::IllegalInput);
+    }
+
+    #[test]
+    fn test_errcode_from_str_unknown_code() {
+        let errcode = "P2_CORE_SOME_FUTURE_ERROR".parse::<Errcode>().unwrap();
+        assert_eq!(errcode, Errcode::Unknown("P2_CORE_SOME_FUTURE_ERROR".to_owned()));
+        assert_eq!(errcode.to_string(), "P2_CORE_SOME_FUTURE_ERROR");
+        assert_eq!(errcode.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        // Remote error bodies with unknown codes stay parseable
+        let error: Error = serde_json::from_str(
+            r#"{"code":"P2_CORE_SOME_FUTURE_ERROR","message":"Something went wrong"}"#,
+        )
+        .unwrap();
+        assert_eq!(error.code, Errcode::Unknown("P2_CORE_SOME_FUTURE_ERROR".to_owned()));
+        assert_eq!(
+            error.to_json(),
+            r#"{"code":"P2_CORE_SOME_FUTURE_ERROR","message":"Something went wrong"}"#
+        );
+
+        // Strings which are not polyproto core error codes at all are still rejected
+        assert!("SOMETHING_ELSE".parse::<Errcode>().is_err());
+        assert!("".parse::<Errcode>().is_err());
+        assert!(serde_json::from_str::<Errcode>("\"SOMETHING_ELSE\"").is_err());
+    }
+
+    #[test]
+    fn test_error_to_problem_details() {
+        let context = Context::new(Some("password"), Some("weak"), Some("strong"), None);
+        let error = Error::new(Errcode::IllegalInput, Some(context));
+
+        let problem_details = error.to_problem_details();
+        assert_eq!(problem_details.get("type").unwrap(), "about:blank");
+        assert_eq!(problem_details.get("title").unwrap(), "Bad Request");
+        assert_eq!(problem_details.get("status").unwrap(), 400);
+        assert_eq!(problem_details.get("detail").unwrap(), &json!(Errcode::IllegalInput.message()));
+        assert_eq!(problem_details.get("code").unwrap(), "P2_CORE_ILLEGAL_INPUT");
+        assert_eq!(
+            problem_details.get("context").and_then(|context| context.get("fieldName")).unwrap(),
+            "password"
+        );
+
+        let problem_details = Error::new(Errcode::Internal, None).to_problem_details();
+        assert!(problem_details.get("context").is_none());
+    }
+
+    #[test]
+    fn test_error_to_problem_details_response() {
+        let response = Error::new(Errcode::Duplicate, None).to_problem_details_response();
+
+        assert_eq!(response.status(), poem::http::StatusCode::CONFLICT);
+        assert_eq!(response.headers().get("content-type").unwrap(), PROBLEM_DETAILS_CONTENT_TYPE);
+    }
+
     #[test]
     fn test_context_new() {
         let context =
@@ -430,6 +612,7 @@ mod tests {
         assert_eq!(Errcode::Unauthorized.to_string(), "P2_CORE_UNAUTHORIZED");
         assert_eq!(Errcode::Duplicate.to_string(), "P2_CORE_DUPLICATE");
         assert_eq!(Errcode::IllegalInput.to_string(), "P2_CORE_ILLEGAL_INPUT");
+        assert_eq!(Errcode::NotFound.to_string(), "P2_CORE_NOT_FOUND");
     }
 
     #[test]
@@ -440,7 +623,19 @@ mod tests {
         assert_eq!(Errcode::from_str("P2_CORE_UNAUTHORIZED").unwrap(), Errcode::Unauthorized);
         assert_eq!(Errcode::from_str("P2_CORE_DUPLICATE").unwrap(), Errcode::Duplicate);
         assert_eq!(Errcode::from_str("P2_CORE_ILLEGAL_INPUT").unwrap(), Errcode::IllegalInput);
+        assert_eq!(Errcode::from_str("P2_CORE_NOT_FOUND").unwrap(), Errcode::NotFound);
 
         assert!(Errcode::from_str("INVALID_CODE").is_err());
     }
+
+    #[test]
+    fn test_errcode_round_trip() {
+        use strum::IntoEnumIterator;
+
+        for errcode in Errcode::iter().filter(|errcode| !matches!(errcode, Errcode::Unknown(_))) {
+            assert_eq!(errcode.to_string().parse::<Errcode>().unwrap(), errcode);
+            let serialized = serde_json::to_string(&errcode).unwrap();
+            assert_eq!(serde_json::from_str::<Errcode>(&serialized).unwrap(), errcode);
+        }
+    }
 }
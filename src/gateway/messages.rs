@@ -0,0 +1,92 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use serde::{Deserialize, Serialize};
+use sqlx::types::Uuid;
+
+use crate::gateway::presence::{GatewayEvent, PresenceStatus};
+
+#[serde_with::serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", content = "d", rename_all = "snake_case")]
+/// The messages exchanged over a gateway connection, each sent as a JSON
+/// text frame such as `{"op": "hello", "d": {"heartbeat_interval": 45000}}`.
+/// Messages without data, such as heartbeats, may omit `d`.
+pub(crate) enum GatewayMessage {
+    /// Sent by the server once the connection has been opened.
+    Hello {
+        /// The interval in milliseconds at which the client has to send
+        /// [GatewayMessage::Heartbeat]s.
+        heartbeat_interval: u32,
+    },
+    /// Sent by the client to keep the connection open.
+    Heartbeat,
+    /// Sent by the server in response to every [GatewayMessage::Heartbeat].
+    HeartbeatAck,
+    /// Sent by the server to all clients, when the operators of the home
+    /// server make an announcement.
+    Announcement {
+        /// The text of the announcement.
+        message: String,
+    },
+    /// Sent by the server to all clients, when an actor comes online or goes
+    /// offline.
+    Presence {
+        #[serde_as(as = "serde_with::DisplayFromStr")]
+        /// The unique actor identifier of the actor.
+        uaid: Uuid,
+        /// The new status of the actor.
+        status: PresenceStatus,
+    },
+}
+
+impl From<GatewayEvent> for GatewayMessage {
+    fn from(value: GatewayEvent) -> Self {
+        match value {
+            GatewayEvent::Presence { uaid, status } => Self::Presence { uaid, status },
+            GatewayEvent::Announcement { message } => Self::Announcement { message },
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_gateway_message_encoding() {
+        assert_eq!(
+            serde_json::to_value(GatewayMessage::Hello { heartbeat_interval: 45000 }).unwrap(),
+            json!({"op": "hello", "d": {"heartbeat_interval": 45000}})
+        );
+        assert_eq!(
+            serde_json::to_value(GatewayMessage::HeartbeatAck).unwrap(),
+            json!({"op": "heartbeat_ack"})
+        );
+        assert_eq!(
+            serde_json::to_value(GatewayMessage::Announcement { message: "Hi".to_owned() })
+                .unwrap(),
+            json!({"op": "announcement", "d": {"message": "Hi"}})
+        );
+        assert_eq!(
+            serde_json::to_value(GatewayMessage::Presence {
+                uaid: Uuid::from_u128(1),
+                status: PresenceStatus::Online
+            })
+            .unwrap(),
+            json!({
+                "op": "presence",
+                "d": {"uaid": "00000000-0000-0000-0000-000000000001", "status": "online"}
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<GatewayMessage>(json!({"op": "heartbeat"})).unwrap(),
+            GatewayMessage::Heartbeat
+        );
+        assert!(serde_json::from_value::<GatewayMessage>(json!({"op": "identify"})).is_err());
+    }
+}
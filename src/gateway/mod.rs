@@ -1,3 +1,288 @@
 // This Source Code Form is subject to the terms of the Mozilla Public
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use log::debug;
+use poem::web::websocket::CloseCode;
+use serde::de::DeserializeOwned;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::GatewayConfig;
+
+/// The messages exchanged over gateway connections.
+pub(crate) mod messages;
+/// Presence of actors and the dispatching of events to gateway connections.
+pub(crate) mod presence;
+/// The WebSocket server accepting gateway connections.
+mod server;
+
+pub(crate) use server::start_gateway;
+
+#[derive(Debug, Clone)]
+/// Limits the number of concurrently open gateway connections to
+/// [GatewayConfig::max_connections]. Clones share the same limit.
+///
+/// The accept loop must obtain a [ConnectionPermit] through
+/// [ConnectionLimiter::try_acquire] before upgrading a connection, and reject
+/// the upgrade with `503 Service Unavailable` if none is available. The permit
+/// has to be held for as long as the connection is open.
+///
+/// The limiter also tracks the current and the peak number of connections,
+/// which the metrics endpoint of the API exposes as the
+/// `sonata_gateway_connections` and `sonata_gateway_connections_peak` gauges.
+pub(crate) struct ConnectionLimiter {
+    /// One permit per connection which may still be opened.
+    semaphore: Arc<Semaphore>,
+    /// The configured maximum number of connections.
+    max_connections: usize,
+    /// The highest number of simultaneously open connections so far.
+    peak_connections: Arc<AtomicUsize>,
+}
+
+#[derive(Debug)]
+/// Proof that a gateway connection may be kept open. Releases its slot in the
+/// [ConnectionLimiter] once dropped.
+pub(crate) struct ConnectionPermit(
+    #[allow(dead_code)] // Only held for its `Drop` implementation
+    OwnedSemaphorePermit,
+);
+
+impl ConnectionLimiter {
+    /// Create a new [ConnectionLimiter] for the `max_connections` of the
+    /// [GatewayConfig].
+    pub(crate) fn new(config: &GatewayConfig) -> Self {
+        let max_connections = config.max_connections.min(Semaphore::MAX_PERMITS);
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_connections)),
+            max_connections,
+            peak_connections: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Try to reserve a slot for a new connection. Returns `None`, if
+    /// [GatewayConfig::max_connections] connections are open already.
+    pub(crate) fn try_acquire(&self) -> Option<ConnectionPermit> {
+        let permit = self.semaphore.clone().try_acquire_owned().ok().map(ConnectionPermit)?;
+        self.peak_connections.fetch_max(self.active_connections(), Ordering::Relaxed);
+        Some(permit)
+    }
+
+    /// The number of currently open connections.
+    pub(crate) fn active_connections(&self) -> usize {
+        self.max_connections.saturating_sub(self.semaphore.available_permits())
+    }
+
+    /// The highest number of simultaneously open connections since the
+    /// limiter has been created.
+    pub(crate) fn peak_connections(&self) -> usize {
+        self.peak_connections.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Reasons for which sonata closes a gateway connection.
+pub(crate) enum GatewayCloseCode {
+    /// The client has not sent a heartbeat in time.
+    SessionTimeout,
+    /// The client has sent a frame which is too large or cannot be
+    /// deserialized.
+    InvalidPayload,
+}
+
+impl From<GatewayCloseCode> for CloseCode {
+    /// The close code sent to the client, taken from the range `4000` to `4999`
+    /// reserved for applications.
+    fn from(code: GatewayCloseCode) -> Self {
+        CloseCode::from(match code {
+            GatewayCloseCode::InvalidPayload => 4002,
+            GatewayCloseCode::SessionTimeout => 4009,
+        })
+    }
+}
+
+/// Deserialize the `payload` of a frame received from a client. The
+/// connection loop has to decode every inbound frame through this function,
+/// and close the connection with the returned code if it fails.
+///
+/// ## Errors
+///
+/// [GatewayCloseCode::InvalidPayload], if the `payload` is larger than
+/// [GatewayConfig::max_frame_size_bytes], which is checked before it is
+/// deserialized, or if it is not a valid JSON encoding of `T`.
+pub(crate) fn decode_frame<T: DeserializeOwned>(
+    config: &GatewayConfig,
+    payload: &[u8],
+) -> Result<T, GatewayCloseCode> {
+    if payload.len() > config.max_frame_size_bytes {
+        debug!(
+            "Received a gateway frame of {} bytes, exceeding the maximum of {} bytes",
+            payload.len(),
+            config.max_frame_size_bytes
+        );
+        return Err(GatewayCloseCode::InvalidPayload);
+    }
+    serde_json::from_slice(payload).map_err(|e| {
+        debug!("Received a gateway frame which cannot be deserialized: {e}");
+        GatewayCloseCode::InvalidPayload
+    })
+}
+
+#[derive(Debug, Clone)]
+/// Keeps track of the heartbeats of a single gateway connection. The
+/// connection loop records every heartbeat received from the client through
+/// [HeartbeatMonitor::record_heartbeat], and closes the connection with the
+/// code returned by [HeartbeatMonitor::check] once it fails.
+///
+/// A heartbeat is due [GatewayConfig::heartbeat_interval_ms] after the
+/// previous one. After that, the client has another
+/// [GatewayConfig::heartbeat_ack_timeout_ms] to send it.
+pub(crate) struct HeartbeatMonitor {
+    /// How long after the previous heartbeat the next one is due.
+    interval: Duration,
+    /// How long the client may be late with a due heartbeat.
+    ack_timeout: Duration,
+    /// When the last heartbeat has been received, or the connection has been
+    /// opened, if there has not been a heartbeat yet.
+    last_heartbeat: Instant,
+}
+
+impl HeartbeatMonitor {
+    /// Create a new [HeartbeatMonitor] for a connection which has been opened
+    /// at `now`.
+    pub(crate) fn new(config: &GatewayConfig, now: Instant) -> Self {
+        Self {
+            interval: Duration::from_millis(config.heartbeat_interval_ms.into()),
+            ack_timeout: Duration::from_millis(config.heartbeat_ack_timeout_ms.into()),
+            last_heartbeat: now,
+        }
+    }
+
+    /// Record a heartbeat received from the client at `now`.
+    pub(crate) fn record_heartbeat(&mut self, now: Instant) {
+        self.last_heartbeat = self.last_heartbeat.max(now);
+    }
+
+    /// The point in time after which the connection is closed, unless another
+    /// heartbeat is recorded.
+    pub(crate) fn deadline(&self) -> Instant {
+        self.last_heartbeat
+            .checked_add(self.interval.saturating_add(self.ack_timeout))
+            .unwrap_or(self.last_heartbeat)
+    }
+
+    /// Check whether the client has sent its heartbeats in time as of `now`.
+    ///
+    /// ## Errors
+    ///
+    /// [GatewayCloseCode::SessionTimeout], if the [Self::deadline] has passed.
+    pub(crate) fn check(&self, now: Instant) -> Result<(), GatewayCloseCode> {
+        if now > self.deadline() { Err(GatewayCloseCode::SessionTimeout) } else { Ok(()) }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_limiter_rejects_connections_above_max() {
+        let mut config = GatewayConfig::for_tests();
+        config.max_connections = 2;
+        let limiter = ConnectionLimiter::new(&config);
+
+        let first = limiter.try_acquire().unwrap();
+        let _second = limiter.clone().try_acquire().unwrap();
+        assert_eq!(limiter.active_connections(), 2);
+        assert!(limiter.try_acquire().is_none());
+
+        // Closing a connection frees its slot
+        drop(first);
+        assert_eq!(limiter.active_connections(), 1);
+        assert!(limiter.try_acquire().is_some());
+    }
+
+    #[test]
+    fn test_connection_limiter_tracks_current_and_peak_connections() {
+        let mut config = GatewayConfig::for_tests();
+        config.max_connections = 10;
+        let limiter = ConnectionLimiter::new(&config);
+        assert_eq!((limiter.active_connections(), limiter.peak_connections()), (0, 0));
+
+        let connections = (0..3).map(|_| limiter.try_acquire().unwrap()).collect::<Vec<_>>();
+        assert_eq!((limiter.active_connections(), limiter.peak_connections()), (3, 3));
+
+        // Disconnecting lowers the current count, but not the peak
+        drop(connections);
+        assert_eq!((limiter.active_connections(), limiter.peak_connections()), (0, 3));
+        let _connection = limiter.try_acquire().unwrap();
+        assert_eq!((limiter.active_connections(), limiter.peak_connections()), (1, 3));
+    }
+
+    fn heartbeat_config() -> GatewayConfig {
+        let mut config = GatewayConfig::for_tests();
+        config.heartbeat_interval_ms = 1000;
+        config.heartbeat_ack_timeout_ms = 500;
+        config
+    }
+
+    #[test]
+    fn test_heartbeat_monitor_ack_within_timeout() {
+        let opened = Instant::now();
+        let mut monitor = HeartbeatMonitor::new(&heartbeat_config(), opened);
+        assert_eq!(monitor.deadline(), opened + Duration::from_millis(1500));
+        assert!(monitor.check(opened + Duration::from_millis(1000)).is_ok());
+        // Late, but within the ack timeout
+        assert!(monitor.check(opened + Duration::from_millis(1500)).is_ok());
+
+        // Every heartbeat pushes the deadline back
+        monitor.record_heartbeat(opened + Duration::from_millis(1400));
+        assert!(monitor.check(opened + Duration::from_millis(2900)).is_ok());
+    }
+
+    #[test]
+    fn test_decode_frame_rejects_oversized_frames() {
+        let mut config = GatewayConfig::for_tests();
+        config.max_frame_size_bytes = 16;
+
+        let frame = br#"{"n":"12345678"}"#;
+        assert_eq!(frame.len(), 16);
+        assert_eq!(
+            decode_frame::<serde_json::Value>(&config, frame).unwrap(),
+            serde_json::json!({"n": "12345678"})
+        );
+        // Valid JSON, but one byte too large
+        assert_eq!(
+            decode_frame::<serde_json::Value>(&config, br#"{"n":"123456789"}"#),
+            Err(GatewayCloseCode::InvalidPayload)
+        );
+        assert_eq!(
+            decode_frame::<serde_json::Value>(&config, b"{not json"),
+            Err(GatewayCloseCode::InvalidPayload)
+        );
+    }
+
+    #[test]
+    fn test_heartbeat_monitor_ack_beyond_timeout() {
+        let opened = Instant::now();
+        let mut monitor = HeartbeatMonitor::new(&heartbeat_config(), opened);
+        assert_eq!(
+            monitor.check(opened + Duration::from_millis(1501)),
+            Err(GatewayCloseCode::SessionTimeout)
+        );
+
+        monitor.record_heartbeat(opened + Duration::from_millis(1000));
+        assert_eq!(
+            monitor.check(opened + Duration::from_millis(2501)),
+            Err(GatewayCloseCode::SessionTimeout)
+        );
+    }
+}
@@ -0,0 +1,253 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use sqlx::types::Uuid;
+use tokio::sync::{broadcast, watch};
+
+use crate::{config::GatewayConfig, shutdown_requested};
+
+/// How many [GatewayEvent]s a subscriber of the [Hub] may lag behind, before
+/// it misses events.
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// How often the task spawned by [Hub::spawn_flush_task] calls [Hub::flush].
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Whether an actor has at least one open gateway connection.
+pub(crate) enum PresenceStatus {
+    /// The actor has at least one open gateway connection.
+    Online,
+    /// The actor has no open gateway connection.
+    Offline,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Events the [Hub] dispatches to the gateway connections subscribed to it.
+pub(crate) enum GatewayEvent {
+    /// The [PresenceStatus] of the actor identified by `uaid` has changed.
+    Presence {
+        /// The unique actor identifier of the actor, as authenticated in the
+        /// handshake of its connections.
+        uaid: Uuid,
+        /// The new status of the actor.
+        status: PresenceStatus,
+    },
+    /// An announcement by the operators of the home server, such as a
+    /// maintenance warning, to be shown to all connected clients.
+    Announcement {
+        /// The text of the announcement.
+        message: String,
+    },
+}
+
+#[derive(Debug)]
+/// The presence of a single actor, as tracked by the [Hub].
+struct Presence {
+    /// Number of currently open connections of the actor.
+    connections: usize,
+    /// When the last connection of the actor has closed. `None`, while the
+    /// actor has open connections.
+    offline_since: Option<Instant>,
+}
+
+#[derive(Debug)]
+/// Dispatches [GatewayEvent]s to all gateway connections and keeps track of
+/// which actors are online.
+///
+/// The gateway calls [Hub::connect] once a connection of an authenticated
+/// actor has been opened, and [Hub::disconnect] once it has closed. Every
+/// connection forwards the events received through its [Hub::subscribe]
+/// receiver to the client. The task started by [Hub::spawn_flush_task] calls
+/// [Hub::flush] periodically to announce actors as offline.
+///
+/// An actor is announced as online when its first connection is opened, and
+/// as offline only once it has had no open connection for
+/// [GatewayConfig::presence_debounce_ms]. Clients reconnecting within that
+/// time are not announced at all.
+pub(crate) struct Hub {
+    /// The presence of all online actors, and of those whose offline
+    /// announcement is still pending.
+    presence: Mutex<HashMap<Uuid, Presence>>,
+    /// Sender of the events to all subscribers.
+    events: broadcast::Sender<GatewayEvent>,
+    /// See [GatewayConfig::presence_debounce_ms].
+    debounce: Duration,
+}
+
+impl Hub {
+    /// Create a new [Hub] without any online actors.
+    pub(crate) fn new(config: &GatewayConfig) -> Self {
+        Self {
+            presence: Mutex::new(HashMap::new()),
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            debounce: Duration::from_millis(config.presence_debounce_ms.into()),
+        }
+    }
+
+    /// Receive all [GatewayEvent]s dispatched from now on.
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<GatewayEvent> {
+        self.events.subscribe()
+    }
+
+    /// Record that a connection of the actor identified by `uaid` has been
+    /// opened, announcing the actor as online, unless it already is.
+    pub(crate) fn connect(&self, uaid: Uuid) {
+        let mut presence = self.presence.lock().unwrap_or_else(PoisonError::into_inner);
+        match presence.get_mut(&uaid) {
+            // Reconnecting before the offline announcement cancels it
+            Some(entry) => {
+                entry.connections = entry.connections.saturating_add(1);
+                entry.offline_since = None;
+            }
+            None => {
+                presence.insert(uaid, Presence { connections: 1, offline_since: None });
+                self.broadcast(GatewayEvent::Presence { uaid, status: PresenceStatus::Online });
+            }
+        }
+    }
+
+    /// Record that a connection of the actor identified by `uaid` has been
+    /// closed at `now`. If it was the last connection of the actor, it is
+    /// announced as offline by the first [Hub::flush] after the debounce time.
+    pub(crate) fn disconnect(&self, uaid: Uuid, now: Instant) {
+        let mut presence = self.presence.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(entry) = presence.get_mut(&uaid) {
+            entry.connections = entry.connections.saturating_sub(1);
+            if entry.connections == 0 {
+                entry.offline_since = Some(now);
+            }
+        }
+    }
+
+    /// Announce all actors as offline, whose last connection has been closed
+    /// at least [GatewayConfig::presence_debounce_ms] before `now`.
+    pub(crate) fn flush(&self, now: Instant) {
+        let mut presence = self.presence.lock().unwrap_or_else(PoisonError::into_inner);
+        presence.retain(|uaid, entry| {
+            let gone = entry.connections == 0
+                && entry
+                    .offline_since
+                    .is_some_and(|since| now.saturating_duration_since(since) >= self.debounce);
+            if gone {
+                self.broadcast(GatewayEvent::Presence {
+                    uaid: *uaid,
+                    status: PresenceStatus::Offline,
+                });
+            }
+            !gone
+        });
+    }
+
+    /// Send `event` to all current subscribers.
+    pub(crate) fn broadcast(&self, event: GatewayEvent) {
+        // Sending only fails if there are no subscribers, who would miss the event
+        _ = self.events.send(event);
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    /// Spawn a task, which calls [Hub::flush] every [FLUSH_INTERVAL], until
+    /// `true` is sent through `shutdown`.
+    pub(crate) fn spawn_flush_task(self: &Arc<Self>, mut shutdown: watch::Receiver<bool>) {
+        let hub = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => hub.flush(Instant::now()),
+                    () = shutdown_requested(&mut shutdown) => return,
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use tokio::sync::broadcast::error::TryRecvError;
+
+    use super::*;
+
+    fn hub() -> Hub {
+        let mut config = GatewayConfig::for_tests();
+        config.presence_debounce_ms = 5000;
+        Hub::new(&config)
+    }
+
+    fn after(start: Instant, secs: u64) -> Instant {
+        start.checked_add(Duration::from_secs(secs)).unwrap()
+    }
+
+    fn presence(uaid: Uuid, status: PresenceStatus) -> GatewayEvent {
+        GatewayEvent::Presence { uaid, status }
+    }
+
+    #[test]
+    fn test_online_on_connect() {
+        let hub = hub();
+        let mut events = hub.subscribe();
+        let uaid = Uuid::from_u128(1);
+
+        hub.connect(uaid);
+        assert_eq!(events.try_recv().unwrap(), presence(uaid, PresenceStatus::Online));
+        // Further connections of the same actor are not announced
+        hub.connect(uaid);
+        assert_eq!(events.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn test_offline_on_last_disconnect() {
+        let hub = hub();
+        let mut events = hub.subscribe();
+        let uaid = Uuid::from_u128(1);
+        let start = Instant::now();
+        hub.connect(uaid);
+        hub.connect(uaid);
+        events.try_recv().unwrap();
+
+        hub.disconnect(uaid, start);
+        hub.flush(after(start, 10));
+        assert_eq!(events.try_recv(), Err(TryRecvError::Empty));
+
+        hub.disconnect(uaid, after(start, 10));
+        hub.flush(after(start, 14));
+        assert_eq!(events.try_recv(), Err(TryRecvError::Empty));
+        hub.flush(after(start, 15));
+        assert_eq!(events.try_recv().unwrap(), presence(uaid, PresenceStatus::Offline));
+    }
+
+    #[test]
+    fn test_flapping_is_debounced() {
+        let hub = hub();
+        let mut events = hub.subscribe();
+        let uaid = Uuid::from_u128(1);
+        let start = Instant::now();
+        hub.connect(uaid);
+        events.try_recv().unwrap();
+
+        for secs in 0..10 {
+            hub.disconnect(uaid, after(start, secs));
+            hub.flush(after(start, secs));
+            hub.connect(uaid);
+        }
+        hub.flush(after(start, 60));
+        assert_eq!(events.try_recv(), Err(TryRecvError::Empty));
+
+        // Reconnecting after the offline announcement is announced again
+        hub.disconnect(uaid, after(start, 60));
+        hub.flush(after(start, 65));
+        hub.connect(uaid);
+        assert_eq!(events.try_recv().unwrap(), presence(uaid, PresenceStatus::Offline));
+        assert_eq!(events.try_recv().unwrap(), presence(uaid, PresenceStatus::Online));
+    }
+}
@@ -0,0 +1,443 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{io::ErrorKind, sync::Arc, time::Instant};
+
+use futures_util::{
+    SinkExt, StreamExt,
+    stream::{SplitSink, SplitStream},
+};
+use log::{debug, error, info};
+use poem::{
+    Endpoint, EndpointExt, IntoResponse, Response, Route, Server, get, handler,
+    http::StatusCode,
+    listener::{Acceptor, AcceptorExt, BoxAcceptor, Listener, TcpListener},
+    web::{
+        Data,
+        websocket::{CloseCode, Message, WebSocket, WebSocketConfig, WebSocketStream},
+    },
+};
+use serde_json::json;
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    watch,
+};
+
+use crate::{
+    StdResult,
+    api::{
+        GRACEFUL_SHUTDOWN_TIMEOUT, extractors::AuthenticatedActor,
+        middlewares::AuthenticationMiddleware,
+    },
+    config::GatewayConfig,
+    database::{Database, tokens::TokenStore},
+    gateway::{
+        ConnectionLimiter, GatewayCloseCode, HeartbeatMonitor, decode_frame,
+        messages::GatewayMessage,
+        presence::{GatewayEvent, Hub},
+    },
+    shutdown_requested,
+};
+
+/// Start the WebSocket gateway server in a new task, bound to every address
+/// of the [GatewayConfig], and return the handle of that task. Connections
+/// are authenticated like API requests, using the `db` and the `token_store`.
+/// Open connections are limited and counted by the `connection_limiter`,
+/// register the presence of their actor with the `hub` and receive the
+/// [GatewayEvent]s broadcast through it. The server shuts down, once `true` is
+/// sent through `shutdown`, closing all open connections.
+///
+/// ## Errors
+///
+/// If the server cannot bind to one of the configured addresses, for example
+/// because the port is already in use.
+pub(crate) async fn start_gateway(
+    gateway_config: GatewayConfig,
+    db: Database,
+    token_store: TokenStore,
+    connection_limiter: ConnectionLimiter,
+    hub: Arc<Hub>,
+    shutdown: watch::Receiver<bool>,
+) -> StdResult<tokio::task::JoinHandle<()>> {
+    let bind_addresses = gateway_config.bind_addresses();
+    hub.spawn_flush_task(shutdown.clone());
+    let routes =
+        setup_routes(gateway_config, db, token_store, connection_limiter, hub, shutdown.clone());
+    let mut acceptor: Option<BoxAcceptor> = None;
+    for (host, port) in bind_addresses {
+        let bound =
+            TcpListener::bind((host.as_str(), port)).into_acceptor().await.map_err(|e| {
+                format!(
+                    "Couldn't start the gateway server at {host}, port {port}: {e}. Is another \
+                 process already using this port?"
+                )
+            })?;
+        acceptor = Some(match acceptor {
+            Some(acceptor) => acceptor.combine(bound).boxed(),
+            None => bound.boxed(),
+        });
+    }
+    let acceptor = acceptor.ok_or("The gateway server has no address to bind to")?;
+    let local_addresses = acceptor.local_addr();
+    let mut shutdown = shutdown;
+    let handle = tokio::task::spawn(async move {
+        if let Err(e) = Server::new_with_acceptor(acceptor)
+            .run_with_graceful_shutdown(
+                routes,
+                async move { shutdown_requested(&mut shutdown).await },
+                Some(GRACEFUL_SHUTDOWN_TIMEOUT),
+            )
+            .await
+        {
+            error!("Gateway server stopped due to an error: {e}");
+        }
+        info!("Gateway server stopped");
+    });
+    for address in local_addresses {
+        info!("Started gateway server at {address}");
+    }
+    Ok(handle)
+}
+
+/// The gateway endpoint, accepting WebSocket connections of authenticated
+/// actors at `/`.
+fn setup_routes(
+    gateway_config: GatewayConfig,
+    db: Database,
+    token_store: TokenStore,
+    connection_limiter: ConnectionLimiter,
+    hub: Arc<Hub>,
+    shutdown: watch::Receiver<bool>,
+) -> impl Endpoint {
+    Route::new()
+        .at("/", get(gateway).with(AuthenticationMiddleware))
+        .data(db)
+        .data(token_store)
+        .data(connection_limiter)
+        .data(gateway_config)
+        .data(hub)
+        .data(shutdown)
+}
+
+#[handler]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Upgrade the request to a gateway connection, unless
+/// [GatewayConfig::max_connections] connections are open already. The actor
+/// is online for as long as the connection is open. Frames and messages larger
+/// than [GatewayConfig::max_frame_size_bytes] are rejected before they are
+/// read into memory.
+fn gateway(
+    websocket: WebSocket,
+    AuthenticatedActor(actor): AuthenticatedActor,
+    Data(gateway_config): Data<&GatewayConfig>,
+    Data(limiter): Data<&ConnectionLimiter>,
+    Data(hub): Data<&Arc<Hub>>,
+    Data(shutdown): Data<&watch::Receiver<bool>>,
+) -> Response {
+    let Some(permit) = limiter.try_acquire() else {
+        debug!("Rejected a gateway connection, as too many connections are open");
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    };
+    let gateway_config = gateway_config.clone();
+    let hub = hub.clone();
+    let events = hub.subscribe();
+    let shutdown = shutdown.clone();
+    let uaid = actor.unique_actor_identifier;
+    let max_size = Some(gateway_config.max_frame_size_bytes);
+    websocket
+        .config(WebSocketConfig::default().max_frame_size(max_size).max_message_size(max_size))
+        .on_upgrade(move |socket| async move {
+            hub.connect(uaid);
+            run_connection(socket, &gateway_config, events, shutdown).await;
+            hub.disconnect(uaid, Instant::now());
+            drop(permit);
+        })
+        .into_response()
+}
+
+/// Drive a single gateway connection: Send the [GatewayMessage::Hello],
+/// acknowledge every [GatewayMessage::Heartbeat], forward the announcements
+/// and presence changes received through `events`, and close the connection
+/// once the client misses a heartbeat, sends an invalid frame, or sonata shuts
+/// down.
+async fn run_connection(
+    socket: WebSocketStream,
+    gateway_config: &GatewayConfig,
+    mut events: broadcast::Receiver<GatewayEvent>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let (mut sink, mut stream) = socket.split();
+    let mut monitor = HeartbeatMonitor::new(gateway_config, Instant::now());
+    let hello = GatewayMessage::Hello { heartbeat_interval: gateway_config.heartbeat_interval_ms };
+    if send(&mut sink, &hello).await.is_err() {
+        return;
+    }
+    let close_code = loop {
+        let deadline = tokio::time::Instant::from_std(monitor.deadline());
+        tokio::select! {
+            frame = next_frame(&mut stream) => {
+                let payload = match frame {
+                    Some(Ok(payload)) => payload,
+                    Some(Err(close_code)) => break close_code.into(),
+                    // The client has closed the connection
+                    None => return,
+                };
+                match decode_frame::<GatewayMessage>(gateway_config, &payload) {
+                    Ok(GatewayMessage::Heartbeat) => {
+                        monitor.record_heartbeat(Instant::now());
+                        if send(&mut sink, &GatewayMessage::HeartbeatAck).await.is_err() {
+                            return;
+                        }
+                    }
+                    Ok(message) => {
+                        debug!("Received a gateway message only servers may send: {message:?}");
+                        break GatewayCloseCode::InvalidPayload.into();
+                    }
+                    Err(close_code) => break close_code.into(),
+                }
+            }
+            event = events.recv() => match event {
+                Ok(event) => {
+                    if send(&mut sink, &GatewayMessage::from(event)).await.is_err() {
+                        return;
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    debug!("A gateway connection has missed {missed} events");
+                }
+                Err(RecvError::Closed) => break CloseCode::Away,
+            },
+            _ = tokio::time::sleep_until(deadline) => {
+                if let Err(close_code) = monitor.check(Instant::now()) {
+                    debug!("Closing a gateway connection, which has missed a heartbeat");
+                    break close_code.into();
+                }
+            }
+            () = shutdown_requested(&mut shutdown) => break CloseCode::Away,
+        }
+    };
+    _ = sink.send(Message::Close(Some((close_code, String::new())))).await;
+}
+
+/// The payload of the next text or binary frame received from the client, or
+/// [GatewayCloseCode::InvalidPayload], if the client has sent a frame which
+/// the WebSocket implementation rejects, such as one exceeding the configured
+/// size limits. `None`, once the connection has been closed or has failed.
+async fn next_frame(
+    stream: &mut SplitStream<WebSocketStream>,
+) -> Option<Result<Vec<u8>, GatewayCloseCode>> {
+    loop {
+        match stream.next().await? {
+            Ok(Message::Text(text)) => return Some(Ok(text.into_bytes())),
+            Ok(Message::Binary(bytes)) => return Some(Ok(bytes)),
+            // Errors of the WebSocket implementation, such as an oversized frame, have
+            // this kind, while I/O errors keep their own
+            Err(e) if e.kind() == ErrorKind::Other => {
+                debug!("Received an invalid gateway frame: {e}");
+                return Some(Err(GatewayCloseCode::InvalidPayload));
+            }
+            Ok(Message::Close(_)) | Err(_) => return None,
+            // Pings are answered by the WebSocket implementation itself
+            Ok(Message::Ping(_) | Message::Pong(_)) => (),
+        }
+    }
+}
+
+/// Send a [GatewayMessage] as a JSON text frame.
+async fn send(
+    sink: &mut SplitSink<WebSocketStream, Message>,
+    message: &GatewayMessage,
+) -> Result<(), std::io::Error> {
+    sink.send(Message::Text(json!(message).to_string())).await
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use sqlx::{Pool, Postgres, types::Uuid};
+    use tokio::{io::AsyncWriteExt, net::TcpStream};
+    use tokio_tungstenite::{
+        MaybeTlsStream, connect_async,
+        tungstenite::{self, client::IntoClientRequest},
+    };
+
+    use super::*;
+    use crate::{database::test_helpers::insert_session, gateway::presence::PresenceStatus};
+
+    type Client = tokio_tungstenite::WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    /// Give `test_user_1` the token `token_1` and `test_user_2` the token
+    /// `token_2`, then serve the gateway on a free local port. The returned
+    /// sender has to be kept alive for as long as the server is used.
+    async fn serve(
+        pool: Pool<Postgres>,
+        gateway_config: &GatewayConfig,
+    ) -> (SocketAddr, Arc<Hub>, watch::Sender<bool>) {
+        insert_session(&pool, "token_1", 1, Uuid::from_u128(1), None).await;
+        insert_session(&pool, "token_2", 2, Uuid::from_u128(2), None).await;
+        let db = Database { pool };
+        let hub = Arc::new(Hub::new(gateway_config));
+        let (shutdown_sender, shutdown) = watch::channel(false);
+        hub.spawn_flush_task(shutdown.clone());
+        let acceptor = TcpListener::bind("127.0.0.1:0").into_acceptor().await.unwrap();
+        let address = acceptor.local_addr().first().unwrap().as_socket_addr().copied().unwrap();
+        let routes = setup_routes(
+            gateway_config.clone(),
+            db.clone(),
+            TokenStore::new(db),
+            ConnectionLimiter::new(gateway_config),
+            hub.clone(),
+            shutdown,
+        );
+        tokio::spawn(Server::new_with_acceptor(acceptor).run(routes));
+        (address, hub, shutdown_sender)
+    }
+
+    /// Connect to the gateway at `address`, authenticating with `token`.
+    async fn connect(address: SocketAddr, token: &str) -> Client {
+        let mut request = format!("ws://{address}/").into_client_request().unwrap();
+        request.headers_mut().insert("Authorization", token.parse().unwrap());
+        connect_async(request).await.unwrap().0
+    }
+
+    async fn receive(client: &mut Client) -> tungstenite::Message {
+        tokio::time::timeout(Duration::from_secs(5), client.next()).await.unwrap().unwrap().unwrap()
+    }
+
+    async fn receive_message(client: &mut Client) -> GatewayMessage {
+        serde_json::from_str(receive(client).await.to_text().unwrap()).unwrap()
+    }
+
+    fn gateway_config(heartbeat_interval_ms: u32, heartbeat_ack_timeout_ms: u32) -> GatewayConfig {
+        let mut config = GatewayConfig::for_tests();
+        config.heartbeat_interval_ms = heartbeat_interval_ms;
+        config.heartbeat_ack_timeout_ms = heartbeat_ack_timeout_ms;
+        config.presence_debounce_ms = 0;
+        config
+    }
+
+    fn presence(uaid: u128, status: PresenceStatus) -> GatewayMessage {
+        GatewayMessage::Presence { uaid: Uuid::from_u128(uaid), status }
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_hello_and_heartbeat_ack(pool: Pool<Postgres>) {
+        let (address, _hub, _shutdown) = serve(pool, &gateway_config(45000, 10000)).await;
+        let mut client = connect(address, "token_1").await;
+        assert_eq!(
+            receive_message(&mut client).await,
+            GatewayMessage::Hello { heartbeat_interval: 45000 }
+        );
+        assert_eq!(receive_message(&mut client).await, presence(1, PresenceStatus::Online));
+
+        for _ in 0..2 {
+            client
+                .send(tungstenite::Message::text(json!({"op": "heartbeat"}).to_string()))
+                .await
+                .unwrap();
+            assert_eq!(receive_message(&mut client).await, GatewayMessage::HeartbeatAck);
+        }
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_unauthenticated_connection_is_rejected(pool: Pool<Postgres>) {
+        let (address, _hub, _shutdown) = serve(pool, &gateway_config(45000, 10000)).await;
+
+        let error = connect_async(format!("ws://{address}/")).await.unwrap_err();
+        let tungstenite::Error::Http(response) = error else {
+            panic!("Expected the upgrade to be rejected, got {error:?}");
+        };
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_missed_heartbeat_closes_connection(pool: Pool<Postgres>) {
+        let (address, _hub, _shutdown) = serve(pool, &gateway_config(50, 50)).await;
+        let mut client = connect(address, "token_1").await;
+        assert_eq!(
+            receive_message(&mut client).await,
+            GatewayMessage::Hello { heartbeat_interval: 50 }
+        );
+        receive_message(&mut client).await;
+
+        let tungstenite::Message::Close(Some(frame)) = receive(&mut client).await else {
+            panic!("Expected the gateway to close the connection");
+        };
+        assert_eq!(
+            u16::from(frame.code),
+            u16::from(CloseCode::from(GatewayCloseCode::SessionTimeout))
+        );
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_invalid_frame_closes_connection(pool: Pool<Postgres>) {
+        let (address, _hub, _shutdown) = serve(pool, &gateway_config(45000, 10000)).await;
+        let mut client = connect(address, "token_1").await;
+        receive_message(&mut client).await;
+        receive_message(&mut client).await;
+
+        client.send(tungstenite::Message::text("{not json")).await.unwrap();
+        let tungstenite::Message::Close(Some(frame)) = receive(&mut client).await else {
+            panic!("Expected the gateway to close the connection");
+        };
+        assert_eq!(
+            u16::from(frame.code),
+            u16::from(CloseCode::from(GatewayCloseCode::InvalidPayload))
+        );
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_oversized_frame_closes_connection(pool: Pool<Postgres>) {
+        let mut gateway_config = gateway_config(45000, 10000);
+        gateway_config.max_frame_size_bytes = 1024;
+        let (address, _hub, _shutdown) = serve(pool, &gateway_config).await;
+        let mut client = connect(address, "token_1").await;
+        receive_message(&mut client).await;
+        receive_message(&mut client).await;
+
+        // Announce a text frame of 1 MiB, which is below the default limits of the
+        // WebSocket implementation, but only send its header. The gateway has to
+        // reject it without waiting for the payload, let alone buffering it.
+        let mut header = vec![0x81, 0xff];
+        header.extend_from_slice(&(1_u64 << 20).to_be_bytes());
+        header.extend_from_slice(&[0; 4]);
+        client.get_mut().write_all(&header).await.unwrap();
+        let tungstenite::Message::Close(Some(frame)) = receive(&mut client).await else {
+            panic!("Expected the gateway to close the connection");
+        };
+        assert_eq!(
+            u16::from(frame.code),
+            u16::from(CloseCode::from(GatewayCloseCode::InvalidPayload))
+        );
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_announcement_reaches_client(pool: Pool<Postgres>) {
+        let (address, hub, _shutdown) = serve(pool, &gateway_config(45000, 10000)).await;
+        let mut client = connect(address, "token_1").await;
+        receive_message(&mut client).await;
+        receive_message(&mut client).await;
+
+        hub.broadcast(GatewayEvent::Announcement { message: "Maintenance at 10:00".to_owned() });
+        assert_eq!(
+            receive_message(&mut client).await,
+            GatewayMessage::Announcement { message: "Maintenance at 10:00".to_owned() }
+        );
+    }
+
+    #[sqlx::test(fixtures("../../fixtures/tokens_base_fixture.sql"))]
+    async fn test_presence_follows_connections(pool: Pool<Postgres>) {
+        let (address, _hub, _shutdown) = serve(pool, &gateway_config(45000, 10000)).await;
+        let mut client = connect(address, "token_1").await;
+        receive_message(&mut client).await;
+        assert_eq!(receive_message(&mut client).await, presence(1, PresenceStatus::Online));
+
+        let mut other_client = connect(address, "token_2").await;
+        assert_eq!(receive_message(&mut client).await, presence(2, PresenceStatus::Online));
+        other_client.close(None).await.unwrap();
+        assert_eq!(receive_message(&mut client).await, presence(2, PresenceStatus::Offline));
+    }
+}
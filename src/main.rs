@@ -10,12 +10,15 @@
  * A robust, performant polyproto home server.
  */
 
-use std::{path::PathBuf, process::exit, str::FromStr};
+use std::{
+    path::{Path, PathBuf},
+    process::exit,
+    str::FromStr,
+    sync::Arc,
+};
 
 use clap::Parser;
-use log::{LevelFilter, debug, error, info, trace};
-use polyproto::signature::Signature;
-use sqlx::query_scalar;
+use log::{LevelFilter, debug, error, info, trace, warn};
 
 /// The maximum password length this server allows. Passwords longer than this
 /// will not be hashed or processed at all, and will result in a `400` status
@@ -34,6 +37,8 @@ pub(crate) mod crypto;
 /// Module defining PostgreSQL database entities as Rust structs and providing
 /// CRUD functionality
 pub(crate) mod database;
+/// The `doctor` subcommand, diagnosing common setup problems.
+mod doctor;
 
 /// Finer-grained error types for sonata.
 pub(crate) mod errors;
@@ -42,13 +47,16 @@ mod gateway;
 
 pub(crate) use crate::errors::{StdError, StdResult};
 use crate::{
-    crypto::ed25519::DigitalSignature,
+    api::{discovery::Discovery, extractors::ServedDomains, models::PasswordChecker},
+    config::ConfigReloader,
+    crypto::signing_key::HomeServerSigningKey,
     database::{
         Issuer,
         algorithm_identifier::AlgorithmIdentifier,
         api_keys::{self, ApiKey},
         tokens::TokenStore,
     },
+    gateway::{ConnectionLimiter, presence::Hub},
 };
 
 #[tokio::main]
@@ -63,7 +71,8 @@ use crate::{
 /// 3. Connect to the Database, run pending migrations and provide a connection.
 /// 4. Inserting the own [AlgorithmIdentifier] and [Issuer] into the respective
 ///    database tables.
-/// 5. Initialize the [TokenStore].
+/// 5. Load the [HomeServerSigningKey] and store its public key.
+/// 6. Initialize the [TokenStore].
 async fn main() -> StdResult<()> {
     use crate::{cli::Args, config::SonataConfig, database::Database};
     _ = Args::parse(); // Has to be done, else clap doesn't work correctly.
@@ -91,10 +100,20 @@ async fn main() -> StdResult<()> {
             LevelFilter::Trace
         }
     };
+    // The filter lets everything from sonata through, so that the log level can be
+    // raised at runtime using `log::set_max_level`. SQL statements are only
+    // logged by sqlx, if `general.log_sql` is set
     env_logger::Builder::new()
         .filter(None, LevelFilter::Off)
-        .filter(Some("sonata"), log_level)
+        .filter(Some("sonata"), LevelFilter::Trace)
+        .filter(Some("sqlx::query"), LevelFilter::Debug)
         .try_init()?;
+    // The `-v` and `-q` flags take precedence over the `log_level` config value
+    let cli_log_level = match (Args::get_or_panic().verbose, Args::get_or_panic().quiet) {
+        (0, 0) => None,
+        _ => Some(log_level),
+    };
+    log::set_max_level(log_level);
     debug!("Hello, world!");
 
     info!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
@@ -105,6 +124,20 @@ async fn main() -> StdResult<()> {
         None => &PathBuf::from_str("sonata.toml")?,
     };
 
+    match &Args::get_or_panic().command {
+        Some(cli::Command::Doctor) => std::process::exit(doctor::run(config_location).await),
+        Some(cli::Command::ApiKey { command }) => {
+            std::process::exit(cli::api_key::run(command.clone(), config_location).await)
+        }
+        Some(cli::Command::ForeignActor { command }) => {
+            std::process::exit(cli::foreign_actor::run(command.clone(), config_location).await)
+        }
+        Some(cli::Command::Init { output, force }) => {
+            std::process::exit(cli::init::run(output, *force))
+        }
+        None => (),
+    }
+
     debug!("Parsing config at {config_location:?}...");
     SonataConfig::init(&match std::fs::read_to_string(config_location) {
         Ok(string) => string,
@@ -117,85 +150,210 @@ async fn main() -> StdResult<()> {
                 ),
             );
         }
-    })?;
+    })
+    .unwrap_or_else(|e| {
+        exit_with_log(
+            1,
+            &format!("The config at {:?} is invalid: {e}", config_location.to_string_lossy()),
+        )
+    });
     debug!("Parsed config!");
     trace!("Read config {:#?}", SonataConfig::get_or_panic());
+    if let (None, Some(config_log_level)) =
+        (cli_log_level, SonataConfig::get_or_panic().general.log_level)
+    {
+        log::set_max_level(config_log_level);
+    }
+    let mut breached_passwords = None;
+    if let Some(path) = &SonataConfig::get_or_panic().security.breached_passwords_file {
+        debug!("Loading breached passwords list from {path:?}...");
+        match api::models::BreachedPasswords::read(path) {
+            Ok(list) => {
+                match list.is_empty() {
+                    true => warn!("The breached passwords list at {path:?} is empty"),
+                    false => debug!("Loaded {} breached passwords!", list.len()),
+                }
+                breached_passwords = Some(list);
+            }
+            Err(e) => exit_with_log(
+                1,
+                &format!(
+                    r#"Couldn't load the breached passwords list at "{}": {e}"#,
+                    path.to_string_lossy()
+                ),
+            ),
+        }
+    }
+    #[cfg(unix)]
+    spawn_config_reload_handler(config_location.clone(), cli_log_level);
 
     debug!("Connecting to the database...");
-    let database =
-        match Database::connect_with_config(&SonataConfig::get_or_panic().general.database).await {
-            Ok(db) => db,
-            Err(e) => exit_with_log(3, &format!("Couldn't connect to the database: {e}")),
-        };
+    let database = match Database::connect_with_config(
+        &SonataConfig::get_or_panic().general.database,
+        SonataConfig::get_or_panic().general.log_sql,
+    )
+    .await
+    {
+        Ok(db) => db,
+        Err(e) => exit_with_log(3, &format!("Couldn't connect to the database: {e}")),
+    };
     debug!("Connected to database!");
+    database.spawn_saturation_monitor(&SonataConfig::get_or_panic().general.database);
     debug!("Applying migrations...");
     match database.run_migrations().await {
         Ok(_) => debug!("Migrations applied!"),
         Err(e) => exit_with_log(4, &format!("Couldn't apply migrations: {e}")),
     };
-    let keys_in_table =
-        query_scalar!("SELECT COUNT(*) FROM api_keys").fetch_one(&database.pool).await?;
-    match keys_in_table {
-        Some(0) | None => {
-            let api_key =
-                api_keys::add_api_key_to_database(&ApiKey::new_random(&mut rand::rng()), &database)
-                    .await
-                    .map_err(|_| String::from("Error adding API key to database}"))?;
-            info!("Added an API key to the database, since none were available: {api_key}");
-            info!("Save this API key, as it will not be shown again on future starts.");
-        }
-        _ => (),
-    };
+    report_orphan_actors(&database, Args::get_or_panic().purge_orphan_actors).await;
+    if api_keys::count_active_api_keys(&database).await? == 0 {
+        let api_key =
+            api_keys::add_api_key_to_database(&ApiKey::new_random(&mut rand::rng()), &database)
+                .await
+                .map_err(|_| String::from("Error adding API key to database}"))?;
+        info!("Added an API key to the database, since no active key was available: {api_key}");
+        info!("Save this API key, as it will not be shown again on future starts.");
+    }
     debug!("Inserting known algorithm identifiers into algorithm_identifiers table...");
-    match AlgorithmIdentifier::try_insert(
-        &database,
-        &DigitalSignature::algorithm_identifier().oid,
-        Some("Edwards-curve Digital Signature Algorithm (EdDSA) Ed25519"),
-        Default::default(),
-    )
-    .await
-    {
-        Ok(a_id) => debug!(
-            "Inserted algorithm_identifier {} {}",
-            a_id.algorithm_identifier,
-            a_id.common_name.unwrap_or_default()
-        ),
-        Err(e) => match e.code {
-            errors::Errcode::Duplicate => {
-                debug!("Algorithm identifier already present, nothing changed")
-            }
-            _ => error!("Could not manipulate database: {e:?}"),
-        },
-    };
-    debug!("Inserting own issuer domain name into the database...");
+    match AlgorithmIdentifier::ensure_all(&database, &crypto::known_algorithm_identifiers()).await {
+        Ok(ids) => debug!("Known algorithm identifiers are present with the IDs {ids:?}"),
+        Err(e) => error!("Could not manipulate database: {e:?}"),
+    }
+    debug!("Inserting own issuer domain names into the database...");
     match Issuer::create_own(&database).await {
-        Ok(i) => match i {
-            Some(issuer) => {
+        Ok(issuers) if issuers.is_empty() => {
+            debug!("Issuer entries already present, nothing changed")
+        }
+        Ok(issuers) => {
+            for issuer in issuers {
                 debug!(r#"Inserted own issuer "{}" into the database!"#, issuer.domain_components)
             }
-            None => debug!("Issuer entry already present, nothing changed"),
-        },
+        }
         Err(e) => {
             error!("Could not manipulate database: {e:?}");
             exit(5)
         }
     }
 
+    let signing_key_file = &SonataConfig::get_or_panic().general.signing_key_file;
+    debug!("Loading the signing key from {signing_key_file:?}...");
+    let signing_key = match HomeServerSigningKey::load_or_generate(signing_key_file) {
+        Ok(key) => key,
+        Err(e) => exit_with_log(
+            7,
+            &format!(
+                r#"Couldn't load the signing key at "{}": {e}"#,
+                signing_key_file.to_string_lossy()
+            ),
+        ),
+    };
+    let signing_key = match HomeServerSigningKey::register(
+        &database,
+        signing_key,
+        &SonataConfig::get_or_panic().security,
+    )
+    .await
+    {
+        Ok(key) => key,
+        Err(e) => exit_with_log(7, &format!("Couldn't store the public signing key: {e}")),
+    };
+
     let token_store = TokenStore::new(database.clone());
+    token_store.spawn_purge_task(&SonataConfig::get_or_panic().general.database);
 
-    let tasks = vec![api::start_api(
+    let (shutdown_sender, shutdown_receiver) = tokio::sync::watch::channel(false);
+    // Shared by the API and the gateway, so that the admin API can broadcast
+    // to gateway connections and the metrics endpoint can report them
+    let hub = Arc::new(Hub::new(&SonataConfig::get_or_panic().gateway));
+    let connection_limiter = ConnectionLimiter::new(&SonataConfig::get_or_panic().gateway);
+    let mut tasks = vec![match api::start_api(
         SonataConfig::get_or_panic().api.clone(),
+        ServedDomains::new(SonataConfig::get_or_panic().general.served_domains()),
+        Discovery::new(
+            &SonataConfig::get_or_panic().general.server_domain,
+            &SonataConfig::get_or_panic().api,
+            &SonataConfig::get_or_panic().gateway,
+        ),
         database.clone(),
         token_store.clone(),
-    )];
+        signing_key,
+        PasswordChecker::new(breached_passwords),
+        ConfigReloader::get_or_panic().handle(),
+        connection_limiter.clone(),
+        hub.clone(),
+        shutdown_receiver.clone(),
+    )
+    .await
+    {
+        Ok((handle, _)) => handle,
+        Err(e) => exit_with_log(6, &e.to_string()),
+    }];
+    if SonataConfig::get_or_panic().gateway.enabled {
+        match gateway::start_gateway(
+            SonataConfig::get_or_panic().gateway.clone(),
+            database.clone(),
+            token_store,
+            connection_limiter,
+            hub,
+            shutdown_receiver,
+        )
+        .await
+        {
+            Ok(handle) => tasks.push(handle),
+            Err(e) => exit_with_log(6, &e.to_string()),
+        }
+    }
+    tokio::spawn(async move {
+        wait_for_shutdown_signal().await;
+        info!("Received shutdown signal, shutting down...");
+        _ = shutdown_sender.send(true);
+    });
 
     for task in tasks.into_iter() {
         task.await.unwrap()
     }
+    database.close().await;
+    debug!("Closed database connections");
+    SonataConfig::zeroize_global_secrets();
+    debug!("Zeroized configuration secrets");
 
     Ok(())
 }
 
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Resolves once sonata has been asked to shut down, either by `Ctrl+C` or, on
+/// Unix, by `SIGTERM`.
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => _ = terminate.recv().await,
+            Err(e) => {
+                error!("Could not install SIGTERM handler: {e}");
+                std::future::pending::<()>().await
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => if let Err(e) = result {
+            error!("Could not listen for Ctrl+C: {e}");
+            std::future::pending::<()>().await
+        },
+        _ = terminate => (),
+    }
+}
+
+/// Resolves once `true` has been sent through `shutdown`, the channel through
+/// which `main` asks the API, the gateway and their background tasks to stop.
+pub(crate) async fn shutdown_requested(shutdown: &mut tokio::sync::watch::Receiver<bool>) {
+    // An error means that the sender has been dropped, which is a shutdown as well
+    _ = shutdown.wait_for(|shutdown| *shutdown).await;
+}
+
 /// Exits the program with a given status code, printing a log message
 /// beforehand.
 #[cfg_attr(coverage_nightly, coverage(off))]
@@ -204,3 +362,81 @@ pub fn exit_with_log(code: i32, message: &str) -> ! {
     error!("Exiting due to previous error.");
     std::process::exit(code)
 }
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Log orphaned local actors found in the database, deleting them if `purge` is
+/// `true`.
+async fn report_orphan_actors(database: &database::Database, purge: bool) {
+    use crate::database::actor::{find_orphan_actors, purge_orphan_actors};
+
+    let result = match purge {
+        true => purge_orphan_actors(database).await,
+        false => find_orphan_actors(database).await,
+    };
+    match result {
+        Ok(orphans) if orphans.is_empty() => debug!("No orphaned actors found"),
+        Ok(orphans) if purge => info!("Purged {} orphaned actors: {orphans:?}", orphans.len()),
+        Ok(orphans) => warn!(
+            "Found {} orphaned actors, start sonata with --purge-orphan-actors to delete them: \
+             {orphans:?}",
+            orphans.len()
+        ),
+        Err(e) => error!("Could not check for orphaned actors: {e}"),
+    }
+}
+
+#[cfg(unix)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Spawn a task which re-reads the configuration file at `config_location`
+/// whenever sonata receives `SIGHUP`, hot-applying the subset of the
+/// configuration which can be changed at runtime. `cli_log_level` is the log
+/// level set using the `-v` and `-q` flags, if any, which takes precedence
+/// over the configuration file.
+fn spawn_config_reload_handler(config_location: PathBuf, cli_log_level: Option<LevelFilter>) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            error!("Could not install SIGHUP handler, configuration reloading is disabled: {e}");
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("Received SIGHUP, reloading configuration...");
+            reload_config(&config_location, cli_log_level);
+        }
+    });
+}
+
+#[cfg(unix)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Re-read the configuration file at `config_location` and hot-apply the
+/// reloadable subset of it, logging what changed.
+fn reload_config(config_location: &Path, cli_log_level: Option<LevelFilter>) {
+    let input = match std::fs::read_to_string(config_location) {
+        Ok(input) => input,
+        Err(e) => {
+            error!("Could not read config at {config_location:?}, keeping current config: {e}");
+            return;
+        }
+    };
+    match ConfigReloader::get_or_panic().reload(&input) {
+        Ok(changes) if changes.is_empty() => info!("Configuration reloaded, nothing changed"),
+        Ok(changes) => {
+            for change in changes {
+                info!("Configuration reloaded: {change}");
+            }
+        }
+        Err(e) => {
+            error!("Could not parse config, keeping current config: {e}");
+            return;
+        }
+    }
+    log::set_max_level(
+        cli_log_level
+            .or(ConfigReloader::get_or_panic().current().log_level)
+            .unwrap_or(LevelFilter::Info),
+    );
+}